@@ -0,0 +1,138 @@
+use conrod_core::{text, widget, Color, Colorable, Positionable, UiCell, Widget};
+
+use crate::{Airline, AirlineFilter, BasicAirline, Plane, PlaneRenderer};
+
+/// The most airlines listed at once, so the legend doesn't cover the map
+pub const MAX_LEGEND_ROWS: usize = 10;
+
+const SWATCH_SIZE: f64 = 10.0;
+const ROW_HEIGHT: f64 = 14.0;
+
+/// Returns the airlines to list in the legend, at most [`MAX_LEGEND_ROWS`].
+///
+/// Airlines selected in `filter` come first, followed by the airlines of the `visible` planes the
+/// filter shows, most common first
+pub fn legend_entries<'a>(
+    visible: impl IntoIterator<Item = &'a Plane>,
+    filter: &AirlineFilter,
+) -> Vec<Airline> {
+    let mut entries: Vec<Airline> = crate::AIRLINE_FILTERS
+        .iter()
+        .map(|(airline, _)| *airline)
+        .filter(|airline| *airline != BasicAirline::All && filter.is_selected(*airline))
+        .map(Airline::from)
+        .collect();
+
+    let shown = visible
+        .into_iter()
+        .filter(|plane| filter.shows(&plane.airline));
+    let mut counts: Vec<_> = crate::planes_by_airline(shown)
+        .into_iter()
+        .map(|(airline, planes)| (airline, planes.len()))
+        .collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| {
+        b_count
+            .cmp(a_count)
+            .then_with(|| a.to_str().cmp(b.to_str()))
+    });
+
+    for (airline, _) in counts {
+        if !entries.contains(airline) {
+            entries.push(airline.clone());
+        }
+    }
+    entries.truncate(MAX_LEGEND_ROWS);
+    entries
+}
+
+/// Draws a column of color swatches and airline names in the bottom left corner of the screen,
+/// using the colors planes are drawn with.
+///
+/// `swatches` and `labels` must have an id for each entry
+pub fn draw(
+    entries: &[Airline],
+    renderer: &PlaneRenderer,
+    swatches: &[widget::Id],
+    labels: &[widget::Id],
+    ui: &mut UiCell,
+    font: text::font::Id,
+) {
+    let left = -ui.win_w / 2.0 + 10.0;
+    let bottom = -ui.win_h / 2.0 + 20.0;
+    //The first entry goes on top
+    let rows = entries.iter().zip(swatches.iter().zip(labels.iter()));
+    for (i, (airline, (swatch, label))) in rows.enumerate() {
+        let y = bottom + (entries.len() - 1 - i) as f64 * ROW_HEIGHT;
+        let [r, g, b] = renderer.airline_color(airline);
+        widget::Rectangle::fill([SWATCH_SIZE, SWATCH_SIZE])
+            .color(Color::Rgba(r, g, b, 1.0))
+            .x_y(left + SWATCH_SIZE / 2.0, y)
+            .set(*swatch, ui);
+
+        widget::Text::new(airline.to_str())
+            .color(conrod_core::color::WHITE)
+            .font_size(10)
+            .font_id(font)
+            .right_from(*swatch, 6.0)
+            .set(*label, ui);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DynamicAirline, PlaneType};
+
+    fn plane(airline: Airline) -> Plane {
+        Plane::new(
+            0.0,
+            0.0,
+            0.0,
+            "TEST1".to_owned(),
+            airline,
+            PlaneType::Commercial,
+        )
+    }
+
+    #[test]
+    fn entries_follow_filter_and_view() {
+        let fedex = Airline::Dynamic(DynamicAirline {
+            callsign: "FDX".to_owned(),
+            name: "FedEx".to_owned(),
+        });
+        let planes = [
+            plane(BasicAirline::Delta.into()),
+            plane(fedex.clone()),
+            plane(fedex.clone()),
+            plane(Airline::Unknown),
+        ];
+
+        //With everything shown the most common airline comes first
+        let entries = legend_entries(&planes, &AirlineFilter::default());
+        assert_eq!(
+            entries,
+            [fedex.clone(), BasicAirline::Delta.into(), Airline::Unknown]
+        );
+
+        //Selected airlines are listed even without planes in view, and hidden ones aren't listed
+        let mut filter = AirlineFilter::default();
+        filter.toggle(BasicAirline::Spirit);
+        filter.toggle(BasicAirline::Delta);
+        let entries = legend_entries(&planes, &filter);
+        assert_eq!(
+            entries,
+            [BasicAirline::Spirit.into(), BasicAirline::Delta.into()]
+        );
+
+        let many: Vec<_> = (0..MAX_LEGEND_ROWS * 2)
+            .map(|i| {
+                plane(Airline::Dynamic(DynamicAirline {
+                    callsign: format!("A{:02}", i),
+                    name: format!("Airline {:02}", i),
+                }))
+            })
+            .collect();
+        let entries = legend_entries(&many, &AirlineFilter::default());
+        assert_eq!(entries.len(), MAX_LEGEND_ROWS);
+    }
+}
@@ -1,7 +1,9 @@
-use serde::Deserialize;
+use std::io::Read;
+
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// Represents an Airport that will be deserialized
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Airport {
     pub id: u32,
     pub ident: String,
@@ -23,33 +25,366 @@ pub struct Airport {
     pub local_code: String,
 }
 
-/// Deserializes a Vec<Airport> from a &[u8] using serde Postcard
+/// The kinds of airports in the OurAirports data set
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AirportType {
+    Small,
+    Medium,
+    Large,
+    Heliport,
+    SeaplaneBase,
+    Balloonport,
+    Closed,
+}
+
+/// The airport types loaded by [`airports_from_bytes`] and [`airports_from_csv_reader`]
+pub const DEFAULT_AIRPORT_TYPES: [AirportType; 2] = [AirportType::Medium, AirportType::Large];
+
+impl AirportType {
+    pub const ALL: [AirportType; 7] = [
+        AirportType::Small,
+        AirportType::Medium,
+        AirportType::Large,
+        AirportType::Heliport,
+        AirportType::SeaplaneBase,
+        AirportType::Balloonport,
+        AirportType::Closed,
+    ];
+
+    /// Returns the name OurAirports uses for this type in its `type` column
+    pub fn to_str(self) -> &'static str {
+        match self {
+            AirportType::Small => "small_airport",
+            AirportType::Medium => "medium_airport",
+            AirportType::Large => "large_airport",
+            AirportType::Heliport => "heliport",
+            AirportType::SeaplaneBase => "seaplane_base",
+            AirportType::Balloonport => "balloonport",
+            AirportType::Closed => "closed",
+        }
+    }
+
+    /// Parses the name OurAirports uses for a type, returning None for names it doesn't know
+    pub fn from_name(name: &str) -> Option<Self> {
+        AirportType::ALL
+            .iter()
+            .copied()
+            .find(|airport_type| airport_type.to_str() == name)
+    }
+}
+
+impl Airport {
+    /// Returns the kind of airport this is, or None if the data set has a type we don't know
+    pub fn kind(&self) -> Option<AirportType> {
+        AirportType::from_name(&self.airport_type)
+    }
+
+    /// Returns the code people know the airport by, which is its IATA code if it has one
+    pub fn code(&self) -> &str {
+        if self.iata_code.is_empty() {
+            &self.ident
+        } else {
+            &self.iata_code
+        }
+    }
+}
+
+/// One row of the [OurAirports](https://ourairports.com/data/) `airports.csv` file.
+///
+/// The country and region names aren't in that file, so they are optional here and fall back to
+/// the ISO codes
+#[derive(Debug, Deserialize)]
+struct CsvAirport {
+    id: u32,
+    ident: String,
+    #[serde(rename = "type")]
+    airport_type: String,
+    name: String,
+    latitude_deg: f32,
+    longitude_deg: f32,
+    elevation_ft: Option<i32>,
+    continent: String,
+    #[serde(default)]
+    country_name: Option<String>,
+    iso_country: String,
+    #[serde(default)]
+    region_name: Option<String>,
+    iso_region: String,
+    #[serde(default)]
+    local_region: Option<String>,
+    municipality: String,
+    #[serde(deserialize_with = "yes_no")]
+    scheduled_service: bool,
+    gps_code: String,
+    iata_code: String,
+    local_code: String,
+}
+
+impl From<CsvAirport> for Airport {
+    fn from(row: CsvAirport) -> Self {
+        //ISO regions look like US-FL, where the part after the dash is the local code
+        let local_region = row.local_region.unwrap_or_else(|| {
+            let (_, local) = row
+                .iso_region
+                .split_once('-')
+                .unwrap_or(("", &row.iso_region));
+            local.to_owned()
+        });
+        Self {
+            id: row.id,
+            ident: row.ident,
+            airport_type: row.airport_type,
+            name: row.name,
+            latitude: row.latitude_deg,
+            longitude: row.longitude_deg,
+            elevation: row.elevation_ft.unwrap_or(0),
+            continent: row.continent,
+            country_name: row.country_name.unwrap_or_else(|| row.iso_country.clone()),
+            iso_country: row.iso_country,
+            region_name: row.region_name.unwrap_or_else(|| row.iso_region.clone()),
+            iso_region: row.iso_region,
+            local_region,
+            municipality: row.municipality,
+            scheduled_service: row.scheduled_service,
+            gps_code: row.gps_code,
+            iata_code: row.iata_code,
+            local_code: row.local_code,
+        }
+    }
+}
+
+/// Deserializes OurAirports' `yes`/`no` columns
+fn yes_no<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    match value.as_str() {
+        "yes" => Ok(true),
+        "no" | "" => Ok(false),
+        _ => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Str(&value),
+            &"yes or no",
+        )),
+    }
+}
+
+/// Returns true if `airport` is one of `types`
+fn is_wanted(airport: &Airport, types: &[AirportType]) -> bool {
+    airport.kind().is_some_and(|kind| types.contains(&kind))
+}
+
+/// Deserializes a Vec<Airport> from a &[u8] using serde Postcard, keeping only the
+/// [`DEFAULT_AIRPORT_TYPES`]
 pub fn airports_from_bytes(bytes: &[u8]) -> Result<Vec<Airport>, Box<bincode::ErrorKind>> {
+    airports_from_bytes_filtered(bytes, &DEFAULT_AIRPORT_TYPES)
+}
+
+/// Deserializes a Vec<Airport> from a &[u8] using serde Postcard, keeping only the airports that
+/// are one of `types`
+pub fn airports_from_bytes_filtered(
+    bytes: &[u8],
+    types: &[AirportType],
+) -> Result<Vec<Airport>, Box<bincode::ErrorKind>> {
     // Deserialize all of the airports
     let airports: Vec<Airport> = bincode::deserialize(bytes)?;
 
     let airports = airports
         .into_iter()
-        .filter(|airport| {
-            matches!(
-                airport.airport_type.as_str(),
-                "medium_airport" | "large_airport"
-            )
-        })
+        .filter(|airport| is_wanted(airport, types))
         .collect();
 
     Ok(airports)
 }
 
+/// Parses airports from the [OurAirports](https://ourairports.com/data/) `airports.csv` format,
+/// so the data set can be updated without rebuilding. Only the [`DEFAULT_AIRPORT_TYPES`] are
+/// returned, like [`airports_from_bytes`]
+pub fn airports_from_csv_reader<R: Read>(reader: R) -> Result<Vec<Airport>, csv::Error> {
+    airports_from_csv_reader_filtered(reader, &DEFAULT_AIRPORT_TYPES)
+}
+
+/// Parses airports from the OurAirports `airports.csv` format, keeping only the airports that are
+/// one of `types`
+pub fn airports_from_csv_reader_filtered<R: Read>(
+    reader: R,
+    types: &[AirportType],
+) -> Result<Vec<Airport>, csv::Error> {
+    let mut airports = Vec::new();
+    for row in csv::Reader::from_reader(reader).deserialize::<CsvAirport>() {
+        let airport = Airport::from(row?);
+        if is_wanted(&airport, types) {
+            airports.push(airport);
+        }
+    }
+    Ok(airports)
+}
+
+/// Serializes airports into the format read by [`airports_from_bytes`], for regenerating
+/// `assets/data/airports.bin`
+pub fn airports_to_bytes(airports: &[Airport]) -> Result<Vec<u8>, Box<bincode::ErrorKind>> {
+    bincode::serialize(airports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "\
+\"id\",\"ident\",\"type\",\"name\",\"latitude_deg\",\"longitude_deg\",\"elevation_ft\",\"continent\",\"iso_country\",\"iso_region\",\"municipality\",\"scheduled_service\",\"gps_code\",\"iata_code\",\"local_code\",\"home_link\",\"wikipedia_link\",\"keywords\"
+3497,\"KDAB\",\"medium_airport\",\"Daytona Beach International Airport\",29.1799,-81.0581,34,\"NA\",\"US\",\"US-FL\",\"Daytona Beach\",\"yes\",\"KDAB\",\"DAB\",\"DAB\",,,
+3622,\"KMCO\",\"large_airport\",\"Orlando International Airport\",28.4294,-81.309,96,\"NA\",\"US\",\"US-FL\",\"Orlando\",\"yes\",\"KMCO\",\"MCO\",\"MCO\",https://flymco.com/,,\"Disney World, Epcot\"
+6523,\"00A\",\"heliport\",\"Total RF Heliport\",40.070985,-74.933689,11,\"NA\",\"US\",\"US-PA\",\"Bensalem\",\"no\",\"K00A\",,\"00A\",,,
+";
+
+    #[test]
+    fn csv_round_trip() {
+        let airports = airports_from_csv_reader(CSV.as_bytes()).unwrap();
+        let idents: Vec<_> = airports.iter().map(|a| a.ident.as_str()).collect();
+        //Heliports are filtered out, like in the built in data
+        assert_eq!(idents, ["KDAB", "KMCO"]);
+
+        let bytes = airports_to_bytes(&airports).unwrap();
+        let airports = airports_from_bytes(&bytes).unwrap();
+        assert_eq!(airports.len(), 2);
+        let orlando = &airports[1];
+        assert_eq!(orlando.name, "Orlando International Airport");
+        assert_eq!(orlando.airport_type, "large_airport");
+        assert_eq!((orlando.latitude, orlando.longitude), (28.4294, -81.309));
+        assert_eq!(orlando.elevation, 96);
+        assert_eq!(orlando.code(), "MCO");
+        assert!(orlando.scheduled_service);
+        assert_eq!(orlando.country_name, "US");
+        assert_eq!(orlando.iso_region, "US-FL");
+        assert_eq!(orlando.local_region, "FL");
+    }
+
+    #[test]
+    fn airport_type_names() {
+        for airport_type in AirportType::ALL {
+            assert_eq!(
+                AirportType::from_name(airport_type.to_str()),
+                Some(airport_type)
+            );
+        }
+        assert_eq!(AirportType::from_name("spaceport"), None);
+    }
+
+    #[test]
+    fn type_filters() {
+        let mut csv = CSV.lines().take(1).collect::<Vec<_>>().join("\n");
+        for (i, airport_type) in AirportType::ALL.iter().enumerate() {
+            csv += &format!(
+                "\n{},\"T{}\",\"{}\",\"Test\",0,0,0,\"NA\",\"US\",\"US-FL\",\"\",\"no\",\"\",\"\",\"\",,,",
+                i,
+                i,
+                airport_type.to_str()
+            );
+        }
+        //Types we don't know about are never kept
+        csv += "\n99,\"T99\",\"spaceport\",\"Test\",0,0,0,\"NA\",\"US\",\"US-FL\",\"\",\"no\",\"\",\"\",\"\",,,";
+
+        let all = airports_from_csv_reader_filtered(csv.as_bytes(), &AirportType::ALL).unwrap();
+        let bytes = airports_to_bytes(&all).unwrap();
+        assert_eq!(all.len(), AirportType::ALL.len());
+
+        for airport_type in AirportType::ALL {
+            let kept = airports_from_bytes_filtered(&bytes, &[airport_type]).unwrap();
+            assert_eq!(kept.len(), 1);
+            assert_eq!(kept[0].kind(), Some(airport_type));
+
+            let others: Vec<_> = AirportType::ALL
+                .into_iter()
+                .filter(|other| *other != airport_type)
+                .collect();
+            let kept = airports_from_csv_reader_filtered(csv.as_bytes(), &others).unwrap();
+            assert_eq!(kept.len(), others.len());
+            assert!(kept
+                .iter()
+                .all(|airport| airport.kind() != Some(airport_type)));
+        }
+
+        let default: Vec<_> = airports_from_bytes(&bytes)
+            .unwrap()
+            .iter()
+            .map(Airport::kind)
+            .collect();
+        assert_eq!(
+            default,
+            [Some(AirportType::Medium), Some(AirportType::Large)]
+        );
+        assert!(airports_from_bytes_filtered(&bytes, &[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn invalid_csv() {
+        let csv = CSV.replacen("\"yes\"", "\"sometimes\"", 1);
+        assert!(airports_from_csv_reader(csv.as_bytes()).is_err());
+    }
+}
+
 /// Useful functions for rendering airports on the map
 pub mod airport_renderer {
-    use conrod_core::{Positionable, Sizeable, UiCell, Widget};
+    use conrod_core::{text, Colorable, Positionable, Sizeable, UiCell, Widget};
+    use glam::DVec2;
     use num::Float;
 
-    use crate::Airport;
+    use crate::request_plane::Plane;
+    use crate::util::haversine_distance_m;
+    use crate::{Airport, AirportType};
+
+    /// Large airports are drawn once the view is zoomed in this far. Any further out and their
+    /// icons are less than two pixels wide
+    pub const LARGE_AIRPORT_MIN_ZOOM: f64 = 13.0;
+
+    /// Medium airports are drawn once the view is zoomed in this far, which is about a state's
+    /// width. They crowd out the large ones when zoomed out past this
+    pub const MEDIUM_AIRPORT_MIN_ZOOM: f64 = 16.0;
+
+    /// Small airports, heliports and everything else are only drawn once the view is zoomed in
+    /// this far, which is about a city's width
+    pub const SMALL_AIRPORT_MIN_ZOOM: f64 = 19.0;
+
+    /// Airports are labeled once the view is zoomed in this far, which is about one map zoom level
+    /// closer than where the app starts. Any further out and the labels cover the map
+    pub const LABEL_MIN_ZOOM: f64 = 19.0;
+
+    /// The airport's full name is added to its label once the view is zoomed in this far
+    pub const LABEL_NAME_ZOOM: f64 = 21.0;
+
+    const LABEL_FONT_SIZE: u32 = 10;
+
+    /// Clicks at most this many pixels from an airport select it, even when its icon is smaller
+    const MIN_CLICK_RADIUS: f64 = 8.0;
+
+    /// Planes within this many meters of an airport are counted as near it
+    pub const NEARBY_PLANE_RADIUS_M: f64 = 50_000.0;
+
+    /// B612 Mono is monospaced, so every character is about this wide relative to the font size
+    const CHARACTER_WIDTH: f64 = 0.6;
+
+    /// A label that would like to be drawn, with the center and size of the box around it
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct LabelCandidate {
+        pub text: String,
+        pub x: f64,
+        pub y: f64,
+        pub width: f64,
+        pub height: f64,
+    }
+
+    impl LabelCandidate {
+        fn overlaps(&self, other: &LabelCandidate) -> bool {
+            (self.x - other.x).abs() * 2.0 < self.width + other.width
+                && (self.y - other.y).abs() * 2.0 < self.height + other.height
+        }
+    }
 
     /// Draws all of the airports onto the map. Should be run before plane rendering, but after the
-    /// map tiles are rendered
+    /// map tiles are rendered.
+    ///
+    /// Past [`LABEL_MIN_ZOOM`] each airport is labeled with its code, skipping labels that would
+    /// overlap one already drawn. Bigger airports are labeled first
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         airports: &[Airport],
         view: &crate::map::TileView,
@@ -57,6 +392,8 @@ pub mod airport_renderer {
         ids: &mut crate::Ids,
         image_id: crate::ImageId,
         ui: &mut UiCell,
+        font: text::font::Id,
+        theme: &crate::Theme,
     ) {
         let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
 
@@ -65,30 +402,290 @@ pub mod airport_renderer {
         ids.airports
             .resize(airports.len(), &mut ui.widget_id_generator());
 
-        let lat_top = crate::util::latitude_from_y(viewport.top_left.y.rem_euclid(1.0)) as f32;
-        let lat_bottom =
-            crate::util::latitude_from_y(viewport.bottom_right.y.rem_euclid(1.0)) as f32;
-        let long_left = crate::util::longitude_from_x(viewport.top_left.x.rem_euclid(1.0)) as f32;
-        let long_right =
-            crate::util::longitude_from_x(viewport.bottom_right.x.rem_euclid(1.0)) as f32;
+        let bounds = crate::PlaneBounds::from_viewport(&viewport);
 
+        let size = icon_size(zoom);
+        let mut labels = Vec::new();
         for (i, airport) in airports.iter().enumerate() {
-            if (airport.latitude > lat_bottom && airport.latitude < lat_top)
-                && (airport.longitude > long_left && airport.longitude < long_right)
+            if is_visible_at_zoom(airport, zoom)
+                && bounds.contains_lat_long(airport.latitude, airport.longitude)
             {
                 // Render airports
-                let world_x = crate::util::x_from_longitude(airport.longitude as f64);
+                let world_x =
+                    viewport.wrap_x(crate::util::x_from_longitude(airport.longitude as f64));
                 let world_y = crate::util::y_from_latitude(airport.latitude as f64);
 
                 let pixel_x = crate::world_x_to_pixel_x(world_x, &viewport, ui.win_w);
                 let pixel_y = crate::world_y_to_pixel_y(world_y, &viewport, ui.win_h);
 
-                let size = 1.5.powf(zoom) / 100.0;
                 conrod_core::widget::Image::new(image_id.normal)
                     .x_y(pixel_x, pixel_y)
                     .w_h(size, size)
                     .set(ids.airports[i], ui);
+
+                if zoom >= LABEL_MIN_ZOOM {
+                    labels.push((label_priority(airport), airport, pixel_x, pixel_y));
+                }
             }
         }
+
+        labels.sort_by_key(|(priority, ..)| std::cmp::Reverse(*priority));
+        let candidates: Vec<_> = labels
+            .into_iter()
+            .map(|(_, airport, x, y)| {
+                let text = label_text(airport, zoom);
+                let width = text.chars().count() as f64 * LABEL_FONT_SIZE as f64 * CHARACTER_WIDTH;
+                LabelCandidate {
+                    text,
+                    //To the right of the icon
+                    x: x + size / 2.0 + 4.0 + width / 2.0,
+                    y,
+                    width,
+                    height: LABEL_FONT_SIZE as f64,
+                }
+            })
+            .collect();
+        let labels = declutter(candidates);
+
+        ids.airport_labels
+            .resize(labels.len(), &mut ui.widget_id_generator());
+        for (label, id) in labels.iter().zip(ids.airport_labels.iter()) {
+            crate::OutlinedText::new(&label.text)
+                .x_y(label.x, label.y)
+                .color(theme.text)
+                .outline_color(theme.text_outline)
+                .font_size(LABEL_FONT_SIZE)
+                .font_id(font)
+                .set(*id, ui);
+        }
+    }
+
+    /// Returns how many pixels wide airport icons are drawn at `zoom`
+    fn icon_size(zoom: f64) -> f64 {
+        1.5.powf(zoom) / 100.0
+    }
+
+    /// Returns the index of the airport drawn closest to `click`, or None if no airport is close
+    /// enough to have been clicked. `click` is in pixels from the top left of the screen
+    pub fn airport_at(
+        airports: &[Airport],
+        view: &crate::map::TileView,
+        click: DVec2,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> Option<usize> {
+        let zoom = view.get_zoom();
+        let radius = f64::max(icon_size(zoom) / 2.0, MIN_CLICK_RADIUS);
+        airports
+            .iter()
+            .enumerate()
+            .filter(|(_, airport)| is_visible_at_zoom(airport, zoom))
+            .map(|(i, airport)| {
+                let pixel = view.lat_long_to_screen(
+                    airport.latitude as f64,
+                    airport.longitude as f64,
+                    screen_width,
+                    screen_height,
+                );
+                (i, pixel.distance(click))
+            })
+            .filter(|(_, distance)| *distance <= radius)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    /// Returns how many of `planes` are within [`NEARBY_PLANE_RADIUS_M`] of `airport`. Planes on
+    /// the ground are only counted if `include_on_ground` is set, like they are only drawn then
+    pub fn planes_near(airport: &Airport, planes: &[Plane], include_on_ground: bool) -> usize {
+        let (latitude, longitude) = (airport.latitude as f64, airport.longitude as f64);
+        planes
+            .iter()
+            .filter(|plane| include_on_ground || !plane.on_ground)
+            .filter(|plane| {
+                let distance = haversine_distance_m(
+                    latitude,
+                    longitude,
+                    plane.latitude as f64,
+                    plane.longitude as f64,
+                );
+                distance <= NEARBY_PLANE_RADIUS_M
+            })
+            .count()
+    }
+
+    /// Returns true if `airport` is big enough to be drawn at `zoom`, so that more airports show up
+    /// as the view is zoomed in
+    pub fn is_visible_at_zoom(airport: &Airport, zoom: f64) -> bool {
+        let min_zoom = match airport.kind() {
+            Some(AirportType::Large) => LARGE_AIRPORT_MIN_ZOOM,
+            Some(AirportType::Medium) => MEDIUM_AIRPORT_MIN_ZOOM,
+            _ => SMALL_AIRPORT_MIN_ZOOM,
+        };
+        zoom >= min_zoom
+    }
+
+    /// Returns how important it is that `airport` is labeled. Big airports with scheduled flights
+    /// are the ones people look for
+    fn label_priority(airport: &Airport) -> u8 {
+        let size = match airport.kind() {
+            Some(AirportType::Large) => 2,
+            Some(AirportType::Medium) => 1,
+            _ => 0,
+        };
+        size * 2 + airport.scheduled_service as u8
+    }
+
+    /// Returns the label for `airport`, which is its [`Airport::code`]. The name is added
+    /// once zoomed in past [`LABEL_NAME_ZOOM`]
+    pub fn label_text(airport: &Airport, zoom: f64) -> String {
+        if zoom >= LABEL_NAME_ZOOM {
+            format!("{} {}", airport.code(), airport.name)
+        } else {
+            airport.code().to_owned()
+        }
+    }
+
+    /// Returns the labels in `candidates` that don't overlap any label before them
+    pub fn declutter(candidates: Vec<LabelCandidate>) -> Vec<LabelCandidate> {
+        let mut placed: Vec<LabelCandidate> = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            if !placed.iter().any(|label| label.overlaps(&candidate)) {
+                placed.push(candidate);
+            }
+        }
+        placed
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn label(text: &str, x: f64, y: f64) -> LabelCandidate {
+            LabelCandidate {
+                text: text.to_owned(),
+                x,
+                y,
+                width: 30.0,
+                height: 10.0,
+            }
+        }
+
+        fn airport(airport_type: &str) -> Airport {
+            Airport {
+                id: 0,
+                ident: "KDAB".to_owned(),
+                airport_type: airport_type.to_owned(),
+                name: "Daytona Beach International Airport".to_owned(),
+                latitude: 29.1799,
+                longitude: -81.0581,
+                elevation: 34,
+                continent: "NA".to_owned(),
+                country_name: "United States".to_owned(),
+                iso_country: "US".to_owned(),
+                region_name: "Florida".to_owned(),
+                iso_region: "US-FL".to_owned(),
+                local_region: "FL".to_owned(),
+                municipality: "Daytona Beach".to_owned(),
+                scheduled_service: true,
+                gps_code: "KDAB".to_owned(),
+                iata_code: "DAB".to_owned(),
+                local_code: "DAB".to_owned(),
+            }
+        }
+
+        #[test]
+        fn airports_appear_with_zoom() {
+            let large = airport("large_airport");
+            let medium = airport("medium_airport");
+            assert!(!is_visible_at_zoom(&large, LARGE_AIRPORT_MIN_ZOOM - 0.1));
+            assert!(is_visible_at_zoom(&large, LARGE_AIRPORT_MIN_ZOOM));
+            assert!(!is_visible_at_zoom(&medium, MEDIUM_AIRPORT_MIN_ZOOM - 0.1));
+            assert!(is_visible_at_zoom(&medium, MEDIUM_AIRPORT_MIN_ZOOM));
+            assert!(is_visible_at_zoom(&medium, LABEL_MIN_ZOOM));
+            let heliport = airport("heliport");
+            assert!(!is_visible_at_zoom(&heliport, SMALL_AIRPORT_MIN_ZOOM - 0.1));
+            assert!(is_visible_at_zoom(&heliport, SMALL_AIRPORT_MIN_ZOOM));
+        }
+
+        #[test]
+        fn nearest_airport_is_clicked() {
+            let daytona = airport("medium_airport");
+            let mut orlando = airport("large_airport");
+            orlando.latitude = 28.4312;
+            orlando.longitude = -81.3081;
+            let mut executive = airport("medium_airport");
+            executive.latitude = 28.5455;
+            executive.longitude = -81.3329;
+            let airports = [daytona, orlando, executive];
+
+            let view = crate::map::TileView::new(28.5, -81.3, 9.0, 1000.0);
+            let screen = |airport: &Airport| {
+                view.lat_long_to_screen(
+                    airport.latitude as f64,
+                    airport.longitude as f64,
+                    1000.0,
+                    1000.0,
+                )
+            };
+            let offset = DVec2::new(3.0, 0.0);
+            for (i, airport) in airports.iter().enumerate() {
+                let click = screen(airport) + offset;
+                assert_eq!(airport_at(&airports, &view, click, 1000.0, 1000.0), Some(i));
+            }
+            //Empty ocean
+            let click = DVec2::new(990.0, 500.0);
+            assert_eq!(airport_at(&airports, &view, click, 1000.0, 1000.0), None);
+
+            //Hidden airports can't be clicked
+            let view = crate::map::TileView::new(28.5, -81.3, 4.0, 1000.0);
+            let click = view.lat_long_to_screen(29.1799, -81.0581, 1000.0, 1000.0);
+            assert_eq!(airport_at(&airports, &view, click, 1000.0, 1000.0), None);
+        }
+
+        #[test]
+        fn nearby_planes_are_counted() {
+            use crate::plane_renderer::{Airline, PlaneType};
+
+            let daytona = airport("medium_airport");
+            let plane = |latitude: f32, longitude: f32| {
+                Plane::new(
+                    longitude,
+                    latitude,
+                    0.0,
+                    "N12345".to_owned(),
+                    Airline::Unknown,
+                    PlaneType::Unknown,
+                )
+            };
+            let mut parked = plane(29.18, -81.05);
+            parked.on_ground = true;
+            let planes = [
+                //Right over the runway
+                plane(29.18, -81.06),
+                //Orlando, about 90 km away
+                plane(28.4312, -81.3081),
+                //New Smyrna Beach, about 20 km away
+                plane(29.0558, -80.9489),
+                parked,
+            ];
+            assert_eq!(planes_near(&daytona, &planes, false), 2);
+            assert_eq!(planes_near(&daytona, &planes, true), 3);
+        }
+
+        #[test]
+        fn overlapping_labels_are_dropped() {
+            let labels = declutter(vec![
+                label("MCO", 0.0, 0.0),
+                //Overlaps MCO, which came first
+                label("ORL", 20.0, 5.0),
+                //Just touching MCO is fine
+                label("SFB", 30.0, 0.0),
+                label("DAB", 0.0, 10.0),
+                //Overlaps DAB but not MCO
+                label("OMN", 10.0, 15.0),
+            ]);
+            let texts: Vec<_> = labels.iter().map(|label| label.text.as_str()).collect();
+            assert_eq!(texts, ["MCO", "SFB", "DAB"]);
+        }
     }
 }
@@ -0,0 +1,430 @@
+use log::warn;
+use std::time::Duration;
+
+use crate::plane_selection::DEFAULT_HOVER_RADIUS;
+use crate::tile::RetryPolicy;
+use crate::{PlaneSource, DEFAULT_CLIMB_THRESHOLD, DEFAULT_PLANE_LINGER};
+
+/// The environment variable overriding the initial window width
+const WIDTH_VAR: &str = "FLIGHT_TRACKER_WIDTH";
+
+/// The environment variable overriding the initial window height
+const HEIGHT_VAR: &str = "FLIGHT_TRACKER_HEIGHT";
+
+/// The environment variable enabling tile prefetching
+const PREFETCH_VAR: &str = "FLIGHT_TRACKER_PREFETCH";
+
+/// The environment variable limiting the frame rate
+const MAX_FPS_VAR: &str = "FLIGHT_TRACKER_MAX_FPS";
+
+/// The environment variable that stops frames from being drawn when nothing changed
+const REDRAW_ON_CHANGE_VAR: &str = "FLIGHT_TRACKER_REDRAW_ON_CHANGE";
+
+/// The environment variable setting how many times failed tile requests are retried
+const TILE_RETRIES_VAR: &str = "FLIGHT_TRACKER_TILE_RETRIES";
+
+/// The most times a failed tile request can be retried. The delay doubles each retry, so the last
+/// of these already waits a couple of minutes
+const MAX_TILE_RETRIES: u32 = 10;
+
+/// The environment variable setting how many seconds a tile request can take
+const TILE_TIMEOUT_VAR: &str = "FLIGHT_TRACKER_TILE_TIMEOUT";
+
+/// The environment variable setting how many tiles can be decoded at once
+const DECODE_THREADS_VAR: &str = "FLIGHT_TRACKER_DECODE_THREADS";
+
+/// The environment variable turning mipmaps for map tiles on or off
+const TILE_MIPMAPS_VAR: &str = "FLIGHT_TRACKER_TILE_MIPMAPS";
+
+/// The environment variable that loads sharper tiles on high DPI screens
+const HIDPI_TILES_VAR: &str = "FLIGHT_TRACKER_HIDPI_TILES";
+
+/// The environment variable turning plane clustering on or off
+const CLUSTER_PLANES_VAR: &str = "FLIGHT_TRACKER_CLUSTER_PLANES";
+
+/// The environment variable setting how close the cursor has to be to a plane to hover it
+const HOVER_RADIUS_VAR: &str = "FLIGHT_TRACKER_HOVER_RADIUS";
+
+/// The environment variable setting how many seconds planes that went missing take to fade out
+const PLANE_LINGER_VAR: &str = "FLIGHT_TRACKER_PLANE_LINGER";
+
+/// The environment variable setting how fast planes have to climb or descend to get a chevron
+const CLIMB_THRESHOLD_VAR: &str = "FLIGHT_TRACKER_CLIMB_THRESHOLD";
+
+/// Settings for starting the app, see [`crate::run_app`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppConfig {
+    /// The initial width of the window in logical pixels
+    pub width: u32,
+    /// The initial height of the window in logical pixels
+    pub height: u32,
+    /// Whether to load the tiles one zoom level around the view ahead of time. Off by default
+    /// because it uses a lot more bandwidth
+    pub prefetch: bool,
+    /// The most frames to draw each second, or `None` to draw as many as possible
+    pub max_fps: Option<u32>,
+    /// Only draw a new frame when something on screen changed, to save power
+    pub redraw_on_change: bool,
+    /// How many times a tile request that failed because of a network error is retried
+    pub tile_retries: u32,
+    /// How long a tile request can take before it is given up on
+    pub tile_timeout: Duration,
+    /// How many tiles can be decoded at once, or `None` for one per CPU core, see
+    /// [`crate::set_decode_threads`]
+    pub decode_threads: Option<usize>,
+    /// Whether map tiles get mipmaps when they are uploaded, see [`crate::TilePipeline::set_mipmaps`].
+    /// On by default
+    pub tile_mipmaps: bool,
+    /// Whether tiles are loaded at the resolution of the screen rather than of the window's logical
+    /// pixels, see [`crate::TilePipeline::set_pixel_density`]. Off by default because it loads up
+    /// to four times as many tiles on high DPI screens
+    pub hidpi_tiles: bool,
+    /// Whether planes close together are drawn as a single marker when zoomed out. On by default
+    pub cluster_planes: bool,
+    /// How far from the cursor a plane can be and still be hovered, in logical pixels
+    pub hover_radius: f64,
+    /// How long planes that stop being reported are kept fading out before they disappear
+    pub plane_linger: Duration,
+    /// How many meters per second planes have to climb or descend to be marked as climbing or
+    /// descending
+    pub climb_threshold: f32,
+    /// Where the planes come from, which is set by the `--record` and `--replay` arguments rather
+    /// than the environment
+    pub plane_source: PlaneSource,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            prefetch: false,
+            max_fps: None,
+            redraw_on_change: false,
+            tile_retries: RetryPolicy::default().max_retries,
+            tile_timeout: RetryPolicy::default().timeout,
+            decode_threads: None,
+            tile_mipmaps: true,
+            hidpi_tiles: false,
+            cluster_planes: true,
+            hover_radius: DEFAULT_HOVER_RADIUS,
+            plane_linger: DEFAULT_PLANE_LINGER,
+            climb_threshold: DEFAULT_CLIMB_THRESHOLD,
+            plane_source: PlaneSource::OpenSky,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Returns the default config, with the window size replaced by the `FLIGHT_TRACKER_WIDTH`
+    /// and `FLIGHT_TRACKER_HEIGHT` environment variables if they are set. Prefetching is enabled
+    /// by setting `FLIGHT_TRACKER_PREFETCH=1`. The frame rate is capped by `FLIGHT_TRACKER_MAX_FPS`,
+    /// and `FLIGHT_TRACKER_REDRAW_ON_CHANGE=1` only draws frames when something changed.
+    /// `FLIGHT_TRACKER_TILE_RETRIES` sets how many times failed tile requests are retried, and
+    /// `FLIGHT_TRACKER_TILE_TIMEOUT` how many seconds each one can take.
+    /// `FLIGHT_TRACKER_DECODE_THREADS` sets how many tiles can be decoded at once,
+    /// `FLIGHT_TRACKER_TILE_MIPMAPS=0` uploads tiles without mipmaps,
+    /// `FLIGHT_TRACKER_HIDPI_TILES=1` loads tiles at the resolution of high DPI screens, and
+    /// `FLIGHT_TRACKER_CLUSTER_PLANES=0` draws every plane even when zoomed out.
+    /// `FLIGHT_TRACKER_HOVER_RADIUS` sets how many pixels from a plane the cursor can be to hover
+    /// it, and `FLIGHT_TRACKER_PLANE_LINGER` how many seconds planes take to fade out once they
+    /// stop being reported. `FLIGHT_TRACKER_CLIMB_THRESHOLD` sets how many meters per second
+    /// planes have to climb or descend to be marked
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            width: parse_dimension(WIDTH_VAR, std::env::var(WIDTH_VAR).ok(), default.width),
+            height: parse_dimension(HEIGHT_VAR, std::env::var(HEIGHT_VAR).ok(), default.height),
+            prefetch: parse_flag(std::env::var(PREFETCH_VAR).ok(), default.prefetch),
+            max_fps: parse_max_fps(std::env::var(MAX_FPS_VAR).ok()),
+            redraw_on_change: parse_flag(
+                std::env::var(REDRAW_ON_CHANGE_VAR).ok(),
+                default.redraw_on_change,
+            ),
+            tile_retries: parse_retries(std::env::var(TILE_RETRIES_VAR).ok(), default.tile_retries),
+            tile_timeout: parse_timeout(std::env::var(TILE_TIMEOUT_VAR).ok(), default.tile_timeout),
+            decode_threads: parse_decode_threads(std::env::var(DECODE_THREADS_VAR).ok()),
+            tile_mipmaps: parse_flag(std::env::var(TILE_MIPMAPS_VAR).ok(), default.tile_mipmaps),
+            hidpi_tiles: parse_flag(std::env::var(HIDPI_TILES_VAR).ok(), default.hidpi_tiles),
+            cluster_planes: parse_flag(
+                std::env::var(CLUSTER_PLANES_VAR).ok(),
+                default.cluster_planes,
+            ),
+            hover_radius: parse_hover_radius(
+                std::env::var(HOVER_RADIUS_VAR).ok(),
+                default.hover_radius,
+            ),
+            plane_linger: parse_linger(std::env::var(PLANE_LINGER_VAR).ok(), default.plane_linger),
+            climb_threshold: parse_climb_threshold(
+                std::env::var(CLIMB_THRESHOLD_VAR).ok(),
+                default.climb_threshold,
+            ),
+            plane_source: default.plane_source,
+        }
+    }
+
+    /// Returns how the tile pipelines should retry failed requests
+    pub fn tile_retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.tile_retries,
+            timeout: self.tile_timeout,
+            ..RetryPolicy::default()
+        }
+    }
+}
+
+/// Parses a window dimension from the environment variable `name`, using `default` if it isn't
+/// set or isn't a positive number
+fn parse_dimension(name: &str, value: Option<String>, default: u32) -> u32 {
+    let value = match value {
+        Some(value) => value,
+        None => return default,
+    };
+    match value.trim().parse() {
+        Ok(size) if size > 0 => size,
+        _ => {
+            warn!(
+                "Ignoring {}={:?}, it must be a positive number",
+                name, value
+            );
+            default
+        }
+    }
+}
+
+/// Parses the frame rate cap. Unset or 0 means no cap
+fn parse_max_fps(value: Option<String>) -> Option<u32> {
+    let value = value?;
+    match value.trim().parse() {
+        Ok(0) => None,
+        Ok(fps) => Some(fps),
+        Err(_) => {
+            warn!("Ignoring {}={:?}, it must be a number", MAX_FPS_VAR, value);
+            None
+        }
+    }
+}
+
+/// Parses the number of tile retries, using `default` if it isn't set or isn't a number. 0 turns
+/// retrying off, and values past [`MAX_TILE_RETRIES`] are lowered to it
+fn parse_retries(value: Option<String>, default: u32) -> u32 {
+    let value = match value {
+        Some(value) => value,
+        None => return default,
+    };
+    match value.trim().parse() {
+        Ok(retries) if retries > MAX_TILE_RETRIES => {
+            warn!(
+                "Lowering {}={} to {}, more retries would wait for hours",
+                TILE_RETRIES_VAR, retries, MAX_TILE_RETRIES
+            );
+            MAX_TILE_RETRIES
+        }
+        Ok(retries) => retries,
+        Err(_) => {
+            warn!(
+                "Ignoring {}={:?}, it must be a number",
+                TILE_RETRIES_VAR, value
+            );
+            default
+        }
+    }
+}
+
+/// Parses the tile request timeout in seconds, using `default` if it isn't set or isn't a positive
+/// number
+fn parse_timeout(value: Option<String>, default: Duration) -> Duration {
+    let value = match value {
+        Some(value) => value,
+        None => return default,
+    };
+    match value.trim().parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Duration::from_secs_f64(secs),
+        _ => {
+            warn!(
+                "Ignoring {}={:?}, it must be a positive number of seconds",
+                TILE_TIMEOUT_VAR, value
+            );
+            default
+        }
+    }
+}
+
+/// Parses the number of decode threads. Unset means one per core
+fn parse_decode_threads(value: Option<String>) -> Option<usize> {
+    let value = value?;
+    match value.trim().parse() {
+        Ok(threads) if threads > 0 => Some(threads),
+        _ => {
+            warn!(
+                "Ignoring {}={:?}, it must be a positive number",
+                DECODE_THREADS_VAR, value
+            );
+            None
+        }
+    }
+}
+
+/// Parses the hover radius in pixels, using `default` if it isn't set or isn't a positive number
+fn parse_hover_radius(value: Option<String>, default: f64) -> f64 {
+    let value = match value {
+        Some(value) => value,
+        None => return default,
+    };
+    match value.trim().parse::<f64>() {
+        Ok(radius) if radius > 0.0 && radius.is_finite() => radius,
+        _ => {
+            warn!(
+                "Ignoring {}={:?}, it must be a positive number of pixels",
+                HOVER_RADIUS_VAR, value
+            );
+            default
+        }
+    }
+}
+
+/// Parses the climb threshold in meters per second, using `default` if it isn't set or is
+/// negative. 0 marks every plane that isn't perfectly level
+fn parse_climb_threshold(value: Option<String>, default: f32) -> f32 {
+    let value = match value {
+        Some(value) => value,
+        None => return default,
+    };
+    match value.trim().parse::<f32>() {
+        Ok(threshold) if threshold >= 0.0 && threshold.is_finite() => threshold,
+        _ => {
+            warn!(
+                "Ignoring {}={:?}, it must be a number of meters per second",
+                CLIMB_THRESHOLD_VAR, value
+            );
+            default
+        }
+    }
+}
+
+/// Parses how many seconds missing planes linger for, using `default` if it isn't set or isn't a
+/// number. 0 removes planes as soon as they go missing
+fn parse_linger(value: Option<String>, default: Duration) -> Duration {
+    let value = match value {
+        Some(value) => value,
+        None => return default,
+    };
+    match value.trim().parse::<f64>() {
+        Ok(secs) if secs >= 0.0 && secs.is_finite() => Duration::from_secs_f64(secs),
+        _ => {
+            warn!(
+                "Ignoring {}={:?}, it must be a number of seconds",
+                PLANE_LINGER_VAR, value
+            );
+            default
+        }
+    }
+}
+
+/// Parses an on/off environment variable, using `default` if it isn't set
+fn parse_flag(value: Option<String>, default: bool) -> bool {
+    match value {
+        Some(value) => matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        ),
+        None => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dimension_parsing() {
+        assert_eq!(parse_dimension(WIDTH_VAR, None, 1280), 1280);
+        assert_eq!(
+            parse_dimension(WIDTH_VAR, Some(" 1920 ".to_owned()), 1280),
+            1920
+        );
+        assert_eq!(parse_dimension(WIDTH_VAR, Some("0".to_owned()), 1280), 1280);
+        assert_eq!(
+            parse_dimension(WIDTH_VAR, Some("wide".to_owned()), 1280),
+            1280
+        );
+    }
+
+    #[test]
+    fn max_fps_parsing() {
+        assert_eq!(parse_max_fps(None), None);
+        assert_eq!(parse_max_fps(Some("60".to_owned())), Some(60));
+        assert_eq!(parse_max_fps(Some("0".to_owned())), None);
+        assert_eq!(parse_max_fps(Some("fast".to_owned())), None);
+    }
+
+    #[test]
+    fn retries_parsing() {
+        assert_eq!(parse_retries(None, 3), 3);
+        assert_eq!(parse_retries(Some(" 5".to_owned()), 3), 5);
+        assert_eq!(parse_retries(Some("0".to_owned()), 3), 0);
+        assert_eq!(parse_retries(Some("-1".to_owned()), 3), 3);
+        assert_eq!(parse_retries(Some("10".to_owned()), 3), 10);
+        assert_eq!(parse_retries(Some("1000".to_owned()), 3), MAX_TILE_RETRIES);
+    }
+
+    #[test]
+    fn timeout_parsing() {
+        let default = Duration::from_secs(10);
+        assert_eq!(parse_timeout(None, default), default);
+        assert_eq!(
+            parse_timeout(Some("2.5".to_owned()), default),
+            Duration::from_millis(2500)
+        );
+        assert_eq!(parse_timeout(Some("0".to_owned()), default), default);
+        assert_eq!(parse_timeout(Some("soon".to_owned()), default), default);
+    }
+
+    #[test]
+    fn decode_threads_parsing() {
+        assert_eq!(parse_decode_threads(None), None);
+        assert_eq!(parse_decode_threads(Some(" 2".to_owned())), Some(2));
+        assert_eq!(parse_decode_threads(Some("0".to_owned())), None);
+        assert_eq!(parse_decode_threads(Some("all".to_owned())), None);
+    }
+
+    #[test]
+    fn hover_radius_parsing() {
+        assert_eq!(parse_hover_radius(None, 8.0), 8.0);
+        assert_eq!(parse_hover_radius(Some(" 12.5".to_owned()), 8.0), 12.5);
+        assert_eq!(parse_hover_radius(Some("0".to_owned()), 8.0), 8.0);
+        assert_eq!(parse_hover_radius(Some("near".to_owned()), 8.0), 8.0);
+    }
+
+    #[test]
+    fn climb_threshold_parsing() {
+        assert_eq!(parse_climb_threshold(None, 2.5), 2.5);
+        assert_eq!(parse_climb_threshold(Some(" 5".to_owned()), 2.5), 5.0);
+        assert_eq!(parse_climb_threshold(Some("0".to_owned()), 2.5), 0.0);
+        assert_eq!(parse_climb_threshold(Some("-1".to_owned()), 2.5), 2.5);
+        assert_eq!(parse_climb_threshold(Some("fast".to_owned()), 2.5), 2.5);
+    }
+
+    #[test]
+    fn linger_parsing() {
+        let default = Duration::from_secs(10);
+        assert_eq!(parse_linger(None, default), default);
+        assert_eq!(
+            parse_linger(Some("2.5".to_owned()), default),
+            Duration::from_millis(2500)
+        );
+        assert_eq!(parse_linger(Some("0".to_owned()), default), Duration::ZERO);
+        assert_eq!(parse_linger(Some("-1".to_owned()), default), default);
+        assert_eq!(parse_linger(Some("long".to_owned()), default), default);
+    }
+
+    #[test]
+    fn flag_parsing() {
+        assert!(!parse_flag(None, false));
+        assert!(parse_flag(None, true));
+        assert!(parse_flag(Some("1".to_owned()), false));
+        assert!(parse_flag(Some(" True".to_owned()), false));
+        assert!(!parse_flag(Some("0".to_owned()), true));
+        assert!(!parse_flag(Some("no".to_owned()), true));
+    }
+}
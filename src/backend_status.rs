@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+use conrod_core::{text, widget, Color, Colorable, Positionable, Sizeable, UiCell, Widget};
+
+use crate::BackendHealth;
+
+/// How long planes can go without an update from OpenSky before their status is shown in red
+pub const PLANE_STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// The status of a tile backend, e.g. "97% ok, last tile 2s ago"
+pub fn backend_status(health: Option<&BackendHealth>, now: Instant) -> String {
+    let rate = match health.and_then(BackendHealth::success_rate) {
+        Some(rate) => rate,
+        None => return "no requests".to_owned(),
+    };
+    match health.and_then(|health| health.last_success) {
+        Some(last) => format!(
+            "{:.0}% ok, last tile {} ago",
+            rate * 100.0,
+            format_age(now.saturating_duration_since(last))
+        ),
+        None => format!("{:.0}% ok, no tiles yet", rate * 100.0),
+    }
+}
+
+/// The status of the plane data and the color to show it in, red once no data has arrived for
+/// [`PLANE_STALE_AFTER`] and `text_color` until then. Before the first update, the time is counted
+/// from `started`
+pub fn plane_status(
+    last_success: Option<Instant>,
+    started: Instant,
+    now: Instant,
+    text_color: Color,
+) -> (String, Color) {
+    let age = now.saturating_duration_since(last_success.unwrap_or(started));
+    let text = match last_success {
+        Some(_) => format!("OpenSky: updated {} ago", format_age(age)),
+        None => format!("OpenSky: no data for {}", format_age(age)),
+    };
+    let color = if age > PLANE_STALE_AFTER {
+        conrod_core::color::RED
+    } else {
+        text_color
+    };
+    (text, color)
+}
+
+/// The text of the badge shown over the map once no plane data has arrived for
+/// [`PLANE_STALE_AFTER`], or `None` while the data is fresh. Before the first update, the time is
+/// counted from `started`
+pub fn stale_planes_badge(
+    last_success: Option<Instant>,
+    started: Instant,
+    now: Instant,
+) -> Option<String> {
+    let age = now.saturating_duration_since(last_success.unwrap_or(started));
+    if age <= PLANE_STALE_AFTER {
+        return None;
+    }
+    Some(match last_success {
+        Some(_) => format!("Planes last updated {} ago", format_age(age)),
+        None => format!("No plane data for {}", format_age(age)),
+    })
+}
+
+/// Draws `message` as a small red badge near the top of the screen, under the error banner, so
+/// that stale plane data can be noticed at a glance without opening the debug menu
+pub fn draw_stale_badge(
+    message: &str,
+    background: widget::Id,
+    label: widget::Id,
+    ui: &mut UiCell,
+    font: text::font::Id,
+) {
+    let text = widget::Text::new(message)
+        .color(conrod_core::color::WHITE)
+        .font_size(12)
+        .font_id(font);
+    let width = text.get_w(ui).unwrap_or(0.0) + 16.0;
+    let y = ui.win_h / 2.0 - 56.0;
+
+    widget::Rectangle::fill([width, 22.0])
+        .color(Color::Rgba(0.6, 0.1, 0.1, 0.85))
+        .x_y(0.0, y)
+        .set(background, ui);
+    text.x_y(0.0, y).set(label, ui);
+}
+
+/// Formats `age` to the second, like "42s" or "3m 5s"
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_staleness() {
+        let start = Instant::now();
+        let secs = |secs| start + Duration::from_secs(secs);
+        let white = conrod_core::color::WHITE;
+
+        let (text, color) = plane_status(Some(secs(10)), start, secs(15), white);
+        assert_eq!(text, "OpenSky: updated 5s ago");
+        assert_eq!(color, conrod_core::color::WHITE);
+
+        let (text, color) = plane_status(Some(secs(10)), start, secs(105), white);
+        assert_eq!(text, "OpenSky: updated 1m 35s ago");
+        assert_eq!(color, conrod_core::color::RED);
+
+        //Never getting any planes goes red too
+        let (text, color) = plane_status(None, start, secs(31), white);
+        assert_eq!(text, "OpenSky: no data for 31s");
+        assert_eq!(color, conrod_core::color::RED);
+    }
+
+    #[test]
+    fn stale_badge_only_when_stale() {
+        let start = Instant::now();
+        let secs = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(stale_planes_badge(Some(secs(10)), start, secs(40)), None);
+        assert_eq!(
+            stale_planes_badge(Some(secs(10)), start, secs(105)).as_deref(),
+            Some("Planes last updated 1m 35s ago")
+        );
+        //Give the first request some time before complaining
+        assert_eq!(stale_planes_badge(None, start, secs(20)), None);
+        assert_eq!(
+            stale_planes_badge(None, start, secs(31)).as_deref(),
+            Some("No plane data for 31s")
+        );
+    }
+
+    #[test]
+    fn backend_status_text() {
+        let now = Instant::now();
+        assert_eq!(backend_status(None, now), "no requests");
+        assert_eq!(
+            backend_status(Some(&BackendHealth::default()), now),
+            "no requests"
+        );
+
+        let mut health = BackendHealth::default();
+        health.record(false, now);
+        assert_eq!(backend_status(Some(&health), now), "0% ok, no tiles yet");
+        health.record(true, now);
+        health.record(true, now);
+        health.record(false, now + Duration::from_secs(1));
+        assert_eq!(
+            backend_status(Some(&health), now + Duration::from_secs(3)),
+            "50% ok, last tile 3s ago"
+        );
+    }
+}
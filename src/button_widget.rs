@@ -1,3 +1,7 @@
+//The conrod derive macros generate their impls inside of a const block, which newer compilers
+//warn about. We can't change the generated code so allow it for the whole file
+#![allow(non_local_definitions)]
+
 use conrod_core::widget::button::{Flat, Image, ImageColor};
 use conrod_core::{
     self, text, widget, widget_ids, Color, Colorable, FontSize, Labelable, Positionable, Scalar,
@@ -20,6 +24,24 @@ pub struct ImageId {
     pub press: conrod_core::image::Id,
 }
 
+///What the user did with an image button this frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ButtonInteraction {
+    ///The button was clicked with the left mouse button
+    pub clicked: bool,
+    ///The cursor is over the button
+    pub hovered: bool,
+    ///The button was clicked with the right mouse button
+    pub right_clicked: bool,
+}
+
+impl ButtonInteraction {
+    ///Returns true if the button was clicked with the left mouse button
+    pub fn clicked(self) -> bool {
+        self.clicked
+    }
+}
+
 ///The structure of a Button.
 #[derive(WidgetCommon)]
 pub struct CircularButton<'a, S> {
@@ -29,6 +51,8 @@ pub struct CircularButton<'a, S> {
     common: widget::CommonBuilder,
     /// Optional label string for the button.
     maybe_label: Option<&'a str>,
+    /// Optional text shown next to the cursor while it hovers over the button.
+    maybe_tooltip: Option<&'a str>,
     ///Type of Button (Image or Text)
     show: S,
     ///What type of Button, Image or Flat
@@ -79,6 +103,8 @@ widget_ids! {
     pub struct CircularImageIds{
         circle,
         image,
+        tooltip,
+        tooltip_text,
     }
 }
 
@@ -94,7 +120,7 @@ pub struct ImageState {
 
 impl<'a, S> CircularButton<'a, S> {
     ///Making a button context
-
+    ///
     ///Specify type of font used
     #[allow(dead_code)]
     pub fn label_font_id(mut self, font_id: conrod_core::text::font::Id) -> Self {
@@ -109,6 +135,7 @@ impl<'a> CircularButton<'a, Image> {
         CircularButton {
             common: widget::CommonBuilder::default(),
             maybe_label: None,
+            maybe_tooltip: None,
             show: Image {
                 image_id,
                 hover_image_id: None,
@@ -151,6 +178,12 @@ impl<'a> CircularButton<'a, Image> {
         self.show.press_image_id = Some(image_id);
         self
     }
+
+    ///Text describing what the button does, shown while the cursor hovers over it
+    pub fn tooltip(mut self, text: &'a str) -> Self {
+        self.maybe_tooltip = Some(text);
+        self
+    }
 }
 
 impl<'a> CircularButton<'a, Flat> {
@@ -163,13 +196,14 @@ impl<'a> CircularButton<'a, Flat> {
             },
             style: Style::default(),
             maybe_label: None,
+            maybe_tooltip: None,
         }
     }
 }
 impl<'a> Widget for CircularButton<'a, Image> {
     type State = ImageState;
     type Style = Style;
-    type Event = bool;
+    type Event = ButtonInteraction;
 
     fn init_state(&self, id_gen: widget::id::Generator) -> Self::State {
         ImageState {
@@ -193,6 +227,7 @@ impl<'a> Widget for CircularButton<'a, Image> {
 
         let CircularButton {
             maybe_label: _,
+            maybe_tooltip,
             show,
             ..
         } = self;
@@ -206,11 +241,14 @@ impl<'a> Widget for CircularButton<'a, Image> {
             src_rect,
         } = show;
 
-        let (button_color, was_clicked) = {
+        let (button_color, interaction) = {
             let input = ui.widget_input(id);
 
-            //If button was clicked
-            let was_clicked = input.clicks().left().next().is_some();
+            let interaction = ButtonInteraction {
+                clicked: input.clicks().left().next().is_some(),
+                hovered: input.mouse().is_some(),
+                right_clicked: input.clicks().right().next().is_some(),
+            };
 
             let color = style.color(&ui.theme);
             let color = input.mouse().map_or(color, |mouse| {
@@ -221,7 +259,7 @@ impl<'a> Widget for CircularButton<'a, Image> {
                 }
             });
 
-            (color, was_clicked)
+            (color, interaction)
         };
 
         let radius = rect.w() / 2.0;
@@ -261,7 +299,23 @@ impl<'a> Widget for CircularButton<'a, Image> {
 
         image.set(state.ids.image, ui);
 
-        was_clicked
+        if let Some(tooltip) = maybe_tooltip {
+            if interaction.hovered {
+                let font_id = style
+                    .label_font_id(&ui.theme)
+                    .or_else(|| ui.fonts.ids().next());
+                draw_tooltip(
+                    Some(id),
+                    state.ids.tooltip,
+                    state.ids.tooltip_text,
+                    tooltip,
+                    font_id,
+                    ui,
+                );
+            }
+        }
+
+        interaction
     }
 }
 impl<'a> Widget for CircularButton<'a, Flat> {
@@ -371,20 +425,161 @@ impl<'a, S> Labelable<'a> for CircularButton<'a, S> {
     }
 }
 
-///Makes a Circle Widget with an ImageId given.
+///Draws `text` in a small box next to the cursor, on top of every other widget.
+///
+///Meant to be called while the cursor hovers over `target`, which gets any clicks on the box.
+///Without a target the box takes clicks itself, which is fine since it is never under the cursor.
+///The box goes to the left of the cursor since most buttons are on the right side of the screen.
+pub fn draw_tooltip(
+    target: Option<widget::id::Id>,
+    background: widget::id::Id,
+    text_id: widget::id::Id,
+    text: &str,
+    font_id: Option<text::font::Id>,
+    ui: &mut UiCell,
+) {
+    const FONT_SIZE: FontSize = 12;
+    const PADDING: Scalar = 6.0;
+
+    let label = widget::Text::new(text)
+        .and_then(font_id, widget::Text::font_id)
+        .font_size(FONT_SIZE)
+        .color(conrod_core::color::WHITE);
+    let w = label.get_w(ui).unwrap_or(0.0) + PADDING * 2.0;
+    let h = FONT_SIZE as Scalar + PADDING * 2.0;
+
+    //Keep the whole box on the screen
+    let [cursor_x, cursor_y] = ui.global_input().current.mouse.xy;
+    let x = f64::max(cursor_x - 12.0 - w / 2.0, -ui.win_w / 2.0 + w / 2.0);
+    let y = f64::max(cursor_y - 12.0 - h / 2.0, -ui.win_h / 2.0 + h / 2.0);
+
+    widget::RoundedRectangle::fill([w, h], 4.0)
+        .x_y(x, y)
+        .color(conrod_core::color::Color::Rgba(0.1, 0.1, 0.1, 0.9))
+        .floating(true)
+        .and_then(target, Widget::graphics_for)
+        .set(background, ui);
+    label
+        .middle_of(background)
+        .parent(background)
+        .graphics_for(target.unwrap_or(background))
+        .set(text_id, ui);
+}
+
+///Makes a Circle Widget with an ImageId given, highlighted when `toggled` is true so that
+///buttons turning something on and off show whether it is on. `tooltip` is shown while the
+///cursor hovers over it.
 ///Takes x and y to position Widget.
 pub fn draw_circle_with_image(
     widget: widget::id::Id,
     ui: &mut UiCell,
     image_id: ImageId,
+    toggled: bool,
+    tooltip: &str,
     widget_x_position: f64,
     widget_y_position: f64,
-) -> bool {
-    CircularButton::image(image_id.normal)
+) -> ButtonInteraction {
+    let mut button = CircularButton::image(image_id.normal).tooltip(tooltip);
+    if toggled {
+        //The same color as selected text buttons
+        button = button.color(conrod_core::color::LIGHT_BLUE);
+    }
+    button
         .x(widget_x_position)
         .y(widget_y_position)
         .w_h(50.0, 50.0)
+        .set(widget, ui)
+}
+
+///Makes a small labeled Circle Widget, highlighted when `selected` is true.
+///Used for groups of buttons where only one option can be picked.
+///Takes x and y to position Widget.
+pub fn draw_circle_with_text(
+    widget: widget::id::Id,
+    ui: &mut UiCell,
+    label: &str,
+    selected: bool,
+    widget_x_position: f64,
+    widget_y_position: f64,
+) -> bool {
+    let color = if selected {
+        conrod_core::color::LIGHT_BLUE
+    } else {
+        conrod_core::color::DARK_CHARCOAL
+    };
+    CircularButton::new()
+        .x(widget_x_position)
+        .y(widget_y_position)
+        .w_h(40.0, 40.0)
+        .color(color)
+        .label_color(conrod_core::color::WHITE)
+        .label_font_size(10)
+        .label(label)
+        .set(widget, ui)
+        .is_some()
+}
+
+///Makes a Circle Widget for a cluster of `count` planes, which grows with the number of planes.
+///Takes x and y to position Widget. Returns true when it is clicked.
+pub fn draw_plane_cluster(
+    widget: widget::id::Id,
+    ui: &mut UiCell,
+    count: usize,
+    widget_x_position: f64,
+    widget_y_position: f64,
+) -> bool {
+    let size = 20.0 + 8.0 * (count as f64).log10();
+    let label = if count < 1000 {
+        count.to_string()
+    } else {
+        format!("{:.1}k", count as f64 / 1000.0)
+    };
+    CircularButton::new()
+        .x(widget_x_position)
+        .y(widget_y_position)
+        .w_h(size, size)
+        .color(conrod_core::Color::Rgba(0.1, 0.35, 0.7, 0.85))
+        .label_color(conrod_core::color::WHITE)
+        .label_font_size(9)
+        .label(&label)
+        .set(widget, ui)
+        .is_some()
+}
+
+///Makes a compass Circle Widget whose needle points north on a map rotated by `bearing` degrees.
+///Takes x and y to position Widget.
+pub fn draw_compass(
+    widget: widget::id::Id,
+    needle: widget::id::Id,
+    ui: &mut UiCell,
+    bearing: f64,
+    widget_x_position: f64,
+    widget_y_position: f64,
+) -> bool {
+    let clicked = CircularButton::new()
+        .x(widget_x_position)
+        .y(widget_y_position)
+        .w_h(40.0, 40.0)
+        .color(conrod_core::color::DARK_CHARCOAL)
         .label_color(conrod_core::color::WHITE)
-        .label("Airplane Button")
+        .label_font_size(10)
+        .label("N")
         .set(widget, ui)
+        .is_some();
+
+    //The needle is a thin triangle near the edge of the circle, turned the opposite way the map
+    //is rotated so that it keeps pointing north
+    let (sin, cos) = bearing.to_radians().sin_cos();
+    let points = [[0.0, 17.0], [-4.0, 8.0], [4.0, 8.0]].map(|[x, y]: [f64; 2]| {
+        [
+            widget_x_position + x * cos - y * sin,
+            widget_y_position + x * sin + y * cos,
+        ]
+    });
+    widget::Polygon::abs_fill(points.iter().copied())
+        .color(conrod_core::color::LIGHT_RED)
+        .graphics_for(widget)
+        .set(needle, ui);
+
+    clicked
 }
@@ -0,0 +1,189 @@
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+use crate::{tile, AppConfig, PlaneSource, Region, Shutdown, TileKind};
+
+/// How to run the app, printed when its arguments can't be parsed
+pub const APP_USAGE: &str = "Usage: flight_tracking_erau_se300 [--record <file> | --replay <file>]
+       flight_tracking_erau_se300 prefetch ...";
+
+/// How to use the `prefetch` subcommand, printed when its arguments can't be parsed
+pub const PREFETCH_USAGE: &str = "Usage: flight_tracking_erau_se300 prefetch \
+<satellite|weather|street> <lat min> <lon min> <lat max> <lon max> <zoom>[-<max zoom>]";
+
+/// The arguments of the `prefetch` subcommand, which downloads the tiles of a region into the disk
+/// cache without opening a window
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefetchArgs {
+    pub kind: TileKind,
+    pub region: Region,
+    pub zoom_levels: RangeInclusive<u32>,
+}
+
+impl PrefetchArgs {
+    /// Parses the arguments after `prefetch`, like `satellite 28.9 -81.4 29.3 -80.9 8-14`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let [kind, lat_min, lon_min, lat_max, lon_max, zoom] = args else {
+            return Err(format!("Expected 6 arguments, got {}", args.len()));
+        };
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "satellite" => TileKind::Satellite,
+            "weather" => TileKind::Weather,
+            "street" => TileKind::Street,
+            _ => return Err(format!("Unknown tile kind {:?}", kind)),
+        };
+        let degrees = |value: &String, limit: f64| match value.parse::<f64>() {
+            Ok(degrees) if degrees.abs() <= limit => Ok(degrees),
+            _ => Err(format!(
+                "{:?} isn't between -{} and {} degrees",
+                value, limit, limit
+            )),
+        };
+        let region = Region {
+            lat_min: degrees(lat_min, 90.0)?,
+            lon_min: degrees(lon_min, 180.0)?,
+            lat_max: degrees(lat_max, 90.0)?,
+            lon_max: degrees(lon_max, 180.0)?,
+        };
+        if region.lat_min > region.lat_max || region.lon_min > region.lon_max {
+            return Err("The minimum latitude and longitude must come first".to_owned());
+        }
+
+        let zoom_level = |value: &str| match value.parse::<u32>() {
+            Ok(zoom) if zoom <= crate::MAX_ZOOM_LEVEL => Ok(zoom),
+            _ => Err(format!(
+                "{:?} isn't a zoom level between 0 and {}",
+                value,
+                crate::MAX_ZOOM_LEVEL
+            )),
+        };
+        let zoom_levels = match zoom.split_once('-') {
+            Some((min, max)) => zoom_level(min)?..=zoom_level(max)?,
+            None => zoom_level(zoom)?..=zoom_level(zoom)?,
+        };
+        if zoom_levels.is_empty() {
+            return Err(format!("{:?} is an empty range of zoom levels", zoom));
+        }
+
+        Ok(Self {
+            kind,
+            region,
+            zoom_levels,
+        })
+    }
+}
+
+/// Parses the arguments given when starting the app normally. `--record <file>` saves the planes
+/// from OpenSky to `file`, and `--replay <file>` plays back a recording instead of using OpenSky
+pub fn parse_plane_source(args: &[String]) -> Result<PlaneSource, String> {
+    match args {
+        [] => Ok(PlaneSource::OpenSky),
+        [option, file] if option == "--record" => Ok(PlaneSource::Record(file.into())),
+        [option, file] if option == "--replay" => Ok(PlaneSource::Replay(file.into())),
+        [option] if option == "--record" || option == "--replay" => {
+            Err(format!("{} needs a file", option))
+        }
+        _ => Err(format!("Unexpected arguments {:?}", args)),
+    }
+}
+
+/// Downloads the tiles described by `args` into the disk cache, printing the progress as it goes.
+/// Exits the process if the tiles can't be prefetched at all
+pub fn run_prefetch(args: PrefetchArgs, config: AppConfig) {
+    let runtime = tokio::runtime::Runtime::new().expect("Unable to create Tokio runtime!");
+    if let Some(threads) = config.decode_threads {
+        tile::set_decode_threads(threads);
+    }
+    //Ctrl-C stops the prefetch once the tiles being downloaded are saved, and a second one quits
+    //straight away
+    let shutdown = Shutdown::new();
+    let ctrl_c_shutdown = shutdown.clone();
+    runtime.spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("\nFinishing the downloads in progress, press Ctrl-C again to quit now");
+            ctrl_c_shutdown.trigger();
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    let result = runtime.block_on(tile::prefetch_region(
+        args.region,
+        args.zoom_levels,
+        args.kind,
+        config.tile_retry_policy(),
+        &shutdown,
+        |progress| {
+            print!("\r{}/{} tiles", progress.done(), progress.total);
+            let _ = std::io::stdout().flush();
+        },
+    ));
+    match result {
+        Ok(result) => println!(
+            "\nDownloaded {} tiles, {} were already cached and {} failed",
+            result.downloaded, result.cached, result.failed
+        ),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<PrefetchArgs, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_owned).collect();
+        PrefetchArgs::parse(&args)
+    }
+
+    #[test]
+    fn prefetch_arguments() {
+        assert_eq!(
+            parse("street 28.9 -81.4 29.3 -80.9 8-14"),
+            Ok(PrefetchArgs {
+                kind: TileKind::Street,
+                region: Region {
+                    lat_min: 28.9,
+                    lon_min: -81.4,
+                    lat_max: 29.3,
+                    lon_max: -80.9,
+                },
+                zoom_levels: 8..=14,
+            })
+        );
+        assert_eq!(
+            parse("Satellite 0 0 1 1 5").map(|args| (args.kind, args.zoom_levels)),
+            Ok((TileKind::Satellite, 5..=5))
+        );
+
+        assert!(parse("street 28.9 -81.4 29.3 -80.9").is_err());
+        assert!(parse("roads 28.9 -81.4 29.3 -80.9 8").is_err());
+        assert!(parse("street 29.3 -81.4 28.9 -80.9 8").is_err());
+        assert!(parse("street 28.9 -181 29.3 -80.9 8").is_err());
+        assert!(parse("street 28.9 -81.4 29.3 -80.9 14-8").is_err());
+        assert!(parse("street 28.9 -81.4 29.3 -80.9 25").is_err());
+    }
+
+    #[test]
+    fn plane_source_arguments() {
+        let parse = |args: &str| {
+            let args: Vec<String> = args.split_whitespace().map(str::to_owned).collect();
+            parse_plane_source(&args)
+        };
+        assert_eq!(parse(""), Ok(PlaneSource::OpenSky));
+        assert_eq!(
+            parse("--record planes.ndjson"),
+            Ok(PlaneSource::Record("planes.ndjson".into()))
+        );
+        assert_eq!(
+            parse("--replay demo.ndjson"),
+            Ok(PlaneSource::Replay("demo.ndjson".into()))
+        );
+        assert!(parse("--replay").is_err());
+        assert!(parse("--play demo.ndjson").is_err());
+        assert!(parse("--record a --replay b").is_err());
+    }
+}
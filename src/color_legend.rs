@@ -0,0 +1,48 @@
+use conrod_core::{text, widget, Color, Colorable, Positionable, UiCell, Widget};
+
+use crate::{gradient_color, PlaneColorMode};
+
+/// How many steps the gradient is drawn with
+pub const LEGEND_SEGMENTS: usize = 12;
+
+const LEGEND_WIDTH: f64 = 240.0;
+const LEGEND_HEIGHT: f64 = 12.0;
+
+/// Draws the gradient used by `mode` along the bottom of the screen, with the values at each end.
+///
+/// Nothing is drawn for modes that don't color planes by a gradient. `segments` must have
+/// [`LEGEND_SEGMENTS`] ids and `labels` two
+pub fn draw(
+    mode: PlaneColorMode,
+    segments: &[widget::Id],
+    labels: &[widget::Id],
+    ui: &mut UiCell,
+    font: text::font::Id,
+) {
+    let (low, high, unit) = match mode.scale() {
+        Some(scale) => scale,
+        None => return,
+    };
+
+    let y = -ui.win_h / 2.0 + 30.0;
+    let segment_width = LEGEND_WIDTH / LEGEND_SEGMENTS as f64;
+    let left = -LEGEND_WIDTH / 2.0;
+    for (i, id) in segments.iter().enumerate() {
+        let t = i as f32 / (LEGEND_SEGMENTS - 1) as f32;
+        let [r, g, b] = gradient_color(t);
+        widget::Rectangle::fill([segment_width, LEGEND_HEIGHT])
+            .color(Color::Rgba(r, g, b, 1.0))
+            .x_y(left + segment_width * (i as f64 + 0.5), y)
+            .set(*id, ui);
+    }
+
+    let label_y = y + LEGEND_HEIGHT + 2.0;
+    for (id, (value, x)) in labels.iter().zip([(low, left), (high, -left)].iter()) {
+        widget::Text::new(&format!("{} {}", value, unit))
+            .color(conrod_core::color::WHITE)
+            .font_size(10)
+            .font_id(font)
+            .x_y(*x, label_y)
+            .set(*id, ui);
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use conrod_core::{text, widget, Color, Colorable, Positionable, Sizeable, UiCell, Widget};
+
+use crate::{TileError, TileKind};
+
+/// How long the banner stays up after the last error that was reported
+const BANNER_DURATION: Duration = Duration::from_secs(8);
+
+/// A message shown at the top of the screen when tiles can't be loaded, so that users running
+/// without a terminal find out why the map is blank
+#[derive(Default)]
+pub struct ErrorBanner {
+    message: Option<(String, Instant)>,
+}
+
+impl ErrorBanner {
+    /// Shows `err` as the reason tiles of `kind` can't be loaded
+    pub fn report(&mut self, kind: TileKind, err: &TileError) {
+        self.report_at(kind, err, Instant::now());
+    }
+
+    fn report_at(&mut self, kind: TileKind, err: &TileError, now: Instant) {
+        self.message = Some((banner_text(kind, err), now));
+    }
+
+    /// Returns the message to show at `now`, or `None` once it has been up long enough
+    pub fn message(&self, now: Instant) -> Option<&str> {
+        match &self.message {
+            Some((message, reported)) if now.duration_since(*reported) < BANNER_DURATION => {
+                Some(message.as_str())
+            }
+            _ => None,
+        }
+    }
+
+    /// Draws the banner centered along the top of the screen, if there is anything to show
+    pub fn draw(
+        &self,
+        background: widget::Id,
+        label: widget::Id,
+        ui: &mut UiCell,
+        font: text::font::Id,
+    ) {
+        let message = match self.message(Instant::now()) {
+            Some(message) => message,
+            None => return,
+        };
+
+        let text = widget::Text::new(message)
+            .color(conrod_core::color::WHITE)
+            .font_size(14)
+            .font_id(font);
+        let width = text.get_w(ui).unwrap_or(0.0) + 24.0;
+        let y = ui.win_h / 2.0 - 24.0;
+
+        widget::Rectangle::fill([width, 28.0])
+            .color(Color::Rgba(0.6, 0.1, 0.1, 0.85))
+            .x_y(0.0, y)
+            .set(background, ui);
+        text.x_y(0.0, y).set(label, ui);
+    }
+}
+
+/// The text of the banner for an error loading tiles of `kind`, e.g. "Satellite tiles
+/// unavailable: 403"
+fn banner_text(kind: TileKind, err: &TileError) -> String {
+    let name = match kind {
+        TileKind::Satellite => "Satellite",
+        TileKind::Weather => "Weather",
+        TileKind::Street => "Street",
+    };
+    match err.status_code() {
+        Some(code) => format!("{} tiles unavailable: {}", name, code),
+        None => format!("{} tiles unavailable: {}", name, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banner_expires() {
+        let err = TileError::Io(std::io::Error::other("no key"));
+        let start = Instant::now();
+        let mut banner = ErrorBanner::default();
+        assert!(banner.message(start).is_none());
+
+        banner.report_at(TileKind::Weather, &err, start);
+        assert_eq!(
+            banner.message(start + Duration::from_secs(1)),
+            Some("Weather tiles unavailable: I/O: no key")
+        );
+        assert!(banner.message(start + BANNER_DURATION).is_none());
+
+        //Another error brings it back
+        banner.report_at(TileKind::Satellite, &err, start + BANNER_DURATION);
+        assert!(banner
+            .message(start + BANNER_DURATION + Duration::from_secs(1))
+            .unwrap()
+            .starts_with("Satellite"));
+    }
+}
@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use crate::Plane;
+
+/// Converts `planes` to a GeoJSON `FeatureCollection` with a `Point` feature for each plane, for
+/// opening in other GIS tools. Planes that are only kept around to fade out are left out.
+///
+/// Each feature has the plane's `callsign`, `airline`, `plane_type`, `track` in degrees clockwise
+/// from north, `altitude` in meters, `velocity` in meters per second and whether it is `on_ground`.
+/// Unknown values are `null`
+pub fn planes_to_geojson(planes: &[Plane]) -> String {
+    let features: Vec<Value> = planes
+        .iter()
+        .filter(|plane| plane.missing_since.is_none())
+        .map(plane_feature)
+        .collect();
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+    .to_string()
+}
+
+fn plane_feature(plane: &Plane) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": [plane.longitude, plane.latitude],
+        },
+        "properties": {
+            "callsign": plane.callsign,
+            "airline": plane.airline.to_str(),
+            "plane_type": plane.plane_type.to_str(),
+            "track": true_track(plane.track),
+            "altitude": plane.altitude,
+            "velocity": plane.velocity,
+            "on_ground": plane.on_ground,
+        },
+    })
+}
+
+/// Converts a plane's `track`, which is stored as the angle its icon is rotated by in radians, back
+/// to the true track OpenSky reported in degrees clockwise from north
+fn true_track(track: f32) -> f32 {
+    (90.0 - track.to_degrees()).rem_euclid(360.0)
+}
+
+/// Saves `planes` as GeoJSON to `planes-<unix time>.geojson` in the current directory, returning
+/// the path of the file
+pub fn save_planes_geojson(planes: &[Plane]) -> std::io::Result<PathBuf> {
+    let unix_time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let path = PathBuf::from(format!("planes-{}.geojson", unix_time));
+    std::fs::write(&path, planes_to_geojson(planes))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Airline, BasicAirline, PlaneType};
+
+    #[test]
+    fn geojson_features() {
+        //Stored the way the requester converts a true track of 45 degrees
+        let track = (-45.0f32 + 90.0).to_radians();
+        let mut delta = Plane::new(
+            -81.05,
+            29.19,
+            track,
+            "DAL123".to_owned(),
+            BasicAirline::Delta.into(),
+            PlaneType::Commercial,
+        );
+        delta.altitude = Some(10000.0);
+        delta.velocity = Some(230.5);
+        let unknown = Plane::new(
+            2.0,
+            -3.0,
+            90f32.to_radians(),
+            "Unknown".to_owned(),
+            Airline::Unknown,
+            PlaneType::Unknown,
+        );
+
+        let mut missing = unknown.clone();
+        missing.missing_since = Some(std::time::Instant::now());
+
+        let geojson: Value =
+            serde_json::from_str(&planes_to_geojson(&[delta, unknown, missing])).unwrap();
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+
+        let feature = &features[0];
+        assert_eq!(feature["type"], "Feature");
+        assert_eq!(feature["geometry"]["type"], "Point");
+        let coordinates = feature["geometry"]["coordinates"].as_array().unwrap();
+        assert!((coordinates[0].as_f64().unwrap() + 81.05).abs() < 1e-4);
+        assert!((coordinates[1].as_f64().unwrap() - 29.19).abs() < 1e-4);
+        let properties = &feature["properties"];
+        assert_eq!(properties["callsign"], "DAL123");
+        assert_eq!(properties["airline"], "Delta Airlines");
+        assert_eq!(properties["plane_type"], "Commercial");
+        assert!((properties["track"].as_f64().unwrap() - 45.0).abs() < 1e-3);
+        assert_eq!(properties["altitude"], 10000.0);
+        assert_eq!(properties["velocity"], 230.5);
+
+        let properties = &features[1]["properties"];
+        assert_eq!(properties["airline"], "Unknown");
+        assert!(properties["track"].as_f64().unwrap().abs() < 1e-3);
+        assert!(properties["altitude"].is_null());
+        assert!(properties["velocity"].is_null());
+    }
+}
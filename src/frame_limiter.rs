@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+/// How often to check for changes when only redrawing on change. Checking is much cheaper than
+/// drawing a frame, and this keeps new tiles and planes from waiting long to show up
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Decides when the next frame should be drawn, so that the app doesn't keep the GPU busy when it
+/// doesn't need to
+#[derive(Debug, Clone)]
+pub struct FrameLimiter {
+    /// The shortest time between frames, or `None` to draw as fast as possible
+    min_frame_time: Option<Duration>,
+    /// When true frames are only drawn after something changed
+    redraw_on_change: bool,
+    /// Whether something changed since the last frame
+    changed: bool,
+    last_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    /// Creates a limiter that draws at most `max_fps` frames a second, and only when something
+    /// changed if `redraw_on_change` is set. With neither it never waits, which is what
+    /// benchmarking wants
+    pub fn new(max_fps: Option<u32>, redraw_on_change: bool) -> Self {
+        Self {
+            min_frame_time: max_fps
+                .filter(|fps| *fps > 0)
+                .map(|fps| Duration::from_secs(1) / fps),
+            redraw_on_change,
+            changed: true,
+            last_frame: None,
+        }
+    }
+
+    /// Records that something on screen changed, so the next frame should be drawn
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+    }
+
+    /// Returns when to check again if a frame shouldn't be drawn at `now`, or `None` if it should
+    pub fn wait_until(&self, now: Instant) -> Option<Instant> {
+        let last_frame = self.last_frame?;
+        if let Some(min_frame_time) = self.min_frame_time {
+            let next_frame = last_frame + min_frame_time;
+            if now < next_frame {
+                return Some(next_frame);
+            }
+        }
+        if self.redraw_on_change && !self.changed {
+            return Some(now + IDLE_CHECK_INTERVAL);
+        }
+        None
+    }
+
+    /// Records that a frame was drawn at `now`
+    pub fn frame_drawn(&mut self, now: Instant) {
+        self.last_frame = Some(now);
+        self.changed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uncapped_never_waits() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(None, false);
+        assert_eq!(limiter.wait_until(start), None);
+        limiter.frame_drawn(start);
+        assert_eq!(limiter.wait_until(start), None);
+
+        // Zero means uncapped too
+        let mut limiter = FrameLimiter::new(Some(0), false);
+        limiter.frame_drawn(start);
+        assert_eq!(limiter.wait_until(start), None);
+    }
+
+    #[test]
+    fn fps_cap() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(50), false);
+        limiter.frame_drawn(start);
+        let next_frame = start + Duration::from_millis(20);
+        assert_eq!(limiter.wait_until(start), Some(next_frame));
+        assert_eq!(
+            limiter.wait_until(start + Duration::from_millis(5)),
+            Some(next_frame)
+        );
+        assert_eq!(limiter.wait_until(next_frame), None);
+    }
+
+    #[test]
+    fn redraw_on_change() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(50), true);
+        // The first frame is always drawn
+        assert_eq!(limiter.wait_until(start), None);
+        limiter.frame_drawn(start);
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(limiter.wait_until(later), Some(later + IDLE_CHECK_INTERVAL));
+        limiter.mark_changed();
+        assert_eq!(limiter.wait_until(later), None);
+
+        // Changes still respect the cap
+        limiter.frame_drawn(later);
+        limiter.mark_changed();
+        assert_eq!(
+            limiter.wait_until(later),
+            Some(later + Duration::from_millis(20))
+        );
+    }
+}
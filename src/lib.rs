@@ -5,44 +5,110 @@ use conrod_core::{
 };
 use glam::DVec2;
 use glium::Surface;
+use log::{error, info, warn};
 
+mod airline_legend;
 mod airports;
+mod app_config;
+mod backend_status;
 mod button_widget;
+mod cli;
+mod color_legend;
+mod error_banner;
+mod export;
+mod frame_limiter;
 mod loading_renderer;
 mod map;
 mod map_renderer;
+mod minimap;
+mod outlined_text;
+mod plane_recording;
 mod plane_renderer;
+mod plane_selection;
 mod request_plane;
+mod ruler;
+mod screenshot;
+mod slider;
 mod support;
+mod theme;
 mod tile;
 mod ui_filter;
 mod util;
+mod view_state;
 
 pub use airports::*;
+pub use app_config::AppConfig;
+#[allow(ambiguous_glob_reexports)]
 pub use button_widget::*;
-pub use loading_renderer::LoadingScreenRenderer;
+pub use cli::{parse_plane_source, run_prefetch, PrefetchArgs, APP_USAGE, PREFETCH_USAGE};
+pub use error_banner::ErrorBanner;
+pub use frame_limiter::FrameLimiter;
+pub use loading_renderer::{LoadingProgress, LoadingScreenRenderer};
 pub use map::*;
+#[allow(ambiguous_glob_reexports)]
 pub use map_renderer::*;
+pub use outlined_text::OutlinedText;
 pub use plane_renderer::*;
+pub use plane_selection::{cycle_plane, PlaneSelection, SelectedPlane};
 pub use request_plane::*;
+pub use ruler::Ruler;
 use statrs::statistics::OrderStatistics;
+pub use theme::{Theme, ThemeKind};
 pub use tile::*;
 pub use ui_filter::*;
 pub use util::*;
+pub use view_state::*;
 
-const WIDTH: u32 = 1280;
-const HEIGHT: u32 = 720;
 const MAX_ZOOM_LEVEL: u32 = 20;
 
+/// How long closing the app waits for tile downloads to finish
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The airline filter buttons, from top to bottom
+const AIRLINE_FILTERS: [(BasicAirline, &str); 7] = [
+    (BasicAirline::American, "American Airlines"),
+    (BasicAirline::Spirit, "Spirit"),
+    (BasicAirline::Southwest, "Southwest"),
+    (BasicAirline::United, "United"),
+    (BasicAirline::Delta, "Delta"),
+    (BasicAirline::Other, "Other Airlines"),
+    (BasicAirline::All, "All"),
+];
+
+/// An optional color table overriding the colors of plane icons, see
+/// [`PlaneRenderer::load_color_table`]
+const AIRLINE_COLORS_PATH: &str = "airline_colors.txt";
+
 widget_ids!(pub struct Ids {
     debug_menu[],
     text,
     viewport,
     map_images[],
     satellite_tiles[],
+    street_tiles[],
     tiles[],
     weather_tiles[],
     weather_button,
+    weather_opacity_slider,
+    base_layer_buttons[],
+    compass_button,
+    compass_needle,
+    ruler_button,
+    ruler_paths[],
+    ruler_start,
+    ruler_end,
+    ruler_label,
+    airline_legend_button,
+    grid_button,
+    minimap_button,
+    follow_button,
+    ground_button,
+    units_button,
+    minimap_background,
+    minimap_tiles[],
+    minimap_view,
+    airline_legend_swatches[],
+    airline_legend_labels[],
     airplane_button,
     debug_button,
     airport_button,
@@ -52,24 +118,38 @@ widget_ids!(pub struct Ids {
     longitude_lines[],
     longitude_text[],
     filer_button[],
+    plane_type_filter_buttons[],
+    color_mode_buttons[],
+    color_legend_segments[],
+    color_legend_labels[],
     airports[],
+    airport_labels[],
     planes[],
+    plane_clusters[],
     square,
     left_screen_details[],
-    hovering_plane_details[],
+    plane_tooltip,
+    plane_tooltip_text,
+    airport_details[],
     loading_background,
+    error_banner,
+    error_banner_text,
+    stale_badge,
+    stale_badge_text,
 });
 
 use std::fmt::Write;
-pub use util::MAP_PERF_DATA;
 
 /// The app's "main" function. Our real main inside `main.rs` calls this function
-pub fn run_app() {
+pub fn run_app(config: AppConfig) {
     // Create our UI's event loop
     let event_loop = glium::glutin::event_loop::EventLoop::new();
     let window = glium::glutin::window::WindowBuilder::new()
         .with_title("Flight Tracker")
-        .with_inner_size(glium::glutin::dpi::LogicalSize::new(WIDTH, HEIGHT));
+        .with_inner_size(glium::glutin::dpi::LogicalSize::new(
+            config.width,
+            config.height,
+        ));
 
     let context = glium::glutin::ContextBuilder::new()
         .with_vsync(false)
@@ -77,8 +157,15 @@ pub fn run_app() {
 
     let display = glium::Display::new(window, context, &event_loop).unwrap();
 
-    let mut map_ui = conrod_core::UiBuilder::new([WIDTH as f64, HEIGHT as f64]).build();
-    let mut overlay_ui = conrod_core::UiBuilder::new([WIDTH as f64, HEIGHT as f64]).build();
+    //The window manager may not give us the size we asked for, so use the size we actually got
+    let window_size: glium::glutin::dpi::LogicalSize<f64> = {
+        let window = display.gl_window();
+        let window = window.window();
+        window.inner_size().to_logical(window.scale_factor())
+    };
+    let ui_size = [window_size.width, window_size.height];
+    let mut map_ui = conrod_core::UiBuilder::new(ui_size).build();
+    let mut overlay_ui = conrod_core::UiBuilder::new(ui_size).build();
 
     // Generate our widget identifiers
     let mut map_ids = Ids::new(map_ui.widget_id_generator());
@@ -117,20 +204,53 @@ pub fn run_app() {
     let mut map_renderer = conrod_glium::Renderer::new(&display).unwrap();
     let mut overlay_renderer = conrod_glium::Renderer::new(&display).unwrap();
     let mut plane_renderer = PlaneRenderer::new(&display);
-    let mut loading_renderer = LoadingScreenRenderer::new(&display);
+    plane_renderer.cluster_planes = config.cluster_planes;
+    plane_renderer.plane_linger = config.plane_linger;
+    plane_renderer.climb_threshold = config.climb_threshold;
+    if let Ok(table) = std::fs::read_to_string(AIRLINE_COLORS_PATH) {
+        if let Err(err) = plane_renderer.load_color_table(&table) {
+            warn!("Failed to load {}: {}", AIRLINE_COLORS_PATH, err);
+        }
+    }
+    //Dropped once the loading screen has faded out
+    let mut loading_renderer = Some(LoadingScreenRenderer::new(&display));
 
     let mut last_time = std::time::Instant::now();
     let mut frame_time_ms = 0.0;
 
     let runtime = tokio::runtime::Runtime::new().expect("Unable to create Tokio runtime!");
 
-    let mut pipelines = tile::pipelines(&runtime);
-    let mut plane_requester = PlaneRequester::new(&runtime);
+    let shutdown = Shutdown::new();
+
+    if let Some(threads) = config.decode_threads {
+        tile::set_decode_threads(threads);
+    }
+    let mut pipelines = tile::pipelines(&runtime, config.tile_retry_policy(), &shutdown);
+    let mut minimap_pipeline =
+        tile::overview_pipeline(&runtime, config.tile_retry_policy(), &shutdown);
+    for pipeline in pipelines.values_mut() {
+        pipeline.set_mipmaps(config.tile_mipmaps);
+    }
+    minimap_pipeline.set_mipmaps(config.tile_mipmaps);
+    let mut error_banner = ErrorBanner::default();
+    let plane_requester = PlaneRequester::new(
+        &runtime,
+        &shutdown,
+        &config.plane_source,
+        config.plane_linger,
+    )
+    .unwrap_or_else(|err| {
+        error!(
+            "Unable to start the planes from {:?}: {}",
+            config.plane_source, err
+        );
+        std::process::exit(1);
+    });
 
     let airports_bin = include_bytes!("../assets/data/airports.bin");
     let airports = airports_from_bytes(airports_bin).expect("Failed to load airports");
 
-    let mut viewer = map::TileView::new(29.18796, -81.04923, 8.0, 1080.0 / 2.0);
+    let mut viewer = map::TileView::new(29.18796, -81.04923, 8.0, window_size.width);
     let mut last_cursor_pos: Option<DVec2> = None;
     let mut left_pressed = false;
     // Set to true if last frame the mouse was clicked
@@ -139,56 +259,223 @@ pub fn run_app() {
     let mut was_mouse_dragged = false;
 
     let mut weather_enabled = false;
+    let mut weather_opacity = map_renderer::DEFAULT_WEATHER_OPACITY;
+    let mut view_state = ViewState::load();
     let mut debug_enabled = false;
+    //Outlines and labels every tile, separately from the debug text so the two don't clutter
+    //each other
+    let mut tile_debug_enabled = false;
 
     let mut filter_enabled: bool = false;
     let mut airport_enabled: bool = true;
-    let mut selected_airline = BasicAirline::All;
+    let mut airline_filter = AirlineFilter::default();
+    let mut plane_type_filter = PlaneTypeFilter::default();
+    let mut color_mode = PlaneColorMode::default();
+    let mut airline_legend_enabled = false;
+    let mut grid = GridStyle::default();
+    let mut minimap_enabled = false;
+    //Keeps the clicked plane in the center of the screen while true
+    let mut following = false;
+    //True while the camera is still catching up with the followed plane
+    let mut follow_easing = false;
+    //Planes in an emergency flash and missing planes fade out, so they need new frames even when
+    //nothing else changes
+    let mut planes_animating = false;
+    //Which of Shift, Control, Alt and Logo are held down
+    let mut modifiers = glium::glutin::event::ModifiersState::empty();
 
     let mut last_fps_print = Instant::now();
     let mut frame_counter = 0;
     let mut frame_times: Option<(Vec<f64>, Instant)> = None;
+    //Set by pressing F12, and saved once the next frame is finished
+    let mut screenshot_requested = false;
 
-    let mut loading = true;
+    let mut frame_limiter = FrameLimiter::new(config.max_fps, config.redraw_on_change);
+    let mut last_planes = plane_requester.planes_storage();
+    let mut loading_progress = LoadingProgress::new(Instant::now());
+    let mut planes_loaded = false;
 
     overlay_ids
         .filer_button
-        .resize(4, &mut overlay_ui.widget_id_generator());
-
-    //Detects everytime the cursor is above a plane
-    let mut selected_plane: Option<SelectedPlane> = None;
-    //Detects everytime a plane is clicked
-    let mut clicked_plane: Option<SelectedPlane> = None;
+        .resize(AIRLINE_FILTERS.len(), &mut overlay_ui.widget_id_generator());
+    overlay_ids
+        .plane_type_filter_buttons
+        .resize(PlaneType::ALL.len(), &mut overlay_ui.widget_id_generator());
+
+    //The plane under the cursor and the one that was clicked, found each frame before the planes
+    //are drawn
+    let mut selection = PlaneSelection::new(config.hover_radius);
+    //Drawn by the plane renderer last frame, after the overlay was laid out
+    let mut plane_clusters: Vec<PlaneCluster> = Vec::new();
     //Holds the plane size
     let mut olds_plane_size = 0.0;
-    //Shows the clicked details when plane clicked
-    let mut show_details = false;
+    //The index of the airport whose details are shown
+    let mut clicked_airport: Option<usize> = None;
+    let mut ruler = Ruler::default();
+    //Where the map was last clicked without dragging, in physical pixels
+    let mut map_click: Option<DVec2> = None;
 
     event_loop.run(move |event, _, control_flow| {
         use glium::glutin::event::{
             ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
         };
+        use glium::glutin::event_loop::ControlFlow;
 
         // Break from the loop upon `Escape` or closed window.
         if let Event::WindowEvent { event, .. } = &event {
+            //Any input could change what is on screen
+            frame_limiter.mark_changed();
             match event {
-                // Break from the loop upon `Escape`.
-                WindowEvent::CloseRequested
-                | WindowEvent::KeyboardInput {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                // Break from the loop upon `Escape`, unless it was used to stop following a plane
+                // or to clear the ruler
+                WindowEvent::KeyboardInput {
                     input:
                         glium::glutin::event::KeyboardInput {
                             virtual_keycode: Some(VirtualKeyCode::Escape),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    if following {
+                        following = false;
+                    } else if !ruler.reset() {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+                // Step through the planes on screen upon `Tab`, or backwards with `Shift`
+                WindowEvent::KeyboardInput {
+                    input:
+                        glium::glutin::event::KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Tab),
+                            state: ElementState::Pressed,
                             ..
                         },
                     ..
-                } => *control_flow = glium::glutin::event_loop::ControlFlow::Exit,
+                } => {
+                    let planes = plane_requester.planes_storage();
+                    let (width, height) = (map_ui.win_w, map_ui.win_h);
+                    let bounds =
+                        PlaneBounds::from_viewport(&viewer.get_world_viewport(width, height));
+                    let now = Instant::now();
+                    let visible = planes.iter().filter(|plane| {
+                        bounds.contains(plane)
+                            && passes_filters(
+                                plane,
+                                &airline_filter,
+                                &plane_type_filter,
+                                plane_renderer.include_on_ground,
+                            )
+                            && !plane.is_faded_out(now, plane_renderer.plane_linger)
+                    });
+                    selection.cycle(visible, modifiers.shift());
+                }
+                WindowEvent::ModifiersChanged(state) => modifiers = *state,
+                // Follow the clicked plane upon `F`
+                WindowEvent::KeyboardInput {
+                    input:
+                        glium::glutin::event::KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => following = !following && selection.clicked.is_some(),
+                // Save the planes for other GIS tools upon `E`
+                WindowEvent::KeyboardInput {
+                    input:
+                        glium::glutin::event::KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::E),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    //Only export the planes the filters show, like a screenshot would
+                    let planes: Vec<Plane> = plane_requester
+                        .planes_storage()
+                        .iter()
+                        .filter(|plane| {
+                            passes_filters(
+                                plane,
+                                &airline_filter,
+                                &plane_type_filter,
+                                plane_renderer.include_on_ground,
+                            )
+                        })
+                        .cloned()
+                        .collect();
+                    match export::save_planes_geojson(&planes) {
+                        Ok(path) => info!("Saved planes to {}", path.display()),
+                        Err(err) => warn!("Failed to save planes: {}", err),
+                    }
+                }
+                // Save a screenshot upon `F12`
+                WindowEvent::KeyboardInput {
+                    input:
+                        glium::glutin::event::KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => screenshot_requested = true,
+                // Show the tile grid upon `F3`
+                WindowEvent::KeyboardInput {
+                    input:
+                        glium::glutin::event::KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F3),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => tile_debug_enabled = !tile_debug_enabled,
+                // Switch between the dark and light theme upon `T`
+                WindowEvent::KeyboardInput {
+                    input:
+                        glium::glutin::event::KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::T),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    view_state.theme = view_state.theme.next();
+                    if let Err(err) = view_state.save() {
+                        warn!("Failed to save view state: {}", err);
+                    }
+                }
+                // Some platforms don't resize the OpenGL surface on their own, which would leave
+                // the planes drawn with the old framebuffer size
+                WindowEvent::Resized(size) => display.gl_window().resize(*size),
+                // Conrod isn't told about scale factor changes, but they change the size of the
+                // window in logical pixels, which is what both UIs measure in
+                WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    display.gl_window().resize(**new_inner_size);
+                    let size = new_inner_size.to_logical::<f64>(*scale_factor);
+                    let resize = conrod_core::event::Input::Resize(size.width, size.height);
+                    map_ui.handle_event(resize.clone());
+                    overlay_ui.handle_event(resize);
+                }
                 WindowEvent::MouseWheel { delta, .. } => {
                     let zoom_change = match delta {
                         MouseScrollDelta::LineDelta(_x, y) => *y as f64,
                         MouseScrollDelta::PixelDelta(data) => data.y / 100.0,
                     };
                     let zoom_change = (-zoom_change / 6.0).clamp(-0.5, 0.5);
-                    viewer.multiply_zoom(1.0 + zoom_change);
+
+                    // Zoom towards the cursor, or the center of the screen if we don't know where
+                    // it is. Conrod's window size is in logical pixels, so convert the cursor too
+                    let scale_factor = display.gl_window().window().scale_factor();
+                    let screen_size = DVec2::new(map_ui.win_w, map_ui.win_h);
+                    let cursor = last_cursor_pos
+                        .map(|pos| pos / scale_factor)
+                        .unwrap_or(screen_size / 2.0);
+                    viewer.zoom_around_point(1.0 + zoom_change, cursor, screen_size);
                 }
                 WindowEvent::CursorMoved { position, .. } => {
                     let position = DVec2::new(position.x, position.y);
@@ -196,6 +483,8 @@ pub fn run_app() {
                         let delta = (last - position).clamp_length_max(300.0);
                         if left_pressed {
                             viewer.move_camera_pixels(delta);
+                            //Panning by hand takes the camera back from the followed plane
+                            following = false;
                         }
                     }
 
@@ -211,8 +500,11 @@ pub fn run_app() {
 
                         if left_pressed {
                             was_mouse_dragged = false;
-                        } else if !was_mouse_dragged && selected_plane.is_none() {
-                            clicked_plane = None;
+                        } else if !was_mouse_dragged {
+                            map_click = last_cursor_pos;
+                            if selection.hovered.is_none() {
+                                selection.clicked = None;
+                            }
                         }
                     }
                 }
@@ -228,10 +520,85 @@ pub fn run_app() {
 
         match &event {
             glium::glutin::event::Event::MainEventsCleared => {
+                let now = Instant::now();
+                let planes = plane_requester.planes_storage();
+                if viewer.is_animating()
+                    || !loading_progress.is_finished(now)
+                    || !std::sync::Arc::ptr_eq(&planes, &last_planes)
+                    || pipelines.values().any(TilePipeline::has_new_tiles)
+                    || minimap_pipeline.has_new_tiles()
+                    || follow_easing
+                    || planes_animating
+                {
+                    frame_limiter.mark_changed();
+                }
+                last_planes = planes;
+
+                //Exit can't be undone, so leave it alone
+                if *control_flow != ControlFlow::Exit {
+                    *control_flow = match frame_limiter.wait_until(now) {
+                        Some(next_check) => ControlFlow::WaitUntil(next_check),
+                        None => ControlFlow::Poll,
+                    };
+                }
+                if *control_flow != ControlFlow::Poll {
+                    return;
+                }
+                frame_limiter.frame_drawn(now);
+
                 // This is only set to true for the exact *first* frame that the mouse is clicked
                 let left_just_pressed = left_pressed && !left_last_pressed;
                 left_last_pressed = left_pressed;
 
+                viewer.animate(frame_time_ms);
+
+                //Use the latest position straight from OpenSky, since the plane may have moved since
+                //it was drawn
+                follow_easing = false;
+                if following {
+                    let followed =
+                        selection.clicked_plane(&last_planes, now, plane_renderer.plane_linger);
+                    match followed {
+                        Some(plane) => {
+                            follow_easing = viewer.ease_toward_lat_long(
+                                plane.latitude as f64,
+                                plane.longitude as f64,
+                                frame_time_ms,
+                            )
+                        }
+                        //The plane was deselected or stopped reporting its position
+                        None => following = false,
+                    }
+                }
+
+                if let Some(click) = map_click.take() {
+                    //Clicks on buttons aren't meant for the map
+                    let on_overlay = overlay_ui
+                        .global_input()
+                        .current
+                        .widget_under_mouse
+                        .is_some();
+                    let scale_factor = display.gl_window().window().scale_factor();
+                    let click = click / scale_factor;
+                    if ruler.is_enabled() && !on_overlay {
+                        let point = viewer.screen_to_lat_long(click, map_ui.win_w, map_ui.win_h);
+                        ruler.click(point);
+                    } else if !on_overlay && selection.hovered.is_none() {
+                        //Clicking anything other than an airport closes its details
+                        clicked_airport = if airport_enabled {
+                            airports::airport_renderer::airport_at(
+                                &airports,
+                                &viewer,
+                                click,
+                                map_ui.win_w,
+                                map_ui.win_h,
+                            )
+                        } else {
+                            None
+                        };
+                    }
+                }
+
                 let mut map_ui = map_ui.set_widgets();
                 let map_ui = &mut map_ui;
                 let mut overlay_ui = overlay_ui.set_widgets();
@@ -239,10 +606,19 @@ pub fn run_app() {
 
                 overlay_ids
                     .filer_button
-                    .resize(6, &mut overlay_ui.widget_id_generator());
+                    .resize(AIRLINE_FILTERS.len(), &mut overlay_ui.widget_id_generator());
+
+                let theme = view_state.theme.theme();
 
                 //========== Draw Map ==========
                 {
+                    //The scale factor can change when the window moves to another screen
+                    if config.hidpi_tiles {
+                        let scale_factor = display.gl_window().window().scale_factor();
+                        for pipeline in pipelines.values_mut() {
+                            pipeline.set_pixel_density(scale_factor);
+                        }
+                    }
                     let map_state = map_renderer::MapRendererState {
                         tile_cache: &mut pipelines,
                         view: &viewer,
@@ -250,10 +626,23 @@ pub fn run_app() {
                         image_map: &mut image_map,
                         ids: &mut map_ids,
                         weather_enabled,
+                        tile_debug_enabled,
+                        weather_opacity,
+                        base_layer: view_state.base_layer,
+                        prefetch_enabled: config.prefetch,
+                        grid,
+                        theme,
                     };
                     map_renderer::draw(map_state, map_ui, b612_map);
                 }
 
+                //Keep showing the loading screen until there is something to look at
+                let now = Instant::now();
+                let base = &pipelines[view_state.base_layer.tile_kind()];
+                let map_loaded = map_renderer::is_map_ready(base, &viewer);
+                loading_progress.update(now, planes_loaded && map_loaded);
+                let loading = !loading_progress.is_finished(now);
+
                 //========== Draw Airports ==========
                 if airport_enabled {
                     airports::airport_renderer::draw(
@@ -263,39 +652,90 @@ pub fn run_app() {
                         &mut map_ids,
                         airport_id,
                         map_ui,
+                        b612_map,
+                        theme,
                     );
                 }
 
+                //========== Draw Plane Clusters ==========
+                overlay_ids
+                    .plane_clusters
+                    .resize(plane_clusters.len(), &mut overlay_ui.widget_id_generator());
+                for (cluster, id) in plane_clusters.iter().zip(overlay_ids.plane_clusters.iter()) {
+                    if button_widget::draw_plane_cluster(
+                        *id,
+                        overlay_ui,
+                        cluster.count,
+                        cluster.location.x,
+                        cluster.location.y,
+                    ) {
+                        //Zoom in two levels on the cluster so its planes spread out
+                        let screen_size = DVec2::new(overlay_ui.win_w, overlay_ui.win_h);
+                        let cursor = DVec2::new(
+                            cluster.location.x + screen_size.x / 2.0,
+                            screen_size.y / 2.0 - cluster.location.y,
+                        );
+                        viewer.zoom_around_point(0.25, cursor, screen_size);
+                    }
+                }
+
                 //========== Draw Debug Data ==========
 
-                let perf_data = crate::take_profile_data();
+                let ProfileSnapshot {
+                    map: map_data,
+                    scopes: perf_data,
+                } = Profiler::snapshot();
 
                 if debug_enabled {
-                    let _scope_debug_view = crate::profile_scope("Render Debug Information");
-                    let mut perf_data: Vec<_> = perf_data.into_iter().collect();
-                    perf_data.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                    let _scope_debug_view = crate::Profiler::scope("Render Debug Information");
 
                     //========== Draw Debug Text ==========
-                    let map_data = {
-                        let mut guard = MAP_PERF_DATA.lock();
-                        guard.snapshot()
-                    };
 
-                    let debug_lines = 4 + map_data.backend_request_secs.len() + perf_data.len();
+                    let planes = plane_requester.planes_storage();
+                    let bounds = PlaneBounds::from_viewport(
+                        &viewer.get_world_viewport(overlay_ui.win_w, overlay_ui.win_h),
+                    );
+                    let shown: Vec<&Plane> = planes
+                        .iter()
+                        .filter(|plane| {
+                            passes_filters(
+                                plane,
+                                &airline_filter,
+                                &plane_type_filter,
+                                plane_renderer.include_on_ground,
+                            )
+                        })
+                        .collect();
+                    let visible_planes = shown.iter().filter(|p| bounds.contains(p)).count();
+                    let mut planes_per_airline =
+                        enum_map::EnumMap::<BasicAirline, usize>::default();
+                    for plane in shown.iter() {
+                        planes_per_airline[AirlineFilter::category(&plane.airline)] += 1;
+                    }
+
+                    //The frame time, zoom, decode time, plane count and OpenSky status take one
+                    //line each, then there is a line for each airline other than All, each tile
+                    //backend and each profiled scope
+                    let debug_lines = 5
+                        + (AIRLINE_FILTERS.len() - 1)
+                        + map_data.backend_request_secs.len()
+                        + perf_data.len();
 
+                    let white = theme.text;
                     let mut i = 0;
                     let mut buf: util::StringFormatter<512> = util::StringFormatter::new();
                     overlay_ids
                         .debug_menu
                         .resize(debug_lines, &mut overlay_ui.widget_id_generator());
 
-                    let mut draw_text = |args: std::fmt::Arguments<'_>| {
+                    let mut draw_text = |color: Color, args: std::fmt::Arguments<'_>| {
                         buf.clear();
-                        buf.write_fmt(args).unwrap();
+                        //Lines too long for the buffer are cut off rather than crashing the app
+                        let _ = buf.write_fmt(args);
                         let gui_text = widget::Text::new(buf.as_str())
-                            .color(conrod_core::color::WHITE)
+                            .color(color)
                             .left_justify()
-                            .font_size(8)
+                            .font_size(theme.debug_font_size)
                             .font_id(b612_overlay);
 
                         let width = gui_text.get_w(overlay_ui).unwrap();
@@ -308,44 +748,104 @@ pub fn run_app() {
                         assert!(i <= debug_lines);
                     };
 
-                    draw_text(format_args!(
-                        "FT: {:.2}, FPS: {}",
-                        frame_time_ms,
-                        (1000.0 / frame_time_ms) as u32
-                    ));
-                    draw_text(format_args!(
-                        "Zoom: {}, Tiles: {}",
-                        map_data.zoom, map_data.tiles_rendered
-                    ));
-                    draw_text(format_args!(
-                        "Decode: {:.2}ms, Upload: {:.2}ms",
-                        map_data.tile_decode_time.as_secs_f64() * 1000.0,
-                        map_data.tile_upload_time.as_secs_f64() * 1000.0
-                    ));
+                    draw_text(
+                        white,
+                        format_args!(
+                            "FT: {:.2}, FPS: {}",
+                            frame_time_ms,
+                            (1000.0 / frame_time_ms) as u32
+                        ),
+                    );
+                    draw_text(
+                        white,
+                        format_args!(
+                            "Zoom: {}, Tiles: {}, On GPU: {}",
+                            map_data.zoom, map_data.tiles_rendered, map_data.tiles_on_gpu
+                        ),
+                    );
+                    draw_text(
+                        white,
+                        format_args!(
+                            "Decode: {:.2}ms, Upload: {:.2}ms{}",
+                            map_data.tile_decode_time.as_secs_f64() * 1000.0,
+                            map_data.tile_upload_time.as_secs_f64() * 1000.0,
+                            if config.tile_mipmaps {
+                                " (mipmapped)"
+                            } else {
+                                ""
+                            }
+                        ),
+                    );
+
+                    draw_text(
+                        white,
+                        format_args!(
+                            "Planes: {} of {}, In View: {}",
+                            shown.len(),
+                            planes.len(),
+                            visible_planes
+                        ),
+                    );
+                    for (airline, label) in AIRLINE_FILTERS.iter() {
+                        if *airline != BasicAirline::All {
+                            draw_text(
+                                white,
+                                format_args!("  {}: {}", label, planes_per_airline[*airline]),
+                            );
+                        }
+                    }
+                    let (plane_status, plane_color) = backend_status::plane_status(
+                        plane_requester.last_success(),
+                        plane_requester.started(),
+                        now,
+                        theme.text,
+                    );
+                    draw_text(plane_color, format_args!("{}", plane_status));
 
                     for (backend_name, time) in map_data.backend_request_secs {
-                        draw_text(format_args!("  {} {:?}", backend_name, time,));
+                        let retries = map_data
+                            .backend_retries
+                            .iter()
+                            .find(|(name, _)| *name == backend_name)
+                            .map_or(0, |(_, retries)| *retries);
+                        let health = map_data
+                            .backend_health
+                            .iter()
+                            .find(|(name, _)| *name == backend_name)
+                            .map(|(_, health)| health);
+                        draw_text(
+                            white,
+                            format_args!(
+                                "  {} {:?}, {} retries, {}",
+                                backend_name,
+                                time,
+                                retries,
+                                backend_status::backend_status(health, now)
+                            ),
+                        );
                     }
+                    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
                     for (name, data) in perf_data {
-                        let samples = data.get_samples();
-                        if samples.len() == 1 {
-                            draw_text(format_args!("{}: {:?}", name, samples[0]));
-                        } else {
-                            let avg: Duration =
-                                samples.iter().sum::<Duration>() / samples.len() as u32;
-                            draw_text(format_args!(
-                                "{}: {} times, {:?} avg",
-                                name,
-                                samples.len(),
-                                avg
-                            ));
-                        };
+                        if let Some(stats) = data.stats() {
+                            draw_text(
+                                white,
+                                format_args!(
+                                    "{}: {}x, avg {:.2} min {:.2} max {:.2} p95 {:.2} ms",
+                                    name,
+                                    stats.count,
+                                    ms(stats.mean),
+                                    ms(stats.min),
+                                    ms(stats.max),
+                                    ms(stats.p95)
+                                ),
+                            );
+                        }
                     }
                 }
 
                 if !loading {
                     //========== Draw Buttons ==========
-                    let scope_render_buttons = crate::profile_scope("Render Buttons");
+                    let scope_render_buttons = crate::Profiler::scope("Render Buttons");
 
                     let widget_x_position = (overlay_ui.win_w / 2.0) * 0.95 - 25.0;
                     let widget_y_position = (overlay_ui.win_h / 2.0) * 0.90;
@@ -355,9 +855,13 @@ pub fn run_app() {
                         overlay_ids.airplane_button,
                         overlay_ui,
                         airplane_button_ids,
+                        filter_enabled,
+                        "Filter planes",
                         widget_x_position,
                         widget_y_position,
-                    ) {
+                    )
+                    .clicked()
+                    {
                         filter_enabled = !filter_enabled;
                     }
 
@@ -366,19 +870,42 @@ pub fn run_app() {
                         overlay_ids.weather_button,
                         overlay_ui,
                         weather_id,
+                        weather_enabled,
+                        "Show weather",
                         widget_x_position,
                         widget_y_position - 70.0,
-                    ) {
+                    )
+                    .clicked()
+                    {
                         weather_enabled = !weather_enabled;
                     }
+                    //========== Draw Weather Opacity Slider ==========
+                    if weather_enabled {
+                        if let Some(opacity) = slider::draw(
+                            overlay_ids.weather_opacity_slider,
+                            overlay_ui,
+                            "Weather Opacity",
+                            weather_opacity as f64,
+                            0.0,
+                            1.0,
+                            widget_x_position - 130.0,
+                            widget_y_position - 70.0,
+                        ) {
+                            weather_opacity = opacity as f32;
+                        }
+                    }
                     //========== Draw Debug Button ==========
                     if button_widget::draw_circle_with_image(
                         overlay_ids.debug_button,
                         overlay_ui,
                         gear_id,
+                        debug_enabled,
+                        "Show debug info",
                         widget_x_position,
                         widget_y_position - 140.0,
-                    ) {
+                    )
+                    .clicked()
+                    {
                         debug_enabled = !debug_enabled;
                     }
                     //========== Draw Airport Button ==========
@@ -386,72 +913,40 @@ pub fn run_app() {
                         overlay_ids.airport_button,
                         overlay_ui,
                         airport_id,
+                        airport_enabled,
+                        "Show airports",
                         widget_x_position,
                         widget_y_position - 210.0,
-                    ) {
+                    )
+                    .clicked()
+                    {
                         airport_enabled = !airport_enabled;
                     }
                     //========== Filtering buttons enabling/disabling ==========
                     if filter_enabled {
-                        //========== Draw American Airlines Filter ==========
-                        if ui_filter::draw(
-                            overlay_ids.filer_button[0],
-                            overlay_ui,
-                            String::from("American Airlines"),
-                            widget_x_position - 130.0,
-                            widget_y_position,
-                        ) {
-                            selected_airline = BasicAirline::American;
-                        }
-                        //========== Draw Spirit Filter ==========
-                        if ui_filter::draw(
-                            overlay_ids.filer_button[1],
-                            overlay_ui,
-                            String::from("Spirit"),
-                            widget_x_position - 130.0,
-                            widget_y_position - 40.0,
-                        ) {
-                            selected_airline = BasicAirline::Spirit;
-                        }
-                        //========== Draw SouthWest Filter ==========
-                        if ui_filter::draw(
-                            overlay_ids.filer_button[2],
-                            overlay_ui,
-                            String::from("Southwest"),
-                            widget_x_position - 130.0,
-                            widget_y_position - 80.0,
-                        ) {
-                            selected_airline = BasicAirline::Southwest;
-                        }
-                        //========== Draw United Filter ==========
-                        if ui_filter::draw(
-                            overlay_ids.filer_button[3],
-                            overlay_ui,
-                            String::from("United"),
-                            widget_x_position - 130.0,
-                            widget_y_position - 120.0,
-                        ) {
-                            selected_airline = BasicAirline::United
-                        }
-                        //========== Draw Other Filter ==========
-                        if ui_filter::draw(
-                            overlay_ids.filer_button[4],
-                            overlay_ui,
-                            String::from("Other Airlines"),
-                            widget_x_position - 130.0,
-                            widget_y_position - 160.0,
-                        ) {
-                            selected_airline = BasicAirline::Other
+                        for (i, (airline, label)) in AIRLINE_FILTERS.iter().enumerate() {
+                            if ui_filter::draw(
+                                overlay_ids.filer_button[i],
+                                overlay_ui,
+                                label,
+                                airline_filter.is_selected(*airline),
+                                widget_x_position - 130.0,
+                                widget_y_position - i as f64 * 40.0,
+                            ) {
+                                airline_filter.toggle(*airline);
+                            }
                         }
-                        //========== Draw All Filter ==========
-                        if ui_filter::draw(
-                            overlay_ids.filer_button[5],
-                            overlay_ui,
-                            String::from("All"),
-                            widget_x_position - 130.0,
-                            widget_y_position - 200.0,
-                        ) {
-                            selected_airline = BasicAirline::All
+                        for (i, plane_type) in PlaneType::ALL.iter().enumerate() {
+                            if ui_filter::draw(
+                                overlay_ids.plane_type_filter_buttons[i],
+                                overlay_ui,
+                                plane_type.to_str(),
+                                plane_type_filter.is_selected(*plane_type),
+                                widget_x_position - 300.0,
+                                widget_y_position - i as f64 * 40.0,
+                            ) {
+                                plane_type_filter.toggle(*plane_type);
+                            }
                         }
                     }
 
@@ -459,24 +954,249 @@ pub fn run_app() {
                         overlay_ids.bench_button,
                         overlay_ui,
                         bench_id,
+                        frame_times.is_some(),
+                        "Benchmark frame times",
                         widget_x_position,
                         widget_y_position - 280.0,
-                    ) {
+                    )
+                    .clicked()
+                    {
                         let now = Instant::now();
                         match frame_times.take() {
                             Some((vec, start)) => {
-                                println!("Captured {} samples over {:?}", vec.len(), now - start);
+                                info!("Captured {} samples over {:?}", vec.len(), now - start);
+                                match util::save_frame_times(&vec) {
+                                    Ok(path) => info!("Saved samples to {}", path.display()),
+                                    Err(err) => warn!("Failed to save samples: {}", err),
+                                }
                                 let mut data = statrs::statistics::Data::new(vec);
-                                println!("  1st  percentile: {:.2}ms", data.percentile(1));
-                                println!("  5th  percentile: {:.2}ms", data.percentile(5));
-                                println!("  Mean FT:         {:.2}ms", data.percentile(50));
-                                println!("  95th percentile: {:.2}ms", data.percentile(95));
-                                println!("  99th percentile: {:.2}ms", data.percentile(99));
+                                info!("  1st  percentile: {:.2}ms", data.percentile(1));
+                                info!("  5th  percentile: {:.2}ms", data.percentile(5));
+                                info!("  Mean FT:         {:.2}ms", data.percentile(50));
+                                info!("  95th percentile: {:.2}ms", data.percentile(95));
+                                info!("  99th percentile: {:.2}ms", data.percentile(99));
                                 frame_times = None;
                             }
                             None => {
                                 frame_times = Some((Vec::new(), now));
-                                println!("Starting frame profiler");
+                                info!("Starting frame profiler");
+                            }
+                        }
+                    }
+
+                    //========== Draw Plane Color Mode Selector ==========
+                    overlay_ids.color_mode_buttons.resize(
+                        PlaneColorMode::ALL.len(),
+                        &mut overlay_ui.widget_id_generator(),
+                    );
+                    for (i, &mode) in PlaneColorMode::ALL.iter().enumerate() {
+                        if button_widget::draw_circle_with_text(
+                            overlay_ids.color_mode_buttons[i],
+                            overlay_ui,
+                            mode.label(),
+                            color_mode == mode,
+                            widget_x_position,
+                            widget_y_position - 445.0 - i as f64 * 50.0,
+                        ) {
+                            color_mode = mode;
+                        }
+                    }
+
+                    overlay_ids.color_legend_segments.resize(
+                        color_legend::LEGEND_SEGMENTS,
+                        &mut overlay_ui.widget_id_generator(),
+                    );
+                    overlay_ids
+                        .color_legend_labels
+                        .resize(2, &mut overlay_ui.widget_id_generator());
+                    color_legend::draw(
+                        color_mode,
+                        &overlay_ids.color_legend_segments,
+                        &overlay_ids.color_legend_labels,
+                        overlay_ui,
+                        b612_overlay,
+                    );
+
+                    //========== Draw Compass ==========
+                    if button_widget::draw_compass(
+                        overlay_ids.compass_button,
+                        overlay_ids.compass_needle,
+                        overlay_ui,
+                        viewer.bearing(),
+                        widget_x_position,
+                        -overlay_ui.win_h / 2.0 + 40.0,
+                    ) {
+                        //Clicking the compass turns the map back to north-up
+                        viewer.set_bearing(0.0);
+                    }
+
+                    //========== Draw Ruler ==========
+                    if button_widget::draw_circle_with_text(
+                        overlay_ids.ruler_button,
+                        overlay_ui,
+                        "Ruler",
+                        ruler.is_enabled(),
+                        widget_x_position - 50.0,
+                        -overlay_ui.win_h / 2.0 + 40.0,
+                    ) {
+                        ruler.toggle();
+                    }
+                    overlay_ids.ruler_paths.resize(
+                        ruler::MAX_PATH_PIECES,
+                        &mut overlay_ui.widget_id_generator(),
+                    );
+                    ruler.draw(
+                        &overlay_ids,
+                        &viewer,
+                        view_state.units,
+                        overlay_ui,
+                        b612_overlay,
+                    );
+
+                    //========== Draw Airline Legend ==========
+                    if button_widget::draw_circle_with_text(
+                        overlay_ids.airline_legend_button,
+                        overlay_ui,
+                        "Key",
+                        airline_legend_enabled,
+                        widget_x_position - 100.0,
+                        -overlay_ui.win_h / 2.0 + 40.0,
+                    ) {
+                        airline_legend_enabled = !airline_legend_enabled;
+                    }
+                    //========== Draw Grid Button ==========
+                    if button_widget::draw_circle_with_text(
+                        overlay_ids.grid_button,
+                        overlay_ui,
+                        match grid.format {
+                            CoordinateFormat::Dms if grid.enabled => "DMS",
+                            _ => "Grid",
+                        },
+                        grid.enabled,
+                        widget_x_position - 150.0,
+                        -overlay_ui.win_h / 2.0 + 40.0,
+                    ) {
+                        grid = grid.next();
+                    }
+
+                    //========== Draw Minimap ==========
+                    if button_widget::draw_circle_with_text(
+                        overlay_ids.minimap_button,
+                        overlay_ui,
+                        "Map",
+                        minimap_enabled,
+                        widget_x_position - 200.0,
+                        -overlay_ui.win_h / 2.0 + 40.0,
+                    ) {
+                        minimap_enabled = !minimap_enabled;
+                    }
+
+                    //========== Draw Follow Button ==========
+                    if button_widget::draw_circle_with_text(
+                        overlay_ids.follow_button,
+                        overlay_ui,
+                        "Follow",
+                        following,
+                        widget_x_position - 250.0,
+                        -overlay_ui.win_h / 2.0 + 40.0,
+                    ) {
+                        following = !following && selection.clicked.is_some();
+                    }
+
+                    //========== Draw On Ground Button ==========
+                    if button_widget::draw_circle_with_text(
+                        overlay_ids.ground_button,
+                        overlay_ui,
+                        "Ground",
+                        plane_renderer.include_on_ground,
+                        widget_x_position - 300.0,
+                        -overlay_ui.win_h / 2.0 + 40.0,
+                    ) {
+                        plane_renderer.include_on_ground = !plane_renderer.include_on_ground;
+                    }
+
+                    //========== Draw Units Button ==========
+                    if button_widget::draw_circle_with_text(
+                        overlay_ids.units_button,
+                        overlay_ui,
+                        view_state.units.distance_label(),
+                        false,
+                        widget_x_position - 350.0,
+                        -overlay_ui.win_h / 2.0 + 40.0,
+                    ) {
+                        view_state.units = view_state.units.next();
+                        if let Err(err) = view_state.save() {
+                            warn!("Failed to save view state: {}", err);
+                        }
+                    }
+                    if minimap_enabled {
+                        //Above the row of buttons along the bottom, left of the column on the right
+                        let x = widget_x_position - 35.0 - minimap::MINIMAP_SIZE / 2.0;
+                        let y = -overlay_ui.win_h / 2.0 + 75.0 + minimap::MINIMAP_SIZE / 2.0;
+                        if let Some(world) = minimap::draw(
+                            &mut minimap_pipeline,
+                            &viewer,
+                            &mut overlay_ids,
+                            x,
+                            y,
+                            overlay_ui,
+                            &display,
+                            &mut image_map,
+                        ) {
+                            viewer.center_on(world);
+                            following = false;
+                        }
+                    }
+
+                    //Colors only mean airlines in the airline color mode
+                    if airline_legend_enabled && color_mode == PlaneColorMode::Airline {
+                        let planes = plane_requester.planes_storage();
+                        let bounds = PlaneBounds::from_viewport(
+                            &viewer.get_world_viewport(overlay_ui.win_w, overlay_ui.win_h),
+                        );
+                        let visible = planes.iter().filter(|plane| {
+                            bounds.contains(plane)
+                                && passes_filters(
+                                    plane,
+                                    &airline_filter,
+                                    &plane_type_filter,
+                                    plane_renderer.include_on_ground,
+                                )
+                        });
+                        let entries = airline_legend::legend_entries(visible, &airline_filter);
+                        overlay_ids
+                            .airline_legend_swatches
+                            .resize(entries.len(), &mut overlay_ui.widget_id_generator());
+                        overlay_ids
+                            .airline_legend_labels
+                            .resize(entries.len(), &mut overlay_ui.widget_id_generator());
+                        airline_legend::draw(
+                            &entries,
+                            &plane_renderer,
+                            &overlay_ids.airline_legend_swatches,
+                            &overlay_ids.airline_legend_labels,
+                            overlay_ui,
+                            b612_overlay,
+                        );
+                    }
+
+                    //========== Draw Base Layer Selector ==========
+                    overlay_ids
+                        .base_layer_buttons
+                        .resize(BaseLayer::ALL.len(), &mut overlay_ui.widget_id_generator());
+                    for (i, &layer) in BaseLayer::ALL.iter().enumerate() {
+                        if button_widget::draw_circle_with_text(
+                            overlay_ids.base_layer_buttons[i],
+                            overlay_ui,
+                            layer.label(),
+                            view_state.base_layer == layer,
+                            widget_x_position,
+                            widget_y_position - 345.0 - i as f64 * 50.0,
+                        ) && view_state.base_layer != layer
+                        {
+                            view_state.base_layer = layer;
+                            if let Err(err) = view_state.save() {
+                                warn!("Failed to save view state: {}", err);
                             }
                         }
                     }
@@ -484,12 +1204,42 @@ pub fn run_app() {
                     scope_render_buttons.end();
                 } else {
                     // Render the loading screen
+                    let opacity = loading_progress.opacity(now);
                     widget::Rectangle::fill([overlay_ui.win_w, overlay_ui.win_h])
-                        .color(Color::Rgba(0.2, 0.2, 0.2, 1.0))
+                        .color(Color::Rgba(0.2, 0.2, 0.2, opacity))
                         .top_left()
                         .set(overlay_ids.loading_background, overlay_ui);
                 }
 
+                //========== Draw Error Banner ==========
+                //Drawn after the loading screen so errors that keep the map from loading show up
+                for (kind, pipeline) in pipelines.iter_mut() {
+                    while let Some(err) = pipeline.poll_error() {
+                        error_banner.report(kind, &err);
+                    }
+                }
+                error_banner.draw(
+                    overlay_ids.error_banner,
+                    overlay_ids.error_banner_text,
+                    overlay_ui,
+                    b612_overlay,
+                );
+
+                //========== Draw Stale Plane Data Badge ==========
+                if let Some(message) = backend_status::stale_planes_badge(
+                    plane_requester.last_success(),
+                    plane_requester.started(),
+                    Instant::now(),
+                ) {
+                    backend_status::draw_stale_badge(
+                        &message,
+                        overlay_ids.stale_badge,
+                        overlay_ids.stale_badge_text,
+                        overlay_ui,
+                        b612_overlay,
+                    );
+                }
+
                 frame_counter += 1;
                 let now = Instant::now();
                 if now - last_fps_print >= Duration::from_secs(1) {
@@ -500,33 +1250,49 @@ pub fn run_app() {
                 }
 
                 //Display text details of planes
-                if left_just_pressed && selected_plane.is_some() {
-                    clicked_plane = selected_plane.clone();
-                    show_details = true;
+                if left_just_pressed && selection.click_hovered() {
+                    clicked_airport = None;
                 }
 
-                if let Some(hover_plane) = &selected_plane {
+                if let Some(hovered) = &selection.hovered {
+                    //The tooltip floats, so it is drawn over the rest of the overlay
+                    let mut buf: util::StringFormatter<128> = util::StringFormatter::new();
+                    let _ =
+                        plane_selection::write_tooltip(&hovered.plane, view_state.units, &mut buf);
+                    button_widget::draw_tooltip(
+                        None,
+                        overlay_ids.plane_tooltip,
+                        overlay_ids.plane_tooltip_text,
+                        buf.as_str(),
+                        Some(b612_overlay),
+                        overlay_ui,
+                    );
+                }
+
+                let planes = plane_requester.planes_storage();
+                if let Some(plane) =
+                    selection.clicked_plane(&planes, Instant::now(), plane_renderer.plane_linger)
+                {
                     //Stores plane airline
-                    let airline = &hover_plane.plane.airline;
-                    let plane = &hover_plane.plane;
-                    let plane_type = hover_plane.plane.plane_type;
+                    let airline = &plane.airline;
+                    let plane_type = plane.plane_type;
 
                     //Where to draw the detail lines
-                    let detail_lines = 5;
+                    let detail_lines = 10;
                     let mut i = 0;
                     let mut buf: util::StringFormatter<512> = util::StringFormatter::new();
                     overlay_ids
-                        .hovering_plane_details
+                        .left_screen_details
                         .resize(detail_lines, &mut overlay_ui.widget_id_generator());
 
                     //Draw text function
                     let mut draw_text = |args: std::fmt::Arguments<'_>| {
                         buf.clear();
-                        buf.write_fmt(args).unwrap();
+                        let _ = buf.write_fmt(args);
                         let plane_text = widget::Text::new(buf.as_str())
-                            .color(conrod_core::color::WHITE)
+                            .color(theme.text)
                             .left_justify()
-                            .font_size(10)
+                            .font_size(theme.details_font_size)
                             .font_id(b612_overlay);
 
                         //let left_side_text = widget::Text::new(buf.as_str())
@@ -535,18 +1301,15 @@ pub fn run_app() {
                         //    .font_size(20)
                         //    .font_id(b612_overlay);
 
-                        let size = hover_plane.size as f64 / 2.0;
-                        let next_to_planex = hover_plane.location.x + 70.0 + size;
-                        let next_to_planey = hover_plane.location.y - 8.0 - i as f64 * 11.0;
+                        olds_plane_size = plane_text.get_w(overlay_ui).unwrap();
+                        let width = olds_plane_size;
 
-                        //let width = left_side_text.get_w(overlay_ui).unwrap();
-
-                        //let left_side_screenx = -overlay_ui.win_w / 2.0 + width / 2.0;
-                        //let left_side_screeny = 0.0 - i as f64 * 20.0;
+                        let left_side_screenx = -overlay_ui.win_w / 2.0 + width / 2.0;
+                        let left_side_screeny = 0.0 - i as f64 * 20.0;
 
                         plane_text
-                            .x_y(next_to_planex, next_to_planey)
-                            .set(overlay_ids.hovering_plane_details[i], overlay_ui);
+                            .x_y(left_side_screenx, left_side_screeny)
+                            .set(overlay_ids.left_screen_details[i], overlay_ui);
                         i += 1;
                     };
 
@@ -554,60 +1317,100 @@ pub fn run_app() {
                     draw_text(format_args!("Airline: {}", airline.to_str()));
                     draw_text(format_args!("Plane Type: {}", plane_type.to_str()));
                     draw_text(format_args!("CallSign: {}", plane.callsign));
+                    draw_text(format_args!(
+                        "ICAO24: {}",
+                        if plane.icao24.is_empty() {
+                            "Unknown"
+                        } else {
+                            &plane.icao24
+                        }
+                    ));
+                    match (&plane.squawk, plane.emergency()) {
+                        (Some(squawk), Some(emergency)) => {
+                            draw_text(format_args!("Squawk: {} ({})", squawk, emergency.to_str()))
+                        }
+                        (Some(squawk), None) => draw_text(format_args!("Squawk: {}", squawk)),
+                        (None, _) => draw_text(format_args!("Squawk: Unknown")),
+                    }
+                    let units = view_state.units;
+                    let unknown = || "Unknown".to_owned();
+                    draw_text(format_args!(
+                        "Altitude: {}",
+                        plane.altitude.map_or_else(unknown, |altitude| {
+                            util::format_altitude(altitude as f64, units)
+                        })
+                    ));
+                    draw_text(format_args!(
+                        "Speed: {}",
+                        plane.velocity.map_or_else(unknown, |velocity| {
+                            util::format_speed(velocity as f64, units)
+                        })
+                    ));
+                    draw_text(format_args!(
+                        "Vertical Rate: {}",
+                        plane.vertical_rate.map_or_else(unknown, |rate| {
+                            util::format_vertical_rate(rate as f64, units)
+                        })
+                    ));
                     draw_text(format_args!("Lat: {}", plane.latitude));
                     draw_text(format_args!("Long: {}", plane.longitude));
                 }
 
-                if show_details {
-                    if let Some(clicked_plane) = &clicked_plane {
-                        //Stores plane airline
-                        let airline = &clicked_plane.plane.airline;
-                        let plane = &clicked_plane.plane;
-                        let plane_type = clicked_plane.plane.plane_type;
-
-                        //Where to draw the detail lines
-                        let detail_lines = 5;
-                        let mut i = 0;
-                        let mut buf: util::StringFormatter<512> = util::StringFormatter::new();
-                        overlay_ids
-                            .left_screen_details
-                            .resize(detail_lines, &mut overlay_ui.widget_id_generator());
-
-                        //Draw text function
-                        let mut draw_text = |args: std::fmt::Arguments<'_>| {
-                            buf.clear();
-                            buf.write_fmt(args).unwrap();
-                            let plane_text = widget::Text::new(buf.as_str())
-                                .color(conrod_core::color::WHITE)
-                                .left_justify()
-                                .font_size(20)
-                                .font_id(b612_overlay);
-
-                            //let left_side_text = widget::Text::new(buf.as_str())
-                            //    .color(conrod_core::color::WHITE)
-                            //    .left_justify()
-                            //    .font_size(20)
-                            //    .font_id(b612_overlay);
-
-                            olds_plane_size = plane_text.get_w(overlay_ui).unwrap();
-                            let width = olds_plane_size;
-
-                            let left_side_screenx = -overlay_ui.win_w / 2.0 + width / 2.0;
-                            let left_side_screeny = 0.0 - i as f64 * 20.0;
-
-                            plane_text
-                                .x_y(left_side_screenx, left_side_screeny)
-                                .set(overlay_ids.left_screen_details[i], overlay_ui);
-                            i += 1;
-                        };
+                if let Some(airport) = clicked_airport.map(|i| &airports[i]) {
+                    let planes = plane_requester.planes_storage();
+                    let nearby_planes = airports::airport_renderer::planes_near(
+                        airport,
+                        &planes,
+                        plane_renderer.include_on_ground,
+                    );
 
-                        //Draw details next to planes
-                        draw_text(format_args!("Airline: {}", airline.to_str()));
-                        draw_text(format_args!("Plane Type: {}", plane_type.to_str()));
-                        draw_text(format_args!("CallSign: {}", plane.callsign));
-                        draw_text(format_args!("Lat: {}", plane.latitude));
-                        draw_text(format_args!("Long: {}", plane.longitude));
-                    }
+                    //Where to draw the detail lines
+                    let detail_lines = 5;
+                    let mut i = 0;
+                    let mut buf: util::StringFormatter<512> = util::StringFormatter::new();
+                    overlay_ids
+                        .airport_details
+                        .resize(detail_lines, &mut overlay_ui.widget_id_generator());
+
+                    //Draw text function, in the same place and style as the clicked plane details
+                    let mut draw_text = |args: std::fmt::Arguments<'_>| {
+                        buf.clear();
+                        let _ = buf.write_fmt(args);
+                        let airport_text = widget::Text::new(buf.as_str())
+                            .color(theme.text)
+                            .left_justify()
+                            .font_size(theme.details_font_size)
+                            .font_id(b612_overlay);
+
+                        let width = airport_text.get_w(overlay_ui).unwrap();
+                        let left_side_screenx = -overlay_ui.win_w / 2.0 + width / 2.0;
+                        let left_side_screeny = 0.0 - i as f64 * 20.0;
+
+                        airport_text
+                            .x_y(left_side_screenx, left_side_screeny)
+                            .set(overlay_ids.airport_details[i], overlay_ui);
+                        i += 1;
+                    };
+
+                    draw_text(format_args!("Airport: {}", airport.name));
+                    draw_text(format_args!("Code: {}", airport.code()));
+                    draw_text(format_args!(
+                        "City: {}, {}",
+                        airport.municipality, airport.country_name
+                    ));
+                    //Airport elevations come in feet
+                    draw_text(format_args!(
+                        "Elevation: {}",
+                        util::format_altitude(airport.elevation as f64 * 0.3048, view_state.units)
+                    ));
+                    draw_text(format_args!(
+                        "Planes within {}: {}",
+                        util::format_distance(
+                            airports::airport_renderer::NEARBY_PLANE_RADIUS_M,
+                            view_state.units
+                        ),
+                        nearby_planes
+                    ));
                 }
 
                 // Time calculations
@@ -625,7 +1428,8 @@ pub fn run_app() {
                 let map_primitives = map_ui.draw();
 
                 let mut target = display.draw();
-                target.clear_color(0.21, 0.32, 0.4, 1.0);
+                let [red, green, blue] = view_state.theme.theme().background;
+                target.clear_color(red, green, blue, 1.0);
 
                 map_renderer.fill(&display, map_primitives, &image_map);
                 map_renderer
@@ -634,18 +1438,43 @@ pub fn run_app() {
 
                 //=========Draw Planes============
 
-                let plane_data = plane_renderer.draw(
+                let planes = plane_requester.planes_storage();
+                let (drawn, clusters) = plane_renderer.layout_planes(
+                    &planes,
+                    &viewer,
+                    DVec2::new(map_ui.win_w, map_ui.win_h),
+                    &airline_filter,
+                    &plane_type_filter,
+                );
+                let scale_factor = display.gl_window().window().scale_factor();
+                let size = plane_size(
+                    viewer.map_zoom() as f32,
+                    plane_renderer.min_plane_size,
+                    plane_renderer.max_plane_size,
+                );
+                selection.update_hover(
+                    &drawn,
+                    &viewer,
+                    last_cursor_pos.map(|pos| pos / scale_factor),
+                    DVec2::new(map_ui.win_w, map_ui.win_h),
+                    size,
+                );
+                plane_renderer.draw(
                     &display,
                     &mut target,
-                    &mut plane_requester,
+                    &drawn,
+                    &plane_requester.trails(),
                     &viewer,
-                    selected_airline,
-                    &mut clicked_plane,
-                    last_cursor_pos,
+                    color_mode,
+                    &selection,
                 );
 
-                loading = !plane_data.planes_loaded;
-                selected_plane = plane_data.plane_selection;
+                planes_loaded = !planes.is_empty();
+                plane_clusters = clusters;
+                //Fully faded planes were already left out of `drawn`, so they don't keep redrawing
+                planes_animating = drawn
+                    .iter()
+                    .any(|plane| plane.emergency().is_some() || plane.missing_since.is_some());
 
                 //=========Draw Overlay===========
 
@@ -655,13 +1484,40 @@ pub fn run_app() {
                     .draw(&display, &mut target, &image_map)
                     .unwrap();
 
-                if loading {
-                    //=========Draw Loading Logo===========
-
-                    loading_renderer.draw(&display, &mut target, frame_time_ms);
+                //=========Draw Loading Logo===========
+                let now = Instant::now();
+                if loading_progress.is_finished(now) {
+                    loading_renderer = None;
+                } else if let Some(loading_renderer) = &mut loading_renderer {
+                    let opacity = loading_progress.opacity(now);
+                    loading_renderer.draw(&display, &mut target, frame_time_ms, opacity);
                 }
 
                 target.finish().unwrap();
+
+                if std::mem::take(&mut screenshot_requested) {
+                    match screenshot::save_screenshot(&display) {
+                        Ok(path) => info!("Saved screenshot to {}", path.display()),
+                        Err(err) => warn!("Failed to save screenshot: {}", err),
+                    }
+                }
+            }
+            //The event loop never returns, so this is the last chance to clean up
+            glium::glutin::event::Event::LoopDestroyed => {
+                shutdown.trigger();
+                //Let the tiles being downloaded finish, so they make it into the disk cache
+                let finished = runtime.block_on(tokio::time::timeout(SHUTDOWN_TIMEOUT, async {
+                    for pipeline in pipelines.values() {
+                        pipeline.finish_requests().await;
+                    }
+                    minimap_pipeline.finish_requests().await;
+                }));
+                if finished.is_err() {
+                    warn!("Gave up waiting for tile requests to finish");
+                }
+                if let Err(err) = view_state.save() {
+                    warn!("Failed to save view state: {}", err);
+                }
             }
             _ => {}
         }
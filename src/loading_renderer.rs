@@ -1,4 +1,5 @@
 use std::io::Cursor;
+use std::time::{Duration, Instant};
 
 use glium::{
     implement_vertex, index::NoIndices, texture::SrgbTexture2d, uniform, DrawParameters, Program,
@@ -54,9 +55,11 @@ impl<'a> LoadingScreenRenderer<'a> {
             out vec4 color;
 
             uniform sampler2D tex;
+            uniform float opacity;
 
             void main() {
                 color = texture(tex, v_tex_coords);
+                color.a *= opacity;
             }
         "#;
 
@@ -95,12 +98,13 @@ impl<'a> LoadingScreenRenderer<'a> {
         }
     }
 
-    /// Draw the rotating logo on the OpenGL Frame that is provided
+    /// Draw the rotating logo on the OpenGL Frame that is provided, faded by `opacity`
     pub fn draw(
         &mut self,
         display: &glium::Display,
         target: &mut glium::Frame,
         frame_time_ms: f64,
+        opacity: f32,
     ) {
         // Here we collect the dynamic numbers for rendering our OpenGL planes
         let (width, height) = target.get_dimensions();
@@ -113,8 +117,8 @@ impl<'a> LoadingScreenRenderer<'a> {
 
         let vertex_buffer = glium::VertexBuffer::new(display, &vertices).unwrap();
 
-        let aspect_ratio = height as f32 / width as f32;
-        let scale_factor = (size_of_logo / height as f32) * dpi_factor;
+        let aspect_ratio = height / width;
+        let scale_factor = (size_of_logo / height) * dpi_factor;
         let matrix: [[f32; 4]; 4] =
             cgmath::Matrix4::from_nonuniform_scale(aspect_ratio * scale_factor, scale_factor, 1.0)
                 .into();
@@ -122,12 +126,13 @@ impl<'a> LoadingScreenRenderer<'a> {
         let uniforms = uniform! {
             matrix: matrix,
             tex: &self.texture,
+            opacity: opacity,
         };
 
         target
             .draw(
                 &vertex_buffer,
-                &self.indices,
+                self.indices,
                 &self.program,
                 &uniforms,
                 &self.draw_parameters,
@@ -143,6 +148,60 @@ impl<'a> LoadingScreenRenderer<'a> {
     }
 }
 
+/// The loading screen is shown for at least this long, so that it doesn't flash on fast caches
+pub const MIN_LOADING_TIME: Duration = Duration::from_secs(1);
+
+/// How long the loading screen takes to fade into the map once everything has loaded
+pub const LOADING_FADE_TIME: Duration = Duration::from_millis(500);
+
+/// The loading screen gives up waiting after this long, so that being offline doesn't hide the
+/// map and its buttons forever
+pub const LOADING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Decides when the loading screen is shown and how far it has faded out
+#[derive(Debug, Clone, Copy)]
+pub struct LoadingProgress {
+    started: Instant,
+    /// When the fade out starts, set once everything has loaded
+    fade_start: Option<Instant>,
+}
+
+impl LoadingProgress {
+    /// Starts showing the loading screen at `now`
+    pub fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            fade_start: None,
+        }
+    }
+
+    /// Records whether the app is `ready` to show the map. Once ready it stays that way, even if
+    /// something is unloaded later. After [`LOADING_TIMEOUT`] the app counts as ready regardless
+    pub fn update(&mut self, now: Instant, ready: bool) {
+        let timed_out = now >= self.started + LOADING_TIMEOUT;
+        if (ready || timed_out) && self.fade_start.is_none() {
+            self.fade_start = Some(now.max(self.started + MIN_LOADING_TIME));
+        }
+    }
+
+    /// Returns how opaque the loading screen is at `now`, from 1.0 while loading to 0.0 once it has
+    /// faded out completely
+    pub fn opacity(&self, now: Instant) -> f32 {
+        match self.fade_start {
+            Some(fade_start) if now > fade_start => {
+                let faded = (now - fade_start).as_secs_f32() / LOADING_FADE_TIME.as_secs_f32();
+                (1.0 - faded).max(0.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Returns true once the loading screen has faded out and the map can be used
+    pub fn is_finished(&self, now: Instant) -> bool {
+        self.opacity(now) <= 0.0
+    }
+}
+
 /// Generates a set of vertices that describe a textured square that must be drawn
 pub fn gen_square(angle: f32) -> [Vertex; 6] {
     let vertex1 = Vertex {
@@ -168,3 +227,37 @@ pub fn gen_square(angle: f32) -> [Vertex; 6] {
 
     [vertex1, vertex2, vertex3, vertex4, vertex3, vertex1]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading_progress() {
+        let start = Instant::now();
+        let mut progress = LoadingProgress::new(start);
+        let later = start + Duration::from_secs(5);
+        assert_eq!(progress.opacity(later), 1.0);
+
+        // Loading quickly still shows the loading screen for the minimum time
+        progress.update(start + Duration::from_millis(100), true);
+        assert_eq!(progress.opacity(start + MIN_LOADING_TIME), 1.0);
+        let halfway = start + MIN_LOADING_TIME + LOADING_FADE_TIME / 2;
+        assert!((progress.opacity(halfway) - 0.5).abs() < 1e-3);
+        assert!(!progress.is_finished(halfway));
+        assert!(progress.is_finished(start + MIN_LOADING_TIME + LOADING_FADE_TIME));
+
+        // Slow loads fade out as soon as they are ready, and stay ready
+        let mut progress = LoadingProgress::new(start);
+        progress.update(later, true);
+        progress.update(later + LOADING_FADE_TIME, false);
+        assert!(progress.is_finished(later + LOADING_FADE_TIME));
+
+        // Nothing loading at all, like when offline, still ends the loading screen eventually
+        let mut progress = LoadingProgress::new(start);
+        progress.update(start + LOADING_TIMEOUT / 2, false);
+        assert!(!progress.is_finished(start + LOADING_TIMEOUT));
+        progress.update(start + LOADING_TIMEOUT, false);
+        assert!(progress.is_finished(start + LOADING_TIMEOUT + LOADING_FADE_TIME));
+    }
+}
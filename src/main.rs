@@ -1,3 +1,32 @@
+use flight_tracking_erau_se300::{
+    parse_plane_source, run_app, run_prefetch, AppConfig, PrefetchArgs, APP_USAGE, PREFETCH_USAGE,
+};
+
 fn main() {
-    flight_tracking_erau_se300::run_app();
+    //Only warnings by default, and what the app itself has to say. RUST_LOG overrides this
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("warn,flight_tracking_erau_se300=info"),
+    )
+    .init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("prefetch") => match PrefetchArgs::parse(&args[1..]) {
+            Ok(prefetch) => run_prefetch(prefetch, AppConfig::from_env()),
+            Err(err) => {
+                eprintln!("{}\n{}", err, PREFETCH_USAGE);
+                std::process::exit(2);
+            }
+        },
+        _ => match parse_plane_source(&args) {
+            Ok(plane_source) => run_app(AppConfig {
+                plane_source,
+                ..AppConfig::from_env()
+            }),
+            Err(err) => {
+                eprintln!("{}\n{}", err, APP_USAGE);
+                std::process::exit(2);
+            }
+        },
+    }
 }
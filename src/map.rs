@@ -32,6 +32,17 @@ pub struct WorldViewport {
     pub bottom_right: DVec2,
 }
 
+impl WorldViewport {
+    /// Moves `world_x` by whole worlds so that it is the copy closest to the middle of the
+    /// viewport. The world repeats horizontally, so this is where a location in the normal 0..1
+    /// range is drawn when the viewport crosses the antimeridian
+    pub fn wrap_x(&self, world_x: f64) -> f64 {
+        let center_x = (self.top_left.x + self.bottom_right.x) / 2.0;
+        world_x + (center_x - world_x).round()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
 pub struct TileView {
     /// The center of the view [0..1] for both x and y
     ///
@@ -47,16 +58,99 @@ pub struct TileView {
     /// the window can be resized and the center will stay in the center, and the zoom level will
     /// remain the same
     pixel_size: f64,
+
+    /// The zoom animation currently in progress, if any
+    zoom_animation: Option<ZoomAnimation>,
+
+    /// Which way is up on screen, in degrees clockwise from north.
+    ///
+    /// The map is always drawn north-up for now, but the viewport already grows to cover a
+    /// rotated screen so tiles can be rotated into place later
+    bearing: f64,
+
+    /// The smallest `pixel_size` the camera can zoom in to, see [`TileView::with_zoom_limits`]
+    min_pixel_size: f64,
+
+    /// The largest `pixel_size` the camera can zoom out to, see [`TileView::with_zoom_limits`]
+    max_pixel_size: f64,
+}
+
+/// The default of the largest pixel size the camera can zoom out to, which keeps the whole world
+/// at least 100 pixels across
+pub const DEFAULT_MAX_PIXEL_SIZE: f64 = 1.0 / 100.0;
+
+/// The default of the smallest pixel size the camera can zoom in to, where a pixel is a little over
+/// 2^-28 of the world across. On a normal screen this is about zoom level 20, the most detailed
+/// tiles most providers have
+pub const DEFAULT_MIN_PIXEL_SIZE: f64 = 1.064 / (1u64 << 28) as f64;
+
+/// How long it takes for a zoom started by [`TileView::zoom_around_point`] to finish
+const ZOOM_ANIMATION_MS: f64 = 150.0;
+
+/// How quickly the camera catches up with a followed plane. After this many milliseconds it has
+/// covered about two thirds of the distance
+const FOLLOW_EASE_MS: f64 = 300.0;
+
+/// Frames longer than this are eased as if they took this long, so that the first frame after the
+/// app was idle glides to the plane instead of snapping to it
+const MAX_FOLLOW_FRAME_MS: f64 = 50.0;
+
+/// An in progress zoom that eases `pixel_size` towards a target over a few frames, while keeping
+/// a single world location under the same pixel on screen
+#[derive(Clone, Copy, Debug)]
+struct ZoomAnimation {
+    /// The pixel size when the animation started
+    start_pixel_size: f64,
+
+    /// The pixel size once the animation finishes
+    target_pixel_size: f64,
+
+    /// The world location that must stay under the cursor
+    anchor_world: DVec2,
+
+    /// The offset in pixels from the center of the screen to the cursor
+    anchor_offset: DVec2,
+
+    /// How far along the animation is, in milliseconds
+    elapsed_ms: f64,
 }
 
 impl TileView {
     pub fn new(latitude: f64, longitude: f64, zoom: f64, window_width: f64) -> Self {
         let x = crate::util::x_from_longitude(longitude);
         let y = crate::util::y_from_latitude(latitude);
-        Self {
+        let mut view = Self {
             center: DVec2::new(x, y),
             pixel_size: pixel_size_from_zoom(zoom, window_width),
-        }
+            zoom_animation: None,
+            bearing: 0.0,
+            min_pixel_size: DEFAULT_MIN_PIXEL_SIZE,
+            max_pixel_size: DEFAULT_MAX_PIXEL_SIZE,
+        };
+        view.clamp_center();
+        view
+    }
+
+    /// Sets how far the camera can zoom, as the sizes of a pixel in world units, where 1.0 is the
+    /// whole world. Zooms that would go past either limit are ignored, and a view that is already
+    /// past one is moved back inside.
+    ///
+    /// Lower the minimum for providers with tiles past zoom level 20, and raise the maximum to
+    /// let very large displays zoom out further. The defaults are [`DEFAULT_MIN_PIXEL_SIZE`] and
+    /// [`DEFAULT_MAX_PIXEL_SIZE`].
+    ///
+    /// Panics if `min_pixel_size` isn't less than `max_pixel_size`
+    pub fn with_zoom_limits(mut self, min_pixel_size: f64, max_pixel_size: f64) -> Self {
+        assert!(
+            min_pixel_size < max_pixel_size,
+            "Minimum pixel size {} must be less than the maximum {}",
+            min_pixel_size,
+            max_pixel_size
+        );
+        self.min_pixel_size = min_pixel_size;
+        self.max_pixel_size = max_pixel_size;
+        self.pixel_size = self.pixel_size.clamp(min_pixel_size, max_pixel_size);
+        self
     }
 
     /// Returns what zoom is visible based on the size of a tile.
@@ -81,6 +175,27 @@ impl TileView {
             .expect("Zoom level too large for i64")
     }
 
+    /// Returns the zoom level of the tiles that [`TileView::tile_iter`] produces for `tile_size`
+    /// and `max_zoom`
+    pub fn capped_tile_zoom_level(
+        &self,
+        tile_size: u32,
+        max_zoom: Option<TileZoomLevel>,
+    ) -> TileZoomLevel {
+        //Tile zoom maxes out at 20, or earlier if the provider has a lower limit
+        let max_zoom = max_zoom.map_or(MAX_ZOOM_LEVEL, |max| max.min(MAX_ZOOM_LEVEL));
+        self.tile_zoom_level(tile_size).min(max_zoom)
+    }
+
+    /// Returns the tile at `zoom` that the center of the screen is on
+    pub fn center_tile(&self, zoom: TileZoomLevel) -> crate::TileId {
+        let tiles_across = 2u32.pow(zoom);
+        let n = tiles_across as f64;
+        let x = (self.center.x.rem_euclid(1.0) * n) as u32;
+        let y = (self.center.y * n).clamp(0.0, n - 1.0) as u32;
+        crate::TileId::new(x.min(tiles_across - 1), y, zoom)
+    }
+
     /// Sets the `zoom` for the entire tile viewport based on the current `window_width`.
     /// The value returned by [`tile_zoom_level`] will always at least as big as `zoom` for a
     /// window larger then the tile size, because more tiles are needed to span the entire window
@@ -89,20 +204,92 @@ impl TileView {
         let new_pixel_size = pixel_size_from_zoom(zoom, window_width);
         self.pixel_size = new_pixel_size;
     }
+
+    /// Returns which way is up on screen, in degrees clockwise from north
+    pub fn bearing(&self) -> f64 {
+        self.bearing
+    }
+
+    /// Sets which way is up on screen, in degrees clockwise from north
+    pub fn set_bearing(&mut self, bearing: f64) {
+        self.bearing = bearing.rem_euclid(360.0);
+    }
+
     ///Returns the zoom level of the current tile positioned.
     pub fn get_zoom(&self) -> f64 {
         zoom_from_pixel_size(self.pixel_size)
     }
+
+    /// Returns the zoom level of the map in the units tile providers use, where the whole world
+    /// fits in a single 256 pixel tile at 0, a continent fills the screen around 3 and streets can
+    /// be read around 15. Unlike [`TileView::get_zoom`] this doesn't change with the window size,
+    /// and it is fractional, unlike [`TileView::tile_zoom_level`]
+    pub fn map_zoom(&self) -> f64 {
+        zoom_from_pixel_size(self.pixel_size * 256.0)
+    }
     pub fn multiply_zoom(&mut self, multiplier: f64) {
+        //Snap to the new zoom, so any animation in progress would fight with us
+        self.zoom_animation = None;
+
         let new_pixel_size = self.pixel_size * multiplier;
-        //Make sure the entire world cannot be smaller that 100 pixels across
-        if new_pixel_size < (1.0 / 100.0) {
-            //Prevent the user from scrolling to where tile coordinates are less that 2^-28
-            //On a normal screen this means you can zoom into about zoom level 20
-            let min_size: f64 = 1.064 / 2.0f64.powi(28);
-            if new_pixel_size > min_size {
-                self.pixel_size = new_pixel_size;
-            }
+        if self.is_valid_pixel_size(new_pixel_size) {
+            self.pixel_size = new_pixel_size;
+        }
+    }
+
+    /// Starts an animated zoom by `multiplier` that keeps the world location under `cursor_px`
+    /// fixed on screen.
+    ///
+    /// `cursor_px` is in window pixels with (0, 0) at the top left, and `screen_size` is the size
+    /// of the window in the same units. Calling this again while a zoom is still animating
+    /// compounds the two zooms. [`TileView::animate`] must be called each frame to make progress
+    pub fn zoom_around_point(&mut self, multiplier: f64, cursor_px: DVec2, screen_size: DVec2) {
+        let current_target = match &self.zoom_animation {
+            Some(animation) => animation.target_pixel_size,
+            None => self.pixel_size,
+        };
+        let target_pixel_size = current_target * multiplier;
+        if !self.is_valid_pixel_size(target_pixel_size) {
+            return;
+        }
+
+        let anchor_offset = cursor_px - screen_size / 2.0;
+        self.zoom_animation = Some(ZoomAnimation {
+            start_pixel_size: self.pixel_size,
+            target_pixel_size,
+            anchor_world: self.center + anchor_offset * self.pixel_size,
+            anchor_offset,
+            elapsed_ms: 0.0,
+        });
+    }
+
+    /// Returns true while a zoom animation is playing
+    pub fn is_animating(&self) -> bool {
+        self.zoom_animation.is_some()
+    }
+
+    /// Advances the zoom animation started by [`TileView::zoom_around_point`] by `frame_time_ms`.
+    ///
+    /// Does nothing if no zoom is in progress
+    pub fn animate(&mut self, frame_time_ms: f64) {
+        let animation = match &mut self.zoom_animation {
+            Some(animation) => animation,
+            None => return,
+        };
+        animation.elapsed_ms += frame_time_ms;
+        let t = (animation.elapsed_ms / ZOOM_ANIMATION_MS).min(1.0);
+
+        //Interpolate exponentially so each frame zooms by the same perceived amount
+        let eased = crate::util::ease_out_cubic(t);
+        let ratio = animation.target_pixel_size / animation.start_pixel_size;
+        self.pixel_size = animation.start_pixel_size * ratio.powf(eased);
+
+        //Move the center so that the anchor stays under the cursor at the new zoom
+        self.center = animation.anchor_world - animation.anchor_offset * self.pixel_size;
+        self.clamp_center();
+
+        if t >= 1.0 {
+            self.zoom_animation = None;
         }
     }
 
@@ -112,6 +299,69 @@ impl TileView {
     /// Visually this will move the camera the same amount regardless of the zoom.
     pub fn move_camera_pixels(&mut self, direction: DVec2) {
         self.center += direction * self.pixel_size;
+        if let Some(animation) = &mut self.zoom_animation {
+            //Drag the anchor along too, otherwise the next frame of the animation snaps back
+            animation.anchor_world += direction * self.pixel_size;
+        }
+        self.clamp_center();
+    }
+
+    /// Moves the camera so that the world position `world` is in the center of the screen, keeping
+    /// the zoom. The copy of `world` closest to the current center is used, so the camera doesn't
+    /// jump to another copy of the world
+    pub fn center_on(&mut self, world: DVec2) {
+        //Finish any zoom in progress straight away, otherwise it would drag the camera back to
+        //where the animation started
+        if let Some(animation) = self.zoom_animation.take() {
+            self.pixel_size = animation.target_pixel_size;
+        }
+        self.center = DVec2::new(world.x + (self.center.x - world.x).round(), world.y);
+        self.clamp_center();
+    }
+
+    /// Moves the camera so that (`latitude`, `longitude`) in degrees is in the center of the
+    /// screen straight away, keeping the zoom
+    pub fn set_center_lat_long(&mut self, latitude: f64, longitude: f64) {
+        self.center_on(DVec2::new(
+            crate::util::x_from_longitude(longitude),
+            crate::util::y_from_latitude(latitude),
+        ));
+    }
+
+    /// Moves the camera part of the way towards having (`latitude`, `longitude`) in degrees in the
+    /// center of the screen, so that calling this every frame glides it there. Unlike
+    /// [`TileView::set_center_lat_long`], zooms in progress carry on.
+    ///
+    /// Returns true while the camera is still more than a pixel away
+    pub fn ease_toward_lat_long(
+        &mut self,
+        latitude: f64,
+        longitude: f64,
+        frame_time_ms: f64,
+    ) -> bool {
+        let x = crate::util::x_from_longitude(longitude);
+        let target = DVec2::new(
+            x + (self.center.x - x).round(),
+            crate::util::y_from_latitude(latitude),
+        );
+        let remaining_pixels = (target - self.center) / self.pixel_size;
+        if remaining_pixels.length() <= 1.0 {
+            self.move_camera_pixels(remaining_pixels);
+            return false;
+        }
+        let frame_time_ms = frame_time_ms.min(MAX_FOLLOW_FRAME_MS);
+        let t = 1.0 - (-frame_time_ms / FOLLOW_EASE_MS).exp();
+        self.move_camera_pixels(remaining_pixels * t);
+        true
+    }
+
+    /// Keeps `center.y` inside the mercator square so the camera can't be moved past the poles.
+    ///
+    /// World y values of 0 and 1 correspond to roughly ±85.05° latitude, past which the mercator
+    /// projection is undefined. `center.x` is left alone so that horizontal wraparound still
+    /// works through `rem_euclid`
+    fn clamp_center(&mut self) {
+        self.center.y = self.center.y.clamp(0.0, 1.0);
     }
 
     pub fn get_world_viewport(&self, screen_width: f64, screen_height: f64) -> WorldViewport {
@@ -121,8 +371,16 @@ impl TileView {
             screen_height * self.pixel_size,
         ) / 2.0;
 
+        //When the map is rotated the screen covers a rotated rectangle of the world, so grow the
+        //viewport to the box around it
+        let (sin, cos) = self.bearing.to_radians().sin_cos();
+        let (sin, cos) = (sin.abs(), cos.abs());
+        let adjusted_half_screen_size = DVec2::new(
+            half_screen_size.x * cos + half_screen_size.y * sin,
+            half_screen_size.x * sin + half_screen_size.y * cos,
+        );
+
         //Calculate where the top left and bottom right of our viewport is world coordinates
-        let adjusted_half_screen_size = DVec2::new(half_screen_size.x, half_screen_size.y);
         let top_left = self.center - adjusted_half_screen_size;
         let bottom_right = self.center + adjusted_half_screen_size;
         WorldViewport {
@@ -131,15 +389,58 @@ impl TileView {
         }
     }
 
+    /// Converts a pixel on screen to the (latitude, longitude) in degrees that is drawn there.
+    ///
+    /// `pixel` is measured from the top left of the screen with y pointing down, in the same units
+    /// as `screen_width` and `screen_height`. Longitudes are wrapped into -180..180 when the camera
+    /// is looking at another copy of the world
+    pub fn screen_to_lat_long(
+        &self,
+        pixel: DVec2,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> (f64, f64) {
+        let screen_size = DVec2::new(screen_width, screen_height);
+        let world = self.center + (pixel - screen_size / 2.0) * self.pixel_size;
+
+        let latitude = crate::util::latitude_from_y(world.y);
+        let longitude = crate::util::longitude_from_x(world.x.rem_euclid(1.0));
+        (latitude, longitude)
+    }
+
+    /// Converts a latitude and longitude in degrees to the pixel on screen where they are drawn.
+    ///
+    /// The inverse of [`TileView::screen_to_lat_long`]. The world repeats horizontally, so the
+    /// copy of the location closest to the center of the screen is used
+    pub fn lat_long_to_screen(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> DVec2 {
+        let mut world = DVec2::new(
+            crate::util::x_from_longitude(longitude),
+            crate::util::y_from_latitude(latitude),
+        );
+        world.x += (self.center.x - world.x).round();
+
+        let screen_size = DVec2::new(screen_width, screen_height);
+        (world - self.center) / self.pixel_size + screen_size / 2.0
+    }
+
+    /// Returns an iterator over the tiles needed to cover the screen.
+    ///
+    /// `max_zoom` caps the zoom level of the tiles for providers that don't have imagery all the
+    /// way down to [`MAX_ZOOM_LEVEL`]. Past that point the tiles are simply drawn larger
     pub fn tile_iter(
         &self,
         tile_size: u32,
+        max_zoom: Option<TileZoomLevel>,
         screen_width: f64,
         screen_height: f64,
     ) -> TileViewIterator {
-        //Tile zoom maxes out at 20.
-        //TODO: Make this configurable in case tile providers have different maxes
-        let tile_zoom = self.tile_zoom_level(tile_size).min(MAX_ZOOM_LEVEL);
+        let tile_zoom = self.capped_tile_zoom_level(tile_size, max_zoom);
         let max_tile = 2u32.pow(tile_zoom) as f64;
 
         //Tile size is the size of a tile in pixels based on the current zoom level
@@ -201,6 +502,24 @@ impl TileView {
             tiles_vertically: tiles_high,
         }
     }
+
+    /// Returns how many tiles [`TileView::tile_iter`] would return for the same arguments, without
+    /// walking through them
+    pub fn visible_tile_count(
+        &self,
+        tile_size: u32,
+        max_zoom: Option<TileZoomLevel>,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> usize {
+        self.tile_iter(tile_size, max_zoom, screen_width, screen_height)
+            .len()
+    }
+
+    /// Returns true if the camera is allowed to zoom to `pixel_size`
+    fn is_valid_pixel_size(&self, pixel_size: f64) -> bool {
+        pixel_size < self.max_pixel_size && pixel_size > self.min_pixel_size
+    }
 }
 
 /// Converts a zoom level and the current window size to a `pixel_size` value.
@@ -217,6 +536,26 @@ fn zoom_from_pixel_size(pixel_size: f64) -> f64 {
     f64::log2(1.0 / pixel_size)
 }
 
+/// Scores how urgently `tile` should be loaded while `viewport` is on screen. Higher scores are
+/// loaded first.
+///
+/// The score is the distance from the center of the tile to the center of the viewport, negated
+/// and measured in half diagonals of the viewport. A tile under the center of the screen scores
+/// close to 0, tiles in the corners close to -1, and tiles further away lower still, so the middle
+/// of the screen fills in before the edges. Horizontal distance is measured to the nearest copy of
+/// the tile, since the world wraps around
+pub fn tile_heuristic(tile: crate::TileId, viewport: &WorldViewport) -> f64 {
+    let tile_length = 1.0 / 2u32.pow(tile.zoom) as f64;
+    let tile_center = DVec2::new(tile.x as f64 + 0.5, tile.y as f64 + 0.5) * tile_length;
+
+    let center = (viewport.top_left + viewport.bottom_right) / 2.0;
+    let half_diagonal = (viewport.bottom_right - viewport.top_left).length() / 2.0;
+
+    let mut offset = tile_center - center;
+    offset.x -= offset.x.round();
+    -offset.length() / half_diagonal
+}
+
 /// Walks the positions of all the tiles currently in view, returning their coordinates for
 /// rendering
 #[derive(Clone, Debug)]
@@ -250,6 +589,38 @@ impl Iterator for TileViewIterator {
             None => None,
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.product.size_hint()
+    }
+}
+
+impl ExactSizeIterator for TileViewIterator {}
+
+impl TileViewIterator {
+    /// Pairs each tile with where its center goes on a `screen_width` by `screen_height` screen,
+    /// in pixels from the center of the screen with y pointing up like conrod.
+    ///
+    /// The product walks the tiles a column at a time from the top, so the `i`th tile is in column
+    /// `i / tiles_vertically` and row `i % tiles_vertically` of the grid
+    pub fn with_positions(
+        self,
+        screen_width: f64,
+        screen_height: f64,
+    ) -> impl Iterator<Item = (TileCoordinate, DVec2)> {
+        let offset = self.tile_offset;
+        let size = self.tile_size;
+        let tiles_vertically = self.tiles_vertically as usize;
+        let half_width = screen_width / 2.0;
+        let half_height = screen_height / 2.0;
+        self.enumerate().map(move |(i, tile)| {
+            let column = (i / tiles_vertically) as f64;
+            let row = (i % tiles_vertically) as f64;
+            let x = offset.x + column * size.x - half_width + size.x / 2.0;
+            let y = offset.y - row * size.y + half_height + size.y / 2.0;
+            (tile, DVec2::new(x, y))
+        })
+    }
 }
 
 #[cfg(test)]
@@ -268,9 +639,9 @@ mod tests {
     }
 
     fn are_tiles_visible(data: IsSameTiles) {
-        let real_iter = data
-            .view
-            .tile_iter(data.tile_size, data.screen_width, data.screen_height);
+        let real_iter =
+            data.view
+                .tile_iter(data.tile_size, None, data.screen_width, data.screen_height);
 
         let real: Vec<TileCoordinate> = real_iter.collect();
         let max_tile = 2u32.pow(data.view.tile_zoom_level(data.tile_size));
@@ -385,6 +756,297 @@ mod tests {
         });
     }
 
+    /// Returns the columns of tiles `tiles` covers in the order they are walked, checking that
+    /// every column has the same rows and that no tile is repeated
+    fn tile_columns(tiles: &[TileCoordinate]) -> Vec<u32> {
+        let mut columns: Vec<u32> = tiles.iter().map(|(x, _)| *x).collect();
+        columns.dedup();
+        let rows = |column: u32| -> Vec<u32> {
+            tiles
+                .iter()
+                .filter(|(x, _)| *x == column)
+                .map(|(_, y)| *y)
+                .collect()
+        };
+        for column in &columns {
+            assert_eq!(rows(*column), rows(columns[0]), "column {}", column);
+        }
+        let mut unique = tiles.to_vec();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), tiles.len(), "repeated tiles in {:?}", tiles);
+        columns
+    }
+
+    #[test]
+    fn tile_view_it_wraps() {
+        //Past the last column of zoom level 2 the iterator starts over at the first
+        let it = TileViewIterator {
+            product: (3..6).cartesian_product(1..3),
+            max_tile: 4,
+            tile_offset: DVec2::new(0.0, 0.0),
+            tile_size: DVec2::new(0.0, 0.0),
+            tiles_horizontally: 3,
+            tiles_vertically: 2,
+            tile_zoom: 2,
+        };
+        assert_eq!(it.len(), 6);
+        let tiles: Vec<TileCoordinate> = it.collect();
+        assert_eq!(tiles, [(3, 1), (3, 2), (0, 1), (0, 2), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn tiles_wrap_across_antimeridian() {
+        let screen_width = 500.0;
+        let screen_height = 500.0;
+
+        //Looking at the antimeridian, half of the world is visible: the last column of tiles on the
+        //left and the first on the right, plus one for the partially visible tile at the edge
+        let cases = [
+            (180.0, [3, 0, 1]),
+            (-180.0, [3, 0, 1]),
+            //Just west of it the partial tile is on the left instead
+            (179.99, [2, 3, 0]),
+            (-179.99, [3, 0, 1]),
+        ];
+        for (longitude, expected) in cases {
+            let view = TileView::new(0.0, longitude, 1.0, screen_width);
+            let it = view.tile_iter(256, None, screen_width, screen_height);
+            assert_eq!(it.tile_zoom, 2);
+            let tiles: Vec<TileCoordinate> = it.collect();
+            assert_eq!(tile_columns(&tiles), expected, "longitude {}", longitude);
+            assert!(tiles.iter().all(|(x, y)| *x < 4 && *y < 4));
+        }
+
+        //Panning east past the antimeridian keeps counting up from the first column
+        let mut view = TileView::new(0.0, 90.0, 1.0, screen_width);
+        let mut columns = Vec::new();
+        for _ in 0..4 {
+            let tiles: Vec<TileCoordinate> = view
+                .tile_iter(256, None, screen_width, screen_height)
+                .collect();
+            columns.push(tile_columns(&tiles)[0]);
+            //A quarter of the world, one tile at zoom level 2
+            view.move_camera_pixels(DVec2::new(250.0, 0.0));
+        }
+        assert_eq!(columns, [2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn tiles_are_positioned_under_their_part_of_the_world() {
+        let screen_width = 1000.0;
+        let screen_height = 600.0;
+
+        //Over Florida, and straddling the antimeridian so some columns wrap
+        for (latitude, longitude, zoom) in [(29.2, -81.0, 5.3), (-10.0, 179.0, 4.7)] {
+            let view = TileView::new(latitude, longitude, zoom, screen_width);
+            let viewport = view.get_world_viewport(screen_width, screen_height);
+            let it = view.tile_iter(256, None, screen_width, screen_height);
+            let tiles_across = 2u32.pow(it.tile_zoom) as f64;
+            let size = it.tile_size;
+
+            for ((x, y), position) in it.with_positions(screen_width, screen_height) {
+                let center_x = viewport.wrap_x((x as f64 + 0.5) / tiles_across);
+                let center_y = (y as f64 + 0.5) / tiles_across;
+                let expected = DVec2::new(
+                    crate::world_x_to_pixel_x(center_x, &viewport, screen_width),
+                    crate::world_y_to_pixel_y(center_y, &viewport, screen_height),
+                );
+                assert!(
+                    (position - expected).length() < 1e-6,
+                    "tile {:?} at {} instead of {}",
+                    (x, y),
+                    position,
+                    expected
+                );
+            }
+
+            //The tiles together cover the whole screen
+            let positions: Vec<DVec2> = view
+                .tile_iter(256, None, screen_width, screen_height)
+                .with_positions(screen_width, screen_height)
+                .map(|(_, position)| position)
+                .collect();
+            let min = positions
+                .iter()
+                .fold(DVec2::splat(f64::MAX), |min, p| min.min(*p))
+                - size / 2.0;
+            let max = positions
+                .iter()
+                .fold(DVec2::splat(f64::MIN), |max, p| max.max(*p))
+                + size / 2.0;
+            assert!(min.x <= -screen_width / 2.0 && min.y <= -screen_height / 2.0);
+            assert!(max.x >= screen_width / 2.0 && max.y >= screen_height / 2.0);
+        }
+    }
+
+    #[test]
+    fn move_camera_clamps_latitude() {
+        let screen_width = 1000.0;
+        let mut view = TileView::new(0.0, 0.0, 4.0, screen_width);
+
+        //Pan way past the north pole
+        view.move_camera_pixels(DVec2::new(0.0, -1_000_000.0));
+        assert!(view.center.y >= 0.0 && view.center.y <= 1.0);
+        assert_eq!(view.center.y, 0.0);
+
+        //Then way past the south pole
+        view.move_camera_pixels(DVec2::new(0.0, 1_000_000_000.0));
+        assert!(view.center.y >= 0.0 && view.center.y <= 1.0);
+        assert_eq!(view.center.y, 1.0);
+
+        //Horizontal movement is still unbounded so that the world wraps around
+        view.move_camera_pixels(DVec2::new(1_000_000_000.0, 0.0));
+        assert!(view.center.x > 1.0);
+    }
+
+    #[test]
+    fn tile_iter_max_zoom() {
+        let screen_width = 1000.0;
+        let screen_height = 500.0;
+        let view = TileView::new(29.18796, -81.04923, 14.0, screen_width);
+        assert!(view.tile_zoom_level(256) > 10);
+
+        let it = view.tile_iter(256, Some(10), screen_width, screen_height);
+        assert_eq!(it.tile_zoom, 10);
+        //Zoomed past the cap, so each tile covers more than its native size on screen
+        assert!(it.tile_size.x > 256.0);
+        for (x, y) in it {
+            assert!(x < 2u32.pow(10) && y < 2u32.pow(10));
+        }
+
+        let it = view.tile_iter(256, None, screen_width, screen_height);
+        assert_eq!(it.tile_zoom, view.tile_zoom_level(256));
+    }
+
+    #[test]
+    fn visible_tile_count_matches_iteration() {
+        let screen_width = 1280.0;
+        let screen_height = 720.0;
+        for (zoom, max_zoom) in [(0.0, None), (8.0, None), (14.0, Some(10)), (3.5, None)] {
+            let view = TileView::new(29.18796, -81.04923, zoom, screen_width);
+            for tile_size in [128, 256, 512] {
+                let count =
+                    view.visible_tile_count(tile_size, max_zoom, screen_width, screen_height);
+                let it = view.tile_iter(tile_size, max_zoom, screen_width, screen_height);
+                assert_eq!(
+                    count,
+                    (it.tiles_horizontally * it.tiles_vertically) as usize
+                );
+                assert_eq!(count, it.count());
+            }
+        }
+
+        //The length counts down as tiles are taken
+        let view = TileView::new(0.0, 0.0, 2.0, screen_width);
+        let mut it = view.tile_iter(256, None, screen_width, screen_height);
+        let total = it.len();
+        it.next();
+        assert_eq!(it.len(), total - 1);
+    }
+
+    #[test]
+    fn tile_iter_uses_own_tile_size() {
+        //Satellite tiles are 128px and weather tiles are 512px. Each pipeline has to iterate with
+        //its own size or its tiles end up at the wrong zoom level
+        let screen_width = 1000.0;
+        let screen_height = 500.0;
+        let view = TileView::new(29.18796, -81.04923, 8.0, screen_width);
+
+        let small = view.tile_iter(128, None, screen_width, screen_height);
+        let large = view.tile_iter(512, None, screen_width, screen_height);
+        assert_eq!(small.tile_zoom, large.tile_zoom + 2);
+        assert!((large.tile_size.x - small.tile_size.x * 4.0).abs() < 1e-6);
+
+        //Either way tiles are drawn close to their native resolution
+        for (it, size) in [(small, 128.0), (large, 512.0)] {
+            assert!(it.tile_size.x >= size / 2.0 && it.tile_size.x <= size * 2.0);
+        }
+    }
+
+    #[test]
+    fn screen_lat_long_round_trip() {
+        let (width, height) = (1000.0, 500.0);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+
+        let view = TileView::new(29.18796, -81.04923, 8.0, width);
+        let (lat, long) = view.screen_to_lat_long(DVec2::new(width, height) / 2.0, width, height);
+        assert!(close(lat, 29.18796) && close(long, -81.04923));
+
+        for pixel in [
+            DVec2::new(0.0, 0.0),
+            DVec2::new(250.0, 400.0),
+            DVec2::new(999.0, 10.0),
+        ] {
+            let (lat, long) = view.screen_to_lat_long(pixel, width, height);
+            let back = view.lat_long_to_screen(lat, long, width, height);
+            assert!(close(back.x, pixel.x) && close(back.y, pixel.y));
+        }
+
+        //Looking across the anti meridian, from a different copy of the world
+        let mut view = TileView::new(0.0, 179.0, 6.0, width);
+        view.move_camera_pixels(DVec2::new(1.0 / view.pixel_size, 0.0));
+        let (_, long) = view.screen_to_lat_long(DVec2::new(900.0, 250.0), width, height);
+        assert!((-180.0..180.0).contains(&long));
+        let back = view.lat_long_to_screen(0.0, long, width, height);
+        assert!(close(back.x, 900.0));
+    }
+
+    #[test]
+    fn following_eases_to_the_plane() {
+        let (width, height) = (1000.0, 500.0);
+        let center = |view: &TileView| {
+            view.screen_to_lat_long(DVec2::new(width, height) / 2.0, width, height)
+        };
+        let close = |(lat, long): (f64, f64), (lat2, long2): (f64, f64)| {
+            (lat - lat2).abs() < 1e-3 && (long - long2).abs() < 1e-3
+        };
+
+        let mut view = TileView::new(29.18796, -81.04923, 14.0, width);
+        view.set_center_lat_long(28.43, -81.31);
+        assert!(close(center(&view), (28.43, -81.31)));
+
+        //One frame only goes part of the way, and a long frame no further than a short one
+        let mut long_frame = view;
+        assert!(view.ease_toward_lat_long(29.18796, -81.04923, 16.0));
+        assert!(long_frame.ease_toward_lat_long(29.18796, -81.04923, 5000.0));
+        let (lat, _) = center(&view);
+        let (long_frame_lat, _) = center(&long_frame);
+        assert!(lat > 28.43 && lat < 29.18796);
+        assert!(long_frame_lat < 29.18796);
+
+        let mut frames = 0;
+        while view.ease_toward_lat_long(29.18796, -81.04923, 16.0) {
+            frames += 1;
+            assert!(frames < 1000, "Never reached the plane");
+        }
+        assert!(close(center(&view), (29.18796, -81.04923)));
+    }
+
+    #[test]
+    fn zoom_around_point_keeps_cursor_fixed() {
+        let screen_size = DVec2::new(1000.0, 500.0);
+        let cursor = DVec2::new(800.0, 100.0);
+        let mut view = TileView::new(29.18796, -81.04923, 6.0, screen_size.x);
+
+        let world_under_cursor =
+            |view: &TileView| view.center + (cursor - screen_size / 2.0) * view.pixel_size;
+        let before = world_under_cursor(&view);
+        let start_pixel_size = view.pixel_size;
+
+        view.zoom_around_point(0.5, cursor, screen_size);
+        //Nothing happens until the animation is stepped
+        assert_eq!(view.pixel_size, start_pixel_size);
+
+        for _ in 0..100 {
+            view.animate(16.0);
+            let during = world_under_cursor(&view);
+            assert!((during - before).length() < 1e-12);
+        }
+        assert!(view.zoom_animation.is_none());
+        assert!((view.pixel_size - start_pixel_size * 0.5).abs() < 1e-15);
+    }
+
     #[test]
     fn tile_view_high_res() {
         let window_width = 1000.0;
@@ -398,7 +1060,171 @@ mod tests {
             //Make sure that we always render more tile pixels wide than window width
             //This makes sure that each final screen pixel is not interpolated because
             //there are too few tile pixels
-            assert!(window_width as f64 <= pixels_across);
+            assert!(window_width <= pixels_across);
+        }
+    }
+
+    #[test]
+    fn tile_view_high_dpi() {
+        //On a 2x screen tiles are laid out at half their size, so that there is a tile pixel for
+        //every physical pixel rather than every logical one
+        let window_width = 1000.0;
+        let tile_width = 256;
+        let density = 2;
+        let mut view = TileView::new(0.0, 0.0, 0.0, window_width);
+        for i in 0..10000 {
+            let zoom = i as f64 / 555.5;
+            view.set_zoom(zoom, window_width);
+            let tile_zoom_level = view.tile_zoom_level(tile_width / density);
+            let pixels_across = tile_width as f64 * 2.0f64.powi(tile_zoom_level as i32);
+            assert!(window_width * density as f64 <= pixels_across);
+            //One level more than at 1x, unless both are clamped to the whole world
+            let low_dpi_level = view.tile_zoom_level(tile_width);
+            assert!(tile_zoom_level == low_dpi_level + 1 || tile_zoom_level == 0);
+        }
+    }
+
+    #[test]
+    fn zoom_stops_at_limits() {
+        let zoom_all_the_way = |view: &mut TileView, multiplier: f64| {
+            for _ in 0..200 {
+                view.multiply_zoom(multiplier);
+            }
+            view.pixel_size
+        };
+
+        //By default the whole world stays at least 100 pixels across, and zooming in stops
+        //around zoom level 20
+        let mut view = TileView::new(29.0, -81.0, 8.0, 1000.0);
+        let zoomed_out = zoom_all_the_way(&mut view, 1.1);
+        assert!(zoomed_out < DEFAULT_MAX_PIXEL_SIZE && zoomed_out * 1.1 >= DEFAULT_MAX_PIXEL_SIZE);
+        let zoomed_in = zoom_all_the_way(&mut view, 0.9);
+        assert!(zoomed_in > DEFAULT_MIN_PIXEL_SIZE && zoomed_in * 0.9 <= DEFAULT_MIN_PIXEL_SIZE);
+        assert_eq!(view.tile_zoom_level(256), 20);
+
+        //Custom limits let a provider with zoom 22 tiles be used, or a huge screen zoom out
+        let min = DEFAULT_MIN_PIXEL_SIZE / 4.0;
+        let max = 1.0 / 20.0;
+        let mut view = TileView::new(29.0, -81.0, 8.0, 1000.0).with_zoom_limits(min, max);
+        let zoomed_out = zoom_all_the_way(&mut view, 1.1);
+        assert!(zoomed_out < max && zoomed_out * 1.1 >= max);
+        let zoomed_in = zoom_all_the_way(&mut view, 0.9);
+        assert!(zoomed_in > min && zoomed_in * 0.9 <= min);
+        assert_eq!(view.tile_zoom_level(256), 22);
+
+        //Animated zooms past the limits are ignored too
+        view.zoom_around_point(0.5, DVec2::ZERO, DVec2::new(1000.0, 500.0));
+        assert!(!view.is_animating());
+
+        //A view zoomed out past the new limits is pulled back in, so zooming in still works
+        let mut view = TileView::new(29.0, -81.0, 0.0, 10.0).with_zoom_limits(min, max);
+        assert_eq!(view.pixel_size, max);
+        view.multiply_zoom(0.9);
+        assert_eq!(view.pixel_size, max * 0.9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zoom_limits_must_be_ordered() {
+        let _ = TileView::new(29.0, -81.0, 8.0, 1000.0).with_zoom_limits(0.1, 0.01);
+    }
+
+    #[test]
+    fn center_tiles_score_higher() {
+        //A viewport exactly covering tiles 4..8 at zoom 4
+        let viewport = WorldViewport {
+            top_left: DVec2::new(0.25, 0.25),
+            bottom_right: DVec2::new(0.5, 0.5),
+        };
+        let score = |x, y| tile_heuristic(crate::TileId::new(x, y, 4), &viewport);
+
+        let center = score(5, 5);
+        let edge = score(7, 5);
+        let corner = score(7, 7);
+        let outside = score(9, 9);
+        assert!(center > edge && edge > corner && corner > outside);
+        assert!(corner > -1.0);
+
+        //Tiles just across the antimeridian are close to a viewport on the left edge of the world
+        let viewport = WorldViewport {
+            top_left: DVec2::new(-0.1, 0.4),
+            bottom_right: DVec2::new(0.1, 0.6),
+        };
+        let wrapped = tile_heuristic(crate::TileId::new(15, 8, 4), &viewport);
+        let far = tile_heuristic(crate::TileId::new(8, 8, 4), &viewport);
+        assert!(wrapped > -1.0 && wrapped > far);
+    }
+
+    #[test]
+    fn world_viewport_with_bearing() {
+        let mut view = TileView::new(0.0, 0.0, 4.0, 1000.0);
+        let north_up = view.get_world_viewport(1000.0, 500.0);
+
+        view.set_bearing(-270.0);
+        assert_eq!(view.bearing(), 90.0);
+        //A quarter turn swaps the width and height of the viewport
+        let sideways = view.get_world_viewport(1000.0, 500.0);
+        let size = north_up.bottom_right - north_up.top_left;
+        let rotated_size = sideways.bottom_right - sideways.top_left;
+        assert!((size.x - rotated_size.y).abs() < 1e-12);
+        assert!((size.y - rotated_size.x).abs() < 1e-12);
+
+        //Part way around the viewport covers more than either
+        view.set_bearing(45.0);
+        let diagonal = view.get_world_viewport(1000.0, 500.0);
+        let diagonal_size = diagonal.bottom_right - diagonal.top_left;
+        assert!(diagonal_size.x > size.x && diagonal_size.y > size.y);
+    }
+
+    #[test]
+    fn center_tile() {
+        let view = TileView::new(0.0, 0.0, 8.0, 1000.0);
+        assert_eq!(view.center_tile(0), crate::TileId::new(0, 0, 0));
+        assert_eq!(view.center_tile(1), crate::TileId::new(1, 1, 1));
+        assert_eq!(view.center_tile(4), crate::TileId::new(8, 8, 4));
+    }
+
+    #[test]
+    fn cloned_view_matches() {
+        let mut view = TileView::new(29.18796, -81.04923, 8.0, 1280.0);
+        view.set_bearing(30.0);
+        view.zoom_around_point(0.8, DVec2::new(200.0, 300.0), DVec2::new(1280.0, 720.0));
+        let copy = view;
+        #[allow(clippy::clone_on_copy)]
+        let clone = view.clone();
+
+        for other in [copy, clone] {
+            let a = view.get_world_viewport(1280.0, 720.0);
+            let b = other.get_world_viewport(1280.0, 720.0);
+            assert_eq!((a.top_left, a.bottom_right), (b.top_left, b.bottom_right));
+
+            let a: Vec<_> = view.tile_iter(256, None, 1280.0, 720.0).collect();
+            let b: Vec<_> = other.tile_iter(256, None, 1280.0, 720.0).collect();
+            assert_eq!(a, b);
+        }
+
+        //The animation is copied too, so both views keep zooming the same way
+        view.animate(50.0);
+        let mut other = copy;
+        other.animate(50.0);
+        assert_eq!(view.get_zoom(), other.get_zoom());
+    }
+
+    #[test]
+    fn world_viewport_non_square() {
+        let view = TileView::new(20.0, -40.0, 6.0, 1000.0);
+        //The same view after the window was resized a few times
+        for (width, height) in [(1280.0, 720.0), (400.0, 900.0), (1920.0, 200.0), (1.0, 1.0)] {
+            let viewport = view.get_world_viewport(width, height);
+            let size = viewport.bottom_right - viewport.top_left;
+
+            //Pixels stay square, so the map isn't stretched
+            assert!((size.x / width - size.y / height).abs() < 1e-15);
+            assert!((size.x / width - view.pixel_size).abs() < 1e-15);
+
+            //Resizing grows and shrinks the viewport around the same center
+            let center = (viewport.top_left + viewport.bottom_right) / 2.0;
+            assert!((center - view.center).length() < 1e-15);
         }
     }
 }
@@ -1,10 +1,13 @@
 use conrod_core::{
-    widget::{id::List, Image, Line, Text},
+    position::Rect,
+    widget::{id::List, Image, Line, RoundedRectangle, Text},
     Colorable, Positionable, Sizeable, UiCell, Widget,
 };
 use glam::DVec2;
+use serde::{Deserialize, Serialize};
 
 use crate::tile::{self, *};
+use crate::{OutlinedText, Theme};
 
 /// Projects a x world location combined with a viewport to determine the x pixel location in the
 /// conrad coordinate system
@@ -80,22 +83,158 @@ fn world_width_from_longitude(lng: f64) -> f64 {
     lng / 360.0
 }
 
+/// The default opacity of the weather layer, low enough that the ground shows through the radar
+pub const DEFAULT_WEATHER_OPACITY: f32 = 0.6;
+
+/// The imagery drawn underneath everything else on the map. Only one is shown at a time
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BaseLayer {
+    #[default]
+    Satellite,
+    Street,
+}
+
+impl BaseLayer {
+    /// Every base layer, in the order they are shown in the selector
+    pub const ALL: [BaseLayer; 2] = [BaseLayer::Satellite, BaseLayer::Street];
+
+    /// The kind of tiles that make up this layer
+    pub fn tile_kind(self) -> TileKind {
+        match self {
+            BaseLayer::Satellite => TileKind::Satellite,
+            BaseLayer::Street => TileKind::Street,
+        }
+    }
+
+    /// A short name that fits on a button
+    pub fn label(self) -> &'static str {
+        match self {
+            BaseLayer::Satellite => "Sat",
+            BaseLayer::Street => "Street",
+        }
+    }
+}
+
+/// How the latitude and longitude labels are written
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CoordinateFormat {
+    /// Decimal degrees, like 29.19°N
+    #[default]
+    Decimal,
+    /// Degrees, minutes and seconds, like 29°11'24"N
+    Dms,
+}
+
+/// Whether the lines of latitude and longitude are drawn, and how they are labeled
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GridStyle {
+    pub enabled: bool,
+    pub format: CoordinateFormat,
+}
+
+impl Default for GridStyle {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            format: CoordinateFormat::default(),
+        }
+    }
+}
+
+impl GridStyle {
+    /// Returns the style after clicking the grid button, which goes from decimal labels to
+    /// degrees, minutes and seconds, then hides the grid
+    pub fn next(self) -> Self {
+        match (self.enabled, self.format) {
+            (true, CoordinateFormat::Decimal) => Self {
+                enabled: true,
+                format: CoordinateFormat::Dms,
+            },
+            (true, CoordinateFormat::Dms) => Self {
+                enabled: false,
+                format: CoordinateFormat::Decimal,
+            },
+            (false, _) => Self::default(),
+        }
+    }
+}
+
+/// Formats a latitude for a grid label. `precision` is how many decimal places a decimal label
+/// needs to tell lines apart
+pub fn format_lat(lat: f64, format: CoordinateFormat, precision: usize) -> String {
+    let hemisphere = if lat >= 0.0 { 'N' } else { 'S' };
+    format_degrees(lat.abs(), hemisphere, format, precision)
+}
+
+/// Formats a longitude for a grid label. `precision` is how many decimal places a decimal label
+/// needs to tell lines apart
+pub fn format_lng(lng: f64, format: CoordinateFormat, precision: usize) -> String {
+    let hemisphere = if lng >= 0.0 { 'E' } else { 'W' };
+    format_degrees(lng.abs(), hemisphere, format, precision)
+}
+
+fn format_degrees(
+    degrees: f64,
+    hemisphere: char,
+    format: CoordinateFormat,
+    precision: usize,
+) -> String {
+    match format {
+        CoordinateFormat::Decimal => format!("{:.1$}°{2}", degrees, precision, hemisphere),
+        CoordinateFormat::Dms => {
+            //A second is about 0.0003°, so lines closer than 0.001° need fractional seconds
+            let second_digits = precision.saturating_sub(3);
+            //Round once up front so that 59.99" becomes the next minute rather than 60"
+            let scale = 10f64.powi(second_digits as i32);
+            let total = (degrees * 3600.0 * scale).round();
+            let per_degree = 3600.0 * scale;
+            let per_minute = 60.0 * scale;
+            let whole_degrees = (total / per_degree).floor();
+            let minutes = ((total - whole_degrees * per_degree) / per_minute).floor();
+            let seconds = (total - whole_degrees * per_degree - minutes * per_minute) / scale;
+            let seconds_width = if second_digits == 0 {
+                2
+            } else {
+                second_digits + 3
+            };
+            format!(
+                "{}°{:02}'{:0width$.digits$}\"{}",
+                whole_degrees,
+                minutes,
+                seconds,
+                hemisphere,
+                width = seconds_width,
+                digits = second_digits
+            )
+        }
+    }
+}
+
 /// The state needed to render the map.
 ///
 /// Implemented as a struct to reduce the number of parameters passed to the map_render function
-pub struct MapRendererState<'a, 'b, 'c, 'd, 'e> {
+pub struct MapRendererState<'a, 'b, 'c, 'd, 'e, 'f> {
     pub tile_cache: &'a mut tile::PipelineMap,
     pub view: &'b crate::map::TileView,
     pub display: &'c glium::Display,
     pub image_map: &'d mut conrod_core::image::Map<glium::Texture2d>,
     pub ids: &'e mut crate::Ids,
     pub weather_enabled: bool,
+    /// Outlines every tile and labels it with its layer, id and load priority when true
+    pub tile_debug_enabled: bool,
+    /// How opaque the weather layer is, from 0.0 (invisible) to 1.0 (hides the base layer)
+    pub weather_opacity: f32,
+    pub base_layer: BaseLayer,
+    /// Loads the tiles one zoom level around the view in the background when true
+    pub prefetch_enabled: bool,
+    pub grid: GridStyle,
+    pub theme: &'f Theme,
 }
 
-/// Draws the satellite tiles, weather tiles (if enabled), latitude lines, and longitude lines,
+/// Draws the base layer tiles, weather tiles (if enabled), latitude lines, and longitude lines,
 /// using the `view` inside `state`
 pub fn draw(state: MapRendererState, ui: &mut UiCell<'_>, font: conrod_core::text::font::Id) {
-    let _scope = crate::profile_scope("map_renderer::draw");
+    let _scope = crate::Profiler::scope("map_renderer::draw");
     //Or value is okay here because `tile_size()` only returns `None` if no tiles are cached, which
     //only happens the first few frames, therefore this value doesn't need to be accurate
     let tile_cache = state.tile_cache;
@@ -106,173 +245,222 @@ pub fn draw(state: MapRendererState, ui: &mut UiCell<'_>, font: conrod_core::tex
 
     let viewport = state.view.get_world_viewport(ui.win_w, ui.win_h);
 
-    let mut cache_it = tile_cache.values_mut();
-    let satellite = cache_it.next().unwrap();
-    let weather = cache_it.next().unwrap();
+    let mut base = None;
+    let mut weather = None;
+    for (kind, pipeline) in tile_cache.iter_mut() {
+        if kind == state.base_layer.tile_kind() {
+            base = Some(pipeline);
+        } else if kind == TileKind::Weather {
+            weather = Some(pipeline);
+        }
+    }
+    let base = base.unwrap();
+    let weather = weather.unwrap();
 
     {
-        let _p = crate::profile_scope("Satellite Tile Cache Update");
-        satellite.update(&viewport, display, image_map);
+        let _p = crate::Profiler::scope("Base Layer Tile Cache Update");
+        let zoom = tile_zoom(base, view);
+        base.update(&viewport, zoom, display, image_map);
     }
 
     {
-        let _p = crate::profile_scope("Weather Tile Cache Update");
+        let _p = crate::Profiler::scope("Weather Tile Cache Update");
 
         if state.weather_enabled {
-            weather.update(&viewport, display, image_map);
+            let zoom = tile_zoom(weather, view);
+            weather.update(&viewport, zoom, display, image_map);
         }
     }
 
-    render_tile_set(satellite, view, &mut ids.satellite_tiles, ui);
+    if state.prefetch_enabled {
+        let _p = crate::Profiler::scope("Tile Prefetch");
+        let screen_size = DVec2::new(ui.win_w, ui.win_h);
+        base.prefetch(view, screen_size);
+        if state.weather_enabled {
+            weather.prefetch(view, screen_size);
+        }
+    }
+
+    let base_ids = match state.base_layer {
+        BaseLayer::Satellite => &mut ids.satellite_tiles,
+        BaseLayer::Street => &mut ids.street_tiles,
+    };
+    let debug = state.tile_debug_enabled;
+    let base_kind = state.base_layer.tile_kind();
+    render_tile_set(base, base_kind, view, base_ids, 1.0, debug, ui);
     if state.weather_enabled {
-        render_tile_set(weather, view, &mut ids.weather_tiles, ui);
+        let opacity = state.weather_opacity.clamp(0.0, 1.0);
+        let weather_ids = &mut ids.weather_tiles;
+        render_tile_set(
+            weather,
+            TileKind::Weather,
+            view,
+            weather_ids,
+            opacity,
+            debug,
+            ui,
+        );
     }
 
     // Draw the latitude and longitude lines
-    draw_lat_long(&viewport, ui, ids, font);
+    if state.grid.enabled {
+        draw_lat_long(&viewport, ui, ids, font, state.grid.format, state.theme);
+    }
+}
+
+/// Returns true if `pipeline` has loaded the tile in the center of `view`, which is when the map
+/// looks ready to use
+pub fn is_center_loaded(pipeline: &TilePipeline, view: &crate::map::TileView) -> bool {
+    match pipeline.layout_tile_size() {
+        Some(tile_size) => {
+            let zoom = view.capped_tile_zoom_level(tile_size, pipeline.max_zoom());
+            pipeline.is_tile_cached(view.center_tile(zoom))
+        }
+        None => false,
+    }
+}
+
+/// Returns true once the loading screen can give way to the map drawn from `pipeline`. That is
+/// when the center tile has loaded, or when the pipeline failed in a way that means it may never
+/// load, so that the user can see the error and switch to another base layer
+pub fn is_map_ready(pipeline: &TilePipeline, view: &crate::map::TileView) -> bool {
+    is_center_loaded(pipeline, view) || pipeline.has_persistent_error()
 }
 
-/// Renders a tile set from a provided tile pipeline
+/// Returns the zoom level of the tiles from `pipeline` that will be rendered for `view`
+fn tile_zoom(pipeline: &TilePipeline, view: &crate::map::TileView) -> u32 {
+    let tile_size = pipeline.layout_tile_size().unwrap();
+    view.capped_tile_zoom_level(tile_size, pipeline.max_zoom())
+}
+
+/// Renders a tile set from a provided tile pipeline.
+///
+/// When `debug` is true, each tile is outlined and labeled with `kind`, its id and its load
+/// priority score so the tile grid is visible
 pub fn render_tile_set(
     pipeline: &mut TilePipeline,
+    kind: TileKind,
     view: &crate::map::TileView,
     ids: &mut List,
+    opacity: f32,
+    debug: bool,
     ui: &mut UiCell<'_>,
 ) {
+    //The textures are `tile_size` pixels wide, but are laid out smaller on high DPI screens
     let tile_size = pipeline.tile_size().unwrap();
+    let layout_size = pipeline.layout_tile_size().unwrap();
+    //Only tint the images when we have to, so opaque layers draw exactly as they did before
+    let tint = (opacity < 1.0).then_some(conrod_core::Color::Rgba(1.0, 1.0, 1.0, opacity));
 
-    let it = view.tile_iter(tile_size, ui.win_w, ui.win_h);
-    let mut size = it.tile_size;
-    let offset = it.tile_offset;
-    let mut zoom_level = it.tile_zoom;
-    let half_width = ui.win_w / 2.0;
-    let half_height = ui.win_h / 2.0;
+    let it = view.tile_iter(layout_size, pipeline.max_zoom(), ui.win_w, ui.win_h);
+    let size = it.tile_size;
+    let zoom_level = it.tile_zoom;
 
-    let tiles_vertically = it.tiles_vertically;
+    let viewport = view.get_world_viewport(ui.win_w, ui.win_h);
 
-    let tiles: Vec<_> = it.collect();
-    {
-        let mut guard = crate::MAP_PERF_DATA.lock();
-        guard.tiles_rendered = tiles.len();
-        guard.zoom = zoom_level;
-    }
+    crate::Profiler::record_tiles_rendered(it.len(), zoom_level);
 
     // The conrod coordinate system places 0, 0 in the center of the window. Up is the positive y
     // axis, and right is the positive x axis.
     // The units are in terms of screen pixels, so on a window with a size of 1000x500 the point
     // (500, 250) would be the top right corner
-    let scope_render_tiles = crate::profile_scope("Render Tiles");
-
-    let mut draw_layers = Vec::new();
-    let mut missing = RenderLayer::new(size, zoom_level);
-
-    // Iteratre through each initial tile
-    for (i, tile) in tiles.iter().enumerate() {
-        let tile_x = i / tiles_vertically as usize;
-        let tile_y = i % tiles_vertically as usize;
-
-        let x = offset.x + tile_x as f64 * size.x - half_width + size.x / 2.0;
-        let y = offset.y - (tile_y as f64 * size.y) + half_height + size.y / 2.0;
-
-        // Set each one as missing so that we can just use the later loop for everything
-        missing.tiles.push((x, y, tile.0, tile.1));
-    }
-
-    while !missing.tiles.is_empty() && zoom_level > 0 {
-        let mut newest_layer = RenderLayer::new(size, zoom_level);
-        let mut new_missing = RenderLayer::new(size * 2.0, zoom_level - 1);
-
-        for (x, y, tile_x, tile_y) in missing.tiles {
-            let tile_id = TileId::new(tile_x, tile_y, missing.zoom_level);
-
-            if pipeline.get_tile(tile_id).is_some() {
-                let data = (x, y, tile_x, tile_y);
-                newest_layer.tiles.push(data);
-            } else {
-                // If the tile isn't present, add the one that should replace it
-                let inner_offset_x = tile_x % 2;
-                let inner_offset_y = tile_y % 2;
-                let tile_x = tile_x / 2;
-                let tile_y = tile_y / 2;
-
-                let x = x - inner_offset_x as f64 * size.x + size.x / 2.0;
-                let y = y + inner_offset_y as f64 * size.y - size.y / 2.0;
-
-                let data = (x, y, tile_x, tile_y);
-
-                new_missing.tiles.push(data);
-            }
+    let scope_render_tiles = crate::Profiler::scope("Render Tiles");
+
+    // Tiles that haven't loaded yet are drawn using part of their closest loaded ancestor, so the
+    // area shows blurry imagery instead of nothing until the sharp tile arrives
+    let mut drawn = Vec::with_capacity(it.len());
+    for (tile, position) in it.with_positions(ui.win_w, ui.win_h) {
+        let (x, y) = (position.x, position.y);
+        let tile_id = TileId::new(tile.0, tile.1, zoom_level);
+        if let Some(image) = pipeline.get_tile(tile_id) {
+            drawn.push((x, y, tile_id, image, None));
+        } else if let Some((image, ancestor)) = pipeline.get_cached_ancestor(tile_id) {
+            let src_rect = placeholder_source_rect(tile_id, ancestor, tile_size as f64);
+            drawn.push((x, y, tile_id, image, Some(src_rect)));
         }
-
-        zoom_level -= 1;
-        size *= 2.0;
-
-        draw_layers.push(newest_layer);
-
-        missing = new_missing;
-    }
-
-    // We now need to account for more tiles than we currently expect to display
-    let mut tile_count = 0;
-
-    for draw_layer in draw_layers.iter() {
-        tile_count += draw_layer.tiles.len();
     }
 
-    // Now we resize
-    ids.resize(tile_count, &mut ui.widget_id_generator());
-
-    // Otherwise this would draw all of the lower-res images on top of the regular res ones instead
-    // of behind like we want
-    draw_layers.reverse();
+    // Debug mode needs two more widgets per tile for the outline and label
+    let ids_per_tile = if debug { 3 } else { 1 };
+    ids.resize(drawn.len() * ids_per_tile, &mut ui.widget_id_generator());
 
-    let mut id_counter = 0;
-
-    for draw_layer in draw_layers {
-        let size = draw_layer.size;
-        let zoom_level = draw_layer.zoom_level;
-
-        for (x, y, tile_x, tile_y) in draw_layer.tiles {
-            let tile_id = TileId::new(tile_x, tile_y, zoom_level);
-
-            if let Some(tile) = pipeline.get_tile(tile_id) {
-                Image::new(tile)
-                    .x_y(x, y)
-                    .w_h(size.x, size.y)
-                    .set(ids[id_counter], ui);
+    for (i, (x, y, tile_id, image, src_rect)) in drawn.into_iter().enumerate() {
+        let id_index = i * ids_per_tile;
+        let mut widget = Image::new(image).x_y(x, y).w_h(size.x, size.y).color(tint);
+        if let Some(src_rect) = src_rect {
+            widget = widget.source_rectangle(src_rect);
+        }
+        widget.set(ids[id_index], ui);
 
-                id_counter += 1;
-            }
+        if debug {
+            // Placeholders are outlined in a different color so it's clear they aren't loaded
+            let color = if src_rect.is_some() {
+                conrod_core::color::YELLOW
+            } else {
+                conrod_core::color::RED
+            };
+            RoundedRectangle::outline([size.x, size.y], 4.0)
+                .x_y(x, y)
+                .color(color)
+                .set(ids[id_index + 1], ui);
+
+            let heuristic = crate::map::tile_heuristic(tile_id, &viewport);
+            let label = tile_debug_label(kind, tile_id, heuristic);
+            //Weather tiles line up with the base layer's, so their labels go below the base
+            //layer's instead of on top of them
+            let label_y = if kind == TileKind::Weather {
+                y - 48.0
+            } else {
+                y
+            };
+            Text::new(label.as_str())
+                .x_y(x, label_y)
+                .font_size(12)
+                .color(color)
+                .set(ids[id_index + 2], ui);
         }
     }
 
     scope_render_tiles.end();
 }
 
-struct RenderLayer {
-    pub size: DVec2,
-    pub zoom_level: u32,
-    pub tiles: Vec<(f64, f64, u32, u32)>,
+/// The text drawn in the middle of each tile by the tile debug overlay
+fn tile_debug_label(kind: TileKind, tile_id: TileId, heuristic: f64) -> String {
+    format!(
+        "{}\n{}/{}/{}\n{:.2}",
+        kind.name(),
+        tile_id.zoom,
+        tile_id.x,
+        tile_id.y,
+        heuristic
+    )
 }
 
-impl RenderLayer {
-    pub fn new(size: DVec2, zoom_level: u32) -> Self {
-        Self {
-            size,
-            zoom_level,
-            tiles: Vec::new(),
-        }
-    }
+/// Returns the part of `ancestor`'s texture that covers `tile`, in the pixel coordinates conrod
+/// expects for [`Image::source_rectangle`]. `texture_size` is the width and height of the texture
+fn placeholder_source_rect(tile: TileId, ancestor: TileId, texture_size: f64) -> Rect {
+    let levels_up = tile.zoom - ancestor.zoom;
+    let sub_tiles = 2u32.pow(levels_up);
+    let sub_size = texture_size / sub_tiles as f64;
+    let sub_x = (tile.x - (ancestor.x << levels_up)) as f64;
+    let sub_y = (tile.y - (ancestor.y << levels_up)) as f64;
+
+    // Texture coordinates start at the bottom left, but tile y increases going down
+    let left = sub_x * sub_size;
+    let top = texture_size - sub_y * sub_size;
+    Rect::from_corners([left, top - sub_size], [left + sub_size, top])
 }
 
-/// Draws the lines of latitude and longitude onto the map
+/// Draws the lines of latitude and longitude onto the map, in the colors of `theme`
 pub fn draw_lat_long(
     viewport: &crate::map::WorldViewport,
     ui: &mut UiCell<'_>,
     ids: &mut crate::Ids,
     font: conrod_core::text::font::Id,
+    format: CoordinateFormat,
+    theme: &Theme,
 ) {
-    let scope_render_latitude = crate::profile_scope("Render Latitude");
+    let scope_render_latitude = crate::Profiler::scope("Render Latitude");
     //Lines of latitude
     let lat_line_distance =
         line_distance_for_viewport_degrees(viewport.bottom_right.y - viewport.top_left.y, ui.win_h);
@@ -295,8 +483,6 @@ pub fn draw_lat_long(
         0usize
     };
 
-    const LINE_ALPHA: f32 = 0.4;
-
     //Latitude decreases as world y increases
     for i in 0..lat_lines {
         let lat = lat_start - i as f64 * lat_line_distance;
@@ -307,26 +493,23 @@ pub fn draw_lat_long(
         Line::new([-half_width, y_pixel], [half_width, y_pixel])
             //Why does this call need to happen?
             .x_y(0.0, 0.0)
-            .color(conrod_core::color::BLACK.alpha(LINE_ALPHA))
+            .color(theme.grid_line)
             .thickness(1.5)
             .set(ids.latitude_lines[i], ui);
 
-        let text = if lat >= 0.0 {
-            format!("{:.1$}°N", lat, precision)
-        } else {
-            format!("{:.1$}°S", -lat, precision)
-        };
-        Text::new(text.as_str())
+        let text = format_lat(lat, format, precision);
+        OutlinedText::new(text.as_str())
             .top_right()
             .y(y_pixel)
-            .color(conrod_core::color::WHITE)
-            .font_size(12)
+            .color(theme.grid_text)
+            .outline_color(theme.text_outline)
+            .font_size(theme.grid_font_size)
             .font_id(font)
             .set(ids.latitude_text[i], ui);
     }
     scope_render_latitude.end();
 
-    let scope_render_longitude = crate::profile_scope("Render Longitude");
+    let scope_render_longitude = crate::Profiler::scope("Render Longitude");
     //Lines of longitude
     let lng_line_distance =
         line_distance_for_viewport_degrees(viewport.bottom_right.x - viewport.top_left.x, ui.win_w);
@@ -362,23 +545,124 @@ pub fn draw_lat_long(
         let half_height = ui.win_h / 2.0;
         Line::new([x_pixel, -half_height], [x_pixel, half_height])
             .x_y(0.0, 0.0)
-            .color(conrod_core::color::BLACK.alpha(LINE_ALPHA))
+            .color(theme.grid_line)
             .thickness(1.5)
             .set(ids.longitude_lines[i], ui);
 
-        let text = if lng >= 0.0 {
-            format!("{:.1$}°E", lng, precision)
-        } else {
-            format!("{:.1$}°W", -lng, precision)
-        };
-        Text::new(text.as_str())
+        let text = format_lng(lng, format, precision);
+        OutlinedText::new(text.as_str())
             .bottom_right()
             .x(x_pixel)
-            .color(conrod_core::color::WHITE)
-            .font_size(12)
+            .color(theme.grid_text)
+            .outline_color(theme.text_outline)
+            .font_size(theme.grid_font_size)
             .font_id(font)
             .set(ids.longitude_text[i], ui);
     }
 
     scope_render_longitude.end();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinate_formatting() {
+        use CoordinateFormat::*;
+        assert_eq!(format_lat(29.19, Decimal, 2), "29.19°N");
+        assert_eq!(format_lat(-33.5, Decimal, 1), "33.5°S");
+        assert_eq!(format_lng(-81.05, Decimal, 2), "81.05°W");
+        assert_eq!(format_lng(0.0, Decimal, 0), "0°E");
+
+        assert_eq!(format_lat(29.19, Dms, 2), "29°11'24\"N");
+        assert_eq!(format_lat(-33.5, Dms, 1), "33°30'00\"S");
+        assert_eq!(format_lng(-81.0492, Dms, 3), "81°02'57\"W");
+        assert_eq!(format_lng(151.2093, Dms, 4), "151°12'33.5\"E");
+        //Rounding up to a whole minute or degree carries over
+        assert_eq!(format_lat(-45.99999, Dms, 2), "46°00'00\"S");
+        assert_eq!(format_lng(0.0, Dms, 0), "0°00'00\"E");
+    }
+
+    #[test]
+    fn grid_button_cycles() {
+        let style = GridStyle::default();
+        assert!(style.enabled);
+        assert_eq!(style.format, CoordinateFormat::Decimal);
+        let style = style.next();
+        assert_eq!(style.format, CoordinateFormat::Dms);
+        let style = style.next();
+        assert!(!style.enabled);
+        assert_eq!(style.next(), GridStyle::default());
+    }
+
+    #[test]
+    fn tile_debug_labels_name_the_layer() {
+        let tile = TileId::new(4, 6, 4);
+        assert_eq!(
+            tile_debug_label(TileKind::Satellite, tile, 0.126),
+            "Satellite\n4/4/6\n0.13"
+        );
+        assert_eq!(
+            tile_debug_label(TileKind::Weather, tile, 2.0),
+            "Weather\n4/4/6\n2.00"
+        );
+    }
+
+    #[test]
+    fn placeholder_sub_rectangles() {
+        // The top left child uses the top left quarter, remembering that y is flipped
+        let parent = TileId::new(3, 5, 4);
+        let rect = placeholder_source_rect(TileId::new(6, 10, 5), parent, 256.0);
+        assert_eq!(rect.l_r_b_t(), (0.0, 128.0, 128.0, 256.0));
+        let rect = placeholder_source_rect(TileId::new(7, 11, 5), parent, 256.0);
+        assert_eq!(rect.l_r_b_t(), (128.0, 256.0, 0.0, 128.0));
+
+        // Two levels up each tile gets a sixteenth of the texture
+        let rect = placeholder_source_rect(TileId::new(13, 20, 6), parent, 256.0);
+        assert_eq!(rect.l_r_b_t(), (64.0, 128.0, 192.0, 256.0));
+
+        // A tile is its own ancestor when nothing is missing
+        let rect = placeholder_source_rect(parent, parent, 256.0);
+        assert_eq!(rect.l_r_b_t(), (0.0, 256.0, 0.0, 256.0));
+    }
+
+    #[test]
+    fn missing_api_key_ends_loading() {
+        use crate::tile::mock_backend::{MockBackend, MockTile};
+        use std::time::{Duration, Instant};
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let shutdown = crate::Shutdown::new();
+        let api = MockBackend::with_default(MockTile::MissingApiKey);
+        let mut pipeline = TilePipeline::new(
+            vec![Box::new(api.clone())],
+            16,
+            RetryPolicy::default(),
+            &shutdown,
+            &runtime,
+        );
+        let view = crate::map::TileView::new(0.0, 0.0, 0.0, 4.0);
+        assert!(!is_map_ready(&pipeline, &view));
+
+        //Request the center tile the way drawing the map would
+        let zoom = tile_zoom(&pipeline, &view);
+        assert_eq!(pipeline.get_tile(view.center_tile(zoom)), None);
+        let viewport = view.get_world_viewport(4.0, 4.0);
+        let mut image_map = conrod_core::image::Map::new();
+        let start = Instant::now();
+        while !is_map_ready(&pipeline, &view) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Loading never ended"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+            pipeline.update_with(&viewport, zoom, &mut image_map, |image| image);
+        }
+        assert!(!is_center_loaded(&pipeline, &view));
+        assert!(matches!(
+            pipeline.poll_error(),
+            Some(TileError::MissingApiKey(_))
+        ));
+    }
+}
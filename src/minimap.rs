@@ -0,0 +1,145 @@
+use conrod_core::{widget, Colorable, Positionable, Sizeable, UiCell, Widget};
+use glam::DVec2;
+
+use crate::map::{TileView, TileZoomLevel, WorldViewport};
+use crate::tile::{TileId, TilePipeline};
+
+/// The zoom level of the tiles in the minimap. At 2 the whole world is a 4x4 grid of tiles
+pub const MINIMAP_ZOOM: TileZoomLevel = 2;
+
+/// How many pixels wide and tall the minimap is. The world is square, so so is the minimap
+pub const MINIMAP_SIZE: f64 = 160.0;
+
+/// The outline of the view is never drawn smaller than this many pixels, so that it can still be
+/// seen when zoomed in on a single airport
+const MIN_VIEW_SIZE: f64 = 4.0;
+
+/// Draws the whole world in a square centered on (`x`, `y`), with the part of it that `view` shows
+/// outlined. The tiles come from `pipeline` at [`MINIMAP_ZOOM`].
+///
+/// Returns the world position that was clicked this frame, if any, so that the main view can be
+/// moved there
+#[allow(clippy::too_many_arguments)]
+pub fn draw(
+    pipeline: &mut TilePipeline,
+    view: &TileView,
+    ids: &mut crate::Ids,
+    x: f64,
+    y: f64,
+    ui: &mut UiCell,
+    display: &glium::Display,
+    image_map: &mut conrod_core::image::Map<glium::Texture2d>,
+) -> Option<DVec2> {
+    let _scope = crate::Profiler::scope("Render Minimap");
+    let world = WorldViewport {
+        top_left: DVec2::ZERO,
+        bottom_right: DVec2::ONE,
+    };
+    pipeline.update(&world, MINIMAP_ZOOM, display, image_map);
+
+    widget::Rectangle::fill([MINIMAP_SIZE, MINIMAP_SIZE])
+        .x_y(x, y)
+        .color(conrod_core::color::Color::Rgba(0.1, 0.1, 0.1, 0.9))
+        .set(ids.minimap_background, ui);
+
+    let tiles_across = 2u32.pow(MINIMAP_ZOOM);
+    let tile_size = MINIMAP_SIZE / tiles_across as f64;
+    ids.minimap_tiles.resize(
+        (tiles_across * tiles_across) as usize,
+        &mut ui.widget_id_generator(),
+    );
+    let left = x - MINIMAP_SIZE / 2.0;
+    let top = y + MINIMAP_SIZE / 2.0;
+    for tile_y in 0..tiles_across {
+        for tile_x in 0..tiles_across {
+            let image = match pipeline.get_tile(TileId::new(tile_x, tile_y, MINIMAP_ZOOM)) {
+                Some(image) => image,
+                None => continue,
+            };
+            let i = (tile_y * tiles_across + tile_x) as usize;
+            widget::Image::new(image)
+                .x_y(
+                    left + (tile_x as f64 + 0.5) * tile_size,
+                    top - (tile_y as f64 + 0.5) * tile_size,
+                )
+                .w_h(tile_size, tile_size)
+                .graphics_for(ids.minimap_background)
+                .set(ids.minimap_tiles[i], ui);
+        }
+    }
+
+    let (top_left, bottom_right) = view_rect(&view.get_world_viewport(ui.win_w, ui.win_h));
+    let size = ((bottom_right - top_left) * MINIMAP_SIZE).max(DVec2::splat(MIN_VIEW_SIZE));
+    let center = (top_left + bottom_right) / 2.0;
+    widget::Rectangle::outline([size.x, size.y])
+        .x_y(
+            left + center.x * MINIMAP_SIZE,
+            top - center.y * MINIMAP_SIZE,
+        )
+        .color(conrod_core::color::YELLOW)
+        .graphics_for(ids.minimap_background)
+        .set(ids.minimap_view, ui);
+
+    ui.widget_input(ids.minimap_background)
+        .clicks()
+        .left()
+        .next()
+        .map(|click| click_to_world(DVec2::new(click.xy[0], click.xy[1])))
+}
+
+/// Returns the top left and bottom right of the part of the world `viewport` covers, clamped to
+/// 0..1 so that it fits in the minimap. The view is moved into the first copy of the world first,
+/// since it can be looking at any of them
+fn view_rect(viewport: &WorldViewport) -> (DVec2, DVec2) {
+    let center_x = (viewport.top_left.x + viewport.bottom_right.x) / 2.0;
+    let shift = DVec2::new(center_x.rem_euclid(1.0) - center_x, 0.0);
+    (
+        (viewport.top_left + shift).clamp(DVec2::ZERO, DVec2::ONE),
+        (viewport.bottom_right + shift).clamp(DVec2::ZERO, DVec2::ONE),
+    )
+}
+
+/// Converts a click on the minimap, relative to its center with y pointing up like conrod, to the
+/// world position under it
+fn click_to_world(click: DVec2) -> DVec2 {
+    let world = DVec2::new(0.5 + click.x / MINIMAP_SIZE, 0.5 - click.y / MINIMAP_SIZE);
+    world.clamp(DVec2::ZERO, DVec2::ONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_rect_wraps_and_clamps() {
+        let close = |a: DVec2, b: DVec2| (a - b).length() < 1e-9;
+
+        let viewport = WorldViewport {
+            top_left: DVec2::new(0.25, 0.4),
+            bottom_right: DVec2::new(0.5, 0.6),
+        };
+        let (top_left, bottom_right) = view_rect(&viewport);
+        assert!(close(top_left, viewport.top_left));
+        assert!(close(bottom_right, viewport.bottom_right));
+
+        //Two worlds to the right, and zoomed out past the poles
+        let viewport = WorldViewport {
+            top_left: DVec2::new(2.1, -0.5),
+            bottom_right: DVec2::new(2.3, 1.5),
+        };
+        let (top_left, bottom_right) = view_rect(&viewport);
+        assert!(close(top_left, DVec2::new(0.1, 0.0)));
+        assert!(close(bottom_right, DVec2::new(0.3, 1.0)));
+    }
+
+    #[test]
+    fn clicks_map_to_world() {
+        let close = |a: DVec2, b: DVec2| (a - b).length() < 1e-9;
+        assert!(close(click_to_world(DVec2::ZERO), DVec2::splat(0.5)));
+        //The top left corner is the north west of the world
+        let corner = DVec2::new(-MINIMAP_SIZE / 2.0, MINIMAP_SIZE / 2.0);
+        assert!(close(click_to_world(corner), DVec2::ZERO));
+        let quarter = DVec2::new(MINIMAP_SIZE / 4.0, -MINIMAP_SIZE / 4.0);
+        assert!(close(click_to_world(quarter), DVec2::splat(0.75)));
+    }
+}
@@ -0,0 +1,167 @@
+//The conrod derive macros generate their impls inside of a const block, which newer compilers
+//warn about. We can't change the generated code so allow it for the whole file
+#![allow(non_local_definitions)]
+
+use conrod_core::{
+    position::Dimension, text, widget, widget_ids, Color, Colorable, FontSize, Positionable,
+    Scalar, Sizeable, Ui, Widget, WidgetCommon, WidgetStyle,
+};
+
+/// How far the outline reaches past the edge of each letter, in pixels
+const OUTLINE_WIDTH: Scalar = 1.0;
+
+/// Where the copies of the text that make up the outline are drawn, relative to the text
+const OUTLINE_OFFSETS: [[Scalar; 2]; 8] = [
+    [-OUTLINE_WIDTH, -OUTLINE_WIDTH],
+    [0.0, -OUTLINE_WIDTH],
+    [OUTLINE_WIDTH, -OUTLINE_WIDTH],
+    [-OUTLINE_WIDTH, 0.0],
+    [OUTLINE_WIDTH, 0.0],
+    [-OUTLINE_WIDTH, OUTLINE_WIDTH],
+    [0.0, OUTLINE_WIDTH],
+    [OUTLINE_WIDTH, OUTLINE_WIDTH],
+];
+
+///A single line of text with an outline in a contrasting color around every letter, so that it
+///stays readable over both bright and dark parts of the map.
+///
+///The outline is made by drawing the text several times, offset in every direction, behind the
+///text itself
+#[derive(WidgetCommon)]
+pub struct OutlinedText<'a> {
+    #[conrod(common_builder)]
+    common: widget::CommonBuilder,
+    text: &'a str,
+    style: Style,
+}
+
+/// The colors and font of an [`OutlinedText`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, WidgetStyle)]
+pub struct Style {
+    /// The color of the text
+    #[conrod(default = "theme.label_color")]
+    pub color: Option<Color>,
+    /// The color of the outline around the text
+    #[conrod(default = "conrod_core::color::BLACK")]
+    pub outline_color: Option<Color>,
+    #[conrod(default = "theme.font_size_medium")]
+    pub font_size: Option<FontSize>,
+    #[conrod(default = "theme.font_id")]
+    pub font_id: Option<Option<text::font::Id>>,
+}
+
+widget_ids! {
+    ///Widget Ids for the text and each copy of it in the outline
+    pub struct OutlinedTextIds {
+        outline[],
+        text,
+    }
+}
+
+pub struct OutlinedTextState {
+    ids: OutlinedTextIds,
+}
+
+impl<'a> OutlinedText<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            common: widget::CommonBuilder::default(),
+            text,
+            style: Style::default(),
+        }
+    }
+
+    pub fn outline_color(mut self, color: Color) -> Self {
+        self.style.outline_color = Some(color);
+        self
+    }
+
+    pub fn font_size(mut self, size: FontSize) -> Self {
+        self.style.font_size = Some(size);
+        self
+    }
+
+    pub fn font_id(mut self, font_id: text::font::Id) -> Self {
+        self.style.font_id = Some(Some(font_id));
+        self
+    }
+
+    /// The text without the outline, as it would be drawn by conrod
+    fn inner_text(&self, ui: &Ui) -> widget::Text<'a> {
+        let font_id = self
+            .style
+            .font_id(&ui.theme)
+            .or_else(|| ui.fonts.ids().next());
+        widget::Text::new(self.text)
+            .and_then(font_id, widget::Text::font_id)
+            .font_size(self.style.font_size(&ui.theme))
+    }
+}
+
+impl<'a> Widget for OutlinedText<'a> {
+    type State = OutlinedTextState;
+    type Style = Style;
+    type Event = ();
+
+    fn init_state(&self, id_gen: widget::id::Generator) -> Self::State {
+        OutlinedTextState {
+            ids: OutlinedTextIds::new(id_gen),
+        }
+    }
+
+    fn style(&self) -> Self::Style {
+        self.style
+    }
+
+    fn default_x_dimension(&self, ui: &Ui) -> Dimension {
+        let width = self.inner_text(ui).get_w(ui).unwrap_or(0.0);
+        Dimension::Absolute(width + OUTLINE_WIDTH * 2.0)
+    }
+
+    fn default_y_dimension(&self, ui: &Ui) -> Dimension {
+        let height = self.inner_text(ui).get_h(ui).unwrap_or(0.0);
+        Dimension::Absolute(height + OUTLINE_WIDTH * 2.0)
+    }
+
+    fn update(self, args: widget::UpdateArgs<Self>) -> Self::Event {
+        let widget::UpdateArgs {
+            id,
+            state,
+            rect,
+            ui,
+            style,
+            ..
+        } = args;
+
+        if state.ids.outline.len() != OUTLINE_OFFSETS.len() {
+            let id_gen = &mut ui.widget_id_generator();
+            state.update(|state| state.ids.outline.resize(OUTLINE_OFFSETS.len(), id_gen));
+        }
+
+        let color = style.color(&ui.theme);
+        let outline_color = style.outline_color(&ui.theme);
+        let text = self.inner_text(ui);
+        let [x, y] = rect.xy();
+
+        for (offset, outline_id) in OUTLINE_OFFSETS.iter().zip(state.ids.outline.iter()) {
+            text.clone()
+                .x_y(x + offset[0], y + offset[1])
+                .color(outline_color)
+                .parent(id)
+                .graphics_for(id)
+                .set(*outline_id, ui);
+        }
+        text.x_y(x, y)
+            .color(color)
+            .parent(id)
+            .graphics_for(id)
+            .set(state.ids.text, ui);
+    }
+}
+
+impl<'a> Colorable for OutlinedText<'a> {
+    fn color(mut self, color: Color) -> Self {
+        self.style.color = Some(color);
+        self
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use simple_moving_average::{SumTreeSMA, SMA};
+use thiserror::Error;
+
+use crate::Plane;
+
+/// The planes from one OpenSky request, and when they arrived
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Milliseconds since the recording started
+    pub time_ms: u64,
+    pub planes: Vec<Plane>,
+}
+
+impl RecordedFrame {
+    /// How long after the recording started these planes arrived
+    pub fn time(&self) -> Duration {
+        Duration::from_millis(self.time_ms)
+    }
+}
+
+/// Why a recording couldn't be written or played back
+#[derive(Error, Debug)]
+pub enum RecordingError {
+    #[error("I/O: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: {source}")]
+    Json {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("the recording has no planes in it")]
+    Empty,
+}
+
+/// Writes the planes from each request to a file, one [`RecordedFrame`] of JSON per line, so that
+/// they can be played back later with `--replay`
+pub struct PlaneRecorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl PlaneRecorder {
+    /// Creates the recording at `path`, replacing any file already there
+    pub fn create(path: &Path) -> Result<Self, RecordingError> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    /// Adds `planes` to the end of the recording. Frames are flushed as they are written, so the
+    /// recording can be played back even if the app doesn't close cleanly
+    pub fn record(&mut self, planes: &[Plane]) -> Result<(), RecordingError> {
+        let time_ms = self.start.elapsed().as_millis() as u64;
+        write_frame(&mut self.writer, time_ms, planes)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes one line of a recording
+fn write_frame(
+    mut writer: impl Write,
+    time_ms: u64,
+    planes: &[Plane],
+) -> Result<(), RecordingError> {
+    #[derive(Serialize)]
+    struct FrameRef<'a> {
+        time_ms: u64,
+        planes: &'a [Plane],
+    }
+
+    serde_json::to_writer(&mut writer, &FrameRef { time_ms, planes })
+        .map_err(std::io::Error::from)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Reads every frame of the recording at `path`
+pub fn load_recording(path: &Path) -> Result<Vec<RecordedFrame>, RecordingError> {
+    read_recording(BufReader::new(File::open(path)?))
+}
+
+/// Reads every frame of a recording, skipping blank lines
+fn read_recording(reader: impl BufRead) -> Result<Vec<RecordedFrame>, RecordingError> {
+    let mut frames = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame = serde_json::from_str(&line).map_err(|source| RecordingError::Json {
+            line: i + 1,
+            source,
+        })?;
+        frames.push(frame);
+    }
+    if frames.is_empty() {
+        return Err(RecordingError::Empty);
+    }
+    Ok(frames)
+}
+
+/// How long to wait after the last frame before playing a recording again. This is the average
+/// gap between the last 16 frames, which is about how often OpenSky was asked for planes when the
+/// recording ended, without a single slow or rate limited request throwing it off
+pub fn loop_delay(frames: &[RecordedFrame]) -> Duration {
+    if frames.len() < 2 {
+        return Duration::from_secs(5);
+    }
+    let mut gaps: SumTreeSMA<Duration, u32, 16> = SumTreeSMA::from_zero(Duration::ZERO);
+    for pair in frames.windows(2) {
+        gaps.add_sample(pair[1].time().saturating_sub(pair[0].time()));
+    }
+    gaps.get_average()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Airline, BasicAirline, PlaneType};
+
+    #[test]
+    fn recording_round_trip() {
+        let mut plane = Plane::new(
+            -81.0,
+            29.2,
+            1.5,
+            "AAL123".to_owned(),
+            BasicAirline::American.into(),
+            PlaneType::Commercial,
+        );
+        plane.altitude = Some(3000.0);
+        let unknown = Plane::new(
+            -80.0,
+            28.0,
+            0.0,
+            "Unknown".to_owned(),
+            Airline::Unknown,
+            PlaneType::Unknown,
+        );
+
+        let mut recording = Vec::new();
+        write_frame(&mut recording, 0, &[plane.clone()]).unwrap();
+        write_frame(&mut recording, 5200, &[plane, unknown]).unwrap();
+        assert_eq!(recording.iter().filter(|byte| **byte == b'\n').count(), 2);
+
+        let frames = read_recording(recording.as_slice()).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].time(), Duration::from_millis(5200));
+        assert_eq!(frames[1].planes[0].callsign, "AAL123");
+        assert_eq!(frames[1].planes[0].airline, BasicAirline::American.into());
+        assert_eq!(frames[1].planes[0].altitude, Some(3000.0));
+        assert_eq!(frames[1].planes[1].airline, Airline::Unknown);
+        assert_eq!(loop_delay(&frames), Duration::from_millis(5200));
+    }
+
+    #[test]
+    fn loop_delay_averages_the_latest_gaps() {
+        let frames = |times: &[u64]| -> Vec<RecordedFrame> {
+            times
+                .iter()
+                .map(|&time_ms| RecordedFrame {
+                    time_ms,
+                    planes: Vec::new(),
+                })
+                .collect()
+        };
+        assert_eq!(loop_delay(&frames(&[0])), Duration::from_secs(5));
+
+        //One slow request doesn't set the pace on its own
+        let delay = loop_delay(&frames(&[0, 5000, 10000, 25000, 30000]));
+        assert_eq!(delay, Duration::from_millis(7500));
+
+        //Only the gaps near the end count, since those are what the loop follows
+        let mut times: Vec<u64> = vec![0, 60_000];
+        times.extend((1..=16).map(|i| 60_000 + i * 5000));
+        assert_eq!(loop_delay(&frames(&times)), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn invalid_recordings() {
+        assert!(matches!(
+            read_recording(&b"\n\n"[..]),
+            Err(RecordingError::Empty)
+        ));
+        assert!(matches!(
+            read_recording(&b"{\"time_ms\": 0, \"planes\": []}\nnot json\n"[..]),
+            Err(RecordingError::Json { line: 2, .. })
+        ));
+    }
+}
@@ -1,4 +1,8 @@
-use std::io::Cursor;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    io::Cursor,
+    time::{Duration, Instant},
+};
 
 use enum_map::{enum_map, Enum, EnumMap};
 use glam::DVec2;
@@ -6,34 +10,79 @@ use glium::{
     implement_vertex, index::NoIndices, texture::SrgbTexture2d, uniform, DrawParameters, Program,
     Surface,
 };
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    util, world_x_to_pixel_x, world_y_to_pixel_y, Plane, PlaneSelection, PlaneTrails, VerticalTrend,
+};
 
-use crate::{map, util, world_x_to_pixel_x, world_y_to_pixel_y, Plane, PlaneRequester};
+/// Planes are drawn as clusters when the map is zoomed out further than this, as a
+/// [`crate::TileView::map_zoom`]. A 1280 pixel wide window then spans about a continent, where
+/// individual planes can't be told apart
+pub const CLUSTER_MAX_ZOOM: f32 = 5.0;
+
+/// Returns true if planes close together are drawn as clusters for `view`, which is when it is
+/// zoomed out past [`CLUSTER_MAX_ZOOM`]
+pub fn is_zoomed_out_to_cluster(view: &crate::TileView) -> bool {
+    (view.map_zoom() as f32) < CLUSTER_MAX_ZOOM
+}
 
-///Normal body of plane we select
-#[derive(Clone)]
-pub struct SelectedPlane {
-    pub plane: Plane,
+/// The size of the grid cells planes are clustered by, in logical pixels
+pub const CLUSTER_CELL_SIZE: f64 = 48.0;
+
+/// Several planes close together on screen, drawn as a single marker showing how many there are
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaneCluster {
+    /// The average position of the planes in logical pixels from the center of the window, the
+    /// same coordinates conrod uses
     pub location: DVec2,
-    pub size: f32,
+    pub count: usize,
 }
 
-impl SelectedPlane {
-    pub fn new(plane: Plane, location: DVec2, size: f32) -> Self {
-        SelectedPlane {
-            plane,
-            location,
-            size,
-        }
+/// Groups `items` by which `cell_size` square of the screen their entry in `positions` falls in.
+///
+/// Items alone in their cell are returned in their original order, and every cell with more than
+/// one item becomes a [`PlaneCluster`]. Clusters are ordered by cell so that each keeps the same
+/// widget from frame to frame
+pub fn cluster_by_cell<T>(
+    items: Vec<T>,
+    positions: &[DVec2],
+    cell_size: f64,
+) -> (Vec<T>, Vec<PlaneCluster>) {
+    let mut cells: BTreeMap<(i64, i64), Vec<usize>> = BTreeMap::new();
+    for (i, position) in positions.iter().enumerate() {
+        let cell = (
+            (position.x / cell_size).floor() as i64,
+            (position.y / cell_size).floor() as i64,
+        );
+        cells.entry(cell).or_default().push(i);
     }
-}
 
-#[derive(Clone)]
-pub struct LoadingStruct {
-    pub planes_loaded: bool,
-    pub plane_selection: Option<SelectedPlane>,
+    let mut alone = vec![false; items.len()];
+    let mut clusters = Vec::new();
+    for members in cells.values() {
+        if let [only] = members.as_slice() {
+            alone[*only] = true;
+        } else {
+            let total = members
+                .iter()
+                .fold(DVec2::ZERO, |total, i| total + positions[*i]);
+            clusters.push(PlaneCluster {
+                location: total / members.len() as f64,
+                count: members.len(),
+            });
+        }
+    }
+    let items = items
+        .into_iter()
+        .zip(alone)
+        .filter_map(|(item, alone)| alone.then_some(item))
+        .collect();
+    (items, clusters)
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Enum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Enum, Serialize, Deserialize)]
 pub enum PlaneType {
     Commercial,
     Trainer,
@@ -43,6 +92,14 @@ pub enum PlaneType {
 }
 
 impl PlaneType {
+    pub const ALL: [PlaneType; 5] = [
+        PlaneType::Commercial,
+        PlaneType::Cargo,
+        PlaneType::Trainer,
+        PlaneType::Business,
+        PlaneType::Unknown,
+    ];
+
     pub fn to_str(self) -> &'static str {
         match self {
             PlaneType::Commercial => "Commercial",
@@ -54,22 +111,35 @@ impl PlaneType {
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+/// The airline a plane flies for.
+///
+/// This is the one airline type shared by the requester, renderer and filter buttons. The few
+/// airlines with their own filter button are [`Airline::Basic`], the rest we know of from their
+/// callsign prefix are [`Airline::Dynamic`], and anything else is [`Airline::Unknown`]. The
+/// filter pseudo airlines [`BasicAirline::All`] and [`BasicAirline::Other`] only ever describe a
+/// filter selection, never a plane.
+///
+/// Serialized with the variant in `kind` and its data in `airline`, like
+/// `{"kind": "basic", "airline": "Delta"}` or `{"kind": "unknown"}`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "airline", rename_all = "snake_case")]
 pub enum Airline {
     Basic(BasicAirline),
     Dynamic(DynamicAirline),
     Unknown,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+/// An airline without its own filter button, looked up from the list of known callsign prefixes
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct DynamicAirline {
+    /// The three letter callsign prefix, like `FDX`
     pub callsign: String,
     pub name: String,
 }
 
 /// Describes a few specific airlines, and also the selections of All or Other which the user can
 /// filter by
-#[derive(Copy, Clone, PartialEq, Eq, Enum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Enum, Serialize, Deserialize)]
 pub enum BasicAirline {
     American,
     Spirit,
@@ -81,6 +151,19 @@ pub enum BasicAirline {
 }
 
 impl BasicAirline {
+    /// Returns the airline that flies under the three letter callsign prefix `header`, if it is
+    /// one of the basic airlines
+    pub fn from_callsign(header: &str) -> Option<Self> {
+        match header {
+            "NKS" => Some(BasicAirline::Spirit),
+            "AAL" => Some(BasicAirline::American),
+            "SWA" => Some(BasicAirline::Southwest),
+            "UAL" => Some(BasicAirline::United),
+            "DAL" => Some(BasicAirline::Delta),
+            _ => None,
+        }
+    }
+
     pub fn to_str(&self) -> &str {
         match self {
             BasicAirline::American => "American Airlines",
@@ -88,8 +171,8 @@ impl BasicAirline {
             BasicAirline::Southwest => "Southwest Airlines",
             BasicAirline::United => "United Airlines",
             BasicAirline::Delta => "Delta Airlines",
-            BasicAirline::All => unreachable!(),
-            BasicAirline::Other => unreachable!(),
+            BasicAirline::All => "All Airlines",
+            BasicAirline::Other => "Other Airlines",
         }
     }
 }
@@ -104,31 +187,376 @@ impl Airline {
     }
 }
 
+/// The airlines picked with the filter buttons. Planes are only drawn if their airline is selected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AirlineFilter {
+    /// Never empty, and if [`BasicAirline::All`] is selected nothing else is
+    selected: HashSet<BasicAirline>,
+}
+
+impl AirlineFilter {
+    /// Returns true if the filter button for `airline` should be shown as active
+    pub fn is_selected(&self, airline: BasicAirline) -> bool {
+        self.selected.contains(&airline)
+    }
+
+    /// Handles a click on the filter button for `airline`.
+    ///
+    /// Clicking [`BasicAirline::All`] clears every other selection. Any other airline is toggled
+    /// on or off, going back to showing all airlines if the last one is turned off
+    pub fn toggle(&mut self, airline: BasicAirline) {
+        if airline == BasicAirline::All {
+            self.selected.clear();
+        } else {
+            self.selected.remove(&BasicAirline::All);
+            if !self.selected.remove(&airline) {
+                self.selected.insert(airline);
+            }
+        }
+        if self.selected.is_empty() {
+            self.selected.insert(BasicAirline::All);
+        }
+    }
+
+    /// Returns true if planes flying for `airline` should be drawn
+    pub fn shows(&self, airline: &Airline) -> bool {
+        self.is_selected(BasicAirline::All) || self.is_selected(Self::category(airline))
+    }
+
+    /// Returns the filter that shows planes flying for `airline`. Dynamic and unknown airlines
+    /// are shown by [`BasicAirline::Other`]
+    pub fn category(airline: &Airline) -> BasicAirline {
+        match airline {
+            Airline::Basic(basic) => *basic,
+            _ => BasicAirline::Other,
+        }
+    }
+}
+
+impl Default for AirlineFilter {
+    fn default() -> Self {
+        Self {
+            selected: std::iter::once(BasicAirline::All).collect(),
+        }
+    }
+}
+
+/// The plane types picked with the plane type filter buttons. When none are picked every type is
+/// shown
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlaneTypeFilter {
+    selected: HashSet<PlaneType>,
+}
+
+impl PlaneTypeFilter {
+    /// Returns true if the filter button for `plane_type` should be shown as active
+    pub fn is_selected(&self, plane_type: PlaneType) -> bool {
+        self.selected.contains(&plane_type)
+    }
+
+    /// Handles a click on the filter button for `plane_type` by turning it on or off
+    pub fn toggle(&mut self, plane_type: PlaneType) {
+        if !self.selected.remove(&plane_type) {
+            self.selected.insert(plane_type);
+        }
+    }
+
+    /// Returns true if planes of `plane_type` should be drawn
+    pub fn shows(&self, plane_type: PlaneType) -> bool {
+        self.selected.is_empty() || self.is_selected(plane_type)
+    }
+}
+
+/// What the color of each plane icon shows
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PlaneColorMode {
+    /// The color of the plane's airline
+    #[default]
+    Airline,
+    /// A gradient from blue for low planes to red for high ones
+    Altitude,
+    /// A gradient from blue for slow planes to red for fast ones
+    Velocity,
+}
+
+impl PlaneColorMode {
+    pub const ALL: [PlaneColorMode; 3] = [
+        PlaneColorMode::Airline,
+        PlaneColorMode::Altitude,
+        PlaneColorMode::Velocity,
+    ];
+
+    /// The short name shown on the button selecting this mode
+    pub fn label(self) -> &'static str {
+        match self {
+            PlaneColorMode::Airline => "Air",
+            PlaneColorMode::Altitude => "Alt",
+            PlaneColorMode::Velocity => "Spd",
+        }
+    }
+
+    /// Returns where `plane` falls on this mode's gradient, or gray if the value isn't known.
+    /// `None` if this mode doesn't use a gradient
+    pub fn gradient_color(self, plane: &Plane) -> Option<[f32; 3]> {
+        let (low, high, _) = self.scale()?;
+        let value = match self {
+            PlaneColorMode::Airline => None,
+            PlaneColorMode::Altitude => plane.altitude,
+            PlaneColorMode::Velocity => plane.velocity,
+        };
+        Some(value.map_or(NEUTRAL_COLOR, |value| {
+            gradient_color((value - low) / (high - low))
+        }))
+    }
+
+    /// The values at the low and high ends of the gradient, and the unit they are in. `None` if
+    /// this mode doesn't use a gradient
+    pub fn scale(self) -> Option<(f32, f32, &'static str)> {
+        match self {
+            PlaneColorMode::Airline => None,
+            PlaneColorMode::Altitude => Some((0.0, 12_000.0, "m")),
+            PlaneColorMode::Velocity => Some((0.0, 300.0, "m/s")),
+        }
+    }
+}
+
+/// Returns the color at `t` along the blue to red gradient used by the altitude and velocity
+/// color modes. `t` is clamped to 0..1
+pub fn gradient_color(t: f32) -> [f32; 3] {
+    let hue = 240.0 * (1.0 - t.clamp(0.0, 1.0));
+    hsv_to_rgb(hue, 0.85, 0.95)
+}
+
+/// The latitude and longitude range visible in a viewport, used to skip planes and airports that
+/// are off screen.
+///
+/// When the viewport crosses the antimeridian `long_left` is greater than `long_right`, and the
+/// visible longitudes are the ones east of `long_left` or west of `long_right`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PlaneBounds {
+    pub lat_top: f32,
+    pub lat_bottom: f32,
+    pub long_left: f32,
+    pub long_right: f32,
+}
+
+impl PlaneBounds {
+    pub fn from_viewport(viewport: &crate::map::WorldViewport) -> Self {
+        //Wrapping the corners of a viewport wider than the world would make it look tiny
+        let (long_left, long_right) = if viewport.bottom_right.x - viewport.top_left.x >= 1.0 {
+            (-180.0, 180.0)
+        } else {
+            (
+                util::longitude_from_x(viewport.top_left.x.rem_euclid(1.0)) as f32,
+                util::longitude_from_x(viewport.bottom_right.x.rem_euclid(1.0)) as f32,
+            )
+        };
+        Self {
+            lat_top: util::latitude_from_y(viewport.top_left.y.rem_euclid(1.0)) as f32,
+            lat_bottom: util::latitude_from_y(viewport.bottom_right.y.rem_euclid(1.0)) as f32,
+            long_left,
+            long_right,
+        }
+    }
+
+    /// Returns true if `plane` is inside these bounds
+    pub fn contains(&self, plane: &Plane) -> bool {
+        self.contains_lat_long(plane.latitude, plane.longitude)
+    }
+
+    /// Returns true if the location at `latitude` and `longitude` is inside these bounds
+    pub fn contains_lat_long(&self, latitude: f32, longitude: f32) -> bool {
+        let in_longitude = if self.long_left <= self.long_right {
+            longitude >= self.long_left && longitude <= self.long_right
+        } else {
+            longitude >= self.long_left || longitude <= self.long_right
+        };
+        (latitude > self.lat_bottom && latitude < self.lat_top) && in_longitude
+    }
+}
+
 impl From<BasicAirline> for Airline {
     fn from(basic: BasicAirline) -> Self {
         Airline::Basic(basic)
     }
 }
 
+/// A corner of the quad that every plane icon is drawn on
 #[derive(Copy, Clone)]
-pub struct Vertex {
+pub struct QuadVertex {
     pub position: [f32; 2],
+    pub tex_coords: [f32; 2],
+}
+
+implement_vertex!(QuadVertex, position, tex_coords);
+
+/// Where and how a single plane is drawn. The quad is drawn once for each of these
+#[derive(Copy, Clone)]
+pub struct PlaneInstance {
     pub angle: f32,
     pub offset: [f32; 2],
+    pub color: [f32; 3],
+    /// 1.0 for planes on the ground, which are drawn as a small dot instead of the plane icon
+    pub on_ground: f32,
+    /// How opaque the plane is, see [`Plane::fade`]
+    pub alpha: f32,
+    /// The part of the icon atlas with the plane's silhouette, see [`plane_tex_rect`]
+    pub tex_rect: [f32; 4],
+}
+
+implement_vertex!(
+    PlaneInstance,
+    angle,
+    offset,
+    color,
+    on_ground,
+    alpha,
+    tex_rect
+);
+
+/// A corner of a single plane's quad, with everything from its [`PlaneInstance`] copied in. Used
+/// to draw planes on graphics drivers without instanced rendering
+#[derive(Copy, Clone)]
+pub struct PlaneVertex {
+    pub position: [f32; 2],
     pub tex_coords: [f32; 2],
+    pub angle: f32,
+    pub offset: [f32; 2],
     pub color: [f32; 3],
+    pub on_ground: f32,
+    pub alpha: f32,
+    pub tex_rect: [f32; 4],
 }
 
-implement_vertex!(Vertex, position, angle, offset, tex_coords, color);
+implement_vertex!(
+    PlaneVertex,
+    position,
+    tex_coords,
+    angle,
+    offset,
+    color,
+    on_ground,
+    alpha,
+    tex_rect
+);
+
+/// Appends the corners of [`PLANE_QUAD`] for each of `instances` to `vertices`, so that they can
+/// be drawn without instancing
+fn plane_vertices(instances: &[PlaneInstance], vertices: &mut Vec<PlaneVertex>) {
+    for instance in instances {
+        vertices.extend(PLANE_QUAD.iter().map(|corner| PlaneVertex {
+            position: corner.position,
+            tex_coords: corner.tex_coords,
+            angle: instance.angle,
+            offset: instance.offset,
+            color: instance.color,
+            on_ground: instance.on_ground,
+            alpha: instance.alpha,
+            tex_rect: instance.tex_rect,
+        }));
+    }
+}
+
+/// One end of a line segment in a plane's trail
+#[derive(Copy, Clone)]
+pub struct TrailVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+implement_vertex!(TrailVertex, position, color);
+
+/// The fewest vertices a [`ReusableVertexBuffer`] makes room for
+const MIN_VERTEX_CAPACITY: usize = 1024;
+
+/// Returns how many vertices a buffer holding `capacity` needs room for to fit `needed`. Buffers
+/// grow to the next power of two so that a slowly growing number of planes doesn't reallocate
+/// every frame, and never shrink
+fn vertex_capacity(needed: usize, capacity: usize) -> usize {
+    if needed <= capacity {
+        capacity
+    } else {
+        needed.next_power_of_two().max(MIN_VERTEX_CAPACITY)
+    }
+}
+
+/// A vertex buffer that is written to every frame, instead of a new one being allocated for the
+/// vertices of each frame
+pub struct ReusableVertexBuffer<T: Copy> {
+    buffer: Option<glium::VertexBuffer<T>>,
+}
+
+impl<T: glium::Vertex + Send + 'static> ReusableVertexBuffer<T> {
+    pub fn new() -> Self {
+        Self { buffer: None }
+    }
+
+    /// Copies `vertices` to the start of the buffer, growing it if they don't fit, and returns the
+    /// part of the buffer they were written to. Returns `None` if there are no vertices
+    pub fn upload(
+        &mut self,
+        display: &glium::Display,
+        vertices: &[T],
+    ) -> Option<glium::vertex::VertexBufferSlice<'_, T>> {
+        if vertices.is_empty() {
+            return None;
+        }
+        let capacity = self.buffer.as_ref().map_or(0, |buffer| buffer.len());
+        let needed = vertex_capacity(vertices.len(), capacity);
+        if needed != capacity {
+            self.buffer = Some(glium::VertexBuffer::empty_dynamic(display, needed).unwrap());
+        }
+        let slice = self.buffer.as_ref()?.slice(0..vertices.len())?;
+        slice.write(vertices);
+        Some(slice)
+    }
+}
+
+impl<T: glium::Vertex + Send + 'static> Default for ReusableVertexBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// This struct renders the planes that are requested by the API and displays them using custom OpenGL
 pub struct PlaneRenderer<'a> {
     pub program: Program,
     pub draw_parameters: DrawParameters<'a>,
-    pub vertices: Vec<Vertex>,
+    /// The two triangles every plane is drawn with
+    pub quad: glium::VertexBuffer<QuadVertex>,
+    pub instances: Vec<PlaneInstance>,
+    /// Where `instances` are uploaded to each frame
+    pub instance_buffer: ReusableVertexBuffer<PlaneInstance>,
+    /// Every corner of every plane, for drivers that can't draw `instances`
+    pub fallback_vertices: Vec<PlaneVertex>,
+    /// Where `fallback_vertices` are uploaded to each frame
+    pub fallback_vertex_buffer: ReusableVertexBuffer<PlaneVertex>,
+    pub trail_program: Program,
+    pub trail_vertices: Vec<TrailVertex>,
+    /// Where `trail_vertices` are uploaded to each frame
+    pub trail_vertex_buffer: ReusableVertexBuffer<TrailVertex>,
     pub texture: SrgbTexture2d,
     pub indices: NoIndices,
     pub color_map: EnumMap<BasicAirline, [f32; 3]>,
+    /// Colors set for dynamic airlines, keyed by callsign prefix. Dynamic airlines without an
+    /// entry get a color from [`dynamic_airline_color`]
+    pub dynamic_color_map: HashMap<String, [f32; 3]>,
+    /// The size of plane icons in pixels when zoomed all the way out
+    pub min_plane_size: f32,
+    /// The size of plane icons in pixels when zoomed all the way in
+    pub max_plane_size: f32,
+    /// Whether planes close together are drawn as clusters when the map is zoomed out past
+    /// [`CLUSTER_MAX_ZOOM`], see [`is_zoomed_out_to_cluster`]
+    pub cluster_planes: bool,
+    /// When the renderer was made, which planes in an emergency flash in time with
+    pub started: Instant,
+    /// Whether planes on the ground are drawn. Off by default
+    pub include_on_ground: bool,
+    /// How long planes that stop being reported take to fade out, which should match what the
+    /// [`crate::PlaneRequester`] was made with
+    pub plane_linger: Duration,
+    /// How many meters per second a plane has to climb or descend to get a chevron next to it
+    pub climb_threshold: f32,
 }
 
 impl<'a> PlaneRenderer<'a> {
@@ -142,17 +570,27 @@ impl<'a> PlaneRenderer<'a> {
             in vec2 offset;
             in vec2 tex_coords;
             in vec3 color;
+            in float on_ground;
+            in float alpha;
+            in vec4 tex_rect;
 
             out vec2 v_tex_coords;
+            out vec2 v_quad_coords;
             out vec3 v_color;
+            out float v_on_ground;
+            out float v_alpha;
 
             uniform mat4 matrix;
             uniform float dpi_factor;
 
             void main() {
-                v_tex_coords = tex_coords;
+                v_tex_coords = tex_rect.xy + tex_coords * tex_rect.zw;
+                v_quad_coords = tex_coords;
                 v_color = color;
-                vec2 pos = position;
+                v_on_ground = on_ground;
+                v_alpha = alpha;
+                // Planes on the ground are a dot with no heading, a third of the size of the icon
+                vec2 pos = position * mix(1.0, 0.35, on_ground);
                 vec2 new_position = vec2(pos.x * cos(angle) - pos.y * sin(angle), pos.x * sin(angle) + pos.y * cos(angle));
                 vec4 scaled = matrix * vec4(new_position, 0.0, 1.0);
                 vec4 with_offset = vec4(offset * dpi_factor, 0.0, 0.0) + scaled;
@@ -164,14 +602,18 @@ impl<'a> PlaneRenderer<'a> {
             #version 140
 
             in vec2 v_tex_coords;
+            in vec2 v_quad_coords;
             in vec3 v_color;
+            in float v_on_ground;
+            in float v_alpha;
             out vec4 color;
 
             uniform sampler2D tex;
 
             void main() {
                 float tex_alpha = texture(tex, v_tex_coords).a;
-                color = vec4(v_color, tex_alpha);
+                float dot_alpha = step(length(v_quad_coords - vec2(0.5)), 0.5) * 0.8;
+                color = vec4(v_color, mix(tex_alpha, dot_alpha, v_on_ground) * v_alpha);
             }
         "#;
 
@@ -179,8 +621,41 @@ impl<'a> PlaneRenderer<'a> {
             glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None)
                 .unwrap();
 
+        let trail_vertex_shader_src = r#"
+            #version 140
+
+            in vec2 position;
+            in vec4 color;
+
+            out vec4 v_color;
+
+            void main() {
+                v_color = color;
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        "#;
+
+        let trail_fragment_shader_src = r#"
+            #version 140
+
+            in vec4 v_color;
+            out vec4 color;
+
+            void main() {
+                color = v_color;
+            }
+        "#;
+
+        let trail_program = glium::Program::from_source(
+            display,
+            trail_vertex_shader_src,
+            trail_fragment_shader_src,
+            None,
+        )
+        .unwrap();
+
         let image = image::load(
-            Cursor::new(&include_bytes!("../assets/images/airplane-image.png")),
+            Cursor::new(&include_bytes!("../assets/images/plane-atlas.png")),
             image::ImageFormat::Png,
         )
         .unwrap()
@@ -205,182 +680,426 @@ impl<'a> PlaneRenderer<'a> {
             BasicAirline::Spirit => [1.0, 1.0, 0.0],
             BasicAirline::United => [146.0 / 255.0, 182.0 / 255.0, 240.0 / 255.0],
             BasicAirline::Southwest => [229.0 / 255.0, 29.0 / 255.0, 35.0 / 255.0],
-            _ => [0.0, 0.0, 0.0]
+            BasicAirline::Delta => [112.0 / 255.0, 20.0 / 255.0, 60.0 / 255.0],
+            BasicAirline::All | BasicAirline::Other => NEUTRAL_COLOR,
         };
 
         Self {
             program,
             draw_parameters,
-            vertices: Vec::new(),
+            quad: glium::VertexBuffer::new(display, &PLANE_QUAD).unwrap(),
+            instances: Vec::new(),
+            instance_buffer: ReusableVertexBuffer::new(),
+            fallback_vertices: Vec::new(),
+            fallback_vertex_buffer: ReusableVertexBuffer::new(),
+            trail_program,
+            trail_vertices: Vec::new(),
+            trail_vertex_buffer: ReusableVertexBuffer::new(),
             texture,
             indices,
             color_map,
+            dynamic_color_map: HashMap::new(),
+            min_plane_size: DEFAULT_MIN_PLANE_SIZE,
+            max_plane_size: DEFAULT_MAX_PLANE_SIZE,
+            cluster_planes: true,
+            started: Instant::now(),
+            include_on_ground: false,
+            plane_linger: crate::DEFAULT_PLANE_LINGER,
+            climb_threshold: DEFAULT_CLIMB_THRESHOLD,
+        }
+    }
+
+    /// Sets the color that planes flying for `airline` are drawn with.
+    ///
+    /// Setting the color of [`Airline::Unknown`] changes the color of
+    /// [`BasicAirline::Other`], which unknown planes share
+    pub fn set_airline_color(&mut self, airline: Airline, color: [f32; 3]) {
+        match airline {
+            Airline::Basic(basic) => self.color_map[basic] = color,
+            Airline::Dynamic(dynamic) => {
+                self.dynamic_color_map.insert(dynamic.callsign, color);
+            }
+            Airline::Unknown => self.color_map[BasicAirline::Other] = color,
+        }
+    }
+
+    /// Returns the color that planes flying for `airline` are drawn with
+    pub fn airline_color(&self, airline: &Airline) -> [f32; 3] {
+        match airline {
+            Airline::Basic(basic) => self.color_map[*basic],
+            Airline::Dynamic(dynamic) => self
+                .dynamic_color_map
+                .get(&dynamic.callsign)
+                .copied()
+                .unwrap_or_else(|| dynamic_airline_color(&dynamic.callsign)),
+            Airline::Unknown => self.color_map[BasicAirline::Other],
         }
     }
 
-    /// Draw the planes on the OpenGL Frame that is provided
+    /// Returns the color `plane` is drawn with in `mode`
+    pub fn plane_color(&self, plane: &Plane, mode: PlaneColorMode) -> [f32; 3] {
+        mode.gradient_color(plane)
+            .unwrap_or_else(|| self.airline_color(&plane.airline))
+    }
+
+    /// Sets airline colors from a color table.
+    ///
+    /// Each line of the table is a callsign prefix and a hex color, like `AAL - #030587`. Blank
+    /// lines and lines starting with `#` are ignored. If any line is invalid no colors are changed
+    pub fn load_color_table(&mut self, table: &str) -> Result<(), ColorTableError> {
+        let colors = parse_color_table(table)?;
+        for (callsign, color) in colors {
+            let airline = match BasicAirline::from_callsign(&callsign) {
+                Some(basic) => Airline::Basic(basic),
+                None => Airline::Dynamic(DynamicAirline {
+                    name: callsign.clone(),
+                    callsign,
+                }),
+            };
+            self.set_airline_color(airline, color);
+        }
+        Ok(())
+    }
+
+    /// Returns which of `planes` are drawn for `view`, the ones that pass the filters and are on
+    /// screen. With the map zoomed out past [`CLUSTER_MAX_ZOOM`], planes close together are left
+    /// out and returned as clusters instead when clustering is enabled.
+    ///
+    /// `screen_size` is the size of the window in logical pixels, the same as the map and overlay
+    /// use.
+    ///
+    /// Planes in an emergency are always drawn on their own, whatever the filters are. Planes that
+    /// have completely faded out are never drawn
+    pub fn layout_planes<'p>(
+        &self,
+        planes: &'p [Plane],
+        view: &crate::TileView,
+        screen_size: DVec2,
+        airline_filter: &AirlineFilter,
+        plane_type_filter: &PlaneTypeFilter,
+    ) -> (Vec<&'p Plane>, Vec<PlaneCluster>) {
+        let (width, height) = (screen_size.x, screen_size.y);
+        let viewport = view.get_world_viewport(width, height);
+        let bounds = PlaneBounds::from_viewport(&viewport);
+        let now = Instant::now();
+
+        let visible: Vec<&Plane> = planes
+            .iter()
+            .filter(|plane| {
+                passes_filters(
+                    plane,
+                    airline_filter,
+                    plane_type_filter,
+                    self.include_on_ground,
+                ) && bounds.contains(plane)
+                    && !plane.is_faded_out(now, self.plane_linger)
+            })
+            .collect();
+
+        // Zoomed far out, planes close together on screen are drawn as one marker instead
+        if self.cluster_planes && is_zoomed_out_to_cluster(view) {
+            let (emergencies, visible): (Vec<&Plane>, Vec<&Plane>) = visible
+                .into_iter()
+                .partition(|plane| plane.emergency().is_some());
+            let positions: Vec<DVec2> = visible
+                .iter()
+                .map(|plane| {
+                    let world_x = viewport.wrap_x(util::x_from_longitude(plane.longitude as f64));
+                    let world_y = util::y_from_latitude(plane.latitude as f64);
+                    DVec2::new(
+                        world_x_to_pixel_x(world_x, &viewport, width),
+                        world_y_to_pixel_y(world_y, &viewport, height),
+                    )
+                })
+                .collect();
+            let (mut alone, clusters) = cluster_by_cell(visible, &positions, CLUSTER_CELL_SIZE);
+            alone.extend(emergencies);
+            (alone, clusters)
+        } else {
+            (visible, Vec::new())
+        }
+    }
+
+    /// Draws `planes`, as chosen by [`PlaneRenderer::layout_planes`], and their trails onto
+    /// `target`. The planes hovered or clicked in `selection` are drawn white, and planes in an
+    /// emergency flash, see [`emergency_color`]. Planes on the ground are drawn as small dots in a
+    /// duller color, and planes that stopped being reported fade out. Planes climbing or
+    /// descending faster than [`PlaneRenderer::climb_threshold`] get a chevron beside them
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &mut self,
         display: &glium::Display,
         target: &mut glium::Frame,
-        plane_requester: &mut PlaneRequester,
+        planes: &[&Plane],
+        trails: &PlaneTrails,
         view: &crate::TileView,
-        selected_airline: BasicAirline,
-        clicked_plane: &mut Option<SelectedPlane>,
-        mut last_cursor_pos: Option<DVec2>,
-    ) -> LoadingStruct {
+        color_mode: PlaneColorMode,
+        selection: &PlaneSelection,
+    ) {
         // Here we collect the dynamic numbers for rendering our OpenGL planes
         let (width, height) = target.get_dimensions();
         let width = width as f32;
         let height = height as f32;
         let dpi_factor = display.gl_window().window().scale_factor() as f32;
 
-        // From PlaneRequester gets all the airlines and planes
-        let airlines = plane_requester.planes_storage();
+        // Viewport of the world, which the view measures in logical pixels like the map does
+        let viewport =
+            view.get_world_viewport((width / dpi_factor) as f64, (height / dpi_factor) as f64);
+        let zoom = view.map_zoom() as f32;
+        let size_of_plane = plane_size(zoom, self.min_plane_size, self.max_plane_size);
+        // Half the width and height of a plane icon in window coordinates
+        let plane_radius = [
+            size_of_plane * dpi_factor / width,
+            size_of_plane * dpi_factor / height,
+        ];
+
+        self.instances.clear();
+        self.trail_vertices.clear();
+        let now = Instant::now();
+        let flash_color = emergency_color(now - self.started);
 
-        let planes_loaded = !airlines.is_empty();
+        // We iterate through all the planes and generated their OpenGL vertices
+        for plane in planes {
+            let color = match plane.emergency() {
+                Some(_) => flash_color,
+                None if plane.on_ground => ground_color(self.plane_color(plane, color_mode)),
+                None => self.plane_color(plane, color_mode),
+            };
 
-        // Viewport of the world
-        let viewport = view.get_world_viewport(width as f64, height as f64);
-        let lat_top = crate::util::latitude_from_y(viewport.top_left.y.rem_euclid(1.0)) as f32;
-        let lat_bottom =
-            crate::util::latitude_from_y(viewport.bottom_right.y.rem_euclid(1.0)) as f32;
-        let long_left = crate::util::longitude_from_x(viewport.top_left.x.rem_euclid(1.0)) as f32;
-        let long_right =
-            crate::util::longitude_from_x(viewport.bottom_right.x.rem_euclid(1.0)) as f32;
-        let zoom = view.get_zoom() as f32;
+            // Translates real world coordinates to window coordinates.
+            let world_x = viewport.wrap_x(util::x_from_longitude(plane.longitude as f64));
+            let world_y = util::y_from_latitude(plane.latitude as f64);
 
-        let size_of_plane = 1.5_f32.powf(zoom) / 30.0;
-        if let Some(pos) = last_cursor_pos {
-            let cursor_x = map(0.0, width as f64, pos.x, -1.0, 1.0) / dpi_factor as f64;
-            let cursor_y = map(0.0, height as f64, pos.y, 1.0, -1.0) / dpi_factor as f64;
+            let offset_x = world_x_to_window_x(world_x, &viewport);
+            let offset_y = world_y_to_window_y(world_y, &viewport);
 
-            last_cursor_pos = Some(DVec2::new(cursor_x, cursor_y));
-        }
+            let alpha = plane.fade(now, self.plane_linger);
+            if let Some(trail) = plane.id().and_then(|id| trails.get(id)) {
+                let [r, g, b] = color;
+                trail_shape(trail, &viewport, [r, g, b, alpha], &mut self.trail_vertices);
+            }
 
-        let mut selected_plane = None;
-        let closest_x = 0.01;
-        let closest_y = 0.01;
+            let [r, g, b] = color;
+            let [radius_x, radius_y] = plane_radius;
+            chevron_shape(
+                [offset_x + radius_x * 1.4, offset_y],
+                [radius_x * 0.4, radius_y * 0.3],
+                plane.vertical_trend(self.climb_threshold),
+                [r, g, b, alpha],
+                &mut self.trail_vertices,
+            );
+
+            self.instances.push(PlaneInstance {
+                angle: plane.track,
+                offset: [offset_x, offset_y],
+                // Selected planes are drawn white so they stand out, but keep their trail color
+                color: if selection.is_highlighted(plane) {
+                    [1.0, 1.0, 1.0]
+                } else {
+                    color
+                },
+                on_ground: if plane.on_ground { 1.0 } else { 0.0 },
+                alpha,
+                tex_rect: plane_tex_rect(plane.plane_type),
+            });
+        }
 
-        //Margin error to compare the distance of planes
-        let margin_error_distance = 0.00001;
+        // Trails and chevrons go first so that planes are drawn on top of them
+        if let Some(trail_buffer) = self
+            .trail_vertex_buffer
+            .upload(display, &self.trail_vertices)
+        {
+            target
+                .draw(
+                    trail_buffer,
+                    glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                    &self.trail_program,
+                    &glium::uniforms::EmptyUniforms,
+                    &self.draw_parameters,
+                )
+                .unwrap();
+        }
 
-        self.vertices.clear();
+        let aspect_ratio = height / width;
+        let scale_factor = (size_of_plane / height) * dpi_factor;
 
-        let mut plane_position: DVec2 = DVec2::new(0.0, 0.0);
+        let matrix: [[f32; 4]; 4] =
+            cgmath::Matrix4::from_nonuniform_scale(aspect_ratio * scale_factor, scale_factor, 1.0)
+                .into();
 
-        // We iterate through all the planes and generated their OpenGL vertices
-        for plane in airlines.iter() {
-            let airline = &plane.airline;
-            let color = match airline {
-                Airline::Basic(airline) => {
-                    if airline == &selected_airline || selected_airline == BasicAirline::All {
-                        Some(self.color_map[airline.clone()])
-                    } else {
-                        None
-                    }
-                }
-                _ => {
-                    if selected_airline == BasicAirline::All
-                        || selected_airline == BasicAirline::Other
-                    {
-                        Some([0.0, 0.0, 0.0])
-                    } else {
-                        None
-                    }
-                } //Only show dynamic airlines when showing all planes
-            };
+        let uniforms = uniform! {
+            matrix: matrix,
+            tex: &self.texture,
+            dpi_factor: dpi_factor
+        };
 
-            if let Some(color) = color {
-                for plane in plane.planes.iter() {
-                    if (plane.latitude > lat_bottom && plane.latitude < lat_top)
-                        && (plane.longitude > long_left && plane.longitude < long_right)
+        if let Some(instances) = self.instance_buffer.upload(display, &self.instances) {
+            match instances.per_instance() {
+                Ok(instances) => target
+                    .draw(
+                        (&self.quad, instances),
+                        self.indices,
+                        &self.program,
+                        &uniforms,
+                        &self.draw_parameters,
+                    )
+                    .unwrap(),
+                //Older drivers can't draw instances, so give them every corner of every plane
+                Err(_) => {
+                    self.fallback_vertices.clear();
+                    plane_vertices(&self.instances, &mut self.fallback_vertices);
+                    if let Some(vertices) = self
+                        .fallback_vertex_buffer
+                        .upload(display, &self.fallback_vertices)
                     {
-                        // Translates real world coordinates to window coordinates.
-                        let world_x = util::x_from_longitude(plane.longitude as f64);
-                        let world_y = util::y_from_latitude(plane.latitude as f64);
-
-                        let offset_x = world_x_to_window_x(world_x, &viewport);
-                        let offset_y = world_y_to_window_y(world_y, &viewport);
-
-                        let pixel_x = world_x_to_pixel_x(world_x, &viewport, width as f64);
-                        let pixel_y = world_y_to_pixel_y(world_y, &viewport, height as f64);
-
-                        let color = if let Some(last_cursor_pos) = last_cursor_pos {
-                            if (offset_x - last_cursor_pos.x as f32).abs() < closest_x
-                                && (offset_y - last_cursor_pos.y as f32).abs() < closest_y
-                            {
-                                //Gets the plane position as a DVec2
-                                plane_position = DVec2::new(pixel_x, pixel_y);
-
-                                selected_plane = Some(plane.clone());
-
-                                // Draw it as white
-                                [1.0, 1.0, 1.0]
-                            } else {
-                                color
-                            }
-                        } else {
-                            color
-                        };
-
-                        //Show details about already clicked planes
-                        if let Some(clicked_plane) = clicked_plane {
-                            if clicked_plane.plane.callsign == plane.callsign
-                                && (clicked_plane.plane.latitude - plane.latitude).abs()
-                                    > margin_error_distance
-                                && (clicked_plane.plane.longitude - plane.longitude).abs()
-                                    > margin_error_distance
-                            {
-                                //Updates the new plane data.
-                                clicked_plane.plane = plane.clone();
-                            }
-                        }
-
-                        let offset = [offset_x, offset_y];
-
-                        // Generate the vertices
-                        let plane = plane_shape(plane.track, offset, color);
-
-                        for vertex in plane {
-                            self.vertices.push(vertex);
-                        }
+                        target
+                            .draw(
+                                vertices,
+                                self.indices,
+                                &self.program,
+                                &uniforms,
+                                &self.draw_parameters,
+                            )
+                            .unwrap();
                     }
                 }
             }
         }
+    }
+}
 
-        let vertex_buffer = glium::VertexBuffer::new(display, &self.vertices).unwrap();
+/// Returns true if `plane` is shown with the airline and plane type filters set as they are, and
+/// planes on the ground shown if `include_on_ground` is set.
+///
+/// Planes in an emergency are always shown, even on the ground with `include_on_ground` unset: a
+/// plane that just landed squawking 7700 is still worth keeping an eye on, and hiding it because
+/// of a filter would defeat the point of flagging emergencies
+pub fn passes_filters(
+    plane: &Plane,
+    airline_filter: &AirlineFilter,
+    plane_type_filter: &PlaneTypeFilter,
+    include_on_ground: bool,
+) -> bool {
+    plane.emergency().is_some()
+        || (include_on_ground || !plane.on_ground)
+            && airline_filter.shows(&plane.airline)
+            && plane_type_filter.shows(plane.plane_type)
+}
 
-        let aspect_ratio = height as f32 / width as f32;
-        let scale_factor = (size_of_plane / height as f32) * dpi_factor;
+/// Returns the duller version of `color` that planes on the ground are drawn with, so that they
+/// don't draw attention away from the planes in the air
+pub fn ground_color(color: [f32; 3]) -> [f32; 3] {
+    color.map(|channel| util::lerp(channel, NEUTRAL_COLOR[0], 0.6))
+}
 
-        let matrix: [[f32; 4]; 4] =
-            cgmath::Matrix4::from_nonuniform_scale(aspect_ratio * scale_factor, scale_factor, 1.0)
-                .into();
+/// How long planes in an emergency are drawn in each of [`EMERGENCY_COLORS`]
+pub const EMERGENCY_FLASH_MS: u128 = 400;
 
-        let uniforms = uniform! {
-            matrix: matrix,
-            tex: &self.texture,
-            dpi_factor: dpi_factor
-        };
+/// The colors planes in an emergency flash between
+const EMERGENCY_COLORS: [[f32; 3]; 2] = [[1.0, 0.05, 0.05], [1.0, 0.85, 0.0]];
 
-        target
-            .draw(
-                &vertex_buffer,
-                &self.indices,
-                &self.program,
-                &uniforms,
-                &self.draw_parameters,
-            )
-            .unwrap();
+/// Returns the color planes in an emergency are drawn with `elapsed` into their flashing. This
+/// wins over the airline and color mode so they can't be missed
+pub fn emergency_color(elapsed: Duration) -> [f32; 3] {
+    let phase = elapsed.as_millis() / EMERGENCY_FLASH_MS;
+    EMERGENCY_COLORS[(phase % 2) as usize]
+}
+
+/// The default size of plane icons in pixels when zoomed all the way out
+pub const DEFAULT_MIN_PLANE_SIZE: f32 = 12.0;
+
+/// The default size of plane icons in pixels when zoomed all the way in
+pub const DEFAULT_MAX_PLANE_SIZE: f32 = 40.0;
+
+/// Plane icons are `min_size` at this zoom level and below
+const MIN_SIZE_ZOOM: f32 = 3.0;
+
+/// Plane icons are `max_size` at this zoom level and above
+const MAX_SIZE_ZOOM: f32 = 15.0;
+
+/// Returns the size of plane icons in pixels at `zoom`, from [`crate::TileView::map_zoom`].
+///
+/// The size grows linearly with the zoom level between [`MIN_SIZE_ZOOM`] and [`MAX_SIZE_ZOOM`],
+/// so each step zooming in grows planes by the same number of pixels
+pub fn plane_size(zoom: f32, min_size: f32, max_size: f32) -> f32 {
+    let t = ((zoom - MIN_SIZE_ZOOM) / (MAX_SIZE_ZOOM - MIN_SIZE_ZOOM)).clamp(0.0, 1.0);
+    min_size + (max_size - min_size) * t
+}
+
+/// The color of planes that don't belong to any airline we know of
+const NEUTRAL_COLOR: [f32; 3] = [0.35, 0.35, 0.35];
+
+/// Picks a color for a dynamic airline from its callsign prefix.
+///
+/// The same callsign always gets the same color, and the colors are bright enough to stand out
+/// from [`NEUTRAL_COLOR`]
+pub fn dynamic_airline_color(callsign: &str) -> [f32; 3] {
+    //FNV-1a, so that colors don't change between runs or rust versions
+    let hash = callsign.bytes().fold(0x811c9dc5_u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    let hue = (hash % 360) as f32;
+    hsv_to_rgb(hue, 0.65, 0.85)
+}
+
+/// Converts a color with a hue in degrees and saturation and value in 0..1 to rgb
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let chroma = value * saturation;
+    let h = hue / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [r + m, g + m, b + m]
+}
+
+/// Reasons a color table passed to [`PlaneRenderer::load_color_table`] can't be read
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ColorTableError {
+    #[error("line {0}: expected `CALLSIGN - #rrggbb`")]
+    MissingColor(usize),
 
-        LoadingStruct {
-            planes_loaded,
-            plane_selection: selected_plane
-                .map(|plane| SelectedPlane::new(plane, plane_position, size_of_plane)),
+    #[error("line {0}: invalid hex color")]
+    InvalidColor(usize),
+}
+
+/// Reads the (callsign prefix, color) pairs out of a color table
+fn parse_color_table(table: &str) -> Result<Vec<(String, [f32; 3])>, ColorTableError> {
+    let mut colors = Vec::new();
+    for (i, line) in table.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
+        let line_number = i + 1;
+        let (callsign, color) = line
+            .split_once('-')
+            .ok_or(ColorTableError::MissingColor(line_number))?;
+        let color =
+            parse_hex_color(color.trim()).ok_or(ColorTableError::InvalidColor(line_number))?;
+        colors.push((callsign.trim().to_owned(), color));
     }
+    Ok(colors)
+}
+
+/// Parses a color written like `#030587`
+fn parse_hex_color(color: &str) -> Option<[f32; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i..i + 2)?, 16)
+            .ok()
+            .map(|c| c as f32 / 255.0)
+    };
+    Some([channel(0)?, channel(2)?, channel(4)?])
 }
 
 /// Projects a x world location combined with a viewport to determine the x location in the OpenGL
@@ -407,36 +1126,606 @@ pub fn world_y_to_window_y(world_y: f64, viewport: &crate::map::WorldViewport) -
     ) as f32
 }
 
-/// Generates a set of vertices that describe a single plane that must be drawn
-pub fn plane_shape(angle: f32, offset: [f32; 2], color: [f32; 3]) -> [Vertex; 6] {
-    let vertex1 = Vertex {
+/// How opaque the newest segment of a trail is. Older segments fade out towards transparent
+const TRAIL_ALPHA: f32 = 0.8;
+
+/// Appends line segments joining the positions in `trail` to `out`, fading out with age. The alpha
+/// of `color` is how opaque the newest end of the trail is, relative to [`TRAIL_ALPHA`]
+///
+/// Segments that cross the antimeridian are skipped, otherwise they would streak across the
+/// whole map
+pub fn trail_shape(
+    trail: &VecDeque<(f32, f32)>,
+    viewport: &crate::map::WorldViewport,
+    color: [f32; 4],
+    out: &mut Vec<TrailVertex>,
+) {
+    let to_window = |(longitude, latitude): (f32, f32)| {
+        [
+            world_x_to_window_x(
+                viewport.wrap_x(util::x_from_longitude(longitude as f64)),
+                viewport,
+            ),
+            world_y_to_window_y(util::y_from_latitude(latitude as f64), viewport),
+        ]
+    };
+    let segments = trail.len().saturating_sub(1);
+    for (i, (start, end)) in trail.iter().zip(trail.iter().skip(1)).enumerate() {
+        if (end.0 - start.0).abs() > 180.0 {
+            continue;
+        }
+        let [r, g, b, a] = color;
+        let alpha_at = |point: usize| a * TRAIL_ALPHA * point as f32 / segments as f32;
+        out.push(TrailVertex {
+            position: to_window(*start),
+            color: [r, g, b, alpha_at(i)],
+        });
+        out.push(TrailVertex {
+            position: to_window(*end),
+            color: [r, g, b, alpha_at(i + 1)],
+        });
+    }
+}
+
+/// The default of [`PlaneRenderer::climb_threshold`] in meters per second, fast enough that the
+/// noise in the vertical rate of planes in level flight doesn't make chevrons flicker
+pub const DEFAULT_CLIMB_THRESHOLD: f32 = 2.5;
+
+/// Appends the two line segments of a chevron centered on `center` to `out`, pointing up for
+/// climbing planes and down for descending ones. Level planes get nothing. `half_size` is half
+/// the chevron's width and height, in window coordinates like `center`
+pub fn chevron_shape(
+    center: [f32; 2],
+    half_size: [f32; 2],
+    trend: VerticalTrend,
+    color: [f32; 4],
+    out: &mut Vec<TrailVertex>,
+) {
+    let direction = match trend {
+        VerticalTrend::Climbing => 1.0,
+        VerticalTrend::Descending => -1.0,
+        VerticalTrend::Level => return,
+    };
+    let [x, y] = center;
+    let [half_width, half_height] = half_size;
+    let tip = [x, y + half_height * direction];
+    for side in [-1.0, 1.0] {
+        let end = [x + half_width * side, y - half_height * direction];
+        out.push(TrailVertex {
+            position: tip,
+            color,
+        });
+        out.push(TrailVertex {
+            position: end,
+            color,
+        });
+    }
+}
+
+/// How many silhouettes are side by side in `plane-atlas.png`
+const PLANE_ATLAS_CELLS: usize = 4;
+
+/// Returns the left, bottom, width and height of the silhouette of `plane_type` in the plane icon
+/// atlas, in texture coordinates. Planes of unknown type are drawn as airliners
+pub fn plane_tex_rect(plane_type: PlaneType) -> [f32; 4] {
+    let cell = match plane_type {
+        PlaneType::Commercial | PlaneType::Unknown => 0,
+        PlaneType::Cargo => 1,
+        PlaneType::Trainer => 2,
+        PlaneType::Business => 3,
+    };
+    let width = 1.0 / PLANE_ATLAS_CELLS as f32;
+    [cell as f32 * width, 0.0, width, 1.0]
+}
+
+/// The vertices of the quad every plane is drawn on, rotated and moved into place by the vertex
+/// shader using the plane's [`PlaneInstance`]
+const PLANE_QUAD: [QuadVertex; 6] = {
+    let top_left = QuadVertex {
         position: [-1.0, 1.0],
-        angle,
-        offset,
         tex_coords: [0.0, 1.0],
-        color,
     };
-    let vertex2 = Vertex {
+    let top_right = QuadVertex {
         position: [1.0, 1.0],
-        angle,
-        offset,
         tex_coords: [1.0, 1.0],
-        color,
     };
-    let vertex3 = Vertex {
+    let bottom_right = QuadVertex {
         position: [1.0, -1.0],
-        angle,
-        offset,
         tex_coords: [1.0, 0.0],
-        color,
     };
-    let vertex4 = Vertex {
+    let bottom_left = QuadVertex {
         position: [-1.0, -1.0],
-        angle,
-        offset,
         tex_coords: [0.0, 0.0],
-        color,
     };
 
-    [vertex1, vertex2, vertex3, vertex4, vertex3, vertex1]
+    [
+        top_left,
+        top_right,
+        bottom_right,
+        bottom_left,
+        bottom_right,
+        top_left,
+    ]
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn airline_serialization() {
+        let airlines = [
+            Airline::Basic(BasicAirline::Delta),
+            Airline::Dynamic(DynamicAirline {
+                callsign: "FDX".to_owned(),
+                name: "FedEx".to_owned(),
+            }),
+            Airline::Unknown,
+        ];
+        let json: Vec<String> = airlines
+            .iter()
+            .map(|airline| serde_json::to_string(airline).unwrap())
+            .collect();
+        assert_eq!(
+            json,
+            [
+                r#"{"kind":"basic","airline":"Delta"}"#,
+                r#"{"kind":"dynamic","airline":{"callsign":"FDX","name":"FedEx"}}"#,
+                r#"{"kind":"unknown"}"#,
+            ]
+        );
+        for (airline, json) in airlines.iter().zip(&json) {
+            assert_eq!(&serde_json::from_str::<Airline>(json).unwrap(), airline);
+        }
+
+        for plane_type in PlaneType::ALL {
+            let json = serde_json::to_string(&plane_type).unwrap();
+            assert_eq!(json, format!("\"{}\"", plane_type.to_str()));
+            assert_eq!(
+                serde_json::from_str::<PlaneType>(&json).unwrap(),
+                plane_type
+            );
+        }
+    }
+
+    #[test]
+    fn planes_cluster_by_cell() {
+        let positions = [
+            DVec2::new(5.0, 5.0),
+            DVec2::new(100.0, 100.0),
+            DVec2::new(15.0, 25.0),
+            DVec2::new(-5.0, 5.0),
+            DVec2::new(25.0, 35.0),
+        ];
+        let (alone, clusters) = cluster_by_cell(vec!['a', 'b', 'c', 'd', 'e'], &positions, 48.0);
+        assert_eq!(alone, ['b', 'd']);
+        assert_eq!(
+            clusters,
+            [PlaneCluster {
+                location: DVec2::new(15.0, 65.0 / 3.0),
+                count: 3,
+            }]
+        );
+
+        //Nothing is clustered when every plane has a cell to itself
+        let (alone, clusters) = cluster_by_cell(vec![1, 2], &positions[..2], 48.0);
+        assert_eq!(alone, [1, 2]);
+        assert!(clusters.is_empty());
+    }
+
+    #[test]
+    fn clustering_depends_on_map_zoom() {
+        //The whole world is clustered however wide the window is
+        for width in [800.0, 1280.0, 2560.0] {
+            let world = crate::TileView::new(0.0, 0.0, 0.0, width);
+            assert!(is_zoomed_out_to_cluster(&world));
+        }
+        //North America, about 60 degrees of longitude across
+        let continent = crate::TileView::new(40.0, -100.0, 2.6, 1280.0);
+        assert!(is_zoomed_out_to_cluster(&continent));
+        //Florida, about 10 degrees across, already shows every plane
+        let state = crate::TileView::new(28.0, -82.0, 5.2, 1280.0);
+        assert!(!is_zoomed_out_to_cluster(&state));
+        let city = crate::TileView::new(29.18796, -81.04923, 9.0, 1280.0);
+        assert!(!is_zoomed_out_to_cluster(&city));
+    }
+
+    #[test]
+    fn fallback_vertices_match_instances() {
+        let instance = |angle| PlaneInstance {
+            angle,
+            offset: [0.5, -0.25],
+            color: [1.0, 0.0, 0.0],
+            on_ground: 0.0,
+            alpha: 0.5,
+            tex_rect: plane_tex_rect(PlaneType::Cargo),
+        };
+        let mut vertices = Vec::new();
+        plane_vertices(&[instance(0.0), instance(90.0)], &mut vertices);
+        assert_eq!(vertices.len(), 2 * PLANE_QUAD.len());
+        for (vertex, corner) in vertices[PLANE_QUAD.len()..].iter().zip(PLANE_QUAD.iter()) {
+            assert_eq!(vertex.position, corner.position);
+            assert_eq!(vertex.tex_coords, corner.tex_coords);
+            assert_eq!(vertex.angle, 90.0);
+            assert_eq!(vertex.offset, [0.5, -0.25]);
+            assert_eq!(vertex.tex_rect, plane_tex_rect(PlaneType::Cargo));
+        }
+    }
+
+    #[test]
+    fn vertex_buffers_grow_rarely() {
+        assert_eq!(vertex_capacity(6, 0), MIN_VERTEX_CAPACITY);
+        assert_eq!(
+            vertex_capacity(600, MIN_VERTEX_CAPACITY),
+            MIN_VERTEX_CAPACITY
+        );
+        assert_eq!(vertex_capacity(1030, MIN_VERTEX_CAPACITY), 2048);
+        //Fewer planes keep the bigger buffer
+        assert_eq!(vertex_capacity(6, 4096), 4096);
+    }
+
+    #[test]
+    fn emergencies_pass_every_filter() {
+        let mut plane = Plane::new(
+            0.0,
+            0.0,
+            0.0,
+            "N123".to_owned(),
+            Airline::Unknown,
+            PlaneType::Unknown,
+        );
+        let mut airline_filter = AirlineFilter::default();
+        airline_filter.toggle(BasicAirline::Delta);
+        let plane_type_filter = PlaneTypeFilter::default();
+        assert!(!passes_filters(
+            &plane,
+            &airline_filter,
+            &plane_type_filter,
+            false
+        ));
+
+        plane.squawk = Some("7600".to_owned());
+        assert!(passes_filters(
+            &plane,
+            &airline_filter,
+            &plane_type_filter,
+            false
+        ));
+
+        //Including when planes on the ground are hidden
+        plane.on_ground = true;
+        assert!(passes_filters(
+            &plane,
+            &airline_filter,
+            &plane_type_filter,
+            false
+        ));
+    }
+
+    #[test]
+    fn planes_on_the_ground_are_optional() {
+        let mut plane = Plane::new(
+            0.0,
+            0.0,
+            0.0,
+            "N123".to_owned(),
+            Airline::Unknown,
+            PlaneType::Unknown,
+        );
+        plane.on_ground = true;
+        let (airlines, plane_types) = (AirlineFilter::default(), PlaneTypeFilter::default());
+        assert!(!passes_filters(&plane, &airlines, &plane_types, false));
+        assert!(passes_filters(&plane, &airlines, &plane_types, true));
+
+        //Duller, but still the same hue
+        let red = ground_color([1.0, 0.0, 0.0]);
+        assert!(red[0] < 1.0 && red[1] > 0.0 && red[0] > red[1]);
+    }
+
+    #[test]
+    fn emergencies_flash() {
+        let first = emergency_color(Duration::ZERO);
+        assert_eq!(emergency_color(Duration::from_millis(399)), first);
+        assert_ne!(emergency_color(Duration::from_millis(400)), first);
+        assert_eq!(emergency_color(Duration::from_millis(800)), first);
+    }
+
+    #[test]
+    fn chevrons_point_the_way_planes_are_heading() {
+        let mut plane = Plane::new(
+            0.0,
+            0.0,
+            0.0,
+            "AAL1".to_owned(),
+            Airline::Unknown,
+            PlaneType::Commercial,
+        );
+        let trend = |rate: Option<f32>, on_ground: bool| {
+            let mut plane = plane.clone();
+            plane.vertical_rate = rate;
+            plane.on_ground = on_ground;
+            plane.vertical_trend(DEFAULT_CLIMB_THRESHOLD)
+        };
+        assert_eq!(trend(Some(8.0), false), VerticalTrend::Climbing);
+        assert_eq!(trend(Some(-8.0), false), VerticalTrend::Descending);
+        //Small changes in level flight and unknown rates don't count
+        assert_eq!(trend(Some(2.0), false), VerticalTrend::Level);
+        assert_eq!(trend(Some(-2.0), false), VerticalTrend::Level);
+        assert_eq!(trend(None, false), VerticalTrend::Level);
+        assert_eq!(trend(Some(8.0), true), VerticalTrend::Level);
+        plane.vertical_rate = Some(2.0);
+        assert_eq!(plane.vertical_trend(1.0), VerticalTrend::Climbing);
+
+        let color = [1.0, 0.0, 0.0, 1.0];
+        let chevron = |trend| {
+            let mut out = Vec::new();
+            chevron_shape([0.5, 0.5], [0.1, 0.2], trend, color, &mut out);
+            out.iter().map(|vertex| vertex.position).collect::<Vec<_>>()
+        };
+        //Both lines start at the tip, which is at the top when climbing
+        assert_eq!(
+            chevron(VerticalTrend::Climbing),
+            [[0.5, 0.7], [0.4, 0.3], [0.5, 0.7], [0.6, 0.3]]
+        );
+        assert_eq!(
+            chevron(VerticalTrend::Descending),
+            [[0.5, 0.3], [0.4, 0.7], [0.5, 0.3], [0.6, 0.7]]
+        );
+        assert!(chevron(VerticalTrend::Level).is_empty());
+    }
+
+    #[test]
+    fn plane_types_have_their_own_silhouettes() {
+        let rects: Vec<[f32; 4]> = PlaneType::ALL.into_iter().map(plane_tex_rect).collect();
+        for [left, bottom, width, height] in &rects {
+            assert!(*left >= 0.0 && left + width <= 1.0);
+            assert_eq!((*bottom, *height), (0.0, 1.0));
+        }
+        //Every known type gets a cell to itself
+        let known = &rects[..4];
+        for (i, a) in known.iter().enumerate() {
+            for b in &known[i + 1..] {
+                assert!(a[0] + a[2] <= b[0] || b[0] + b[2] <= a[0]);
+            }
+        }
+        assert_eq!(
+            plane_tex_rect(PlaneType::Unknown),
+            plane_tex_rect(PlaneType::Commercial)
+        );
+
+        //The atlas is as wide as the cells say
+        let atlas =
+            image::load_from_memory(include_bytes!("../assets/images/plane-atlas.png")).unwrap();
+        let (width, height) = atlas.to_rgba8().dimensions();
+        assert_eq!(width, height * PLANE_ATLAS_CELLS as u32);
+    }
+
+    #[test]
+    fn plane_size_stays_in_bounds() {
+        let size = |zoom| plane_size(zoom, DEFAULT_MIN_PLANE_SIZE, DEFAULT_MAX_PLANE_SIZE);
+        assert_eq!(size(0.0), DEFAULT_MIN_PLANE_SIZE);
+        assert_eq!(size(MIN_SIZE_ZOOM), DEFAULT_MIN_PLANE_SIZE);
+        assert_eq!(size(MAX_SIZE_ZOOM), DEFAULT_MAX_PLANE_SIZE);
+        assert_eq!(size(25.0), DEFAULT_MAX_PLANE_SIZE);
+
+        let mut last = 0.0;
+        for zoom in 0..=20 {
+            let size = size(zoom as f32);
+            assert!((DEFAULT_MIN_PLANE_SIZE..=DEFAULT_MAX_PLANE_SIZE).contains(&size));
+            assert!(size >= last);
+            last = size;
+        }
+
+        //Looking at the whole world, planes are about as small as they get however wide the
+        //window is
+        let view_size = |view: crate::TileView| size(view.map_zoom() as f32);
+        for width in [800.0, 1280.0, 2560.0] {
+            let world = crate::TileView::new(0.0, 0.0, 0.0, width);
+            assert!(view_size(world) < DEFAULT_MIN_PLANE_SIZE + 1.0);
+        }
+
+        //With a city about 70 kilometers across filling the window, planes are in between
+        let city = crate::TileView::new(29.18796, -81.04923, 9.0, 1280.0);
+        let city_size = view_size(city);
+        assert!(city_size > DEFAULT_MIN_PLANE_SIZE && city_size < DEFAULT_MAX_PLANE_SIZE);
+        //Zoomed in to a few streets they are as big as they get
+        let streets = crate::TileView::new(29.18796, -81.04923, 15.0, 1280.0);
+        assert_eq!(view_size(streets), DEFAULT_MAX_PLANE_SIZE);
+    }
+
+    #[test]
+    fn trails_fade_and_skip_antimeridian() {
+        let viewport = crate::map::WorldViewport {
+            top_left: DVec2::new(0.0, 0.0),
+            bottom_right: DVec2::new(1.0, 1.0),
+        };
+        let trail: VecDeque<_> = vec![(170.0, 0.0), (179.0, 0.0), (-179.0, 0.0), (-170.0, 0.0)]
+            .into_iter()
+            .collect();
+        let mut vertices = Vec::new();
+        trail_shape(&trail, &viewport, [1.0, 0.0, 0.0, 1.0], &mut vertices);
+
+        //The middle segment jumps across the map, so only the outer two are drawn
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(vertices[0].color[3], 0.0);
+        assert_eq!(vertices[3].color[3], TRAIL_ALPHA);
+        assert!(vertices[1].color[3] < vertices[2].color[3]);
+        assert!(vertices.iter().all(|v| v.position[0].abs() <= 1.0));
+    }
+
+    #[test]
+    fn airline_filter_selection() {
+        let american = Airline::from(BasicAirline::American);
+        let united = Airline::from(BasicAirline::United);
+        let mut filter = AirlineFilter::default();
+        assert!(filter.shows(&american) && filter.shows(&Airline::Unknown));
+
+        filter.toggle(BasicAirline::American);
+        filter.toggle(BasicAirline::United);
+        assert!(!filter.is_selected(BasicAirline::All));
+        assert!(filter.shows(&american) && filter.shows(&united));
+        assert!(!filter.shows(&Airline::Unknown));
+
+        filter.toggle(BasicAirline::Other);
+        assert!(filter.shows(&Airline::Unknown));
+
+        //Turning everything back off shows all airlines again
+        filter.toggle(BasicAirline::American);
+        filter.toggle(BasicAirline::United);
+        filter.toggle(BasicAirline::Other);
+        assert_eq!(filter, AirlineFilter::default());
+
+        filter.toggle(BasicAirline::Spirit);
+        filter.toggle(BasicAirline::All);
+        assert_eq!(filter, AirlineFilter::default());
+    }
+
+    #[test]
+    fn plane_type_filter_selection() {
+        let mut filter = PlaneTypeFilter::default();
+        assert!(PlaneType::ALL.iter().all(|t| filter.shows(*t)));
+
+        filter.toggle(PlaneType::Cargo);
+        assert!(filter.shows(PlaneType::Cargo));
+        assert!(!filter.shows(PlaneType::Commercial));
+
+        filter.toggle(PlaneType::Cargo);
+        assert_eq!(filter, PlaneTypeFilter::default());
+    }
+
+    #[test]
+    fn color_modes() {
+        let mut plane = Plane::new(
+            0.0,
+            0.0,
+            0.0,
+            "AAL1".to_owned(),
+            BasicAirline::American.into(),
+            PlaneType::Commercial,
+        );
+        assert_eq!(PlaneColorMode::Airline.gradient_color(&plane), None);
+        assert_eq!(
+            PlaneColorMode::Altitude.gradient_color(&plane),
+            Some(NEUTRAL_COLOR)
+        );
+
+        //Low planes are blue and high ones are red, even beyond the ends of the scale
+        plane.altitude = Some(-50.0);
+        let low = PlaneColorMode::Altitude.gradient_color(&plane).unwrap();
+        plane.altitude = Some(20_000.0);
+        let high = PlaneColorMode::Altitude.gradient_color(&plane).unwrap();
+        assert_eq!(low, gradient_color(0.0));
+        assert_eq!(high, gradient_color(1.0));
+        assert!(low[2] > low[0] && high[0] > high[2]);
+
+        plane.velocity = Some(150.0);
+        assert_eq!(
+            PlaneColorMode::Velocity.gradient_color(&plane),
+            Some(gradient_color(0.5))
+        );
+    }
+
+    #[test]
+    fn plane_bounds() {
+        let viewport = crate::map::WorldViewport {
+            top_left: DVec2::new(0.25, 0.25),
+            bottom_right: DVec2::new(0.75, 0.75),
+        };
+        let bounds = PlaneBounds::from_viewport(&viewport);
+        let plane_at = |longitude, latitude| {
+            Plane::new(
+                longitude,
+                latitude,
+                0.0,
+                "AAL1".to_owned(),
+                Airline::Unknown,
+                PlaneType::Unknown,
+            )
+        };
+        assert!(bounds.contains(&plane_at(0.0, 0.0)));
+        assert!(bounds.contains(&plane_at(80.0, 60.0)));
+        assert!(!bounds.contains(&plane_at(100.0, 0.0)));
+        assert!(!bounds.contains(&plane_at(0.0, -70.0)));
+
+        //A viewport from 144°E across the antimeridian to 144°W
+        let viewport = crate::map::WorldViewport {
+            top_left: DVec2::new(0.9, 0.25),
+            bottom_right: DVec2::new(1.1, 0.75),
+        };
+        let bounds = PlaneBounds::from_viewport(&viewport);
+        assert!(bounds.contains(&plane_at(170.0, 0.0)));
+        assert!(bounds.contains(&plane_at(-170.0, 10.0)));
+        assert!(bounds.contains(&plane_at(180.0, 0.0)));
+        assert!(!bounds.contains(&plane_at(0.0, 0.0)));
+        assert!(!bounds.contains(&plane_at(-100.0, 0.0)));
+        //Planes on the other side of the antimeridian are drawn to the right of it
+        let x = viewport.wrap_x(util::x_from_longitude(-170.0));
+        assert!(x > 1.0 && x < viewport.bottom_right.x);
+        let x = viewport.wrap_x(util::x_from_longitude(170.0));
+        assert!(x > viewport.top_left.x && x < 1.0);
+
+        //Zoomed out far enough to see the whole world more than once
+        let viewport = crate::map::WorldViewport {
+            top_left: DVec2::new(-0.3, 0.25),
+            bottom_right: DVec2::new(1.3, 0.75),
+        };
+        let bounds = PlaneBounds::from_viewport(&viewport);
+        assert!(bounds.contains(&plane_at(-179.0, 0.0)));
+        assert!(bounds.contains(&plane_at(0.0, 0.0)));
+        assert!(bounds.contains(&plane_at(179.0, 0.0)));
+    }
+
+    #[test]
+    fn airline_names() {
+        assert_eq!(
+            Airline::from(BasicAirline::Delta).to_str(),
+            "Delta Airlines"
+        );
+        assert_eq!(Airline::from(BasicAirline::All).to_str(), "All Airlines");
+        assert_eq!(Airline::Unknown.to_str(), "Unknown");
+        let dynamic = Airline::Dynamic(DynamicAirline {
+            callsign: "FDX".to_owned(),
+            name: "FedEx Express".to_owned(),
+        });
+        assert_eq!(dynamic.to_str(), "FedEx Express");
+        assert_eq!(
+            BasicAirline::from_callsign("DAL"),
+            Some(BasicAirline::Delta)
+        );
+        assert_eq!(BasicAirline::from_callsign("FDX"), None);
+    }
+
+    #[test]
+    fn dynamic_colors_are_stable_and_distinct() {
+        assert_eq!(dynamic_airline_color("FDX"), dynamic_airline_color("FDX"));
+        assert_ne!(dynamic_airline_color("FDX"), dynamic_airline_color("UPS"));
+        assert_ne!(dynamic_airline_color("FDX"), NEUTRAL_COLOR);
+        for channel in dynamic_airline_color("JBU") {
+            assert!((0.0..=1.0).contains(&channel));
+        }
+    }
+
+    #[test]
+    fn color_table_parsing() {
+        let table = "# Airline colors\n\nAAL - #030587\n FDX - #ff0000 \n";
+        assert_eq!(
+            parse_color_table(table),
+            Ok(vec![
+                ("AAL".to_owned(), [3.0 / 255.0, 5.0 / 255.0, 135.0 / 255.0]),
+                ("FDX".to_owned(), [1.0, 0.0, 0.0]),
+            ])
+        );
+        assert_eq!(
+            parse_color_table("AAL #030587"),
+            Err(ColorTableError::MissingColor(1))
+        );
+        assert_eq!(
+            parse_color_table("\nAAL - #03058"),
+            Err(ColorTableError::InvalidColor(2))
+        );
+        assert_eq!(
+            parse_color_table("AAL - #0305zz"),
+            Err(ColorTableError::InvalidColor(1))
+        );
+    }
 }
@@ -0,0 +1,345 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use glam::DVec2;
+
+use crate::map::{TileView, WorldViewport};
+use crate::util::{self, Units};
+use crate::{world_x_to_pixel_x, world_y_to_pixel_y, Plane};
+
+/// How far from the cursor a plane can be and still be hovered, in logical pixels, unless
+/// `FLIGHT_TRACKER_HOVER_RADIUS` says otherwise
+pub const DEFAULT_HOVER_RADIUS: f64 = 8.0;
+
+///Normal body of plane we select
+#[derive(Clone)]
+pub struct SelectedPlane {
+    pub plane: Plane,
+    /// Where the plane is drawn, in logical pixels from the center of the window like conrod
+    pub location: DVec2,
+    pub size: f32,
+}
+
+impl SelectedPlane {
+    pub fn new(plane: Plane, location: DVec2, size: f32) -> Self {
+        SelectedPlane {
+            plane,
+            location,
+            size,
+        }
+    }
+}
+
+/// The planes the user has picked out, either by hovering over them or by clicking them or
+/// stepping to them with the keyboard.
+///
+/// The plane data is replaced on every update from OpenSky, so planes are matched up with their
+/// new data by [`Plane::id`]. The plane renderer highlights whichever planes are selected here
+#[derive(Clone)]
+pub struct PlaneSelection {
+    /// The plane under the cursor, which has a tooltip drawn next to the cursor
+    pub hovered: Option<SelectedPlane>,
+    /// The plane whose details are shown on the left of the window, as it was when it was clicked.
+    /// See [`PlaneSelection::clicked_plane`] for its latest data
+    pub clicked: Option<Plane>,
+    /// How far from the cursor a plane can be and still be hovered, in logical pixels
+    hover_radius: f64,
+}
+
+impl Default for PlaneSelection {
+    fn default() -> Self {
+        Self::new(DEFAULT_HOVER_RADIUS)
+    }
+}
+
+impl PlaneSelection {
+    pub fn new(hover_radius: f64) -> Self {
+        Self {
+            hovered: None,
+            clicked: None,
+            hover_radius,
+        }
+    }
+
+    /// Finds the plane under `cursor` among `planes`, the ones drawn individually this frame.
+    ///
+    /// `cursor` is in logical pixels from the top left of the window, and `plane_size` is how
+    /// many pixels wide planes are drawn
+    pub fn update_hover(
+        &mut self,
+        planes: &[&Plane],
+        view: &TileView,
+        cursor: Option<DVec2>,
+        screen_size: DVec2,
+        plane_size: f32,
+    ) {
+        let viewport = view.get_world_viewport(screen_size.x, screen_size.y);
+        self.hovered = cursor.and_then(|cursor| {
+            plane_under_cursor(planes, &viewport, cursor, screen_size, self.hover_radius)
+                .map(|(plane, location)| SelectedPlane::new(plane.clone(), location, plane_size))
+        });
+    }
+
+    /// Shows the details of the hovered plane, returning false if there isn't one
+    pub fn click_hovered(&mut self) -> bool {
+        match &self.hovered {
+            Some(hovered) => {
+                self.clicked = Some(hovered.plane.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Clicks the plane after the clicked one among `visible`, or the one before it when
+    /// `backwards` is true. See [`cycle_plane`]
+    pub fn cycle<'a>(&mut self, visible: impl IntoIterator<Item = &'a Plane>, backwards: bool) {
+        if let Some(plane) = cycle_plane(visible, self.clicked.as_ref(), backwards) {
+            self.clicked = Some(plane.clone());
+        }
+    }
+
+    /// Returns the latest data of the clicked plane, or `None` if it is no longer in `planes` or has
+    /// faded out completely at `now`, see [`Plane::fade`]
+    pub fn clicked_plane<'a>(
+        &self,
+        planes: &'a [Plane],
+        now: Instant,
+        linger: Duration,
+    ) -> Option<&'a Plane> {
+        let clicked = self.clicked.as_ref()?;
+        planes
+            .iter()
+            .find(|plane| plane.is_same_plane(clicked) && !plane.is_faded_out(now, linger))
+    }
+
+    /// Returns true if `plane` is hovered or clicked
+    pub fn is_highlighted(&self, plane: &Plane) -> bool {
+        self.clicked
+            .as_ref()
+            .is_some_and(|clicked| clicked.is_same_plane(plane))
+            || self
+                .hovered
+                .as_ref()
+                .is_some_and(|hovered| hovered.plane.is_same_plane(plane))
+    }
+}
+
+/// Returns the plane in `planes` closest to `cursor`, as long as it is within `radius` pixels of
+/// it, along with where the plane is in conrod's coordinates.
+///
+/// `cursor` is in pixels from the top left of the window. Planes at the same distance are ordered
+/// by callsign so the pick doesn't flicker between overlapping planes as the requester reorders
+/// them
+fn plane_under_cursor<'a>(
+    planes: &[&'a Plane],
+    viewport: &WorldViewport,
+    cursor: DVec2,
+    screen_size: DVec2,
+    radius: f64,
+) -> Option<(&'a Plane, DVec2)> {
+    let cursor = DVec2::new(
+        cursor.x - screen_size.x / 2.0,
+        screen_size.y / 2.0 - cursor.y,
+    );
+    planes
+        .iter()
+        .map(|plane| {
+            let world_x = viewport.wrap_x(util::x_from_longitude(plane.longitude as f64));
+            let world_y = util::y_from_latitude(plane.latitude as f64);
+            let location = DVec2::new(
+                world_x_to_pixel_x(world_x, viewport, screen_size.x),
+                world_y_to_pixel_y(world_y, viewport, screen_size.y),
+            );
+            (*plane, location, location.distance(cursor))
+        })
+        .filter(|(_, _, distance)| *distance < radius)
+        .min_by(|(a, _, a_distance), (b, _, b_distance)| {
+            a_distance
+                .total_cmp(b_distance)
+                .then_with(|| a.callsign.cmp(&b.callsign))
+        })
+        .map(|(plane, location, _)| (plane, location))
+}
+
+/// Writes the short description of `plane` shown in the tooltip while it is hovered: its callsign
+/// and altitude in `units`
+pub fn write_tooltip(plane: &Plane, units: Units, out: &mut impl Write) -> std::fmt::Result {
+    write!(out, "{}", plane.callsign)?;
+    match plane.altitude {
+        Some(altitude) => write!(out, "  {}", util::format_altitude(altitude as f64, units)),
+        None => write!(out, "  altitude unknown"),
+    }
+}
+
+/// Returns the plane after `current` among `planes` ordered by callsign, or the one before it when
+/// `backwards` is true, for stepping through planes with the keyboard. Planes sharing a callsign
+/// are ordered by their ICAO 24-bit address.
+///
+/// Wraps around at either end. When `current` isn't in `planes` it picks up from where it would be
+/// in the order
+pub fn cycle_plane<'a>(
+    planes: impl IntoIterator<Item = &'a Plane>,
+    current: Option<&Plane>,
+    backwards: bool,
+) -> Option<&'a Plane> {
+    fn order(plane: &Plane) -> (&str, &str) {
+        (&plane.callsign, &plane.icao24)
+    }
+    let mut planes: Vec<&Plane> = planes.into_iter().collect();
+    if planes.is_empty() {
+        return None;
+    }
+    planes.sort_by(|a, b| order(a).cmp(&order(b)));
+
+    let index = match (current.map(order), backwards) {
+        (None, false) => 0,
+        (None, true) => planes.len() - 1,
+        (Some(current), false) => {
+            let after = planes.partition_point(|plane| order(plane) <= current);
+            after % planes.len()
+        }
+        (Some(current), true) => {
+            let before = planes.partition_point(|plane| order(plane) < current);
+            before.checked_sub(1).unwrap_or(planes.len() - 1)
+        }
+    };
+    Some(planes[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Airline, PlaneType};
+
+    fn plane(callsign: &str, latitude: f32, longitude: f32) -> Plane {
+        Plane::new(
+            longitude,
+            latitude,
+            0.0,
+            callsign.to_owned(),
+            Airline::Unknown,
+            PlaneType::Commercial,
+        )
+    }
+
+    #[test]
+    fn faded_planes_cannot_be_clicked() {
+        let (now, linger) = (Instant::now(), Duration::from_secs(10));
+        let mut missing = plane("AAL1", 0.0, 0.0);
+        let mut selection = PlaneSelection::default();
+        selection.cycle([&missing], false);
+
+        //Still fading out
+        missing.missing_since = Some(now - linger / 2);
+        assert!(selection
+            .clicked_plane(std::slice::from_ref(&missing), now, linger)
+            .is_some());
+
+        //Fully faded, but not removed by the requester yet
+        missing.missing_since = Some(now - linger);
+        assert!(selection
+            .clicked_plane(std::slice::from_ref(&missing), now, linger)
+            .is_none());
+    }
+
+    #[test]
+    fn cycling_through_planes() {
+        let planes = [
+            plane("DAL2", 0.0, 0.0),
+            plane("AAL1", 0.0, 0.0),
+            plane("SWA3", 0.0, 0.0),
+        ];
+        let next = |current: Option<&str>, backwards| {
+            let current = current.map(|callsign| plane(callsign, 0.0, 0.0));
+            cycle_plane(&planes, current.as_ref(), backwards).map(|plane| plane.callsign.as_str())
+        };
+
+        assert_eq!(next(None, false), Some("AAL1"));
+        assert_eq!(next(None, true), Some("SWA3"));
+        assert_eq!(next(Some("AAL1"), false), Some("DAL2"));
+        assert_eq!(next(Some("DAL2"), true), Some("AAL1"));
+        //Wraps around at the ends
+        assert_eq!(next(Some("SWA3"), false), Some("AAL1"));
+        assert_eq!(next(Some("AAL1"), true), Some("SWA3"));
+        //A plane that flew out of view is replaced by its neighbor
+        assert_eq!(next(Some("BAW9"), false), Some("DAL2"));
+        assert_eq!(next(Some("BAW9"), true), Some("AAL1"));
+
+        assert!(cycle_plane(&[], Some(&planes[0]), false).is_none());
+
+        //Planes without a callsign are still stepped through one at a time
+        let mut unknown = [plane("Unknown", 0.0, 0.0), plane("Unknown", 0.0, 0.0)];
+        unknown[0].icao24 = "a00002".to_owned();
+        unknown[1].icao24 = "a00001".to_owned();
+        let first = cycle_plane(&unknown, None, false).unwrap();
+        assert_eq!(first.icao24, "a00001");
+        let second = cycle_plane(&unknown, Some(first), false).unwrap();
+        assert_eq!(second.icao24, "a00002");
+    }
+
+    #[test]
+    fn nearest_plane_is_hovered() {
+        let screen_size = DVec2::new(1000.0, 500.0);
+        let view = TileView::new(29.0, -81.0, 16.0, screen_size.x);
+        let (lat, long) = view.screen_to_lat_long(DVec2::new(600.0, 200.0), 1000.0, 500.0);
+        let (lat, long) = (lat as f32, long as f32);
+        let far = plane("AAL1", 29.0, -81.0);
+        let near = plane("UAL2", lat, long);
+        //Drawn on top of the near plane, so ties go to the lowest callsign
+        let tied = plane("DAL3", lat, long);
+
+        let mut selection = PlaneSelection::default();
+        let cursor = Some(DVec2::new(601.0, 201.0));
+        selection.update_hover(&[&far, &near], &view, cursor, screen_size, 10.0);
+        let hovered = selection.hovered.as_ref().unwrap();
+        assert_eq!(hovered.plane.callsign, "UAL2");
+        assert!((hovered.location - DVec2::new(100.0, 50.0)).length() < 0.1);
+
+        selection.update_hover(&[&near, &tied, &far], &view, cursor, screen_size, 10.0);
+        assert_eq!(selection.hovered.as_ref().unwrap().plane.callsign, "DAL3");
+        assert!(selection.click_hovered());
+        assert!(selection.is_highlighted(&tied));
+
+        //Too far from any plane, but the clicked one stays clicked
+        let cursor = Some(DVec2::new(900.0, 450.0));
+        selection.update_hover(&[&far, &near], &view, cursor, screen_size, 10.0);
+        assert!(selection.hovered.is_none());
+        assert!(!selection.click_hovered());
+        assert!(selection.is_highlighted(&tied));
+        assert!(!selection.is_highlighted(&near));
+        let (now, linger) = (Instant::now(), Duration::from_secs(10));
+        assert_eq!(
+            selection
+                .clicked_plane(&[far.clone(), tied.clone()], now, linger)
+                .unwrap()
+                .callsign,
+            "DAL3"
+        );
+        assert!(selection.clicked_plane(&[far], now, linger).is_none());
+
+        //A bigger radius reaches planes further from the cursor
+        let cursor = Some(DVec2::new(620.0, 200.0));
+        selection.update_hover(&[&near], &view, cursor, screen_size, 10.0);
+        assert!(selection.hovered.is_none());
+        let mut selection = PlaneSelection::new(30.0);
+        selection.update_hover(&[&near], &view, cursor, screen_size, 10.0);
+        assert_eq!(selection.hovered.unwrap().plane.callsign, "UAL2");
+    }
+
+    #[test]
+    fn tooltip_text() {
+        let mut plane = plane("AAL1", 0.0, 0.0);
+        let mut text = String::new();
+        write_tooltip(&plane, Units::Metric, &mut text).unwrap();
+        assert_eq!(text, "AAL1  altitude unknown");
+
+        plane.altitude = Some(10_668.4);
+        text.clear();
+        write_tooltip(&plane, Units::Metric, &mut text).unwrap();
+        assert_eq!(text, "AAL1  10668 m");
+        text.clear();
+        write_tooltip(&plane, Units::Aviation, &mut text).unwrap();
+        assert_eq!(text, "AAL1  35001 ft");
+    }
+}
@@ -1,16 +1,24 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::{runtime::Runtime, time::Instant};
 
+use log::warn;
 use opensky_api::errors::Error;
+use serde::{Deserialize, Serialize};
 
-use crate::{Airline, BasicAirline, DynamicAirline, PlaneType};
+use crate::plane_recording::{self, PlaneRecorder, RecordedFrame, RecordingError};
+use crate::{Airline, BasicAirline, DynamicAirline, PlaneType, Shutdown};
 
 /// The body of a Plane
 ///
 /// Right Now we only care about Long and Lat;
 /// It will maybe be bigger depending on things we may like
 /// The planes to do.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Plane {
     pub longitude: f32,
     pub latitude: f32,
@@ -18,6 +26,29 @@ pub struct Plane {
     pub airline: Airline,
     pub plane_type: PlaneType,
     pub callsign: String,
+    /// The ICAO 24-bit address of the plane's transponder as six hex digits. Unlike the callsign
+    /// it is unique to each plane, see [`Plane::id`]. Empty in recordings made before it was kept
+    #[serde(default)]
+    pub icao24: String,
+    /// Barometric altitude in meters, if known
+    pub altitude: Option<f32>,
+    /// Ground speed in meters per second, if known
+    pub velocity: Option<f32>,
+    /// How fast the plane is climbing in meters per second, negative when descending, if known.
+    /// See [`Plane::vertical_trend`]
+    #[serde(default)]
+    pub vertical_rate: Option<f32>,
+    /// The four digit code set on the plane's transponder, if known. See [`Plane::emergency`]
+    #[serde(default)]
+    pub squawk: Option<String>,
+    /// Whether the plane is on the ground, taxiing or parked. These are only drawn when
+    /// [`crate::PlaneRenderer::include_on_ground`] is set
+    #[serde(default)]
+    pub on_ground: bool,
+    /// When the plane went missing from the updates, for planes that are kept around for a while
+    /// to fade out. `None` while it is still being reported, see [`Plane::fade`]
+    #[serde(skip)]
+    pub missing_since: Option<std::time::Instant>,
 }
 impl Plane {
     ///Constructor on to make a new Plane
@@ -36,54 +67,291 @@ impl Plane {
             airline,
             plane_type,
             callsign,
+            icao24: String::new(),
+            altitude: None,
+            velocity: None,
+            vertical_rate: None,
+            squawk: None,
+            on_ground: false,
+            missing_since: None,
         }
     }
+
+    /// Returns how opaque the plane is drawn at `now`: fully while it is still being reported, then
+    /// fading away over `linger` once it goes missing
+    pub fn fade(&self, now: std::time::Instant, linger: Duration) -> f32 {
+        match self.missing_since {
+            None => 1.0,
+            Some(_) if linger.is_zero() => 0.0,
+            Some(since) => {
+                let missing = now.saturating_duration_since(since);
+                1.0 - (missing.as_secs_f32() / linger.as_secs_f32()).min(1.0)
+            }
+        }
+    }
+
+    /// Returns true once the plane has completely faded out at `now`, so it is no longer drawn or
+    /// selectable, even though it may not have been removed yet. See [`Plane::fade`]
+    pub fn is_faded_out(&self, now: std::time::Instant, linger: Duration) -> bool {
+        self.fade(now, linger) == 0.0
+    }
+
+    /// Returns the emergency the crew is reporting with the transponder, if any
+    pub fn emergency(&self) -> Option<Emergency> {
+        self.squawk.as_deref().and_then(Emergency::from_squawk)
+    }
+
+    /// Returns whether the plane is climbing or descending faster than `threshold` meters per
+    /// second. Planes on the ground and planes with an unknown vertical rate are level
+    pub fn vertical_trend(&self, threshold: f32) -> VerticalTrend {
+        match self.vertical_rate {
+            _ if self.on_ground => VerticalTrend::Level,
+            Some(rate) if rate > threshold => VerticalTrend::Climbing,
+            Some(rate) if rate < -threshold => VerticalTrend::Descending,
+            _ => VerticalTrend::Level,
+        }
+    }
+
+    /// Returns what tells this plane apart from the others from one update to the next: its ICAO
+    /// 24-bit address, or its callsign in recordings without addresses. Planes with neither can't
+    /// be told apart, so they have no id
+    pub fn id(&self) -> Option<&str> {
+        if !self.icao24.is_empty() {
+            Some(&self.icao24)
+        } else if self.callsign != "Unknown" {
+            Some(&self.callsign)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if `other` is this plane, possibly from a different update
+    pub fn is_same_plane(&self, other: &Plane) -> bool {
+        self.id().is_some() && self.id() == other.id()
+    }
 }
 
-pub struct PlaneBody {
-    pub planes: Vec<Plane>,
-    pub airline: Airline,
-    pub plane_type: PlaneType,
+/// Whether a plane is gaining or losing height, see [`Plane::vertical_trend`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VerticalTrend {
+    Climbing,
+    Level,
+    Descending,
 }
 
-impl PlaneBody {
-    pub fn new(planes: Vec<Plane>, airline: Airline, plane_type: PlaneType) -> Self {
-        PlaneBody {
-            planes,
-            airline,
-            plane_type,
+/// What is wrong on a plane whose crew set one of the emergency codes on its transponder
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Emergency {
+    /// Squawk 7500
+    Hijacking,
+    /// Squawk 7600, the plane can't talk to air traffic control over the radio
+    RadioFailure,
+    /// Squawk 7700, any other emergency
+    General,
+}
+
+impl Emergency {
+    /// Returns the emergency the transponder code `squawk` means, if it is one of the emergency
+    /// codes
+    pub fn from_squawk(squawk: &str) -> Option<Self> {
+        match squawk.trim() {
+            "7500" => Some(Emergency::Hijacking),
+            "7600" => Some(Emergency::RadioFailure),
+            "7700" => Some(Emergency::General),
+            _ => None,
         }
     }
-    pub fn empty_commercial(airline: Airline) -> Self {
-        PlaneBody {
-            planes: Vec::new(),
-            airline,
-            plane_type: PlaneType::Commercial,
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Emergency::Hijacking => "Hijacking",
+            Emergency::RadioFailure => "Radio failure",
+            Emergency::General => "Emergency",
         }
     }
 }
 
+/// How long planes that went missing from the updates are kept fading out by default
+pub const DEFAULT_PLANE_LINGER: Duration = Duration::from_secs(10);
+
+/// How many past positions are kept for each plane's trail
+pub const TRAIL_LENGTH: usize = 16;
+
+/// The last few (longitude, latitude) positions of each plane, keyed by [`Plane::id`].
+///
+/// The oldest position is at the front and the newest, which is where the plane is now, at the back
+pub type PlaneTrails = HashMap<String, VecDeque<(f32, f32)>>;
+
+/// Where [`PlaneRequester`] gets its planes from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum PlaneSource {
+    /// Live planes from the OpenSky API
+    #[default]
+    OpenSky,
+    /// Live planes from the OpenSky API, which are also written to a file to be replayed later
+    Record(PathBuf),
+    /// Planes recorded earlier with [`PlaneSource::Record`], played back at the pace they were
+    /// recorded at. The recording loops once it reaches the end
+    Replay(PathBuf),
+}
+
+/// The plane data shared between [`PlaneRequester`] and the task that fetches the planes
+#[derive(Clone, Default)]
+struct SharedPlanes {
+    planes: Arc<Mutex<Arc<Vec<Plane>>>>,
+    trails: Arc<Mutex<Arc<PlaneTrails>>>,
+    last_success: Arc<Mutex<Option<std::time::Instant>>>,
+    /// How long planes missing from the latest data are kept, see [`keep_missing_planes`]
+    linger: Duration,
+}
+
+impl SharedPlanes {
+    fn new(linger: Duration) -> Self {
+        Self {
+            planes: Default::default(),
+            trails: Default::default(),
+            last_success: Default::default(),
+            linger,
+        }
+    }
+
+    /// Publishes newly requested plane data and records the new positions in the trails. Planes
+    /// that were in the last data but not this one are kept for a while to fade out
+    fn store(&self, mut plane_data: Vec<Plane>) {
+        let previous = self.planes.lock().unwrap().clone();
+        keep_missing_planes(
+            &mut plane_data,
+            &previous,
+            std::time::Instant::now(),
+            self.linger,
+        );
+        {
+            let mut guard = self.trails.lock().unwrap();
+            update_trails(Arc::make_mut(&mut guard), &plane_data);
+        }
+        let mut guard = self.planes.lock().unwrap();
+        *guard = Arc::new(plane_data);
+        *self.last_success.lock().unwrap() = Some(std::time::Instant::now());
+    }
+}
+
 ///Structure to save te Plane data we request
 ///We put it into an Arc and Mutex to make it easier to read.
 pub struct PlaneRequester {
-    planes_storage: Arc<Mutex<Arc<Vec<PlaneBody>>>>,
+    shared: SharedPlanes,
+    started: std::time::Instant,
 }
 
 impl PlaneRequester {
-    ///Constructor on how to request the plane data.
-    pub fn new(runtime: &Runtime) -> Self {
-        let planes_storage = Arc::new(Mutex::new(Arc::new(Vec::new())));
-
-        runtime.spawn(plane_data_loop(planes_storage.clone()));
+    ///Constructor on how to request the plane data from `source`.
+    ///The requests stop once `shutdown` is triggered.
+    ///Planes that stop being reported are kept for `linger` so that they can fade out.
+    ///
+    ///Fails if the recording to replay can't be read, or the one to record to can't be created.
+    pub fn new(
+        runtime: &Runtime,
+        shutdown: &Shutdown,
+        source: &PlaneSource,
+        linger: Duration,
+    ) -> Result<Self, RecordingError> {
+        let shared = SharedPlanes::new(linger);
+
+        match source {
+            PlaneSource::OpenSky => {
+                runtime.spawn(plane_data_loop(shared.clone(), None, shutdown.subscribe()));
+            }
+            PlaneSource::Record(path) => {
+                let recorder = PlaneRecorder::create(path)?;
+                runtime.spawn(plane_data_loop(
+                    shared.clone(),
+                    Some(recorder),
+                    shutdown.subscribe(),
+                ));
+            }
+            PlaneSource::Replay(path) => {
+                let frames = plane_recording::load_recording(path)?;
+                runtime.spawn(replay_loop(shared.clone(), frames, shutdown.subscribe()));
+            }
+        }
 
-        PlaneRequester { planes_storage }
+        Ok(PlaneRequester {
+            shared,
+            started: std::time::Instant::now(),
+        })
     }
 
     ///Returns a clone of the Mutex list of planes.
-    pub fn planes_storage(&self) -> Arc<Vec<PlaneBody>> {
-        let guard = self.planes_storage.lock().unwrap();
+    pub fn planes_storage(&self) -> Arc<Vec<Plane>> {
+        let guard = self.shared.planes.lock().unwrap();
         guard.clone()
     }
+
+    ///Returns a clone of the recent positions of every plane.
+    pub fn trails(&self) -> Arc<PlaneTrails> {
+        let guard = self.shared.trails.lock().unwrap();
+        guard.clone()
+    }
+
+    ///Returns when plane data was last received, or `None` if it never has been.
+    pub fn last_success(&self) -> Option<std::time::Instant> {
+        *self.shared.last_success.lock().unwrap()
+    }
+
+    ///Returns when the requests started, which is how long the planes have been missing for
+    ///if no data was ever received.
+    pub fn started(&self) -> std::time::Instant {
+        self.started
+    }
+}
+
+/// Adds the planes in `previous` that are missing from `planes` to the end of `planes`, marked
+/// with when they went missing, until they have been missing for `linger`.
+///
+/// Planes without an id can't be matched up between updates, so they are never kept
+fn keep_missing_planes(
+    planes: &mut Vec<Plane>,
+    previous: &[Plane],
+    now: std::time::Instant,
+    linger: Duration,
+) {
+    let reported: HashSet<&str> = planes.iter().filter_map(Plane::id).collect();
+    let missing: Vec<Plane> = previous
+        .iter()
+        .filter(|plane| plane.id().is_some_and(|id| !reported.contains(id)))
+        .filter_map(|plane| {
+            let since = plane.missing_since.unwrap_or(now);
+            (now.saturating_duration_since(since) < linger).then(|| Plane {
+                missing_since: Some(since),
+                ..plane.clone()
+            })
+        })
+        .collect();
+    planes.extend(missing);
+}
+
+/// Adds the current position of every plane in `planes` to `trails`, and forgets the trails of
+/// planes that are no longer reported.
+///
+/// Planes without an id can't be told apart, so they don't get trails
+fn update_trails(trails: &mut PlaneTrails, planes: &[Plane]) {
+    let mut seen = HashSet::with_capacity(trails.len());
+    for plane in planes {
+        let id = match plane.id() {
+            Some(id) => id,
+            None => continue,
+        };
+        let position = (plane.longitude, plane.latitude);
+        let trail = trails.entry(id.to_owned()).or_default();
+        //Skip positions that haven't changed since the last update
+        if trail.back() != Some(&position) {
+            if trail.len() == TRAIL_LENGTH {
+                trail.pop_front();
+            }
+            trail.push_back(position);
+        }
+        seen.insert(id);
+    }
+    trails.retain(|id, _| seen.contains(id.as_str()));
 }
 
 /// Loop to get plane data.
@@ -92,140 +360,180 @@ impl PlaneRequester {
 /// The OpenSky Api gets data every 5-6 seconds,
 /// the function must also follow that running time.
 ///
-async fn plane_data_loop(list_of_planes: Arc<Mutex<Arc<Vec<PlaneBody>>>>) {
+/// Every set of planes received is also written to `recorder`, if there is one.
+async fn plane_data_loop(
+    shared: SharedPlanes,
+    mut recorder: Option<PlaneRecorder>,
+    mut shutdown: tokio::sync::broadcast::Receiver<()>,
+) {
     loop {
-        let start = Instant::now();
+        tokio::select! {
+            _ = shutdown.recv() => return,
+            _ = update_planes(&shared, &mut recorder) => {}
+        }
+    }
+}
 
-        match request_plane_data().await {
-            Ok(plane_data) => {
-                let mut guard = list_of_planes.lock().unwrap();
-                *guard = Arc::new(plane_data);
-            }
-            Err(error) => {
-                println!("Error at getting plane data: {:?}", error)
+/// Requests the planes once, then waits until it is time for the next request
+async fn update_planes(shared: &SharedPlanes, recorder: &mut Option<PlaneRecorder>) {
+    let start = Instant::now();
+
+    let mut store = |plane_data: Vec<Plane>| {
+        if let Some(recorder) = recorder {
+            if let Err(err) = recorder.record(&plane_data) {
+                warn!("Unable to record planes: {}", err);
             }
         }
+        shared.store(plane_data);
+    };
 
-        if let Ok(plane_data) = request_plane_data().await {
-            let mut guard = list_of_planes.lock().unwrap();
-            *guard = Arc::new(plane_data);
-        };
+    match request_plane_data().await {
+        Ok(plane_data) => store(plane_data),
+        Err(error) => {
+            warn!("Error at getting plane data: {:?}", error)
+        }
+    }
 
-        let end = Instant::now();
+    if let Ok(plane_data) = request_plane_data().await {
+        store(plane_data);
+    };
 
-        let time_interval = tokio::time::Duration::from_secs(5);
-        let seconds = end - start;
+    let end = Instant::now();
 
-        let sleep_time = if seconds <= tokio::time::Duration::from_secs(5) {
-            time_interval - seconds
-        } else {
-            tokio::time::Duration::from_secs(0)
-        };
+    let time_interval = tokio::time::Duration::from_secs(5);
+    let seconds = end - start;
+
+    let sleep_time = if seconds <= tokio::time::Duration::from_secs(5) {
+        time_interval - seconds
+    } else {
+        tokio::time::Duration::from_secs(0)
+    };
+
+    tokio::time::sleep(sleep_time).await;
+}
 
-        tokio::time::sleep(sleep_time).await;
+/// Plays back `frames` at the pace they were recorded at, over and over until `shutdown` is
+/// triggered. The trails are cleared each time the recording starts over, so that planes don't
+/// get a line back to where they started
+async fn replay_loop(
+    shared: SharedPlanes,
+    frames: Vec<RecordedFrame>,
+    mut shutdown: tokio::sync::broadcast::Receiver<()>,
+) {
+    let loop_delay = plane_recording::loop_delay(&frames);
+    let first_time = frames[0].time();
+    loop {
+        *shared.trails.lock().unwrap() = Arc::new(HashMap::new());
+        let start = Instant::now();
+        for frame in &frames {
+            let at = start + frame.time().saturating_sub(first_time);
+            tokio::select! {
+                _ = shutdown.recv() => return,
+                _ = tokio::time::sleep_until(at) => shared.store(frame.planes.clone()),
+            }
+        }
+        tokio::select! {
+            _ = shutdown.recv() => return,
+            _ = tokio::time::sleep(loop_delay) => {}
+        }
     }
 }
 
 /// In here we call the OpenSky Api to get the data from planes.
 ///
 /// Request the plane data and makes it into a Vec.
-async fn request_plane_data() -> Result<Vec<PlaneBody>, Error> {
+///
+/// Every plane is in the one list with its airline set, see [`planes_by_airline`] to group them
+async fn request_plane_data() -> Result<Vec<Plane>, Error> {
     let open_sky = opensky_api::OpenSkyApi::new();
 
     let state_request = open_sky.get_states();
-    let mut list_of_planes: Vec<PlaneBody> = Vec::new();
-
-    let mut spirit_planes: PlaneBody = PlaneBody::empty_commercial(BasicAirline::Spirit.into());
-    let mut american_al_planes: PlaneBody =
-        PlaneBody::empty_commercial(BasicAirline::American.into());
-    let mut southwest_planes: PlaneBody =
-        PlaneBody::empty_commercial(BasicAirline::Southwest.into());
-    let mut united_al_planes: PlaneBody = PlaneBody::empty_commercial(BasicAirline::United.into());
-    let mut other_planes: PlaneBody = PlaneBody::empty_commercial(Airline::Unknown);
+    let mut list_of_planes: Vec<Plane> = Vec::new();
 
     let dynamic_plane_types = get_dynamic_plane_types();
 
     let open_sky = state_request.send().await?;
     for state in open_sky.states {
-        let longitude = state.longitude;
-        let latitude = state.latitude;
-        let track = (-state.true_track.unwrap_or(0.0) + 90.0) * (std::f32::consts::PI / 180.0);
-
-        if !state.on_ground {
-            if let Some(longitude) = longitude {
-                let latitude = latitude.unwrap();
-                let mut maybe_airline = None;
-                let mut maybe_callsign = None;
-                let mut maybe_plane_type = None;
-
-                if let Some(callsign) = &state.callsign {
-                    maybe_callsign = Some(callsign.clone());
-                    if callsign.len() > 3 {
-                        let callsign_header = &callsign[0..3];
-                        match callsign_header {
-                            "NKS" => maybe_airline = Some(BasicAirline::Spirit.into()),
-                            "AAL" => maybe_airline = Some(BasicAirline::American.into()),
-                            "SWA" => maybe_airline = Some(BasicAirline::Southwest.into()),
-                            "UAL" => maybe_airline = Some(BasicAirline::United.into()),
-                            "DAL" => maybe_airline = Some(BasicAirline::Delta.into()),
-                            _ => {
-                                //Try to match dynamic airlines
-                                for (dyn_airline, dyn_plane_type) in &dynamic_plane_types {
-                                    // println!(
-                                    //     "Matched callsign: {} - {}",
-                                    //     callsign_header, callsign
-                                    // );
-                                    if dyn_airline.callsign == callsign_header {
-                                        maybe_airline = Some(Airline::Dynamic(dyn_airline.clone()));
-                                        maybe_plane_type = Some(*dyn_plane_type);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-
-                let plane_type = match (maybe_plane_type, &maybe_airline) {
-                    (Some(plane_type), _) => plane_type,
-                    (_, Some(Airline::Basic(_))) => PlaneType::Commercial,
-                    _ => PlaneType::Unknown,
-                };
-                let plane = Plane {
-                    longitude,
-                    latitude,
-                    track,
-                    airline: maybe_airline.clone().unwrap_or(Airline::Unknown),
-                    //Default to commercial because we only set it in the case of spirit, american etc.
-                    plane_type,
-                    callsign: maybe_callsign.unwrap_or("Unknown".to_owned()),
-                };
-
-                match maybe_airline {
-                    Some(Airline::Basic(BasicAirline::Spirit)) => spirit_planes.planes.push(plane),
-                    Some(Airline::Basic(BasicAirline::American)) => {
-                        american_al_planes.planes.push(plane)
-                    }
-                    Some(Airline::Basic(BasicAirline::Southwest)) => {
-                        southwest_planes.planes.push(plane)
-                    }
-                    Some(Airline::Basic(BasicAirline::United)) => {
-                        united_al_planes.planes.push(plane)
-                    }
-                    _ => other_planes.planes.push(plane),
-                }
-            }
+        if let Some(plane) = plane_from_state(state, &dynamic_plane_types) {
+            list_of_planes.push(plane);
         }
     }
 
-    list_of_planes.push(spirit_planes);
-    list_of_planes.push(american_al_planes);
-    list_of_planes.push(southwest_planes);
-    list_of_planes.push(united_al_planes);
-    list_of_planes.push(other_planes);
-
     Ok(list_of_planes)
 }
 
+/// Makes a plane out of a state vector from OpenSky, or returns `None` if it has no position.
+/// Planes on the ground are kept, and marked with [`Plane::on_ground`].
+///
+/// Planes without a callsign are still kept, under the name "Unknown". They are told apart by
+/// their ICAO 24-bit address, see [`Plane::id`]
+fn plane_from_state(
+    state: opensky_api::StateVector,
+    dynamic_plane_types: &[(DynamicAirline, PlaneType)],
+) -> Option<Plane> {
+    let longitude = state.longitude?;
+    let latitude = state.latitude?;
+    let track = (-state.true_track.unwrap_or(0.0) + 90.0) * (std::f32::consts::PI / 180.0);
+
+    //OpenSky pads callsigns with spaces, and sends an empty one for some planes
+    let callsign = state
+        .callsign
+        .as_deref()
+        .map(str::trim)
+        .filter(|callsign| !callsign.is_empty());
+    let mut maybe_airline = None;
+    let mut maybe_plane_type = None;
+
+    if let Some(callsign) = callsign {
+        if callsign.len() > 3 {
+            let callsign_header = &callsign[0..3];
+            //The plane type comes from the table even for the basic airlines, so
+            //that it is always respected
+            let known = dynamic_plane_types
+                .iter()
+                .find(|(dyn_airline, _)| dyn_airline.callsign == callsign_header);
+            maybe_plane_type = known.map(|(_, dyn_plane_type)| *dyn_plane_type);
+            maybe_airline = match (BasicAirline::from_callsign(callsign_header), known) {
+                (Some(airline), _) => Some(airline.into()),
+                (None, Some((dyn_airline, _))) => Some(Airline::Dynamic(dyn_airline.clone())),
+                (None, None) => None,
+            };
+        }
+    }
+
+    let plane_type = match (maybe_plane_type, &maybe_airline) {
+        (Some(plane_type), _) => plane_type,
+        (_, Some(Airline::Basic(_))) => PlaneType::Commercial,
+        _ => PlaneType::Unknown,
+    };
+    Some(Plane {
+        longitude,
+        latitude,
+        track,
+        airline: maybe_airline.unwrap_or(Airline::Unknown),
+        plane_type,
+        callsign: callsign.unwrap_or("Unknown").to_owned(),
+        icao24: state.icao24,
+        altitude: state.baro_altitude.or(state.geo_altitude),
+        velocity: state.velocity,
+        vertical_rate: state.vertical_rate,
+        squawk: state.squawk,
+        on_ground: state.on_ground,
+        missing_since: None,
+    })
+}
+
+/// Groups `planes` by the airline they fly for
+pub fn planes_by_airline<'a>(
+    planes: impl IntoIterator<Item = &'a Plane>,
+) -> HashMap<&'a Airline, Vec<&'a Plane>> {
+    let mut groups: HashMap<&Airline, Vec<&Plane>> = HashMap::new();
+    for plane in planes {
+        groups.entry(&plane.airline).or_default().push(plane);
+    }
+    groups
+}
+
 fn get_dynamic_plane_types() -> Vec<(DynamicAirline, PlaneType)> {
     let mut result = Vec::new();
 
@@ -282,7 +590,7 @@ RPA - Republic Airlines - airline
             "cargo" => PlaneType::Cargo,
             "trainer" => PlaneType::Trainer,
             "business" => PlaneType::Business,
-            s => unreachable!(s),
+            s => unreachable!("{}", s),
         };
 
         result.push((
@@ -296,3 +604,196 @@ RPA - Republic Airlines - airline
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(planes: &[(&str, f32, f32)]) -> Vec<Plane> {
+        planes
+            .iter()
+            .map(|(callsign, longitude, latitude)| {
+                Plane::new(
+                    *longitude,
+                    *latitude,
+                    0.0,
+                    callsign.to_string(),
+                    Airline::Unknown,
+                    PlaneType::Unknown,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn grouping_by_airline() {
+        let mut planes = body(&[("AAL1", 0.0, 0.0), ("AAL2", 1.0, 1.0), ("N123", 2.0, 2.0)]);
+        planes[0].airline = BasicAirline::American.into();
+        planes[1].airline = BasicAirline::American.into();
+
+        let groups = planes_by_airline(&planes);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[&Airline::from(BasicAirline::American)].len(), 2);
+        assert_eq!(groups[&Airline::Unknown][0].callsign, "N123");
+    }
+
+    #[test]
+    fn trails_are_bounded_and_pruned() {
+        let mut trails = PlaneTrails::new();
+        for i in 0..TRAIL_LENGTH + 4 {
+            update_trails(
+                &mut trails,
+                &body(&[("AAL1", i as f32, 0.0), ("Unknown", 0.0, 0.0)]),
+            );
+        }
+        let trail = &trails["AAL1"];
+        assert_eq!(trail.len(), TRAIL_LENGTH);
+        assert_eq!(trail.back(), Some(&((TRAIL_LENGTH + 3) as f32, 0.0)));
+        assert!(!trails.contains_key("Unknown"));
+
+        //Repeated positions aren't added twice
+        update_trails(
+            &mut trails,
+            &body(&[("AAL1", 19.0, 0.0), ("UAL2", 1.0, 1.0)]),
+        );
+        update_trails(
+            &mut trails,
+            &body(&[("AAL1", 19.0, 0.0), ("UAL2", 1.0, 1.0)]),
+        );
+        assert_eq!(trails["UAL2"].len(), 1);
+
+        //Planes that disappear lose their trail
+        update_trails(&mut trails, &body(&[("UAL2", 1.0, 1.0)]));
+        assert!(!trails.contains_key("AAL1"));
+    }
+
+    fn state(icao24: &str, callsign: Option<&str>) -> opensky_api::StateVector {
+        opensky_api::StateVector {
+            icao24: icao24.to_owned(),
+            callsign: callsign.map(str::to_owned),
+            origin_country: "United States".to_owned(),
+            time_position: Some(0),
+            last_contact: 0,
+            longitude: Some(-81.0),
+            latitude: Some(29.2),
+            baro_altitude: Some(3000.0),
+            on_ground: false,
+            velocity: Some(200.0),
+            true_track: Some(90.0),
+            vertical_rate: None,
+            sensors: None,
+            geo_altitude: None,
+            squawk: None,
+            spi: false,
+            position_source: 0,
+            undocumented: None,
+        }
+    }
+
+    #[test]
+    fn planes_from_state_vectors() {
+        let types = get_dynamic_plane_types();
+
+        let plane = plane_from_state(state("a1b2c3", Some("DAL123  ")), &types).unwrap();
+        assert_eq!(plane.callsign, "DAL123");
+        assert_eq!(plane.airline, BasicAirline::Delta.into());
+        assert_eq!(plane.id(), Some("a1b2c3"));
+
+        //Planes without a callsign are kept, and each one is still its own plane
+        let first = plane_from_state(state("a1b2c4", None), &types).unwrap();
+        let second = plane_from_state(state("a1b2c5", Some("   ")), &types).unwrap();
+        assert_eq!(first.callsign, "Unknown");
+        assert_eq!(second.callsign, "Unknown");
+        assert_eq!(first.airline, Airline::Unknown);
+        assert_eq!(first.id(), Some("a1b2c4"));
+        assert!(!first.is_same_plane(&second));
+        let mut trails = PlaneTrails::new();
+        update_trails(&mut trails, &[first, second]);
+        assert_eq!(trails.len(), 2);
+
+        assert!(!plane.on_ground);
+        let mut grounded = state("a1b2c6", None);
+        grounded.on_ground = true;
+        assert!(plane_from_state(grounded, &types).unwrap().on_ground);
+        let mut squawking = state("a1b2c8", Some("N123"));
+        squawking.squawk = Some("7700".to_owned());
+        let squawking = plane_from_state(squawking, &types).unwrap();
+        assert_eq!(squawking.emergency(), Some(Emergency::General));
+        assert_eq!(plane.emergency(), None);
+
+        let mut lost = state("a1b2c7", None);
+        lost.latitude = None;
+        assert!(plane_from_state(lost, &types).is_none());
+    }
+
+    #[test]
+    fn missing_planes_linger() {
+        let linger = Duration::from_secs(10);
+        let start = std::time::Instant::now();
+        let first = body(&[
+            ("AAL1", 0.0, 0.0),
+            ("UAL2", 1.0, 1.0),
+            ("Unknown", 2.0, 2.0),
+        ]);
+
+        //UAL2 and the plane without an id go missing
+        let mut second = body(&[("AAL1", 0.5, 0.0)]);
+        keep_missing_planes(&mut second, &first, start, linger);
+        assert_eq!(second.len(), 2);
+        assert_eq!(second[1].callsign, "UAL2");
+        assert_eq!(second[1].missing_since, Some(start));
+        assert_eq!(second[1].fade(start, linger), 1.0);
+        assert_eq!(second[1].fade(start + linger / 2, linger), 0.5);
+        assert_eq!(second[0].fade(start + linger, linger), 1.0);
+        assert!(!second[1].is_faded_out(start + linger / 2, linger));
+        assert!(second[1].is_faded_out(start + linger, linger));
+
+        //Still missing, it keeps fading from when it first went missing
+        let mut third = body(&[("AAL1", 1.0, 0.0)]);
+        keep_missing_planes(&mut third, &second, start + linger / 2, linger);
+        assert_eq!(third[1].missing_since, Some(start));
+
+        //Gone for good once it has faded out
+        let mut fourth = body(&[("AAL1", 1.5, 0.0)]);
+        keep_missing_planes(&mut fourth, &third, start + linger, linger);
+        assert_eq!(fourth.len(), 1);
+
+        //Coming back makes it solid again
+        let mut back = body(&[("AAL1", 1.5, 0.0), ("UAL2", 1.0, 1.0)]);
+        keep_missing_planes(&mut back, &third, start + linger, linger);
+        assert_eq!(back.len(), 2);
+        assert_eq!(back[1].missing_since, None);
+    }
+
+    #[test]
+    fn emergency_squawks() {
+        assert_eq!(Emergency::from_squawk("7500"), Some(Emergency::Hijacking));
+        assert_eq!(
+            Emergency::from_squawk("7600"),
+            Some(Emergency::RadioFailure)
+        );
+        assert_eq!(Emergency::from_squawk("7700 "), Some(Emergency::General));
+        assert_eq!(Emergency::from_squawk("1200"), None);
+        assert_eq!(Emergency::from_squawk(""), None);
+    }
+
+    #[test]
+    fn planes_are_told_apart_by_address() {
+        let mut planes = body(&[("Unknown", 0.0, 0.0), ("Unknown", 1.0, 1.0)]);
+        assert_eq!(planes[0].id(), None);
+        assert!(!planes[0].is_same_plane(&planes[1]));
+
+        planes[0].icao24 = "a1b2c3".to_owned();
+        planes[1].icao24 = "a1b2c4".to_owned();
+        let mut trails = PlaneTrails::new();
+        update_trails(&mut trails, &planes);
+        assert_eq!(trails["a1b2c3"].back(), Some(&(0.0, 0.0)));
+        assert_eq!(trails["a1b2c4"].back(), Some(&(1.0, 1.0)));
+
+        //The address wins over the callsign, which can change between flights
+        let mut renamed = planes[0].clone();
+        renamed.callsign = "AAL1".to_owned();
+        assert!(renamed.is_same_plane(&planes[0]));
+        assert!(!renamed.is_same_plane(&planes[1]));
+    }
+}
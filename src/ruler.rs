@@ -0,0 +1,246 @@
+use conrod_core::{text, widget, Colorable, Positionable, UiCell, Widget};
+use glam::DVec2;
+
+use crate::map::TileView;
+use crate::util::{
+    format_distance, haversine_distance_m, initial_bearing_deg, project_point, Units,
+};
+
+/// How many straight lines the great circle between the two points is drawn with
+const PATH_SEGMENTS: usize = 32;
+
+/// The most pieces the great circle is split into where it wraps around the edge of the world, see
+/// [`split_at_wrap`]. A path shorter than half of the earth crosses that edge at most once
+pub const MAX_PATH_PIECES: usize = 2;
+
+/// A (latitude, longitude) pair in degrees
+pub type LatLong = (f64, f64);
+
+/// Measures the great circle distance between two points clicked on the map
+#[derive(Clone, Debug, Default)]
+pub struct Ruler {
+    enabled: bool,
+    start: Option<LatLong>,
+    end: Option<LatLong>,
+}
+
+/// The distance and direction between the two points of a [`Ruler`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub distance_m: f64,
+    /// The direction to start off in from the first point, in degrees clockwise from north
+    pub bearing_deg: f64,
+}
+
+impl Ruler {
+    /// Returns true if clicks on the map are used to place points
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turns the ruler on or off, forgetting any points when it is turned off
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.reset();
+        }
+    }
+
+    /// Places the start point, then the end point. A third click clears both
+    pub fn click(&mut self, point: LatLong) {
+        match (self.start, self.end) {
+            (None, _) => self.start = Some(point),
+            (Some(_), None) => self.end = Some(point),
+            (Some(_), Some(_)) => {
+                self.reset();
+            }
+        }
+    }
+
+    /// Clears both points, returning true if there were any to clear
+    pub fn reset(&mut self) -> bool {
+        let had_points = self.start.is_some();
+        self.start = None;
+        self.end = None;
+        had_points
+    }
+
+    /// Returns the distance and bearing between the two points once both are placed
+    pub fn measurement(&self) -> Option<Measurement> {
+        let (start, end) = (self.start?, self.end?);
+        Some(Measurement {
+            distance_m: haversine_distance_m(start.0, start.1, end.0, end.1),
+            bearing_deg: initial_bearing_deg(start.0, start.1, end.0, end.1),
+        })
+    }
+
+    /// Draws the points that have been placed, and the great circle between them with its length
+    /// and bearing.
+    ///
+    /// `ids.ruler_paths` must have [`MAX_PATH_PIECES`] ids
+    pub fn draw(
+        &self,
+        ids: &crate::Ids,
+        view: &TileView,
+        units: Units,
+        ui: &mut UiCell,
+        font: text::font::Id,
+    ) {
+        let start = match self.start {
+            Some(start) => start,
+            None => return,
+        };
+        let color = conrod_core::color::LIGHT_YELLOW;
+        let (win_w, win_h) = (ui.win_w, ui.win_h);
+        let to_ui = |(latitude, longitude): LatLong| {
+            let pixel = view.lat_long_to_screen(latitude, longitude, win_w, win_h);
+            //Conrod puts the origin in the center of the screen, with y going up
+            [pixel.x - win_w / 2.0, win_h / 2.0 - pixel.y]
+        };
+
+        let start_point = to_ui(start);
+        widget::Circle::fill(4.0)
+            .x_y(start_point[0], start_point[1])
+            .color(color)
+            .set(ids.ruler_start, ui);
+
+        let (end, measurement) = match (self.end, self.measurement()) {
+            (Some(end), Some(measurement)) => (end, measurement),
+            _ => return,
+        };
+        let path: Vec<_> = great_circle_path(start, measurement)
+            .into_iter()
+            .map(to_ui)
+            .collect();
+        let end_point = to_ui(end);
+        //One whole world across in conrod's units, since the world is 1 wide
+        let world_width = view.get_zoom().exp2();
+        let pieces = split_at_wrap(&path, world_width);
+        for (piece, id) in pieces.into_iter().zip(ids.ruler_paths.iter()) {
+            widget::PointPath::abs(piece)
+                .color(color)
+                .thickness(2.0)
+                .set(*id, ui);
+        }
+        widget::Circle::fill(4.0)
+            .x_y(end_point[0], end_point[1])
+            .color(color)
+            .set(ids.ruler_end, ui);
+
+        let label = format!(
+            "{}, {:03.0}°",
+            format_distance(measurement.distance_m, units),
+            measurement.bearing_deg
+        );
+        //The middle of the path, which is on the line even if it wraps around the world
+        let middle = DVec2::from(path[PATH_SEGMENTS / 2]);
+        widget::Text::new(&label)
+            .x_y(middle.x, middle.y + 12.0)
+            .color(color)
+            .font_size(12)
+            .font_id(font)
+            .set(ids.ruler_label, ui);
+    }
+}
+
+/// Returns points along the great circle from `start` covering `measurement`, including both ends
+fn great_circle_path(start: LatLong, measurement: Measurement) -> Vec<LatLong> {
+    (0..=PATH_SEGMENTS)
+        .map(|i| {
+            let distance = measurement.distance_m * i as f64 / PATH_SEGMENTS as f64;
+            project_point(start.0, start.1, measurement.bearing_deg, distance)
+        })
+        .collect()
+}
+
+/// Splits `path` wherever it jumps more than half of `world_width` from one point to the next,
+/// which happens where it crosses the edge of the world copy the view is centered on, usually the
+/// antimeridian. Each piece is carried on past the edge to where the next one starts, so the line
+/// reaches the side of the screen instead of stopping a segment short
+fn split_at_wrap(path: &[[f64; 2]], world_width: f64) -> Vec<Vec<[f64; 2]>> {
+    let mut pieces = Vec::new();
+    let mut piece: Vec<[f64; 2]> = Vec::new();
+    for &point in path {
+        if let Some(&last) = piece.last() {
+            let jump = point[0] - last[0];
+            if jump.abs() > world_width / 2.0 {
+                let shift = world_width * jump.signum();
+                piece.push([point[0] - shift, point[1]]);
+                pieces.push(std::mem::take(&mut piece));
+                piece.push([last[0] + shift, last[1]]);
+            }
+        }
+        piece.push(point);
+    }
+    if piece.len() > 1 {
+        pieces.push(piece);
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ruler_clicks() {
+        let mut ruler = Ruler::default();
+        assert!(!ruler.is_enabled());
+        ruler.toggle();
+        assert!(ruler.is_enabled());
+
+        ruler.click((40.6413, -73.7781));
+        assert_eq!(ruler.measurement(), None);
+        ruler.click((51.4700, -0.4543));
+        let measurement = ruler.measurement().unwrap();
+        assert!((measurement.distance_m - 5_540_000.0).abs() < 5_000.0);
+        assert!((measurement.bearing_deg - 51.35).abs() < 0.01);
+
+        //A third click starts over
+        ruler.click((0.0, 0.0));
+        assert_eq!(ruler.measurement(), None);
+        assert!(!ruler.reset());
+
+        //Escape clears the points, and turning the ruler off does too
+        ruler.click((0.0, 0.0));
+        assert!(ruler.reset());
+        ruler.click((0.0, 0.0));
+        ruler.toggle();
+        assert!(!ruler.is_enabled());
+        assert!(!ruler.reset());
+    }
+
+    #[test]
+    fn path_ends_at_both_points() {
+        let start = (29.1799, -81.0581);
+        let end = (28.4312, -81.3081);
+        let measurement = Measurement {
+            distance_m: haversine_distance_m(start.0, start.1, end.0, end.1),
+            bearing_deg: initial_bearing_deg(start.0, start.1, end.0, end.1),
+        };
+        let path = great_circle_path(start, measurement);
+        assert_eq!(path.len(), PATH_SEGMENTS + 1);
+        let last = path[PATH_SEGMENTS];
+        assert!(haversine_distance_m(last.0, last.1, end.0, end.1) < 0.01);
+        assert!(haversine_distance_m(path[0].0, path[0].1, start.0, start.1) < 0.01);
+    }
+
+    #[test]
+    fn paths_split_across_the_antimeridian() {
+        //Nothing to split when the path stays on one copy of the world
+        let path = [[0.0, 0.0], [10.0, 5.0], [20.0, 10.0]];
+        assert_eq!(split_at_wrap(&path, 100.0), vec![path.to_vec()]);
+
+        //Going east past the edge of the world comes back in from the west
+        let path = [[30.0, 0.0], [45.0, 1.0], [-45.0, 2.0], [-30.0, 3.0]];
+        let pieces = split_at_wrap(&path, 100.0);
+        assert_eq!(
+            pieces,
+            vec![
+                vec![[30.0, 0.0], [45.0, 1.0], [55.0, 2.0]],
+                vec![[-55.0, 1.0], [-45.0, 2.0], [-30.0, 3.0]],
+            ]
+        );
+        assert!(pieces.len() <= MAX_PATH_PIECES);
+    }
+}
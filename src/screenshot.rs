@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use image::RgbaImage;
+use thiserror::Error;
+
+/// Why a screenshot couldn't be saved
+#[derive(Error, Debug)]
+pub enum ScreenshotError {
+    #[error("reading the window: {0:?}")]
+    Read(glium::ReadError),
+    #[error("the window's pixels don't fill a {0}x{1} image")]
+    Size(u32, u32),
+    #[error("saving the image: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+/// Saves what was drawn in the last frame to `screenshot-<unix time>.png` in the current directory,
+/// returning the path of the file. Must be called after the frame is finished, since it reads the
+/// front buffer.
+///
+/// The image is the size of the framebuffer in physical pixels, so on a high DPI screen it is
+/// bigger than the window's logical size, and matches what is on screen pixel for pixel
+pub fn save_screenshot(display: &glium::Display) -> Result<PathBuf, ScreenshotError> {
+    let raw: glium::texture::RawImage2d<'static, u8> =
+        display.read_front_buffer().map_err(ScreenshotError::Read)?;
+    let image = framebuffer_to_image(raw.data.into_owned(), raw.width, raw.height)?;
+
+    let unix_time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let path = PathBuf::from(format!("screenshot-{}.png", unix_time));
+    image.save(&path)?;
+    Ok(path)
+}
+
+/// Converts RGBA pixels read from OpenGL, which start at the bottom row, to an image that starts at
+/// the top row
+fn framebuffer_to_image(
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage, ScreenshotError> {
+    let image =
+        RgbaImage::from_raw(width, height, pixels).ok_or(ScreenshotError::Size(width, height))?;
+    Ok(image::imageops::flip_vertical(&image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framebuffer_rows_are_flipped() {
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        //Two pixels wide and two tall, with the red row at the bottom as OpenGL reads it
+        let pixels = [red, red, blue, blue].concat();
+
+        let image = framebuffer_to_image(pixels, 2, 2).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, blue);
+        assert_eq!(image.get_pixel(1, 1).0, red);
+
+        assert!(matches!(
+            framebuffer_to_image(vec![0; 12], 2, 2),
+            Err(ScreenshotError::Size(2, 2))
+        ));
+    }
+}
@@ -0,0 +1,285 @@
+//The conrod derive macros generate their impls inside of a const block, which newer compilers
+//warn about. We can't change the generated code so allow it for the whole file
+#![allow(non_local_definitions)]
+
+use conrod_core::{
+    self, text, widget, widget_ids, Color, Colorable, FontSize, Labelable, Positionable, Scalar,
+    Sizeable, UiCell, Widget,
+};
+
+use conrod_core::{WidgetCommon, WidgetStyle};
+
+///Custom made widget for picking a value between a min and a max by dragging a handle along a
+///track
+#[derive(WidgetCommon)]
+pub struct Slider<'a> {
+    /// An object that handles some of the dirty work of rendering a GUI. We don't
+    /// really have to worry about it.
+    #[conrod(common_builder)]
+    common: widget::CommonBuilder,
+    /// Optional label string drawn on the track.
+    maybe_label: Option<&'a str>,
+    /// See the Style struct below.
+    style: Style,
+    /// The value the handle is at
+    value: f64,
+    /// The value at the left end of the track
+    min: f64,
+    /// The value at the right end of the track
+    max: f64,
+}
+
+/// Represents the unique styling for our widget.
+#[derive(Copy, Clone, Debug, Default, PartialEq, WidgetStyle)]
+pub struct Style {
+    /// Color of the track.
+    #[conrod(default = "theme.shape_color")]
+    pub color: Option<Color>,
+    /// Color of the part of the track left of the handle.
+    #[conrod(default = "conrod_core::color::LIGHT_BLUE")]
+    pub fill_color: Option<Color>,
+    /// Color of the handle.
+    #[conrod(default = "conrod_core::color::WHITE")]
+    pub handle_color: Option<Color>,
+    /// The color of the Slider's label.
+    #[conrod(default = "theme.label_color")]
+    pub label_color: Option<Color>,
+    /// The font size of the Slider's label.
+    #[conrod(default = "theme.font_size_small")]
+    pub label_font_size: Option<FontSize>,
+    /// The ID of the font used to display the label.
+    #[conrod(default = "theme.font_id")]
+    pub label_font_id: Option<Option<text::font::Id>>,
+}
+
+widget_ids! {
+    pub struct SliderWidgetIds{
+        track,
+        fill,
+        handle,
+        label,
+    }
+}
+
+///Declaration of the Slider State
+pub struct SliderWidgetState {
+    ids: SliderWidgetIds,
+}
+
+impl<'a> Slider<'a> {
+    pub fn new(value: f64, min: f64, max: f64) -> Self {
+        Slider {
+            common: widget::CommonBuilder::default(),
+            maybe_label: None,
+            style: Style::default(),
+            value,
+            min,
+            max,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn label_font_id(mut self, font_id: conrod_core::text::font::Id) -> Self {
+        self.style.label_font_id = Some(Some(font_id));
+        self
+    }
+
+    ///Color of the part of the track left of the handle
+    #[allow(dead_code)]
+    pub fn fill_color(mut self, color: Color) -> Self {
+        self.style.fill_color = Some(color);
+        self
+    }
+
+    ///Color of the handle
+    #[allow(dead_code)]
+    pub fn handle_color(mut self, color: Color) -> Self {
+        self.style.handle_color = Some(color);
+        self
+    }
+}
+
+impl<'a> Widget for Slider<'a> {
+    type State = SliderWidgetState;
+    type Style = Style;
+    /// The new value while the handle is being dragged
+    type Event = Option<f64>;
+
+    fn init_state(&self, id_gen: widget::id::Generator) -> Self::State {
+        SliderWidgetState {
+            ids: SliderWidgetIds::new(id_gen),
+        }
+    }
+
+    fn style(&self) -> Self::Style {
+        self.style
+    }
+
+    fn update(self, args: widget::UpdateArgs<Self>) -> Self::Event {
+        let widget::UpdateArgs {
+            id,
+            state,
+            rect,
+            ui,
+            style,
+            ..
+        } = args;
+
+        let Slider {
+            maybe_label,
+            value,
+            min,
+            max,
+            ..
+        } = self;
+
+        //The handle is a circle as tall as the slider, and its center stays on the track
+        let handle_size = rect.h();
+        let track_w = rect.w() - handle_size;
+
+        let (track_color, new_value) = {
+            let input = ui.widget_input(id);
+            let color = style.color(&ui.theme);
+
+            //Conrod keeps sending the mouse to the widget it was pressed on, so dragging past
+            //the ends of the track still works
+            match input.mouse() {
+                Some(mouse) if mouse.buttons.left().is_down() => {
+                    let new_value = value_at(mouse.rel_xy()[0], track_w, min, max);
+                    (color.clicked(), Some(new_value))
+                }
+                Some(_) => (color.highlighted(), None),
+                None => (color, None),
+            }
+        };
+        let shown_value = new_value.unwrap_or(value);
+        let handle_x = position_of(shown_value, track_w, min, max);
+
+        widget::RoundedRectangle::fill([rect.w(), rect.h()], rect.h() / 2.0)
+            .middle_of(id)
+            .graphics_for(id)
+            .color(track_color)
+            .set(state.ids.track, ui);
+
+        let fill_w = handle_x + rect.w() / 2.0 + handle_size / 2.0;
+        widget::RoundedRectangle::fill([fill_w, rect.h()], rect.h() / 2.0)
+            .x_y(rect.left() + fill_w / 2.0, rect.y())
+            .parent(id)
+            .graphics_for(id)
+            .color(style.fill_color(&ui.theme))
+            .set(state.ids.fill, ui);
+
+        widget::Oval::fill([handle_size, handle_size])
+            .x_y(rect.x() + handle_x, rect.y())
+            .resolution(20)
+            .parent(id)
+            .graphics_for(id)
+            .color(style.handle_color(&ui.theme))
+            .set(state.ids.handle, ui);
+
+        if let Some(label) = maybe_label {
+            let font_id = style
+                .label_font_id(&ui.theme)
+                .or_else(|| ui.fonts.ids().next());
+            widget::Text::new(label)
+                .and_then(font_id, widget::Text::font_id)
+                .middle_of(id)
+                .parent(id)
+                .graphics_for(id)
+                .color(style.label_color(&ui.theme))
+                .font_size(style.label_font_size(&ui.theme))
+                .set(state.ids.label, ui);
+        }
+
+        //Only report changes, so callers can tell when the user moved the handle
+        new_value.filter(|new_value| *new_value != value)
+    }
+}
+
+impl<'a> Colorable for Slider<'a> {
+    fn color(mut self, color: conrod_core::Color) -> Self {
+        self.style.color = Some(color);
+        self
+    }
+}
+
+/// Provide the chainable label(), label_color(), and label_font_size()
+/// configuration methods.
+impl<'a> Labelable<'a> for Slider<'a> {
+    fn label(mut self, text: &'a str) -> Self {
+        self.maybe_label = Some(text);
+        self
+    }
+    fn label_color(mut self, color: conrod_core::Color) -> Self {
+        self.style.label_color = Some(color);
+        self
+    }
+    fn label_font_size(mut self, size: conrod_core::FontSize) -> Self {
+        self.style.label_font_size = Some(size);
+        self
+    }
+}
+
+/// Returns the value for a handle `x` pixels from the middle of a track `track_w` pixels wide
+fn value_at(x: Scalar, track_w: Scalar, min: f64, max: f64) -> f64 {
+    if track_w <= 0.0 {
+        return min;
+    }
+    let fraction = (x / track_w + 0.5).clamp(0.0, 1.0);
+    min + fraction * (max - min)
+}
+
+/// Returns how many pixels from the middle of a track `track_w` pixels wide the handle for `value`
+/// goes
+fn position_of(value: f64, track_w: Scalar, min: f64, max: f64) -> Scalar {
+    if max == min {
+        return -track_w / 2.0;
+    }
+    let fraction = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    (fraction - 0.5) * track_w
+}
+
+/// Draws a labeled slider for picking a value between `min` and `max`. Returns the new value if
+/// the user dragged the handle
+#[allow(clippy::too_many_arguments)]
+pub fn draw(
+    widget_id: widget::id::Id,
+    ui: &mut UiCell,
+    label: &str,
+    value: f64,
+    min: f64,
+    max: f64,
+    widget_x_position: f64,
+    widget_y_position: f64,
+) -> Option<f64> {
+    Slider::new(value, min, max)
+        .x(widget_x_position)
+        .y(widget_y_position)
+        .w_h(150.0, 20.0)
+        .label_font_size(10)
+        .label_color(conrod_core::color::WHITE)
+        .label(label)
+        .set(widget_id, ui)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_positions() {
+        assert_eq!(value_at(-50.0, 100.0, 0.0, 1.0), 0.0);
+        assert_eq!(value_at(0.0, 100.0, 0.0, 1.0), 0.5);
+        assert_eq!(value_at(25.0, 100.0, 10.0, 20.0), 17.5);
+        //Dragging past the ends stops at the limits
+        assert_eq!(value_at(-80.0, 100.0, 0.0, 1.0), 0.0);
+        assert_eq!(value_at(80.0, 100.0, 0.0, 1.0), 1.0);
+
+        for value in [0.0, 0.25, 0.5, 1.0] {
+            let x = position_of(value, 100.0, 0.0, 1.0);
+            assert_eq!(value_at(x, 100.0, 0.0, 1.0), value);
+        }
+        assert_eq!(position_of(3.0, 100.0, 0.0, 1.0), 50.0);
+        assert_eq!(position_of(3.0, 100.0, 3.0, 3.0), -50.0);
+    }
+}
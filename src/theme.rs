@@ -0,0 +1,86 @@
+use conrod_core::{Color, FontSize};
+use serde::{Deserialize, Serialize};
+
+/// The colors and text sizes used to draw the map and the overlay on top of it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// What shows through where no tiles are loaded, as RGB from 0.0 to 1.0
+    pub background: [f32; 3],
+    /// The lines of latitude and longitude
+    pub grid_line: Color,
+    /// The latitude and longitude labels along the edges of the window
+    pub grid_text: Color,
+    /// The outline drawn around labels on the map so they stand out from the imagery under them
+    pub text_outline: Color,
+    /// Text drawn straight onto the map, like the debug menu and plane details
+    pub text: Color,
+    pub grid_font_size: FontSize,
+    pub debug_font_size: FontSize,
+    /// The details of the clicked plane or airport on the left of the window
+    pub details_font_size: FontSize,
+}
+
+impl Theme {
+    /// Light text over dark lines, which suits the ocean and street map
+    pub const DARK: Theme = Theme {
+        background: [0.21, 0.32, 0.4],
+        grid_line: Color::Rgba(0.0, 0.0, 0.0, 0.4),
+        grid_text: Color::Rgba(1.0, 1.0, 1.0, 1.0),
+        text_outline: Color::Rgba(0.0, 0.0, 0.0, 0.7),
+        text: Color::Rgba(1.0, 1.0, 1.0, 1.0),
+        grid_font_size: 12,
+        debug_font_size: 8,
+        details_font_size: 20,
+    };
+
+    /// Dark text, which stays readable over snow, clouds and desert in the satellite imagery
+    pub const LIGHT: Theme = Theme {
+        background: [0.82, 0.86, 0.89],
+        grid_line: Color::Rgba(0.1, 0.1, 0.1, 0.6),
+        grid_text: Color::Rgba(0.05, 0.05, 0.1, 1.0),
+        text_outline: Color::Rgba(1.0, 1.0, 1.0, 0.8),
+        text: Color::Rgba(0.05, 0.05, 0.1, 1.0),
+        grid_font_size: 12,
+        debug_font_size: 8,
+        details_font_size: 20,
+    };
+}
+
+/// Which [`Theme`] is in use, saved in the [`crate::ViewState`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThemeKind {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl ThemeKind {
+    pub fn theme(self) -> &'static Theme {
+        match self {
+            ThemeKind::Dark => &Theme::DARK,
+            ThemeKind::Light => &Theme::LIGHT,
+        }
+    }
+
+    /// Returns the other theme, for toggling between them
+    pub fn next(self) -> Self {
+        match self {
+            ThemeKind::Dark => ThemeKind::Light,
+            ThemeKind::Light => ThemeKind::Dark,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_toggle() {
+        assert_eq!(ThemeKind::default().theme(), &Theme::DARK);
+        assert_eq!(ThemeKind::Dark.next(), ThemeKind::Light);
+        assert_eq!(ThemeKind::Dark.next().next(), ThemeKind::Dark);
+        //The whole point of the light theme is text that differs from the dark one
+        assert_ne!(Theme::DARK.text, Theme::LIGHT.text);
+    }
+}
@@ -1,9 +1,8 @@
 use async_trait::async_trait;
 use image::{ImageBuffer, Rgba};
-use simple_moving_average::{SumTreeSMA, SMA};
-
-use std::time::Duration;
+use std::sync::OnceLock;
 use thiserror::Error;
+use tokio::sync::Semaphore;
 
 use super::TileId;
 
@@ -40,6 +39,54 @@ pub enum TileError {
     Join(#[from] tokio::task::JoinError),
     #[error("Maptiler: {0}")]
     Maptiler(#[from] maptiler_cloud::errors::Error),
+    #[error("Rain Viewer: {0}")]
+    RainViewer(#[from] rain_viewer::Error),
+    #[error("HTTP: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("No api key. Set the {0} environment variable")]
+    MissingApiKey(&'static str),
+    #[error("Timed out after {0:?}")]
+    Timeout(std::time::Duration),
+}
+
+impl TileError {
+    /// The HTTP status code the server responded with, if this error came from one
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            TileError::Maptiler(maptiler_cloud::errors::Error::Http(code)) => Some(code.as_u16()),
+            TileError::Maptiler(maptiler_cloud::errors::Error::Reqwest(err)) => {
+                err.status().map(|code| code.as_u16())
+            }
+            TileError::RainViewer(rain_viewer::Error::Http(code)) => Some(code.as_u16()),
+            TileError::RainViewer(rain_viewer::Error::Reqwest(err)) => {
+                err.status().map(|code| code.as_u16())
+            }
+            TileError::Http(err) => err.status().map(|code| code.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Returns true if retrying the request won't help, because the api rejected our key or we
+    /// ran out of quota. These are worth telling the user about
+    pub fn is_persistent(&self) -> bool {
+        matches!(self, TileError::MissingApiKey(_))
+            || matches!(self.status_code(), Some(401 | 403 | 429))
+    }
+
+    /// Returns true if this is a network error that may go away by itself, such as a dropped
+    /// connection or the server having a bad moment
+    pub fn is_transient(&self) -> bool {
+        let from_network = matches!(
+            self,
+            TileError::Maptiler(_)
+                | TileError::Http(_)
+                | TileError::Timeout(_)
+                | TileError::RainViewer(
+                    rain_viewer::Error::Reqwest(_) | rain_viewer::Error::Http(_)
+                )
+        );
+        from_network && matches!(self.status_code(), None | Some(408) | Some(500..=599))
+    }
 }
 
 pub type Texture = ImageBuffer<Rgba<u8>, Vec<u8>>;
@@ -62,15 +109,7 @@ pub trait Backend: Send + Sync {
 
         let result = self.request_inner(tile).await?;
 
-        let duration = start.elapsed();
-        {
-            let mut guard = crate::MAP_PERF_DATA.lock();
-            guard
-                .backend_request_secs
-                .entry(self.name())
-                .or_insert_with(|| SumTreeSMA::from_zero(Duration::ZERO))
-                .add_sample(duration);
-        }
+        crate::Profiler::record_backend_request(self.name(), start.elapsed());
         match result {
             Some(bytes) => {
                 let image = load_tile(bytes).await?;
@@ -80,9 +119,16 @@ pub trait Backend: Send + Sync {
         }
     }
 
-    /// Returns true if transparent tiles should return Ok(None) when requested.
+    /// Gives this backend a copy of a tile that was loaded by a slower backend further down the
+    /// pipeline.
     ///
-    /// Used for weather tiles so we avoid the need to load them
+    /// Used by in memory caches to hold onto tiles. Does nothing by default
+    async fn store(&self, _tile: TileId, _image: &Texture) {}
+
+    /// Returns true if fully transparent tiles from this backend should be treated as unavailable,
+    /// see [`is_fully_transparent`].
+    ///
+    /// Used for weather tiles so that empty radar tiles don't take up space on the GPU
     fn ignore_transparent_tiles(&self) -> bool;
 
     /// Queries the readiness status for a given tile in this backend.
@@ -98,23 +144,59 @@ pub trait Backend: Send + Sync {
     /// Returns `None` if unknown
     fn tile_size(&self) -> Option<u32>;
 
+    /// The highest zoom level this backend can provide tiles for.
+    ///
+    /// Returns `None` if this backend doesn't impose a limit beyond [`crate::MAX_ZOOM_LEVEL`]
+    fn max_zoom(&self) -> Option<u32> {
+        None
+    }
+
     /// Requests a tile from the this backend, returning the image bytes if the tile could be
     /// requested successfully
     async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError>;
 }
 
+/// Returns true if every pixel of `image` is fully transparent, so drawing it would do nothing
+pub fn is_fully_transparent(image: &Texture) -> bool {
+    image.pixels().all(|pixel| pixel[3] == 0)
+}
+
+/// Limits how many tiles are decoded at once, see [`set_decode_threads`]
+static DECODE_PERMITS: OnceLock<Semaphore> = OnceLock::new();
+
+/// Sets how many tiles can be decoded at once, which defaults to the number of CPU cores. Only
+/// takes effect if called before the first tile is decoded.
+///
+/// Decoding happens on Tokio's blocking thread pool, which the disk cache also uses for file IO.
+/// Rather than shrinking that pool, which would hold up cache reads behind decodes, decodes wait
+/// for a permit first. More decode threads get tiles on screen sooner on machines with cores to
+/// spare, while fewer leave more CPU time for the UI and the downloads on small machines.
+/// `tile_decode_time` in the debug menu shows how long each decode takes
+pub fn set_decode_threads(threads: usize) {
+    let _ = DECODE_PERMITS.set(Semaphore::new(threads.max(1)));
+}
+
+fn decode_permits() -> &'static Semaphore {
+    DECODE_PERMITS.get_or_init(|| {
+        let cores = std::thread::available_parallelism().map_or(4, |cores| cores.get());
+        Semaphore::new(cores)
+    })
+}
+
 /// Decodes a compressed png or jpeg image into a RGBA memory byte buffer.
 ///
 /// Users will usually call this and then upload the result to the GPU
 pub async fn load_tile(bytes: Vec<u8>) -> Result<Texture, TileError> {
+    let _permit = decode_permits()
+        .acquire()
+        .await
+        .expect("Decode semaphore closed");
     let result: Result<Texture, TileError> = tokio::task::spawn_blocking(move || {
         let start = std::time::Instant::now();
 
         let image = image::load_from_memory(&bytes)?.into_rgba8();
 
-        let duration = start.elapsed();
-        let mut guard = crate::MAP_PERF_DATA.lock();
-        guard.tile_decode_time.add_sample(duration);
+        crate::Profiler::record_tile_decode(start.elapsed());
         Ok(image)
     })
     .await?;
@@ -124,3 +206,21 @@ pub async fn load_tile(bytes: Vec<u8>) -> Result<Texture, TileError> {
     assert_eq!(image.width(), image.height());
     Ok(image)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transparent_tiles() {
+        let mut image = Texture::new(4, 4);
+        assert!(is_fully_transparent(&image));
+
+        //Color doesn't matter if nothing is visible
+        image.put_pixel(1, 2, Rgba([255, 0, 0, 0]));
+        assert!(is_fully_transparent(&image));
+
+        image.put_pixel(3, 3, Rgba([0, 0, 255, 1]));
+        assert!(!is_fully_transparent(&image));
+    }
+}
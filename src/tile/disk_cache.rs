@@ -1,10 +1,22 @@
 use std::{
     path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
     time::{Duration, SystemTime},
 };
 
-use super::{Backend, ReadinessStatus, TileError, TileId};
+use super::{
+    packed_cache::{opened_packed_store, packed_store},
+    Backend, ReadinessStatus, TileError, TileId,
+};
 use async_trait::async_trait;
+use log::{debug, warn};
+
+/// Temporary files older than this are left over from a crash or a failed write, and are deleted
+/// when the cache is purged. Much longer than any write takes, so ones in use are never touched
+const STALE_TEMP_AGE: Duration = Duration::from_secs(60 * 10);
+
+/// Counts up to give every temporary file written by this process a different name
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 fn get_tile_path(folder_name: &str, extension: &str, tile: TileId) -> String {
     format!(
@@ -13,32 +25,209 @@ fn get_tile_path(folder_name: &str, extension: &str, tile: TileId) -> String {
     )
 }
 
+/// How a [`DiskCache`] lays its tiles out on disk
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CacheLayout {
+    /// One file per tile, at `zoom/x/y.ext`
+    #[default]
+    Directory,
+
+    /// Tiles packed together into a few large blob files, with an index to find them.
+    /// Much easier on the file system when there are millions of tiles
+    Packed,
+}
+
 #[derive(Copy, Clone)]
 pub struct DiskCacheData {
     pub folder_name: &'static str,
     pub image_extension: &'static str,
     pub invalidate_time: Duration,
+    /// The most bytes of tiles this cache may hold before the least recently used are deleted.
+    /// `None` means the cache is unbounded
+    pub max_bytes: Option<u64>,
+    pub layout: CacheLayout,
 }
 
 impl DiskCacheData {
     pub async fn cache_tile(&self, tile: TileId, bytes: &[u8]) -> Result<(), std::io::Error> {
+        if self.layout == CacheLayout::Packed {
+            let store = packed_store(self.folder_name).await?;
+            let bytes = bytes.to_vec();
+            return tokio::task::spawn_blocking(move || store.write(tile, &bytes)).await?;
+        }
+
         let str_path = get_tile_path(self.folder_name, self.image_extension, tile);
         let path = Path::new(str_path.as_str());
         if let Some(parent) = path.parent() {
             if !parent.exists() {
                 if let Some(err) = tokio::fs::create_dir_all(parent).await.err() {
-                    println!("Failed to create dir: {} for cache: {:?}", str_path, err);
+                    warn!("Failed to create dir: {} for cache: {:?}", str_path, err);
+                }
+            }
+        }
+
+        //Write to a temporary file first so that a tile is never left half written if the app
+        //closes in the middle, then move it into place. The same tile can be written by more than
+        //one request at once, so each write gets its own temporary file
+        let temp_path = format!(
+            "{}.{}-{}.tmp",
+            str_path,
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let result = match tokio::fs::write(&temp_path, bytes).await {
+            Ok(()) => tokio::fs::rename(&temp_path, path).await,
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+        }
+        result
+    }
+
+    /// Deletes every tile in this cache that is older than `invalidate_time`, returning how many
+    /// were deleted.
+    ///
+    /// The directory walk happens on a blocking thread so it doesn't hold up tile requests
+    pub async fn purge_expired(&self) -> Result<usize, std::io::Error> {
+        let invalidate_time = self.invalidate_time;
+        if self.layout == CacheLayout::Packed {
+            let store = packed_store(self.folder_name).await?;
+            return tokio::task::spawn_blocking(move || store.purge_expired(invalidate_time))
+                .await?;
+        }
+
+        let folder = PathBuf::from(self.folder_name);
+        tokio::task::spawn_blocking(move || purge_dir(&folder, invalidate_time, SystemTime::now()))
+            .await?
+    }
+
+    /// Deletes the least recently used tiles until this cache fits within `max_bytes`, returning
+    /// how many were deleted. Does nothing if the cache is unbounded
+    pub async fn enforce_max_bytes(&self) -> Result<usize, std::io::Error> {
+        let max_bytes = match self.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return Ok(0),
+        };
+        if self.layout == CacheLayout::Packed {
+            let store = packed_store(self.folder_name).await?;
+            return tokio::task::spawn_blocking(move || store.shrink(max_bytes)).await?;
+        }
+
+        let folder = PathBuf::from(self.folder_name);
+        tokio::task::spawn_blocking(move || shrink_dir(&folder, max_bytes)).await?
+    }
+}
+
+/// Recursively deletes files in `dir` which were last modified more than `invalidate_time` before
+/// `now`, returning how many tiles were deleted. Temporary files older than [`STALE_TEMP_AGE`] are
+/// deleted too without being counted, as are directories left empty
+fn purge_dir(dir: &Path, invalidate_time: Duration, now: SystemTime) -> std::io::Result<usize> {
+    let mut purged = 0;
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let path = entry.path();
+        if metadata.is_dir() {
+            if let Ok(count) = purge_dir(&path, invalidate_time, now) {
+                purged += count;
+            }
+            //Only succeeds if the directory is empty, which is exactly what we want
+            let _ = std::fs::remove_dir(&path);
+        } else if let Ok(modified) = metadata.modified() {
+            let age = now.duration_since(modified).unwrap_or(Duration::ZERO);
+            if path.extension().is_some_and(|extension| extension == "tmp") {
+                if age > STALE_TEMP_AGE {
+                    let _ = std::fs::remove_file(&path);
                 }
+            } else if age > invalidate_time && std::fs::remove_file(&path).is_ok() {
+                purged += 1;
             }
         }
+    }
+    Ok(purged)
+}
 
-        tokio::fs::write(path, bytes).await
+/// Deletes the least recently used files in `dir` and its sub directories until their total size
+/// is at most `max_bytes`
+fn shrink_dir(dir: &Path, max_bytes: u64) -> std::io::Result<usize> {
+    let mut files = Vec::new();
+    list_files(dir, &mut files)?;
+
+    let mut total_bytes: u64 = files.iter().map(|(_, size, _)| size).sum();
+    if total_bytes <= max_bytes {
+        return Ok(0);
+    }
+
+    //Oldest first
+    files.sort_unstable_by_key(|(_, _, last_used)| *last_used);
+    let mut deleted = 0;
+    for (path, size, _) in files {
+        if total_bytes <= max_bytes {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total_bytes -= size;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// Appends the path, size and last use time of every file under `dir` to `files`.
+///
+/// The access time is used when the file system tracks it, otherwise we fall back to the time the
+/// tile was downloaded. Temporary files are left out, since they may still be being written, and
+/// [`purge_dir`] already deletes the stale ones
+fn list_files(dir: &Path, files: &mut Vec<(PathBuf, u64, SystemTime)>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let path = entry.path();
+        if metadata.is_dir() {
+            let _ = list_files(&path, files);
+        } else if path.extension().is_none_or(|extension| extension != "tmp") {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            let last_used = metadata.accessed().map_or(modified, |a| a.max(modified));
+            files.push((path, metadata.len(), last_used));
+        }
+    }
+    Ok(())
+}
+
+/// Periodically purges expired tiles from the cache described by `data`, and keeps it under its
+/// size limit.
+///
+/// Runs forever, so it should be cancelled through its `JoinHandle` when no longer needed
+pub async fn purge_expired_loop(data: DiskCacheData) {
+    //Sweep at least every ten minutes so that a long session can't blow far past the size limit,
+    //but more often for short lived caches so they don't pile up
+    let period = data.invalidate_time.min(Duration::from_secs(60 * 10));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match data.purge_expired().await {
+            Ok(0) => {}
+            Ok(count) => debug!("Purged {} expired tiles from {}", count, data.folder_name),
+            Err(err) => warn!("Failed to purge {}: {:?}", data.folder_name, err),
+        }
+        match data.enforce_max_bytes().await {
+            Ok(0) => {}
+            Ok(count) => debug!("Evicted {} tiles from {}", count, data.folder_name),
+            Err(err) => warn!("Failed to shrink {}: {:?}", data.folder_name, err),
+        }
     }
 }
 
 pub struct DiskCache {
     inner: DiskCacheData,
     ignore_transparent_tiles: bool,
+    /// The width of the tiles, once found by [`Backend::tile_size`]. 0 until then
+    tile_size: AtomicU32,
 }
 
 impl DiskCache {
@@ -48,6 +237,7 @@ impl DiskCache {
         Self {
             inner: data,
             ignore_transparent_tiles,
+            tile_size: AtomicU32::new(0),
         }
     }
 }
@@ -55,6 +245,14 @@ impl DiskCache {
 #[async_trait]
 impl Backend for DiskCache {
     async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        if self.inner.layout == CacheLayout::Packed {
+            let store = packed_store(self.inner.folder_name).await?;
+            let invalidate_time = self.inner.invalidate_time;
+            return Ok(
+                tokio::task::spawn_blocking(move || store.read(tile, invalidate_time)).await??,
+            );
+        }
+
         let path = get_tile_path(self.inner.folder_name, self.inner.image_extension, tile);
         match std::fs::metadata(&path) {
             Ok(metadata) => {
@@ -62,7 +260,7 @@ impl Backend for DiskCache {
                     if let Ok(age) = SystemTime::now().duration_since(last_modified) {
                         if age > self.inner.invalidate_time {
                             if let Err(err) = tokio::fs::remove_file(&path).await {
-                                println!(
+                                warn!(
                                     "Error: {:?} while deleting old tile {:?} at {}. {:?} old",
                                     err, tile, &path, age
                                 );
@@ -79,6 +277,18 @@ impl Backend for DiskCache {
     }
 
     async fn readiness(&self, tile: TileId) -> ReadinessStatus {
+        if self.inner.layout == CacheLayout::Packed {
+            //Don't wait out a compaction just to answer a hint
+            return match packed_store(self.inner.folder_name).await {
+                Ok(store) => match store.try_contains(tile) {
+                    Some(true) => ReadinessStatus::Available,
+                    Some(false) => ReadinessStatus::NotAvailable,
+                    None => ReadinessStatus::Unknown,
+                },
+                Err(_) => ReadinessStatus::NotAvailable,
+            };
+        }
+
         let path = get_tile_path(self.inner.folder_name, self.inner.image_extension, tile);
         match std::fs::metadata(&path) {
             Ok(_) => ReadinessStatus::Available,
@@ -91,6 +301,34 @@ impl Backend for DiskCache {
     }
 
     fn tile_size(&self) -> Option<u32> {
+        //Called from the render thread every frame, so only look at the tiles until one is found
+        let size = self.tile_size.load(Ordering::Relaxed);
+        if size != 0 {
+            return Some(size);
+        }
+        let size = self.find_tile_size()?;
+        self.tile_size.store(size, Ordering::Relaxed);
+        Some(size)
+    }
+
+    fn ignore_transparent_tiles(&self) -> bool {
+        self.ignore_transparent_tiles
+    }
+}
+
+impl DiskCache {
+    /// Returns the width of a tile in the cache by decoding one, or `None` if there aren't any yet
+    fn find_tile_size(&self) -> Option<u32> {
+        if self.inner.layout == CacheLayout::Packed {
+            //Only use the store once a tile request opened it, and never wait on a compaction
+            let bytes = opened_packed_store(self.inner.folder_name)?.try_any_tile()?;
+            let image = image::load_from_memory(&bytes[..]).ok()?.to_rgb8();
+            if image.width() != image.height() {
+                panic!("Image in cache: {}, is not square", self.inner.folder_name);
+            }
+            return Some(image.width());
+        }
+
         //Traverse directory tree, and return length of first image
         fn inner(mut dir_path: PathBuf) -> Result<u32, std::io::Error> {
             let it1 = std::fs::read_dir(&dir_path)?;
@@ -105,7 +343,7 @@ impl Backend for DiskCache {
                                 if rgb.width() != rgb.height() {
                                     panic!("Image in cache: {}, is not square", str_path);
                                 }
-                                println!(
+                                debug!(
                                     "Using image {} as model cache size: {}",
                                     str_path,
                                     rgb.width()
@@ -128,8 +366,151 @@ impl Backend for DiskCache {
 
         inner(PathBuf::from(self.inner.folder_name)).ok()
     }
+}
 
-    fn ignore_transparent_tiles(&self) -> bool {
-        self.ignore_transparent_tiles
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn purge_expired_tiles() {
+        let folder = std::env::temp_dir().join(format!("ft-purge-test-{}", std::process::id()));
+        let old_dir = folder.join("3/2");
+        let new_dir = folder.join("3/4");
+        std::fs::create_dir_all(&old_dir).unwrap();
+        std::fs::create_dir_all(&new_dir).unwrap();
+
+        //Back date a few tiles by a day
+        let day_ago = SystemTime::now() - Duration::from_secs(60 * 60 * 24);
+        for y in 0..3 {
+            let file = std::fs::File::create(old_dir.join(format!("{}.png", y))).unwrap();
+            file.set_modified(day_ago).unwrap();
+        }
+        std::fs::write(new_dir.join("1.png"), b"fresh").unwrap();
+
+        //A write that crashed an hour ago, and one that is still going
+        let stale_temp = new_dir.join("2.png.1-0.tmp");
+        std::fs::File::create(&stale_temp)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60 * 60))
+            .unwrap();
+        let fresh_temp = new_dir.join("3.png.1-1.tmp");
+        std::fs::write(&fresh_temp, b"writing").unwrap();
+
+        let data = DiskCacheData {
+            folder_name: Box::leak(folder.to_string_lossy().into_owned().into_boxed_str()),
+            image_extension: "png",
+            invalidate_time: Duration::from_secs(60 * 5),
+            max_bytes: None,
+            layout: CacheLayout::Directory,
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let purged = runtime.block_on(data.purge_expired()).unwrap();
+
+        assert_eq!(purged, 3);
+        assert!(!old_dir.exists());
+        assert!(new_dir.join("1.png").exists());
+        assert!(!stale_temp.exists());
+        assert!(fresh_temp.exists());
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writes_of_a_tile() {
+        //Tile paths are always relative to the working directory
+        let folder = format!("target/ft-write-test-{}", std::process::id());
+        let data = DiskCacheData {
+            folder_name: Box::leak(folder.clone().into_boxed_str()),
+            image_extension: "png",
+            invalidate_time: Duration::from_secs(60 * 60 * 24),
+            max_bytes: None,
+            layout: CacheLayout::Directory,
+        };
+        let tile = TileId::new(1, 2, 3);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let writes = (0..16u8)
+                .map(|i| tokio::spawn(async move { data.cache_tile(tile, &[i; 4096]).await }));
+            for write in writes.collect::<Vec<_>>() {
+                write.await.unwrap().unwrap();
+            }
+        });
+
+        //Whichever write finished last wins, but it is always one whole tile
+        let bytes = std::fs::read(get_tile_path(&folder, "png", tile)).unwrap();
+        assert_eq!(bytes.len(), 4096);
+        assert!(bytes.iter().all(|byte| *byte == bytes[0]));
+        let leftovers = std::fs::read_dir(format!("{}/3/1", folder))
+            .unwrap()
+            .count();
+        assert_eq!(leftovers, 1);
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn enforce_max_bytes_evicts_oldest() {
+        let folder = std::env::temp_dir().join(format!("ft-shrink-test-{}", std::process::id()));
+        let tile_dir = folder.join("5/7");
+        std::fs::create_dir_all(&tile_dir).unwrap();
+
+        //Twenty 100 byte tiles, each used a minute after the last
+        let start = SystemTime::now() - Duration::from_secs(60 * 60);
+        for y in 0..20 {
+            let path = tile_dir.join(format!("{}.png", y));
+            std::fs::write(&path, [0u8; 100]).unwrap();
+            let used = start + Duration::from_secs(60 * y);
+            let times = std::fs::FileTimes::new()
+                .set_accessed(used)
+                .set_modified(used);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_times(times)
+                .unwrap();
+        }
+
+        //A download still being written, older and bigger than any tile, which must be left alone
+        let temp = tile_dir.join("20.png.1-0.tmp");
+        std::fs::write(&temp, [0u8; 500]).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&temp)
+            .unwrap()
+            .set_times(
+                std::fs::FileTimes::new()
+                    .set_accessed(start)
+                    .set_modified(start),
+            )
+            .unwrap();
+
+        let data = DiskCacheData {
+            folder_name: Box::leak(folder.to_string_lossy().into_owned().into_boxed_str()),
+            image_extension: "png",
+            invalidate_time: Duration::from_secs(60 * 60 * 24),
+            max_bytes: Some(1000),
+            layout: CacheLayout::Directory,
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let evicted = runtime.block_on(data.enforce_max_bytes()).unwrap();
+
+        assert_eq!(evicted, 10);
+        for y in 0..20 {
+            assert_eq!(tile_dir.join(format!("{}.png", y)).exists(), y >= 10);
+        }
+        assert!(temp.exists());
+
+        //Already under budget, so nothing else goes
+        assert_eq!(runtime.block_on(data.enforce_max_bytes()).unwrap(), 0);
+
+        let unbounded = DiskCacheData {
+            max_bytes: None,
+            ..data
+        };
+        assert_eq!(runtime.block_on(unbounded.enforce_max_bytes()).unwrap(), 0);
+
+        std::fs::remove_dir_all(&folder).unwrap();
     }
 }
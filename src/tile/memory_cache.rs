@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use intmap::IntMap;
+use parking_lot::Mutex;
+
+use super::{tile_coord_to_u64, Backend, ReadinessStatus, Texture, TileError, TileId};
+
+/// Holds recently decoded tiles in RAM so that tiles evicted from the GPU can be uploaded again
+/// without going back to the disk and decoding them a second time.
+///
+/// This sits at the top of a pipeline, and is filled by [`Backend::store`] whenever a slower
+/// backend loads a tile. Tiles are dropped once they are as old as the disk cache would let them
+/// get, so that frequently updated imagery like weather radar doesn't go stale in memory
+pub struct MemoryCache {
+    images: Mutex<ImageLru>,
+    ignore_transparent_tiles: bool,
+}
+
+impl MemoryCache {
+    /// Creates a new memory cache that holds at most `budget_bytes` worth of decoded images, each
+    /// for at most `max_age`
+    pub fn new(budget_bytes: usize, max_age: Duration, ignore_transparent_tiles: bool) -> Self {
+        Self {
+            images: Mutex::new(ImageLru::new(budget_bytes, max_age)),
+            ignore_transparent_tiles,
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for MemoryCache {
+    async fn request(&self, tile: TileId) -> Result<Option<Texture>, TileError> {
+        //The images are already decoded, so skip `request_inner` and the decoding step entirely
+        let start = std::time::Instant::now();
+        let image = self.images.lock().get(tile, start);
+        crate::Profiler::record_backend_request(self.name(), start.elapsed());
+
+        Ok(image)
+    }
+
+    async fn request_inner(&self, _tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        //We only hold decoded images, so there are never any bytes to return
+        Ok(None)
+    }
+
+    async fn store(&self, tile: TileId, image: &Texture) {
+        self.images
+            .lock()
+            .insert(tile, image.clone(), Instant::now());
+    }
+
+    async fn readiness(&self, tile: TileId) -> ReadinessStatus {
+        if self.images.lock().contains(tile, Instant::now()) {
+            ReadinessStatus::Available
+        } else {
+            ReadinessStatus::NotAvailable
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Memory"
+    }
+
+    fn tile_size(&self) -> Option<u32> {
+        None
+    }
+
+    fn ignore_transparent_tiles(&self) -> bool {
+        self.ignore_transparent_tiles
+    }
+}
+
+/// An image in an [`ImageLru`]
+struct CachedImage {
+    image: Texture,
+    /// The tick the image was last used on
+    last_used: u64,
+    /// When the image was put in the cache
+    stored: Instant,
+}
+
+/// A least recently used cache of images, bounded by the number of bytes the images take up and
+/// by how long ago they were stored
+struct ImageLru {
+    /// Maps encoded tile ids to their images
+    images: IntMap<CachedImage>,
+
+    /// Maps the tick each tile was last used on to its encoded tile id, so the oldest can be found
+    /// quickly
+    by_age: BTreeMap<u64, u64>,
+
+    /// Incremented each time a tile is used
+    tick: u64,
+    size_bytes: usize,
+    budget_bytes: usize,
+    /// How long an image is kept after it was stored
+    max_age: Duration,
+}
+
+impl ImageLru {
+    fn new(budget_bytes: usize, max_age: Duration) -> Self {
+        Self {
+            images: IntMap::new(),
+            by_age: BTreeMap::new(),
+            tick: 0,
+            size_bytes: 0,
+            budget_bytes,
+            max_age,
+        }
+    }
+
+    /// Returns a copy of the image for `tile`, marking it as the most recently used. Images older
+    /// than `max_age` at `now` are removed instead
+    fn get(&mut self, tile: TileId, now: Instant) -> Option<Texture> {
+        let id = tile_coord_to_u64(tile);
+        if !self.contains(tile, now) {
+            self.remove(id);
+            return None;
+        }
+        self.tick += 1;
+        let cached = self.images.get_mut(id)?;
+        self.by_age.remove(&cached.last_used);
+        cached.last_used = self.tick;
+        self.by_age.insert(self.tick, id);
+
+        Some(cached.image.clone())
+    }
+
+    /// Returns true if an image for `tile` is stored and not older than `max_age` at `now`
+    fn contains(&self, tile: TileId, now: Instant) -> bool {
+        self.images
+            .get(tile_coord_to_u64(tile))
+            .is_some_and(|cached| now.saturating_duration_since(cached.stored) < self.max_age)
+    }
+
+    /// Adds `image` to the cache, evicting the least recently used images if we go over budget
+    fn insert(&mut self, tile: TileId, image: Texture, now: Instant) {
+        let id = tile_coord_to_u64(tile);
+        let size = image_size(&image);
+        if size > self.budget_bytes {
+            return;
+        }
+        self.remove(id);
+
+        while self.size_bytes + size > self.budget_bytes {
+            let oldest = match self.by_age.values().next() {
+                Some(&oldest) => oldest,
+                None => break,
+            };
+            self.remove(oldest);
+        }
+
+        self.tick += 1;
+        self.size_bytes += size;
+        self.by_age.insert(self.tick, id);
+        self.images.insert(
+            id,
+            CachedImage {
+                image,
+                last_used: self.tick,
+                stored: now,
+            },
+        );
+    }
+
+    fn remove(&mut self, id: u64) {
+        if let Some(cached) = self.images.remove(id) {
+            self.by_age.remove(&cached.last_used);
+            self.size_bytes -= image_size(&cached.image);
+        }
+    }
+}
+
+/// The number of bytes used by the pixels of `image`
+fn image_size(image: &Texture) -> usize {
+    image.as_raw().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+    fn image(size: u32) -> Texture {
+        Texture::new(size, size)
+    }
+
+    #[test]
+    fn lru_stays_within_budget() {
+        let now = Instant::now();
+        //Room for exactly four 16x16 RGBA images
+        let mut lru = ImageLru::new(16 * 16 * 4 * 4, DAY);
+        for x in 0..4 {
+            lru.insert(TileId::new(x, 0, 2), image(16), now);
+        }
+        assert_eq!(lru.size_bytes, 16 * 16 * 4 * 4);
+
+        //Use the first tile so that the second is now the oldest
+        assert!(lru.get(TileId::new(0, 0, 2), now).is_some());
+        lru.insert(TileId::new(0, 1, 2), image(16), now);
+
+        assert!(lru.contains(TileId::new(0, 0, 2), now));
+        assert!(!lru.contains(TileId::new(1, 0, 2), now));
+        assert!(lru.contains(TileId::new(0, 1, 2), now));
+        assert_eq!(lru.size_bytes, 16 * 16 * 4 * 4);
+
+        //A big image pushes out as many as it needs to
+        lru.insert(TileId::new(1, 1, 2), image(32), now);
+        assert!(lru.size_bytes <= lru.budget_bytes);
+        assert!(lru.contains(TileId::new(1, 1, 2), now));
+        assert_eq!(lru.images.len(), 1);
+    }
+
+    #[test]
+    fn lru_replaces_duplicates() {
+        let now = Instant::now();
+        let mut lru = ImageLru::new(1024 * 1024, DAY);
+        for _ in 0..10 {
+            lru.insert(TileId::new(3, 3, 3), image(16), now);
+        }
+        assert_eq!(lru.images.len(), 1);
+        assert_eq!(lru.by_age.len(), 1);
+        assert_eq!(lru.size_bytes, 16 * 16 * 4);
+    }
+
+    #[test]
+    fn lru_ignores_images_over_budget() {
+        let now = Instant::now();
+        let mut lru = ImageLru::new(100, DAY);
+        lru.insert(TileId::new(0, 0, 0), image(16), now);
+        assert!(!lru.contains(TileId::new(0, 0, 0), now));
+        assert_eq!(lru.size_bytes, 0);
+    }
+
+    #[test]
+    fn lru_forgets_old_images() {
+        //Like radar frames, which are replaced every few minutes
+        let max_age = Duration::from_secs(60 * 5);
+        let start = Instant::now();
+        let mut lru = ImageLru::new(1024 * 1024, max_age);
+        let tile = TileId::new(1, 1, 1);
+        lru.insert(tile, image(16), start);
+        assert!(lru.get(tile, start + max_age / 2).is_some());
+
+        assert!(!lru.contains(tile, start + max_age));
+        assert!(lru.get(tile, start + max_age).is_none());
+        assert_eq!(lru.size_bytes, 0);
+        assert!(lru.by_age.is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use super::{Backend, ReadinessStatus, Texture, TileError, TileId};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// What a [`MockBackend`] does when a tile is requested
+#[derive(Clone, Debug)]
+pub enum MockTile {
+    /// Returns the image
+    Image(Texture),
+    /// Returns `Ok(None)`, like a cache that doesn't have the tile
+    Missing,
+    /// Fails with an error that isn't worth retrying
+    Error,
+    /// Fails because there is no api key, which the pipeline reports to the user
+    MissingApiKey,
+    /// Fails with a timeout, which the pipeline retries
+    TransientError,
+    /// Never finishes, like a hung connection
+    Hang,
+    /// Returns the image after waiting
+    Delayed(std::time::Duration, Texture),
+}
+
+impl MockTile {
+    /// A 2x2 tile of a single color
+    pub fn solid(color: [u8; 4]) -> Self {
+        MockTile::Image(Texture::from_pixel(2, 2, image::Rgba(color)))
+    }
+}
+
+struct MockState {
+    tiles: HashMap<TileId, MockTile>,
+    /// Used for tiles without an entry in `tiles`
+    default: MockTile,
+    requests: Vec<TileId>,
+    stored: Vec<TileId>,
+}
+
+/// An in memory [`Backend`] for tests, which serves whatever it was seeded with and records what
+/// was asked of it.
+///
+/// Clones share the same state, so a test can keep one to inspect after handing the other to a
+/// pipeline
+#[derive(Clone)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+    ignore_transparent_tiles: bool,
+}
+
+impl MockBackend {
+    /// Creates a backend that has no tiles
+    pub fn new() -> Self {
+        Self::with_default(MockTile::Missing)
+    }
+
+    /// Creates a backend that responds to every tile it wasn't seeded with using `default`
+    pub fn with_default(default: MockTile) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                tiles: HashMap::new(),
+                default,
+                requests: Vec::new(),
+                stored: Vec::new(),
+            })),
+            ignore_transparent_tiles: false,
+        }
+    }
+
+    /// Makes requests for `tile` respond with `result`
+    pub fn set_tile(&self, tile: TileId, result: MockTile) {
+        self.state.lock().tiles.insert(tile, result);
+    }
+
+    /// Sets the value returned by [`Backend::ignore_transparent_tiles`]
+    pub fn ignoring_transparent_tiles(mut self) -> Self {
+        self.ignore_transparent_tiles = true;
+        self
+    }
+
+    /// Every tile requested so far, in order
+    pub fn requests(&self) -> Vec<TileId> {
+        self.state.lock().requests.clone()
+    }
+
+    /// Every tile given to [`Backend::store`] so far, in order
+    pub fn stored(&self) -> Vec<TileId> {
+        self.state.lock().stored.clone()
+    }
+}
+
+#[async_trait]
+impl Backend for MockBackend {
+    async fn request(&self, tile: TileId) -> Result<Option<Texture>, TileError> {
+        let result = {
+            let mut state = self.state.lock();
+            state.requests.push(tile);
+            state.tiles.get(&tile).unwrap_or(&state.default).clone()
+        };
+        match result {
+            MockTile::Image(image) => Ok(Some(image)),
+            MockTile::Missing => Ok(None),
+            MockTile::Error => Err(TileError::Io(std::io::Error::other("mock error"))),
+            MockTile::MissingApiKey => Err(TileError::MissingApiKey("MOCK_API_KEY")),
+            MockTile::TransientError => Err(TileError::Timeout(std::time::Duration::ZERO)),
+            MockTile::Hang => std::future::pending().await,
+            MockTile::Delayed(delay, image) => {
+                tokio::time::sleep(delay).await;
+                Ok(Some(image))
+            }
+        }
+    }
+
+    async fn request_inner(&self, _tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        //Images are handed out already decoded by `request`
+        Ok(None)
+    }
+
+    /// Keeps the image, like a cache would
+    async fn store(&self, tile: TileId, image: &Texture) {
+        let mut state = self.state.lock();
+        state.stored.push(tile);
+        state.tiles.insert(tile, MockTile::Image(image.clone()));
+    }
+
+    async fn readiness(&self, tile: TileId) -> ReadinessStatus {
+        let state = self.state.lock();
+        match state.tiles.get(&tile).unwrap_or(&state.default) {
+            MockTile::Image(_) | MockTile::Delayed(..) => ReadinessStatus::Available,
+            MockTile::Missing | MockTile::Error | MockTile::MissingApiKey => {
+                ReadinessStatus::NotAvailable
+            }
+            MockTile::TransientError | MockTile::Hang => ReadinessStatus::Unknown,
+        }
+    }
+
+    fn ignore_transparent_tiles(&self) -> bool {
+        self.ignore_transparent_tiles
+    }
+
+    fn name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn tile_size(&self) -> Option<u32> {
+        Some(2)
+    }
+}
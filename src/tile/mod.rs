@@ -1,18 +1,31 @@
 mod backend;
 mod disk_cache;
+mod memory_cache;
+#[cfg(test)]
+pub(crate) mod mock_backend;
+mod osm_requester;
+mod packed_cache;
 mod pipeline;
+mod prefetch;
 
 mod satellite_requester;
+mod template_requester;
 mod weather_requester;
 
 pub use backend::*;
 pub use pipeline::*;
+pub use prefetch::{prefetch_region, region_tiles, PrefetchError, PrefetchProgress, Region};
 
 use disk_cache::*;
+use memory_cache::*;
+use osm_requester::*;
 use satellite_requester::*;
+pub use template_requester::{TemplateError, TemplateRequester, UrlTemplate};
 use weather_requester::*;
 
+use crate::Shutdown;
 use enum_map::{enum_map, Enum, EnumMap};
+use log::warn;
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
@@ -29,43 +42,339 @@ impl TileId {
     pub fn new(x: u32, y: u32, zoom: u32) -> Self {
         Self { x, y, zoom }
     }
+
+    /// Returns true if this tile exists, which is when `x` and `y` are less than `2^zoom`
+    pub fn is_valid(&self) -> bool {
+        let tiles_across = 1u64 << self.zoom.min(63);
+        (self.x as u64) < tiles_across && (self.y as u64) < tiles_across
+    }
+
+    /// Returns the tile one zoom level out that contains this one, or `None` at zoom level 0
+    pub fn parent(&self) -> Option<TileId> {
+        let zoom = self.zoom.checked_sub(1)?;
+        Some(TileId::new(self.x / 2, self.y / 2, zoom))
+    }
+
+    /// Returns the four tiles one zoom level in that make up this one, in the order top left, top
+    /// right, bottom left, bottom right. They are valid whenever this tile is
+    pub fn children(&self) -> [TileId; 4] {
+        let (x, y, zoom) = (self.x * 2, self.y * 2, self.zoom + 1);
+        [
+            TileId::new(x, y, zoom),
+            TileId::new(x + 1, y, zoom),
+            TileId::new(x, y + 1, zoom),
+            TileId::new(x + 1, y + 1, zoom),
+        ]
+    }
 }
 
 /// The kind of imagery this tile represents
-#[derive(Debug, Enum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Enum)]
 pub enum TileKind {
     /// A satellite tile
     Satellite,
     /// A weather tile
     Weather,
+    /// An OpenStreetMap street tile
+    Street,
+}
+
+impl TileKind {
+    /// A short name for labeling tiles in the tile debug overlay
+    pub fn name(self) -> &'static str {
+        match self {
+            TileKind::Satellite => "Satellite",
+            TileKind::Weather => "Weather",
+            TileKind::Street => "Street",
+        }
+    }
+}
+
+/// The environment variable holding a URL template, like
+/// `https://{s}.tile.example.com/{z}/{x}/{y}.png`, for a tile server to use instead of
+/// OpenStreetMap for the street layer
+const STREET_TILES_VAR: &str = "FLIGHT_TRACKER_STREET_TILES";
+
+/// The environment variable holding the comma separated subdomains `{s}` is replaced with
+const STREET_SUBDOMAINS_VAR: &str = "FLIGHT_TRACKER_STREET_SUBDOMAINS";
+
+/// The environment variable holding the highest zoom level the custom street server has tiles for
+const STREET_MAX_ZOOM_VAR: &str = "FLIGHT_TRACKER_STREET_MAX_ZOOM";
+
+/// The highest zoom level most tile servers have tiles for, used when `FLIGHT_TRACKER_STREET_MAX_ZOOM`
+/// isn't set
+const DEFAULT_STREET_MAX_ZOOM: u32 = 19;
+
+/// A tile server set with the `FLIGHT_TRACKER_STREET_TILES` environment variable that the street
+/// layer uses instead of OpenStreetMap
+struct CustomStreetServer {
+    template: UrlTemplate,
+    /// Where its tiles are cached, which is different for every template
+    folder_name: String,
+    max_zoom: u32,
+}
+
+/// Returns the custom street tile server set in the environment, or `None` to use OpenStreetMap
+fn street_server_from_env() -> Option<CustomStreetServer> {
+    let raw_template = std::env::var(STREET_TILES_VAR).ok()?;
+    let template = street_template(&raw_template)?;
+    Some(CustomStreetServer {
+        template,
+        folder_name: custom_street_folder(&raw_template),
+        max_zoom: parse_street_max_zoom(std::env::var(STREET_MAX_ZOOM_VAR).ok()),
+    })
+}
+
+/// Returns the folder tiles from the server at `template` are cached in. Each template gets its own
+/// folder so that switching servers never shows tiles from the previous one
+fn custom_street_folder(template: &str) -> String {
+    //FNV-1a, because unlike the standard library's hasher its output never changes between builds
+    let hash = template.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!(".cache/custom_street/{:016x}", hash)
+}
+
+/// Parses the value of `FLIGHT_TRACKER_STREET_MAX_ZOOM`, falling back to
+/// [`DEFAULT_STREET_MAX_ZOOM`] if it is missing or invalid
+fn parse_street_max_zoom(value: Option<String>) -> u32 {
+    let value = match value {
+        Some(value) => value,
+        None => return DEFAULT_STREET_MAX_ZOOM,
+    };
+    match value.trim().parse::<u32>() {
+        Ok(zoom) if zoom <= crate::MAX_ZOOM_LEVEL => zoom,
+        _ => {
+            warn!(
+                "Ignoring {}={:?}, expected a zoom level from 0 to {}",
+                STREET_MAX_ZOOM_VAR,
+                value,
+                crate::MAX_ZOOM_LEVEL
+            );
+            DEFAULT_STREET_MAX_ZOOM
+        }
+    }
+}
+
+/// Parses the street tile server template `template`, with the subdomains from the
+/// `FLIGHT_TRACKER_STREET_SUBDOMAINS` environment variable. Returns `None` if it is invalid
+fn street_template(template: &str) -> Option<UrlTemplate> {
+    let subdomains = std::env::var(STREET_SUBDOMAINS_VAR)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|subdomain| !subdomain.is_empty())
+        .map(str::to_owned)
+        .collect();
+    match UrlTemplate::parse(template, subdomains) {
+        Ok(template) => Some(template),
+        Err(err) => {
+            warn!(
+                "Ignoring {}={:?}, {}. Using OpenStreetMap instead",
+                STREET_TILES_VAR, template, err
+            );
+            None
+        }
+    }
+}
+
+/// Returns true if the server providing tiles of `kind` allows downloading them in bulk, like
+/// the `prefetch` subcommand does. OpenStreetMap's tile usage policy forbids it, so street tiles
+/// can only be prefetched from a custom server
+fn allows_bulk_download(kind: TileKind) -> bool {
+    kind != TileKind::Street || street_server_from_env().is_some()
 }
 
 /// A mapping between imagery kinds and the tile pipeline that provides access to tile images
 pub type PipelineMap = EnumMap<TileKind, TilePipeline>;
 
-/// Returns the default pipelines for `TileKinds`.
+/// Returns the disk cache settings and the api backend that provide tiles of `kind`. The api
+/// backend stores every tile it downloads in the disk cache
+fn tile_source(kind: TileKind) -> (DiskCacheData, Box<dyn Backend>) {
+    match kind {
+        TileKind::Satellite => {
+            let cache = DiskCacheData {
+                folder_name: ".cache/satellite",
+                image_extension: "jpg",
+                invalidate_time: Duration::from_secs(60 * 60 * 24 * 30), //One month long cache
+                max_bytes: Some(2 * 1024 * 1024 * 1024),
+                layout: CacheLayout::Directory,
+            };
+            (cache, Box::new(SatelliteRequester::new(cache)))
+        }
+        TileKind::Weather => {
+            let cache = DiskCacheData {
+                folder_name: ".cache/weather",
+                image_extension: "png",
+                invalidate_time: Duration::from_secs(60 * 5), //Five minute cache
+                max_bytes: Some(256 * 1024 * 1024),
+                layout: CacheLayout::Directory,
+            };
+            (cache, Box::new(WeatherRequester::new(cache)))
+        }
+        TileKind::Street => {
+            let cache = DiskCacheData {
+                folder_name: ".cache/street",
+                image_extension: "png",
+                //OpenStreetMap's usage policy asks for tiles to be cached for at least a week
+                invalidate_time: Duration::from_secs(60 * 60 * 24 * 7),
+                max_bytes: Some(1024 * 1024 * 1024),
+                layout: CacheLayout::Directory,
+            };
+            match street_server_from_env() {
+                Some(server) => {
+                    //Pipelines are only created a few times, so leaking the folder name is cheap
+                    let cache = DiskCacheData {
+                        folder_name: Box::leak(server.folder_name.into_boxed_str()),
+                        ..cache
+                    };
+                    let requester = TemplateRequester::new(
+                        server.template,
+                        256,
+                        Some(server.max_zoom),
+                        4,
+                        &user_agent(),
+                        cache,
+                    );
+                    (cache, Box::new(requester))
+                }
+                None => (cache, Box::new(OsmRequester::new(cache))),
+            }
+        }
+    }
+}
+
+/// Returns the default pipelines for `TileKinds`, which retry failed requests according to
+/// `retry_policy`, and stop starting requests once `shutdown` is triggered.
 ///
-/// Each imagery type is backed by a disk cache and access to an api which retrieves the tiles if
-/// the disk cache misses
-pub fn pipelines(runtime: &Runtime) -> PipelineMap {
-    let satellite_cache = DiskCacheData {
-        folder_name: ".cache/satellite",
-        image_extension: "jpg",
-        invalidate_time: Duration::from_secs(60 * 60 * 24 * 30), //One month long cache
-    };
-    let weather_cache = DiskCacheData {
-        folder_name: ".cache/weather",
-        image_extension: "png",
-        invalidate_time: Duration::from_secs(60 * 5), //Five minute cache
+/// Each imagery type is backed by a memory cache of decoded tiles, a disk cache, and access to an
+/// api which retrieves the tiles if both caches miss
+pub fn pipelines(runtime: &Runtime, retry_policy: RetryPolicy, shutdown: &Shutdown) -> PipelineMap {
+    let pipeline = |kind: TileKind, memory_bytes: usize, gpu_tile_budget: usize| {
+        let (mut pipeline, cache) = tile_pipeline(
+            kind,
+            memory_bytes,
+            gpu_tile_budget,
+            retry_policy,
+            shutdown,
+            runtime,
+        );
+        pipeline.add_background_task(runtime.spawn(purge_expired_loop(cache)));
+        pipeline
     };
     enum_map! {
-        TileKind::Satellite => TilePipeline::new(vec![
-            Box::new(DiskCache::new(satellite_cache, false)),
-            Box::new(SatelliteRequester::new(satellite_cache))
-        ], runtime),
-        TileKind::Weather => TilePipeline::new(vec![
-            Box::new(DiskCache::new(weather_cache, true)),
-            Box::new(WeatherRequester::new(weather_cache))
-        ], runtime),
+        //Satellite tiles are small, so we can afford to keep plenty around for when the user pans back
+        TileKind::Satellite => pipeline(TileKind::Satellite, 128 * 1024 * 1024, 512),
+        //Weather tiles are 512x512, so each one is a whole megabyte on the GPU
+        TileKind::Weather => pipeline(TileKind::Weather, 64 * 1024 * 1024, 64),
+        TileKind::Street => pipeline(TileKind::Street, 128 * 1024 * 1024, 512),
+    }
+}
+
+/// Returns a small pipeline of satellite tiles for the minimap, which only ever shows the whole
+/// world at one low zoom level. It shares the disk cache of the main satellite pipeline, which is
+/// left to purge it
+pub fn overview_pipeline(
+    runtime: &Runtime,
+    retry_policy: RetryPolicy,
+    shutdown: &Shutdown,
+) -> TilePipeline {
+    let (pipeline, _) = tile_pipeline(
+        TileKind::Satellite,
+        8 * 1024 * 1024,
+        32,
+        retry_policy,
+        shutdown,
+        runtime,
+    );
+    pipeline
+}
+
+/// Returns a pipeline for `kind` backed by a memory cache of `memory_bytes`, the disk cache and
+/// the api, along with the settings of the disk cache
+fn tile_pipeline(
+    kind: TileKind,
+    memory_bytes: usize,
+    gpu_tile_budget: usize,
+    retry_policy: RetryPolicy,
+    shutdown: &Shutdown,
+    runtime: &Runtime,
+) -> (TilePipeline, DiskCacheData) {
+    let (cache, requester) = tile_source(kind);
+    //Empty radar tiles are common and not worth keeping around
+    let ignore_transparent_tiles = kind == TileKind::Weather;
+    let pipeline = TilePipeline::new(
+        vec![
+            Box::new(MemoryCache::new(
+                memory_bytes,
+                cache.invalidate_time,
+                ignore_transparent_tiles,
+            )),
+            Box::new(DiskCache::new(cache, ignore_transparent_tiles)),
+            requester,
+        ],
+        gpu_tile_budget,
+        retry_policy,
+        shutdown,
+        runtime,
+    );
+    (pipeline, cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parents_and_children() {
+        assert_eq!(TileId::new(0, 0, 0).parent(), None);
+        assert_eq!(TileId::new(1, 0, 1).parent(), Some(TileId::new(0, 0, 0)));
+        assert_eq!(TileId::new(7, 10, 5).parent(), Some(TileId::new(3, 5, 4)));
+
+        assert_eq!(
+            TileId::new(0, 0, 0).children(),
+            [
+                TileId::new(0, 0, 1),
+                TileId::new(1, 0, 1),
+                TileId::new(0, 1, 1),
+                TileId::new(1, 1, 1),
+            ]
+        );
+        let tile = TileId::new(3, 5, 4);
+        for child in tile.children().iter() {
+            assert_eq!(child.parent(), Some(tile));
+        }
+
+        // Children of the last tile in a row stay inside the grid
+        let corner = TileId::new(3, 3, 2);
+        assert!(corner.children().iter().all(TileId::is_valid));
+        assert!(!TileId::new(4, 0, 2).is_valid());
+        assert!(TileId::new(0, 0, 0).is_valid());
+    }
+
+    #[test]
+    fn custom_street_servers_are_cached_apart() {
+        let first = custom_street_folder("https://a.example.com/{z}/{x}/{y}.png");
+        let second = custom_street_folder("https://b.example.com/{z}/{x}/{y}.png");
+        assert_ne!(first, second);
+        assert!(first.starts_with(".cache/custom_street/"));
+        assert_eq!(
+            first,
+            custom_street_folder("https://a.example.com/{z}/{x}/{y}.png")
+        );
+    }
+
+    #[test]
+    fn street_max_zoom() {
+        assert_eq!(parse_street_max_zoom(None), DEFAULT_STREET_MAX_ZOOM);
+        assert_eq!(parse_street_max_zoom(Some(" 16 ".to_owned())), 16);
+        assert_eq!(
+            parse_street_max_zoom(Some("deep".to_owned())),
+            DEFAULT_STREET_MAX_ZOOM
+        );
+        assert_eq!(
+            parse_street_max_zoom(Some((crate::MAX_ZOOM_LEVEL + 1).to_string())),
+            DEFAULT_STREET_MAX_ZOOM
+        );
     }
 }
@@ -0,0 +1,81 @@
+use super::{disk_cache::DiskCacheData, Backend, ReadinessStatus, TileError, TileId};
+
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+
+/// OpenStreetMap's tile usage policy asks that clients don't hammer the servers, so we keep only
+/// a couple of downloads going at once
+const MAX_CONCURRENT_DOWNLOADS: usize = 2;
+
+/// Fetches street map tiles from the OpenStreetMap tile servers
+pub struct OsmRequester {
+    client: reqwest::Client,
+    permits: Semaphore,
+    cache_data: DiskCacheData,
+}
+
+/// Returns a user agent that identifies the application, which tile servers like OpenStreetMap's
+/// require
+pub fn user_agent() -> String {
+    format!(
+        "{}/{} (+{})",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_REPOSITORY")
+    )
+}
+
+impl OsmRequester {
+    pub fn new(cache_data: DiskCacheData) -> Self {
+        //The usage policy requires a user agent that identifies the application
+        let user_agent = user_agent();
+        Self {
+            client: reqwest::Client::builder()
+                .user_agent(user_agent)
+                .build()
+                .expect("Failed to create OpenStreetMap TLS backend!"),
+            permits: Semaphore::new(MAX_CONCURRENT_DOWNLOADS),
+            cache_data,
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for OsmRequester {
+    fn name(&self) -> &'static str {
+        "Street Requester"
+    }
+
+    async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        let url = format!(
+            "https://tile.openstreetmap.org/{}/{}/{}.png",
+            tile.zoom, tile.x, tile.y
+        );
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("OpenStreetMap semaphore closed");
+
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        let bytes = response.bytes().await?.to_vec();
+        let _ = self.cache_data.cache_tile(tile, bytes.as_slice()).await;
+        Ok(Some(bytes))
+    }
+
+    async fn readiness(&self, _tile: TileId) -> ReadinessStatus {
+        ReadinessStatus::Unknown
+    }
+
+    fn tile_size(&self) -> Option<u32> {
+        Some(256)
+    }
+
+    fn max_zoom(&self) -> Option<u32> {
+        Some(19)
+    }
+
+    fn ignore_transparent_tiles(&self) -> bool {
+        false
+    }
+}
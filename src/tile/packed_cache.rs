@@ -0,0 +1,554 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+use super::{tile_coord_to_u64, TileId};
+
+/// Once a blob file grows past this size, new tiles are appended to a fresh one
+const BLOB_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The size of one record in the index file
+const RECORD_BYTES: usize = 32;
+
+/// Written to the index in place of a blob number when a tile is deleted
+const TOMBSTONE: u32 = u32::MAX;
+
+lazy_static! {
+    /// Every packed store opened so far, so that the disk cache backend, the requester which fills
+    /// it and the background sweeper all share the same index.
+    ///
+    /// An async mutex so that tasks waiting on another task opening a store yield to the runtime
+    static ref STORES: tokio::sync::Mutex<HashMap<PathBuf, Arc<PackedStore>>> =
+        tokio::sync::Mutex::new(HashMap::new());
+}
+
+/// Returns the packed store kept in `folder`, opening it if this is the first use.
+///
+/// Opening reads the whole index, so it happens on a blocking thread
+pub async fn packed_store(folder: &str) -> Result<Arc<PackedStore>, std::io::Error> {
+    let folder = PathBuf::from(folder);
+    let mut stores = STORES.lock().await;
+    if let Some(store) = stores.get(&folder) {
+        return Ok(Arc::clone(store));
+    }
+    let open_folder = folder.clone();
+    let store = tokio::task::spawn_blocking(move || PackedStore::open(&open_folder)).await??;
+    let store = Arc::new(store);
+    stores.insert(folder, Arc::clone(&store));
+    Ok(store)
+}
+
+/// Returns the packed store kept in `folder` if it has already been opened, without waiting on
+/// anything. For callers outside of the async runtime that can try again later
+pub fn opened_packed_store(folder: &str) -> Option<Arc<PackedStore>> {
+    let stores = STORES.try_lock().ok()?;
+    stores.get(Path::new(folder)).cloned()
+}
+
+/// Stores tiles in a handful of large append only blob files, instead of one file per tile.
+///
+/// An index file records where each tile lives. It is append only too, so a record for a tile
+/// overrides every earlier one. Space taken up by deleted or replaced tiles is reclaimed by
+/// rewriting everything that is still alive into new blobs once enough of it builds up
+pub struct PackedStore {
+    folder: PathBuf,
+    state: Mutex<PackedState>,
+}
+
+struct PackedState {
+    /// Maps encoded tile ids to where they are stored
+    entries: HashMap<u64, Entry>,
+    index: File,
+
+    /// The blob new tiles are appended to
+    active_blob: u32,
+    active_file: File,
+    active_len: u64,
+
+    /// The number of bytes in blobs that no longer belong to any tile
+    dead_bytes: u64,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Entry {
+    blob: u32,
+    offset: u64,
+    len: u32,
+    /// Seconds since the unix epoch when this tile was downloaded
+    written: u64,
+    /// Seconds since the unix epoch when this tile was last read. Only kept in memory
+    last_used: u64,
+}
+
+impl Entry {
+    fn is_expired(&self, invalidate_time: Duration, now: u64) -> bool {
+        now.saturating_sub(self.written) > invalidate_time.as_secs()
+    }
+}
+
+impl PackedStore {
+    /// Opens the store in `folder`, creating it if it doesn't exist
+    pub fn open(folder: &Path) -> Result<Self, std::io::Error> {
+        std::fs::create_dir_all(folder)?;
+
+        let index_path = folder.join("index");
+        let mut index_bytes = Vec::new();
+        if let Ok(mut file) = File::open(&index_path) {
+            file.read_to_end(&mut index_bytes)?;
+        }
+
+        let mut entries = HashMap::new();
+        for record in index_bytes.chunks_exact(RECORD_BYTES) {
+            let (id, entry) = decode_record(record);
+            if entry.blob == TOMBSTONE {
+                entries.remove(&id);
+            } else {
+                entries.insert(id, entry);
+            }
+        }
+
+        //Find every blob on disk so that space taken by dead tiles is accounted for
+        let mut blob_lens = HashMap::new();
+        for dir_entry in std::fs::read_dir(folder)?.flatten() {
+            let name = dir_entry.file_name();
+            let blob = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("blob-"))
+                .and_then(|number| number.parse::<u32>().ok());
+            if let (Some(blob), Ok(metadata)) = (blob, dir_entry.metadata()) {
+                blob_lens.insert(blob, metadata.len());
+            }
+        }
+
+        //Tiles whose bytes never made it to disk before a crash are dropped
+        entries.retain(|_, entry| {
+            blob_lens
+                .get(&entry.blob)
+                .is_some_and(|&len| entry.offset + entry.len as u64 <= len)
+        });
+
+        let live_bytes: u64 = entries.values().map(|entry| entry.len as u64).sum();
+        let total_bytes: u64 = blob_lens.values().sum();
+        let active_blob = blob_lens.keys().copied().max().unwrap_or(0);
+        let active_len = blob_lens.get(&active_blob).copied().unwrap_or(0);
+
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&index_path)?;
+        //A partially written record would misalign everything appended after it
+        index.set_len((index_bytes.len() - index_bytes.len() % RECORD_BYTES) as u64)?;
+
+        let active_file = open_blob(folder, active_blob)?;
+
+        Ok(Self {
+            folder: folder.to_path_buf(),
+            state: Mutex::new(PackedState {
+                entries,
+                index,
+                active_blob,
+                active_file,
+                active_len,
+                dead_bytes: total_bytes.saturating_sub(live_bytes),
+            }),
+        })
+    }
+
+    /// Appends `bytes` to the store as the image for `tile`, replacing any older version
+    pub fn write(&self, tile: TileId, bytes: &[u8]) -> Result<(), std::io::Error> {
+        let id = tile_coord_to_u64(tile);
+        let mut state = self.state.lock();
+
+        if state.active_len > 0 && state.active_len + bytes.len() as u64 > BLOB_BYTES {
+            state.active_blob += 1;
+            state.active_file = open_blob(&self.folder, state.active_blob)?;
+            state.active_len = 0;
+        }
+
+        //Data goes in first so the index never points at bytes that aren't there
+        state.active_file.write_all(bytes)?;
+        let now = unix_secs(SystemTime::now());
+        let entry = Entry {
+            blob: state.active_blob,
+            offset: state.active_len,
+            len: bytes.len() as u32,
+            written: now,
+            last_used: now,
+        };
+        state.index.write_all(&encode_record(id, &entry))?;
+
+        state.active_len += bytes.len() as u64;
+        if let Some(old) = state.entries.insert(id, entry) {
+            state.dead_bytes += old.len as u64;
+        }
+        Ok(())
+    }
+
+    /// Reads the image for `tile`, returning `None` if it isn't stored or has expired
+    pub fn read(
+        &self,
+        tile: TileId,
+        invalidate_time: Duration,
+    ) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let now = unix_secs(SystemTime::now());
+        let mut state = self.state.lock();
+        let entry = match state.entries.get_mut(&tile_coord_to_u64(tile)) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if entry.is_expired(invalidate_time, now) {
+            //Left for the sweeper to delete
+            return Ok(None);
+        }
+        entry.last_used = now;
+
+        //Keep holding the lock so compaction can't delete the blob out from under us
+        let entry = *entry;
+        self.read_entry(&entry).map(Some)
+    }
+
+    /// Returns whether an image for `tile` is stored, even if it has expired. Returns `None`
+    /// instead of waiting if the store is busy, such as while it is being compacted
+    pub fn try_contains(&self, tile: TileId) -> Option<bool> {
+        let state = self.state.try_lock()?;
+        Some(state.entries.contains_key(&tile_coord_to_u64(tile)))
+    }
+
+    /// Returns the bytes of an arbitrary tile in the store, if there are any. Returns `None`
+    /// instead of waiting if the store is busy, so it is safe to call from the render thread
+    pub fn try_any_tile(&self) -> Option<Vec<u8>> {
+        let state = self.state.try_lock()?;
+        let entry = state.entries.values().next()?;
+        self.read_entry(entry).ok()
+    }
+
+    /// Deletes every tile downloaded more than `invalidate_time` ago, returning how many there
+    /// were
+    pub fn purge_expired(&self, invalidate_time: Duration) -> Result<usize, std::io::Error> {
+        let now = unix_secs(SystemTime::now());
+        let mut state = self.state.lock();
+        let expired: Vec<u64> = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(invalidate_time, now))
+            .map(|(&id, _)| id)
+            .collect();
+
+        for &id in &expired {
+            remove(&mut state, id)?;
+        }
+        self.compact_if_needed(&mut state, None)?;
+        Ok(expired.len())
+    }
+
+    /// Deletes the least recently used tiles until at most `max_bytes` are stored, returning how
+    /// many were deleted
+    pub fn shrink(&self, max_bytes: u64) -> Result<usize, std::io::Error> {
+        let mut state = self.state.lock();
+        let mut live = live_bytes(&state);
+        let mut deleted = 0;
+        if live > max_bytes {
+            let mut by_age: Vec<(u64, u64, u32)> = state
+                .entries
+                .iter()
+                .map(|(&id, entry)| (entry.last_used, id, entry.len))
+                .collect();
+            by_age.sort_unstable();
+
+            for (_, id, len) in by_age {
+                if live <= max_bytes {
+                    break;
+                }
+                remove(&mut state, id)?;
+                live -= len as u64;
+                deleted += 1;
+            }
+        }
+        self.compact_if_needed(&mut state, Some(max_bytes))?;
+        Ok(deleted)
+    }
+
+    /// Compacts the store once at least half of it is dead, or when the dead space alone puts it
+    /// over `max_bytes`
+    fn compact_if_needed(
+        &self,
+        state: &mut PackedState,
+        max_bytes: Option<u64>,
+    ) -> Result<(), std::io::Error> {
+        let live = live_bytes(state);
+        let over_budget = max_bytes.is_some_and(|max| live + state.dead_bytes > max);
+        if state.dead_bytes > 0 && (state.dead_bytes >= live || over_budget) {
+            self.compact(state)?;
+        }
+        Ok(())
+    }
+
+    /// Copies every live tile into new blobs and rewrites the index, then deletes the old blobs.
+    ///
+    /// This holds the lock for the whole copy, so it should be run off of the async runtime
+    fn compact(&self, state: &mut PackedState) -> Result<(), std::io::Error> {
+        let first_blob = state.active_blob + 1;
+        let mut blob = first_blob;
+        let mut blob_file = open_new_blob(&self.folder, blob)?;
+        let mut blob_len = 0;
+
+        //Copy in the order tiles are laid out on disk so reads are sequential
+        let mut old: Vec<(u64, Entry)> = state.entries.iter().map(|(&id, &e)| (id, e)).collect();
+        old.sort_unstable_by_key(|(_, entry)| (entry.blob, entry.offset));
+
+        let mut entries = HashMap::with_capacity(old.len());
+        let mut index_bytes = Vec::with_capacity(old.len() * RECORD_BYTES);
+        for (id, entry) in old {
+            let bytes = self.read_entry(&entry)?;
+            if blob_len > 0 && blob_len + bytes.len() as u64 > BLOB_BYTES {
+                blob += 1;
+                blob_file = open_new_blob(&self.folder, blob)?;
+                blob_len = 0;
+            }
+            blob_file.write_all(&bytes)?;
+
+            let moved = Entry {
+                blob,
+                offset: blob_len,
+                ..entry
+            };
+            blob_len += bytes.len() as u64;
+            index_bytes.extend_from_slice(&encode_record(id, &moved));
+            entries.insert(id, moved);
+        }
+        blob_file.sync_all()?;
+
+        //Swap the index over in one step so a crash leaves either the old or new store intact
+        let index_path = self.folder.join("index");
+        let temp_path = self.folder.join("index.tmp");
+        std::fs::write(&temp_path, &index_bytes)?;
+        std::fs::rename(&temp_path, &index_path)?;
+
+        for old_blob in 0..first_blob {
+            let _ = std::fs::remove_file(blob_path(&self.folder, old_blob));
+        }
+
+        state.entries = entries;
+        state.index = OpenOptions::new().append(true).open(&index_path)?;
+        state.active_blob = blob;
+        state.active_file = open_blob(&self.folder, blob)?;
+        state.active_len = blob_len;
+        state.dead_bytes = 0;
+        Ok(())
+    }
+
+    fn read_entry(&self, entry: &Entry) -> Result<Vec<u8>, std::io::Error> {
+        let mut file = File::open(blob_path(&self.folder, entry.blob))?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut bytes = vec![0; entry.len as usize];
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Removes the tile with the encoded id `id`, recording it in the index
+fn remove(state: &mut PackedState, id: u64) -> Result<(), std::io::Error> {
+    if let Some(entry) = state.entries.remove(&id) {
+        state.dead_bytes += entry.len as u64;
+        let tombstone = Entry {
+            blob: TOMBSTONE,
+            ..entry
+        };
+        state.index.write_all(&encode_record(id, &tombstone))?;
+    }
+    Ok(())
+}
+
+fn live_bytes(state: &PackedState) -> u64 {
+    state.entries.values().map(|entry| entry.len as u64).sum()
+}
+
+fn blob_path(folder: &Path, blob: u32) -> PathBuf {
+    folder.join(format!("blob-{}", blob))
+}
+
+/// Opens a blob for appending, creating it if needed
+fn open_blob(folder: &Path, blob: u32) -> Result<File, std::io::Error> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(blob_path(folder, blob))
+}
+
+/// Creates an empty blob, discarding anything left over from an interrupted compaction
+fn open_new_blob(folder: &Path, blob: u32) -> Result<File, std::io::Error> {
+    File::create(blob_path(folder, blob))
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn encode_record(id: u64, entry: &Entry) -> [u8; RECORD_BYTES] {
+    let mut record = [0; RECORD_BYTES];
+    record[0..8].copy_from_slice(&id.to_le_bytes());
+    record[8..12].copy_from_slice(&entry.blob.to_le_bytes());
+    record[12..16].copy_from_slice(&entry.len.to_le_bytes());
+    record[16..24].copy_from_slice(&entry.offset.to_le_bytes());
+    record[24..32].copy_from_slice(&entry.written.to_le_bytes());
+    record
+}
+
+fn decode_record(record: &[u8]) -> (u64, Entry) {
+    let u32_at = |i: usize| u32::from_le_bytes(record[i..i + 4].try_into().unwrap());
+    let u64_at = |i: usize| u64::from_le_bytes(record[i..i + 8].try_into().unwrap());
+    let written = u64_at(24);
+    let entry = Entry {
+        blob: u32_at(8),
+        len: u32_at(12),
+        offset: u64_at(16),
+        written,
+        last_used: written,
+    };
+    (u64_at(0), entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("ft-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        folder
+    }
+
+    /// The number of bytes the blobs take up on disk, including dead tiles
+    fn disk_bytes(store: &PackedStore) -> u64 {
+        let state = store.state.lock();
+        live_bytes(&state) + state.dead_bytes
+    }
+
+    #[test]
+    fn packed_round_trip() {
+        let folder = temp_folder("packed-round-trip");
+        let day = Duration::from_secs(60 * 60 * 24);
+        {
+            let store = PackedStore::open(&folder).unwrap();
+            for x in 0..10 {
+                store.write(TileId::new(x, 1, 4), &[x as u8; 50]).unwrap();
+            }
+            //Replacing a tile leaves its old bytes dead
+            store.write(TileId::new(3, 1, 4), b"replaced").unwrap();
+            assert_eq!(disk_bytes(&store), 10 * 50 + 8);
+
+            assert_eq!(
+                store.read(TileId::new(3, 1, 4), day).unwrap().unwrap(),
+                b"replaced"
+            );
+            assert!(store.read(TileId::new(3, 2, 4), day).unwrap().is_none());
+        }
+
+        //Everything comes back after reopening
+        let store = PackedStore::open(&folder).unwrap();
+        assert_eq!(store.try_contains(TileId::new(9, 1, 4)), Some(true));
+        assert!(store.try_any_tile().is_some());
+        {
+            //A busy store, like one being compacted, doesn't make callers wait
+            let _compacting = store.state.lock();
+            assert_eq!(store.try_contains(TileId::new(9, 1, 4)), None);
+            assert!(store.try_any_tile().is_none());
+        }
+        assert_eq!(
+            store.read(TileId::new(7, 1, 4), day).unwrap().unwrap(),
+            vec![7; 50]
+        );
+        assert_eq!(
+            store.read(TileId::new(3, 1, 4), day).unwrap().unwrap(),
+            b"replaced"
+        );
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn packed_shrink_compacts() {
+        let folder = temp_folder("packed-shrink");
+        let store = PackedStore::open(&folder).unwrap();
+        for y in 0..20 {
+            store.write(TileId::new(0, y, 5), &[y as u8; 100]).unwrap();
+        }
+        //Use the first few tiles so they outlive the rest
+        {
+            let mut state = store.state.lock();
+            for (i, entry) in state.entries.values_mut().enumerate() {
+                entry.last_used = i as u64;
+            }
+            for y in 0..5 {
+                let id = tile_coord_to_u64(TileId::new(0, y, 5));
+                state.entries.get_mut(&id).unwrap().last_used = u64::MAX;
+            }
+        }
+
+        assert_eq!(store.shrink(1000).unwrap(), 10);
+        assert_eq!(disk_bytes(&store), 1000);
+        for y in 0..5 {
+            assert_eq!(store.try_contains(TileId::new(0, y, 5)), Some(true));
+        }
+
+        //Compaction moved everything into a new blob, and the old one is gone
+        assert!(!blob_path(&folder, 0).exists());
+        let on_disk: u64 = std::fs::read_dir(&folder)
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("blob-"))
+            .map(|entry| entry.metadata().unwrap().len())
+            .sum();
+        assert_eq!(on_disk, 1000);
+
+        //The compacted index survives a reopen
+        drop(store);
+        let store = PackedStore::open(&folder).unwrap();
+        let day = Duration::from_secs(60 * 60 * 24);
+        assert_eq!(
+            store.read(TileId::new(0, 2, 5), day).unwrap().unwrap(),
+            vec![2; 100]
+        );
+        assert_eq!(store.state.lock().entries.len(), 10);
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn packed_purge_expired() {
+        let folder = temp_folder("packed-purge");
+        let store = PackedStore::open(&folder).unwrap();
+        for x in 0..4 {
+            store.write(TileId::new(x, 0, 2), b"tile").unwrap();
+        }
+        {
+            let mut state = store.state.lock();
+            for x in 0..2 {
+                let id = tile_coord_to_u64(TileId::new(x, 0, 2));
+                state.entries.get_mut(&id).unwrap().written -= 60 * 60;
+            }
+        }
+
+        let minute = Duration::from_secs(60);
+        assert!(store.read(TileId::new(0, 0, 2), minute).unwrap().is_none());
+        assert_eq!(store.purge_expired(minute).unwrap(), 2);
+        assert_eq!(store.try_contains(TileId::new(1, 0, 2)), Some(false));
+        assert!(store.read(TileId::new(2, 0, 2), minute).unwrap().is_some());
+
+        drop(store);
+        let store = PackedStore::open(&folder).unwrap();
+        assert_eq!(store.try_contains(TileId::new(0, 0, 2)), Some(false));
+        assert_eq!(store.try_contains(TileId::new(3, 0, 2)), Some(true));
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -1,18 +1,88 @@
 use super::*;
-use crate::{TileId, WorldViewport};
+use crate::{Shutdown, TileId, TileZoomLevel, WorldViewport};
 
-use simple_moving_average::SMA;
+use glam::DVec2;
+use log::{debug, warn};
+use parking_lot::Mutex;
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
 
 use intmap::IntMap;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+/// The maximum number of tile requests that can be in flight at once for a single pipeline.
+///
+/// Requests past this limit wait in the request channel, where they can still be preempted if the
+/// user scrolls away before they are started
+const MAX_CONCURRENT_REQUESTS: usize = 16;
+
+/// The maximum number of prefetches that can be in flight at once for a single pipeline. These
+/// have their own budget so that they never hold up tiles on screen
+const MAX_CONCURRENT_PREFETCHES: usize = 4;
+
+/// Once this many tiles have been prefetched we forget which ones, so that the set doesn't grow
+/// forever. At worst this prefetches a few tiles a second time
+const MAX_PREFETCHED: usize = 8192;
+
+/// How a pipeline retries requests that fail with a transient error, like a dropped connection or a
+/// 5xx response. Errors the server won't change its mind about, like a 404, are never retried
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times a backend is retried before moving on to the next one
+    pub max_retries: u32,
+    /// The delay before the first retry. Doubles with each retry after that
+    pub initial_delay: Duration,
+    /// How long a single request can take before it fails with [`TileError::Timeout`], so that a
+    /// hung connection doesn't leave a tile loading forever
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(250),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait before retry number `retry`, counting from 0
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.initial_delay * 2u32.saturating_pow(retry)
+    }
+}
+
 struct MemoryTile {
     pub id: TileId,
-    pub image: Option<image::RgbaImage>,
+    pub image: TileImage,
+}
+
+/// The outcome of a tile request sent back to the pipeline
+enum TileImage {
+    /// The tile was loaded and is ready to be uploaded to the GPU
+    Loaded(image::RgbaImage),
+    /// No backend could provide this tile
+    NotAvailable,
+    /// A backend kept failing with transient errors, so the tile may load if it is requested again
+    Failed,
+    /// The tile left the screen before it finished loading, so the request was dropped
+    Cancelled,
+}
+
+/// The part of the world currently on screen, shared with the request task so that it can skip
+/// tiles which are no longer needed
+#[derive(Copy, Clone, Debug)]
+struct VisibleArea {
+    top_left: DVec2,
+    bottom_right: DVec2,
+    /// The zoom level of the tiles being rendered
+    zoom: TileZoomLevel,
 }
 
 /// Holds multiple levels of cache for requesting tiles in a generic manner.
@@ -22,64 +92,385 @@ pub struct TilePipeline {
     backends: Arc<Vec<Box<dyn Backend>>>,
 
     /// The cache of tiles on the GPU
-    cache: IntMap<CachedTile>,
+    cache: GpuTileCache,
     upload_rx: Receiver<MemoryTile>,
     request_tx: Arc<UnboundedSender<TileId>>,
+    /// Low priority requests that only warm up the caches, see [`TilePipeline::prefetch`]
+    prefetch_tx: UnboundedSender<TileId>,
+    /// Tiles already sent to `prefetch_tx`, so they aren't requested every frame
+    prefetched: IntMap<()>,
+    /// Prefetches that failed or were dropped, which are forgotten so they can be tried again
+    prefetch_retry_rx: UnboundedReceiver<TileId>,
+    /// Persistent errors from backends, such as a rejected api key, for the UI to show
+    error_rx: UnboundedReceiver<TileError>,
+    /// Set once a backend fails with a persistent error, see [`TilePipeline::has_persistent_error`]
+    persistent_error: Arc<AtomicBool>,
     tile_size: AtomicU32,
+    /// Whether textures get mipmaps when they are uploaded, see [`TilePipeline::set_mipmaps`]
+    mipmaps: bool,
+    /// How many physical pixels each tile pixel should cover, see
+    /// [`TilePipeline::set_pixel_density`]
+    pixel_density: f64,
+
+    /// The area rendered last frame. `None` until the first call to [`TilePipeline::update`]
+    visible_area: Arc<Mutex<Option<VisibleArea>>>,
+
+    /// Tasks working on behalf of this pipeline which are cancelled when it is dropped
+    background_tasks: Vec<JoinHandle<()>>,
+
+    /// One for each request that can be in flight, see [`TilePipeline::finish_requests`]
+    permits: Arc<Semaphore>,
+    /// One for each prefetch that can be in flight
+    prefetch_permits: Arc<Semaphore>,
 }
 
 #[derive(Debug, Copy, Clone)]
 enum CachedTile {
     NotAvailable,
     Pending,
-    Cached(conrod_core::image::Id),
+    Cached {
+        image: conrod_core::image::Id,
+        /// The last frame this tile was drawn on. Used to find the least recently used tiles
+        last_used: u64,
+    },
+}
+
+/// Keeps track of the state of every tile the pipeline knows about, and decides which textures
+/// to remove from the GPU once there are more than `budget` of them.
+///
+/// This doesn't own the textures themselves, so it can be used without an OpenGL context. The
+/// image ids it evicts must be removed from the image map by the caller
+struct GpuTileCache {
+    tiles: IntMap<CachedTile>,
+
+    /// The number of textures we try to keep on the GPU at once
+    budget: usize,
+
+    /// The number of `CachedTile::Cached` entries in `tiles`
+    on_gpu: usize,
+
+    /// Incremented once per frame so we know how recently each tile was used
+    frame: u64,
+}
+
+impl GpuTileCache {
+    fn new(budget: usize) -> Self {
+        Self {
+            //Use large initial size here because we will have a few hundred tiles on the GPU at
+            //minimum, and rehashing is EXPENSIVE
+            tiles: IntMap::with_capacity(1024),
+            budget,
+            on_gpu: 0,
+            frame: 0,
+        }
+    }
+
+    /// Returns the state of `tile`, marking it as used this frame if it is on the GPU
+    fn get(&mut self, tile: TileId) -> Option<CachedTile> {
+        let frame = self.frame;
+        let entry = self.tiles.get_mut(tile_coord_to_u64(tile))?;
+        if let CachedTile::Cached { last_used, .. } = entry {
+            *last_used = frame;
+        }
+        Some(*entry)
+    }
+
+    /// Sets the state of `tile` to `value`, returning the image id of the texture it replaced
+    fn insert(&mut self, tile: TileId, value: CachedTile) -> Option<conrod_core::image::Id> {
+        if matches!(value, CachedTile::Cached { .. }) {
+            self.on_gpu += 1;
+        }
+        let id = tile_coord_to_u64(tile);
+        //`IntMap::insert` does nothing if the key is already present, so replace it by hand
+        let old = match self.tiles.get_mut(id) {
+            Some(old) => std::mem::replace(old, value),
+            None => {
+                self.tiles.insert(id, value);
+                return None;
+            }
+        };
+        self.released_image(old)
+    }
+
+    /// Records that `image` holds the texture for `tile`, returning the image id of the texture it
+    /// replaced
+    fn insert_image(
+        &mut self,
+        tile: TileId,
+        image: conrod_core::image::Id,
+    ) -> Option<conrod_core::image::Id> {
+        let cached = CachedTile::Cached {
+            image,
+            last_used: self.frame,
+        };
+        self.insert(tile, cached)
+    }
+
+    /// Forgets about `tile`, returning the image id of its texture if it had one
+    fn remove(&mut self, tile: TileId) -> Option<conrod_core::image::Id> {
+        let old = self.tiles.remove(tile_coord_to_u64(tile))?;
+        self.released_image(old)
+    }
+
+    fn released_image(&mut self, old: CachedTile) -> Option<conrod_core::image::Id> {
+        match old {
+            CachedTile::Cached { image, .. } => {
+                self.on_gpu -= 1;
+                Some(image)
+            }
+            _ => None,
+        }
+    }
+
+    /// Starts a new frame. Tiles used after this are considered more recent than all before
+    fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Evicts tiles until we are back under budget, returning the image ids that were removed.
+    ///
+    /// Tiles outside `area` are evicted first, then tiles that aren't at the current zoom level,
+    /// then the least recently used. Tiles drawn on the current frame are never evicted, so the
+    /// cache can go over budget if the screen needs more tiles than that
+    fn evict(&mut self, area: Option<&VisibleArea>) -> Vec<conrod_core::image::Id> {
+        if self.on_gpu <= self.budget {
+            return Vec::new();
+        }
+
+        let frame = self.frame;
+        let mut candidates: Vec<_> = self
+            .tiles
+            .iter()
+            .filter_map(|(&id, tile)| match *tile {
+                CachedTile::Cached { last_used, .. } if last_used != frame => {
+                    let tile = u64_to_tile_coord(id);
+                    let (visible, current_zoom) = match area {
+                        Some(area) => (is_tile_visible(tile, area), tile.zoom == area.zoom),
+                        None => (true, true),
+                    };
+                    Some(((visible, current_zoom, last_used), tile))
+                }
+                _ => None,
+            })
+            .collect();
+        candidates.sort_unstable_by_key(|(priority, _)| *priority);
+
+        let to_evict = self.on_gpu - self.budget;
+        candidates
+            .into_iter()
+            .take(to_evict)
+            .filter_map(|(_, tile)| self.remove(tile))
+            .collect()
+    }
+
+    /// The number of tiles currently on the GPU
+    fn on_gpu(&self) -> usize {
+        self.on_gpu
+    }
 }
 
 impl TilePipeline {
     /// Creates a new `TilePipeline` with the given backends.
     ///
+    /// `gpu_tile_budget` is the number of tile textures this pipeline tries to keep on the GPU.
+    /// Once more are loaded, the least useful ones are evicted.
+    /// Failed requests are retried according to `retry_policy`. No new requests are started once
+    /// `shutdown` is triggered.
     /// Uses `runtime` to spawn required asynchronous background tasks
-    pub fn new(backends: Vec<Box<dyn Backend>>, runtime: &Runtime) -> Self {
-        //Use large initial size here because we will have a few hundred tiles on the GPU at
-        //minimum, and rehashing is EXPENSIVE
+    pub fn new(
+        backends: Vec<Box<dyn Backend>>,
+        gpu_tile_budget: usize,
+        retry_policy: RetryPolicy,
+        shutdown: &Shutdown,
+        runtime: &Runtime,
+    ) -> Self {
         let (upload_tx, upload_rx) = tokio::sync::mpsc::channel(24);
         let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (prefetch_tx, prefetch_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (prefetch_retry_tx, prefetch_retry_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (error_tx, error_rx) = tokio::sync::mpsc::unbounded_channel();
 
         let backends = Arc::new(backends);
-        runtime.spawn(tile_requester(upload_tx, request_rx, backends.clone()));
+        let visible_area = Arc::new(Mutex::new(None));
+        let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS));
+        let prefetch_permits = Arc::new(Semaphore::new(MAX_CONCURRENT_PREFETCHES));
+        let persistent_error = Arc::new(AtomicBool::new(false));
+        let requester = runtime.spawn(tile_requester(
+            upload_tx,
+            request_rx,
+            prefetch_rx,
+            prefetch_retry_tx,
+            error_tx,
+            persistent_error.clone(),
+            backends.clone(),
+            visible_area.clone(),
+            retry_policy,
+            permits.clone(),
+            prefetch_permits.clone(),
+            shutdown.subscribe(),
+        ));
         Self {
-            cache: IntMap::with_capacity(1024),
+            cache: GpuTileCache::new(gpu_tile_budget),
             upload_rx,
             request_tx: Arc::new(request_tx),
+            prefetch_tx,
+            prefetched: IntMap::new(),
+            prefetch_retry_rx,
+            error_rx,
+            persistent_error,
             backends,
             tile_size: AtomicU32::new(0),
+            mipmaps: true,
+            pixel_density: 1.0,
+            visible_area,
+            background_tasks: vec![requester],
+            permits,
+            prefetch_permits,
         }
     }
 
+    /// Sets whether tiles uploaded from now on get mipmaps. With them, tiles drawn smaller than
+    /// their texture, like while zooming out, are smoothed instead of shimmering, at the cost of
+    /// a third more GPU memory per tile and more time spent uploading. On by default
+    pub fn set_mipmaps(&mut self, enabled: bool) {
+        self.mipmaps = enabled;
+    }
+
+    /// Sets how many physical pixels make up a logical pixel where the tiles are drawn. Tiles are
+    /// then picked from the zoom level where each of their pixels covers a physical pixel rather
+    /// than a logical one, so they stay sharp on high DPI screens at the cost of loading up to four
+    /// times as many tiles. 1.0 by default
+    pub fn set_pixel_density(&mut self, density: f64) {
+        self.pixel_density = density.max(1.0);
+    }
+
+    /// Returns how many logical pixels wide tiles are laid out as, which is what the zoom level
+    /// of the tiles is chosen with. This is [`TilePipeline::tile_size`] shrunk by the pixel
+    /// density, see [`TilePipeline::set_pixel_density`]
+    pub fn layout_tile_size(&self) -> Option<u32> {
+        let size = self.tile_size()?;
+        Some(((size as f64 / self.pixel_density).round() as u32).max(1))
+    }
+
+    /// Waits for the requests that are in flight to finish, including writing their tiles to the
+    /// disk cache. Only returns once no new requests are being started, so should be called after
+    /// the pipeline's shutdown was triggered
+    pub async fn finish_requests(&self) {
+        let _ = self
+            .permits
+            .acquire_many(MAX_CONCURRENT_REQUESTS as u32)
+            .await;
+        let _ = self
+            .prefetch_permits
+            .acquire_many(MAX_CONCURRENT_PREFETCHES as u32)
+            .await;
+    }
+
     /// Fetches the image id of `tile`, or starts loading the texture,
     /// returning None on this frame and subsequent frames until the asynchronous request finishes
     pub fn get_tile(&mut self, tile: TileId) -> Option<conrod_core::image::Id> {
-        //TODO: Have the caller pass the lock in so that we dont lock, unlock, then lock again
-        {
-            match self.cache.get(tile_coord_to_u64(tile)) {
-                Some(&CachedTile::Cached(id)) => {
-                    return Some(id);
-                }
-                Some(&CachedTile::NotAvailable) => return None,
-                Some(&CachedTile::Pending) => return None,
-                None => {}
-            };
-        }
+        match self.cache.get(tile) {
+            Some(CachedTile::Cached { image, .. }) => {
+                return Some(image);
+            }
+            Some(CachedTile::NotAvailable) => return None,
+            Some(CachedTile::Pending) => return None,
+            None => {}
+        };
         assert!(
             self.request_tx.send(tile).is_ok(),
             "Tile request channel closed! Cannot fetch more tiles"
         );
 
-        self.cache
-            .insert(tile_coord_to_u64(tile), CachedTile::Pending);
+        self.cache.insert(tile, CachedTile::Pending);
+        None
+    }
+
+    /// Returns true if tiles have finished loading and are waiting for [`TilePipeline::update`] to
+    /// upload them
+    pub fn has_new_tiles(&self) -> bool {
+        !self.upload_rx.is_empty()
+    }
+
+    /// Returns true if the texture for `tile` is on the GPU, without requesting it
+    pub fn is_tile_cached(&self, tile: TileId) -> bool {
+        matches!(
+            self.cache.tiles.get(tile_coord_to_u64(tile)),
+            Some(CachedTile::Cached { .. })
+        )
+    }
+
+    /// Returns the closest ancestor of `tile` whose texture is already on the GPU, along with its
+    /// id, so that it can stand in while `tile` loads.
+    ///
+    /// Unlike [`TilePipeline::get_tile`] this never starts a request
+    pub fn get_cached_ancestor(
+        &mut self,
+        tile: TileId,
+    ) -> Option<(conrod_core::image::Id, TileId)> {
+        let mut ancestor = tile.parent();
+        while let Some(tile) = ancestor {
+            if let Some(CachedTile::Cached { image, .. }) = self.cache.get(tile) {
+                return Some((image, tile));
+            }
+            ancestor = tile.parent();
+        }
         None
     }
 
+    /// Warms up the caches with the tiles one zoom level above and below the ones covering the
+    /// screen, so that zooming in or out has something to show straight away.
+    ///
+    /// These requests only run when nothing on screen is waiting to load, and the tiles are kept
+    /// in the faster backends without being uploaded to the GPU
+    pub fn prefetch(&mut self, view: &crate::map::TileView, screen_size: DVec2) {
+        let tile_size = match self.layout_tile_size() {
+            Some(size) => size,
+            None => return,
+        };
+        let max_zoom = self.max_zoom();
+        let zoom = view.capped_tile_zoom_level(tile_size, max_zoom);
+        let viewport = view.get_world_viewport(screen_size.x, screen_size.y);
+
+        if self.prefetched.len() > MAX_PREFETCHED {
+            self.prefetched.clear();
+        }
+        while let Ok(tile) = self.prefetch_retry_rx.try_recv() {
+            self.prefetched.remove(tile_coord_to_u64(tile));
+        }
+        let adjacent = [zoom.checked_sub(1), Some(zoom + 1)];
+        let max_zoom = max_zoom.map_or(crate::MAX_ZOOM_LEVEL, |max| max.min(crate::MAX_ZOOM_LEVEL));
+        for zoom in adjacent.iter().flatten().filter(|zoom| **zoom <= max_zoom) {
+            for tile in tiles_covering(&viewport, *zoom) {
+                let id = tile_coord_to_u64(tile);
+                if self.cache.tiles.contains_key(id) || self.prefetched.contains_key(id) {
+                    continue;
+                }
+                self.prefetched.insert(id, ());
+                let _ = self.prefetch_tx.send(tile);
+            }
+        }
+    }
+
+    /// Returns the next persistent error reported by one of this pipeline's backends, if any.
+    ///
+    /// Transient errors are retried by the pipeline and never show up here
+    pub fn poll_error(&mut self) -> Option<TileError> {
+        self.error_rx.try_recv().ok()
+    }
+
+    /// Returns true once one of this pipeline's backends has failed with a persistent error, such
+    /// as a missing or rejected api key, in which case its tiles may never load
+    pub fn has_persistent_error(&self) -> bool {
+        self.persistent_error.load(Ordering::Relaxed)
+    }
+
+    /// Ties a background task to the lifetime of this pipeline, so that it is cancelled when the
+    /// pipeline is dropped
+    pub fn add_background_task(&mut self, task: JoinHandle<()>) {
+        self.background_tasks.push(task);
+    }
+
     /// Returns the size of tiles returned by this pipeline, or `None` or unknown
     pub fn tile_size(&self) -> Option<u32> {
         let cached_size = self.tile_size.load(Ordering::Relaxed);
@@ -89,7 +480,7 @@ impl TilePipeline {
 
         for backend in self.backends.iter() {
             if let Some(size) = backend.tile_size() {
-                println!("Backend {} gave size: {}", backend.name(), size);
+                debug!("Backend {} gave size: {}", backend.name(), size);
                 self.tile_size.store(size, Ordering::Relaxed);
                 return Some(size);
             }
@@ -97,81 +488,325 @@ impl TilePipeline {
         None
     }
 
+    /// Returns the highest zoom level that every backend in this pipeline can provide, or `None`
+    /// if none of them impose a limit
+    pub fn max_zoom(&self) -> Option<u32> {
+        self.backends
+            .iter()
+            .filter_map(|backend| backend.max_zoom())
+            .min()
+    }
+
     /// Called each frame to allow the pipeline to upload newly fetched tiles to the GPU.
     ///
     /// `viewport`: The viewport of the currently rendered scene. This is used for preemption
+    /// `zoom`: The zoom level of the tiles being rendered. Requests for more detailed tiles are
+    /// dropped, as they were made before the user zoomed out
     pub fn update(
         &mut self,
-        _viewport: &WorldViewport,
+        viewport: &WorldViewport,
+        zoom: TileZoomLevel,
         display: &glium::Display,
         image_map: &mut conrod_core::image::Map<glium::Texture2d>,
     ) {
-        //TODO: Pass viewport to preemption code
+        let mipmaps = self.mipmaps;
+        self.update_with(viewport, zoom, image_map, |image| {
+            create_texture(display, image, mipmaps)
+        });
+    }
+
+    /// The part of [`TilePipeline::update`] that doesn't need OpenGL. Loaded tiles are turned into
+    /// entries of `image_map` by `upload`, and the entries of evicted tiles are removed from it.
+    ///
+    /// Lets the caching logic run in tests, with the images themselves standing in for textures
+    pub fn update_with<T>(
+        &mut self,
+        viewport: &WorldViewport,
+        zoom: TileZoomLevel,
+        image_map: &mut conrod_core::image::Map<T>,
+        mut upload: impl FnMut(image::RgbaImage) -> T,
+    ) {
+        let visible_area = VisibleArea {
+            top_left: viewport.top_left,
+            bottom_right: viewport.bottom_right,
+            zoom,
+        };
+        *self.visible_area.lock() = Some(visible_area);
+
         const MAX_PROCESS_TIME: Duration = Duration::from_millis(50);
         let start = std::time::Instant::now();
         let mut tiles_processed = 0;
 
-        while let Ok(tile) = self.upload_rx.try_recv() {
+        loop {
+            //Check the time before receiving so that we never drop a tile on the floor
             let time_spent = start.elapsed();
             if time_spent > MAX_PROCESS_TIME {
-                println!(
+                debug!(
                     "Breaking from process loop after {} ms. Processed {} tiles",
                     time_spent.as_micros() as f64 / 1000.0,
                     tiles_processed
                 );
                 break;
             }
+            let tile = match self.upload_rx.try_recv() {
+                Ok(tile) => tile,
+                Err(_) => break,
+            };
             let tile_id = tile.id;
 
             match tile.image {
-                None => {
-                    let _ = self
-                        .cache
-                        .insert(tile_coord_to_u64(tile_id), CachedTile::NotAvailable);
+                TileImage::NotAvailable => {
+                    self.cache.insert(tile_id, CachedTile::NotAvailable);
                 }
-                Some(image) => {
-                    let texture = create_texture(display, image);
-                    let image_id = image_map.insert(texture);
-
-                    let id = tile_coord_to_u64(tile_id);
-                    match self.cache.get_mut(id) {
-                        Some(value) => {
-                            *value = CachedTile::Cached(image_id);
-                        }
-                        None => {
-                            self.cache.insert(id, CachedTile::Cached(image_id));
-                        }
+                TileImage::Cancelled | TileImage::Failed => {
+                    //Forget about the tile so that it is requested again if it comes back into view
+                    self.cache.remove(tile_id);
+                }
+                TileImage::Loaded(_) if !is_tile_visible(tile_id, &visible_area) => {
+                    //Scrolled away while the request was in flight, so don't bother uploading
+                    self.cache.remove(tile_id);
+                }
+                TileImage::Loaded(image) => {
+                    let image_id = image_map.insert(upload(image));
+                    if let Some(old_image) = self.cache.insert_image(tile_id, image_id) {
+                        image_map.remove(old_image);
                     }
 
                     tiles_processed += 1;
                 }
             }
         }
+
+        let on_gpu_before = self.cache.on_gpu();
+        for image_id in self.cache.evict(Some(&visible_area)) {
+            image_map.remove(image_id);
+        }
+        //Tiles drawn after this call belong to the next frame
+        self.cache.next_frame();
+
+        crate::Profiler::record_tiles_on_gpu(on_gpu_before, self.cache.on_gpu());
+    }
+}
+
+impl Drop for TilePipeline {
+    fn drop(&mut self) {
+        for task in &self.background_tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Returns true if `tile` is still worth loading for `area`.
+///
+/// Tiles more detailed than the current zoom are never needed. Tiles at the current zoom or
+/// lower are needed if they overlap the screen, with a border of one tile so that panning
+/// slowly doesn't cause tiles to pop in
+fn is_tile_visible(tile: TileId, area: &VisibleArea) -> bool {
+    if tile.zoom > area.zoom {
+        return false;
+    }
+    let tile_length = 1.0 / 2u32.pow(tile.zoom) as f64;
+    let margin = 1.0 / 2u32.pow(area.zoom) as f64;
+
+    let left = area.top_left.x - margin;
+    let right = area.bottom_right.x + margin;
+    let top = area.top_left.y - margin;
+    let bottom = area.bottom_right.y + margin;
+
+    //The world doesn't wrap vertically
+    let tile_top = tile.y as f64 * tile_length;
+    let tile_bottom = tile_top + tile_length;
+    if tile_bottom < top || tile_top > bottom {
+        return false;
+    }
+
+    //The viewport is unbounded horizontally, so move the tile into the first copy of the world
+    //that ends at or after the left edge of the viewport
+    let tile_left = tile.x as f64 * tile_length;
+    let tile_right = tile_left + tile_length;
+    let world_offset = (left - tile_right).ceil();
+    tile_left + world_offset <= right
+}
+
+/// Returns every tile at `zoom` that overlaps `viewport`
+pub(super) fn tiles_covering(
+    viewport: &WorldViewport,
+    zoom: TileZoomLevel,
+) -> impl Iterator<Item = TileId> {
+    let tiles_across = 2u32.pow(zoom);
+    let n = tiles_across as f64;
+    //The viewport is unbounded horizontally, so wrap x around, but not y
+    let left = (viewport.top_left.x * n).floor() as i64;
+    let right = (viewport.bottom_right.x * n).ceil() as i64;
+    let top = (viewport.top_left.y * n).floor().max(0.0) as u32;
+    let bottom = ((viewport.bottom_right.y * n).ceil().min(n) as u32).max(top);
+    //Don't visit the same tile twice if the viewport is wider than the world
+    let right = right.min(left + tiles_across as i64);
+
+    (left..right).flat_map(move |x| {
+        let x = x.rem_euclid(tiles_across as i64) as u32;
+        (top..bottom).map(move |y| TileId::new(x, y, zoom))
+    })
+}
+
+/// Returns true if a prefetch of `tile` is still useful, which is when it is at most
+/// one zoom level away from the tiles on screen and overlaps the screen
+fn is_prefetch_wanted(tile: TileId, visible_area: &Mutex<Option<VisibleArea>>) -> bool {
+    match *visible_area.lock() {
+        Some(area) => {
+            let area = VisibleArea {
+                zoom: area.zoom + 1,
+                ..area
+            };
+            is_tile_visible(tile, &area)
+        }
+        None => true,
     }
 }
 
-/// An infinite async loop that waits for tile requests, and dispatches them through the levels of
-/// cache to produce a texture
+/// Returns true if `tile` should still be loaded according to the shared visible area
+fn is_tile_wanted(tile: TileId, visible_area: &Mutex<Option<VisibleArea>>) -> bool {
+    match *visible_area.lock() {
+        Some(area) => is_tile_visible(tile, &area),
+        None => true,
+    }
+}
+
+/// An async loop that waits for tile requests, and dispatches them through the levels of cache to
+/// produce a texture. Stops starting requests once `shutdown` is received.
+///
+/// Each request holds one of `permits` until it finishes, and each prefetch one of
+/// `prefetch_permits`. Prefetches that don't finish are sent back through `prefetch_retry_tx`.
+/// Persistent errors are sent through `error_tx`, and also set `persistent_error`
+#[allow(clippy::too_many_arguments)]
 async fn tile_requester(
     upload_tx: Sender<MemoryTile>,
     mut request_rx: UnboundedReceiver<TileId>,
+    mut prefetch_rx: UnboundedReceiver<TileId>,
+    prefetch_retry_tx: UnboundedSender<TileId>,
+    error_tx: UnboundedSender<TileError>,
+    persistent_error: Arc<AtomicBool>,
     backends: Arc<Vec<Box<dyn Backend>>>,
+    visible_area: Arc<Mutex<Option<VisibleArea>>>,
+    retry_policy: RetryPolicy,
+    permits: Arc<Semaphore>,
+    prefetch_permits: Arc<Semaphore>,
+    mut shutdown: tokio::sync::broadcast::Receiver<()>,
 ) {
     //TODO: Reduce Arcing here with some king of task queue that we select so that the lifetimes
     //work out
     let upload_tx = Arc::new(upload_tx);
-    while let Some(tile) = request_rx.recv().await {
-        //TODO: Limit concurrent requests. Maybe use some kind of convar or custom atomicint?
+    //Requests that haven't been started yet
+    let mut pending = Vec::new();
+    let mut pending_prefetches = Vec::new();
+    loop {
+        if pending.is_empty() && pending_prefetches.is_empty() {
+            tokio::select! {
+                biased;
+                _ = shutdown.recv() => return,
+                tile = request_rx.recv() => match tile {
+                    Some(tile) => pending.push(tile),
+                    None => return,
+                },
+                Some(tile) = prefetch_rx.recv() => pending_prefetches.push(tile),
+            }
+        }
+        //Wait for a free slot so that pending requests queue up. A tile for the screen arriving
+        //while we wait on a prefetch slot goes ahead of it
+        let for_prefetch = pending.is_empty();
+        let budget = if for_prefetch {
+            &prefetch_permits
+        } else {
+            &permits
+        };
+        let permit = tokio::select! {
+            biased;
+            _ = shutdown.recv() => return,
+            Some(tile) = request_rx.recv(), if for_prefetch => {
+                pending.push(tile);
+                continue;
+            }
+            permit = budget.clone().acquire_owned() => permit.expect("Tile request semaphore closed"),
+        };
+        if crate::is_shutting_down(&mut shutdown) {
+            return;
+        }
+
+        //Start whichever request is most important now that the user may have moved
+        while let Ok(tile) = request_rx.try_recv() {
+            pending.push(tile);
+        }
+        while let Ok(tile) = prefetch_rx.try_recv() {
+            pending_prefetches.push(tile);
+        }
+        if for_prefetch && !pending.is_empty() {
+            //Something on screen came in, so it needs a request slot instead
+            continue;
+        }
+        let area = *visible_area.lock();
+        if for_prefetch {
+            //Only prefetch when nothing on screen is waiting
+            let tile = take_most_urgent(&mut pending_prefetches, area.as_ref());
+            let backends = backends.clone();
+            let visible_area = visible_area.clone();
+            let prefetch_retry_tx = prefetch_retry_tx.clone();
+            tokio::spawn(async move {
+                let outcome =
+                    prefetch_tile(tile, &backends, &visible_area, retry_policy.timeout).await;
+                drop(permit);
+                match outcome {
+                    Prefetched::Done => {}
+                    Prefetched::Dropped => {
+                        let _ = prefetch_retry_tx.send(tile);
+                    }
+                    Prefetched::Failed => {
+                        //Give the server as long as a request on screen would have before its
+                        //last retry, so a struggling server isn't asked again every frame
+                        tokio::time::sleep(retry_policy.delay(retry_policy.max_retries)).await;
+                        let _ = prefetch_retry_tx.send(tile);
+                    }
+                }
+            });
+            continue;
+        }
+        let tile = take_most_urgent(&mut pending, area.as_ref());
+
         let upload_tx = upload_tx.clone();
+        let error_tx = error_tx.clone();
+        let persistent_error = persistent_error.clone();
         let backends = backends.clone();
+        let visible_area = visible_area.clone();
         tokio::spawn(async move {
-            for backend in backends.iter() {
+            let _permit = permit;
+            let mut failed = false;
+            for (i, backend) in backends.iter().enumerate() {
+                //Check before each level of cache in case the user moved on while we waited
+                if !is_tile_wanted(tile, &visible_area) {
+                    let _ = upload_tx
+                        .send(MemoryTile {
+                            image: TileImage::Cancelled,
+                            id: tile,
+                        })
+                        .await;
+                    return;
+                }
+
                 //Go through each level of cache and try to obtain tile
-                match backend.request(tile).await {
+                match request_with_retries(backend.as_ref(), tile, retry_policy).await {
+                    //Empty weather tiles aren't worth a spot on the GPU, or in the faster caches
+                    Ok(Some(image))
+                        if backend.ignore_transparent_tiles() && is_fully_transparent(&image) =>
+                    {
+                        break
+                    }
                     Ok(Some(image)) => {
+                        //Give the faster levels of cache a copy so next time we don't come this far
+                        for faster in &backends[..i] {
+                            faster.store(tile, &image).await;
+                        }
                         let _ = upload_tx
                             .send(MemoryTile {
-                                image: Some(image),
+                                image: TileImage::Loaded(image),
                                 id: tile,
                             })
                             .await;
@@ -179,33 +814,160 @@ async fn tile_requester(
                     }
                     Ok(None) => {}
                     Err(err) => {
-                        println!("Error getting tile {:?}: {}", tile, err);
+                        warn!("Error getting tile {:?}: {}", tile, err);
+                        failed |= err.is_transient();
+                        if err.is_persistent() {
+                            persistent_error.store(true, Ordering::Relaxed);
+                            let _ = error_tx.send(err);
+                        }
                     }
                 }
             }
-            let _ = upload_tx
-                .send(MemoryTile {
-                    image: None,
-                    id: tile,
-                })
-                .await;
+            //Don't give up on tiles that only failed because of a bad connection
+            let image = if failed {
+                TileImage::Failed
+            } else {
+                TileImage::NotAvailable
+            };
+            let _ = upload_tx.send(MemoryTile { image, id: tile }).await;
         });
     }
 }
 
-/// Uploads an RGBA texture to the GPU
-fn create_texture(display: &glium::Display, image: image::RgbaImage) -> glium::Texture2d {
+/// What became of a prefetch started by [`prefetch_tile`]
+enum Prefetched {
+    /// The tile made it into the faster backends, or can't be loaded, so there is no point asking
+    /// for it again
+    Done,
+    /// The user moved away before the tile loaded, so it can be prefetched again if they come back
+    Dropped,
+    /// A backend failed with a transient error, so the prefetch is worth trying again later
+    Failed,
+}
+
+/// Loads `tile` into the fastest backend that doesn't have it, without sending it to the GPU.
+///
+/// Errors the server won't change its mind about, like a rejected api key, give up on the tile
+/// for good, the same as if no backend had it
+async fn prefetch_tile(
+    tile: TileId,
+    backends: &[Box<dyn Backend>],
+    visible_area: &Mutex<Option<VisibleArea>>,
+    timeout: Duration,
+) -> Prefetched {
+    let mut failed = false;
+    for (i, backend) in backends.iter().enumerate() {
+        if !is_prefetch_wanted(tile, visible_area) {
+            return Prefetched::Dropped;
+        }
+        //Prefetches aren't worth retrying straight away, or reporting errors for
+        match request_with_timeout(backend.as_ref(), tile, timeout).await {
+            Ok(Some(image)) => {
+                if backend.ignore_transparent_tiles() && is_fully_transparent(&image) {
+                    return Prefetched::Done;
+                }
+                for faster in &backends[..i] {
+                    faster.store(tile, &image).await;
+                }
+                return Prefetched::Done;
+            }
+            Ok(None) => {}
+            Err(err) => failed |= err.is_transient(),
+        }
+    }
+    if failed {
+        Prefetched::Failed
+    } else {
+        Prefetched::Done
+    }
+}
+
+/// Removes and returns the tile in `pending` that is closest to the center of `area`, according to
+/// [`crate::map::tile_heuristic`]. Takes the oldest request if nothing is on screen yet.
+///
+/// `pending` must not be empty
+fn take_most_urgent(pending: &mut Vec<TileId>, area: Option<&VisibleArea>) -> TileId {
+    let area = match area {
+        Some(area) => area,
+        None => return pending.remove(0),
+    };
+    let viewport = WorldViewport {
+        top_left: area.top_left,
+        bottom_right: area.bottom_right,
+    };
+    let index = pending
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| {
+            let a = crate::map::tile_heuristic(**a, &viewport);
+            let b = crate::map::tile_heuristic(**b, &viewport);
+            a.total_cmp(&b)
+        })
+        .map(|(i, _)| i)
+        .expect("No pending tile requests");
+    //Order doesn't matter past this point, since every pick searches the whole list
+    pending.swap_remove(index)
+}
+
+/// Requests `tile` from `backend`, retrying with a growing delay if it fails with a transient
+/// network error
+pub(super) async fn request_with_retries(
+    backend: &dyn Backend,
+    tile: TileId,
+    policy: RetryPolicy,
+) -> Result<Option<Texture>, TileError> {
+    let mut retries = 0;
+    loop {
+        match request_with_timeout(backend, tile, policy.timeout).await {
+            Err(err) if err.is_transient() && retries < policy.max_retries => {
+                crate::Profiler::record_backend_retry(backend.name());
+                tokio::time::sleep(policy.delay(retries)).await;
+                retries += 1;
+            }
+            result => {
+                match &result {
+                    Ok(Some(_)) => crate::Profiler::record_backend_result(backend.name(), true),
+                    Ok(None) => {}
+                    Err(_) => crate::Profiler::record_backend_result(backend.name(), false),
+                }
+                return result;
+            }
+        }
+    }
+}
+
+/// Requests `tile` from `backend`, failing with [`TileError::Timeout`] if it takes longer than
+/// `timeout`
+async fn request_with_timeout(
+    backend: &dyn Backend,
+    tile: TileId,
+    timeout: Duration,
+) -> Result<Option<Texture>, TileError> {
+    tokio::time::timeout(timeout, backend.request(tile))
+        .await
+        .unwrap_or(Err(TileError::Timeout(timeout)))
+}
+
+/// Uploads an RGBA texture to the GPU, generating its mipmaps when `mipmaps` is true. Generating
+/// them is part of the upload time recorded for the debug menu
+fn create_texture(
+    display: &glium::Display,
+    image: image::RgbaImage,
+    mipmaps: bool,
+) -> glium::Texture2d {
     let image_dimensions = image.dimensions();
     let start = std::time::Instant::now();
 
     let raw_image =
         glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
 
-    let result = glium::texture::Texture2d::new(display, raw_image).unwrap();
-    {
-        let mut guard = crate::MAP_PERF_DATA.lock();
-        guard.tile_upload_time.add_sample(start.elapsed());
-    }
+    let mipmaps = if mipmaps {
+        glium::texture::MipmapsOption::AutoGeneratedMipmaps
+    } else {
+        glium::texture::MipmapsOption::NoMipmap
+    };
+    let result = glium::texture::Texture2d::with_mipmaps(display, raw_image, mipmaps).unwrap();
+    crate::Profiler::record_tile_upload(start.elapsed());
     result
 }
 
@@ -258,6 +1020,297 @@ pub fn u64_to_tile_coord(bits: u64) -> TileId {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tile::mock_backend::{MockBackend, MockTile};
+
+    const WORLD: WorldViewport = WorldViewport {
+        top_left: DVec2::ZERO,
+        bottom_right: DVec2::ONE,
+    };
+
+    /// Runs the pipeline's updates until `done` returns true, failing the test if that takes
+    /// too long
+    fn update_until(
+        pipeline: &mut TilePipeline,
+        image_map: &mut conrod_core::image::Map<image::RgbaImage>,
+        mut done: impl FnMut(&mut TilePipeline) -> bool,
+    ) {
+        let start = std::time::Instant::now();
+        while !done(pipeline) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Pipeline never finished"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+            pipeline.update_with(&WORLD, 1, image_map, |image| image);
+        }
+    }
+
+    fn pipeline(
+        backends: &[&MockBackend],
+        policy: RetryPolicy,
+        shutdown: &Shutdown,
+        runtime: &Runtime,
+    ) -> TilePipeline {
+        let backends = backends
+            .iter()
+            .map(|backend| Box::new((*backend).clone()) as Box<dyn Backend>)
+            .collect();
+        TilePipeline::new(backends, 16, policy, shutdown, runtime)
+    }
+
+    #[test]
+    fn pixel_density_shrinks_layout_tile_size() {
+        let runtime = Runtime::new().unwrap();
+        let shutdown = Shutdown::new();
+        let backend = MockBackend::new();
+        let mut pipeline = pipeline(&[&backend], RetryPolicy::default(), &shutdown, &runtime);
+        assert_eq!(pipeline.layout_tile_size(), pipeline.tile_size());
+
+        //Mock tiles are 2 pixels wide, so on a 2x screen they take up a single logical pixel
+        pipeline.set_pixel_density(2.0);
+        assert_eq!(pipeline.tile_size(), Some(2));
+        assert_eq!(pipeline.layout_tile_size(), Some(1));
+        //Densities below 1 would pick blurrier tiles than the default, so are ignored
+        pipeline.set_pixel_density(0.5);
+        assert_eq!(pipeline.layout_tile_size(), Some(2));
+    }
+
+    #[test]
+    fn pipeline_loads_tiles_once() {
+        let runtime = Runtime::new().unwrap();
+        let shutdown = Shutdown::new();
+        let (left, right) = (TileId::new(0, 0, 1), TileId::new(1, 0, 1));
+        let cache = MockBackend::new();
+        let api = MockBackend::new();
+        api.set_tile(left, MockTile::solid([255, 0, 0, 255]));
+        let mut pipeline = pipeline(&[&cache, &api], RetryPolicy::default(), &shutdown, &runtime);
+        let mut image_map = conrod_core::image::Map::new();
+
+        //Asking again while the tiles are pending doesn't send more requests
+        update_until(&mut pipeline, &mut image_map, |pipeline| {
+            let loaded = pipeline.get_tile(left).is_some();
+            assert_eq!(pipeline.get_tile(right), None);
+            loaded && matches!(pipeline.cache.get(right), Some(CachedTile::NotAvailable))
+        });
+        let image = pipeline.get_tile(left).unwrap();
+        assert_eq!(image_map.get(&image).unwrap().get_pixel(0, 0)[0], 255);
+        assert_eq!(pipeline.get_tile(right), None);
+        assert!(pipeline.is_tile_cached(left));
+
+        let mut requests = api.requests();
+        requests.sort_by_key(|tile| tile.x);
+        assert_eq!(requests, [left, right]);
+        assert_eq!(cache.requests().len(), 2);
+
+        //The faster backend was given a copy, so it has the tile from now on
+        assert_eq!(cache.stored(), [left]);
+        assert!(matches!(
+            runtime.block_on(cache.readiness(left)),
+            ReadinessStatus::Available
+        ));
+        assert!(matches!(
+            runtime.block_on(cache.readiness(right)),
+            ReadinessStatus::NotAvailable
+        ));
+    }
+
+    #[test]
+    fn failed_tiles_are_requested_again() {
+        let runtime = Runtime::new().unwrap();
+        let shutdown = Shutdown::new();
+        let tile = TileId::new(0, 0, 1);
+        let api = MockBackend::with_default(MockTile::TransientError);
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let mut pipeline = pipeline(&[&api], policy, &shutdown, &runtime);
+        let mut image_map = conrod_core::image::Map::new();
+
+        assert_eq!(pipeline.get_tile(tile), None);
+        update_until(&mut pipeline, &mut image_map, |pipeline| {
+            pipeline.cache.get(tile).is_none()
+        });
+        assert_eq!(api.requests().len(), 3);
+
+        //Unlike a missing tile it isn't given up on
+        api.set_tile(tile, MockTile::solid([0, 255, 0, 255]));
+        update_until(&mut pipeline, &mut image_map, |pipeline| {
+            pipeline.get_tile(tile).is_some()
+        });
+        assert_eq!(api.requests().len(), 4);
+    }
+
+    #[test]
+    fn transparent_tiles_are_not_available() {
+        let runtime = Runtime::new().unwrap();
+        let shutdown = Shutdown::new();
+        let tile = TileId::new(0, 0, 1);
+        let cache = MockBackend::new().ignoring_transparent_tiles();
+        let api =
+            MockBackend::with_default(MockTile::solid([0, 0, 0, 0])).ignoring_transparent_tiles();
+        let mut pipeline = pipeline(&[&cache, &api], RetryPolicy::default(), &shutdown, &runtime);
+        let mut image_map = conrod_core::image::Map::new();
+
+        assert_eq!(pipeline.get_tile(tile), None);
+        update_until(&mut pipeline, &mut image_map, |pipeline| {
+            matches!(pipeline.cache.get(tile), Some(CachedTile::NotAvailable))
+        });
+        assert!(cache.stored().is_empty());
+        assert_eq!(image_map.len(), 0);
+    }
+
+    #[test]
+    fn shutdown_waits_for_requests() {
+        let runtime = Runtime::new().unwrap();
+        let shutdown = Shutdown::new();
+        let tile = TileId::new(0, 0, 1);
+        let cache = MockBackend::new();
+        let api = MockBackend::new();
+        let image = Texture::from_pixel(2, 2, image::Rgba([0, 0, 255, 255]));
+        api.set_tile(tile, MockTile::Delayed(Duration::from_millis(50), image));
+        let mut pipeline = pipeline(&[&cache, &api], RetryPolicy::default(), &shutdown, &runtime);
+
+        assert_eq!(pipeline.get_tile(tile), None);
+        let start = std::time::Instant::now();
+        while api.requests().is_empty() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Request never started"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        shutdown.trigger();
+        runtime.block_on(pipeline.finish_requests());
+        assert_eq!(cache.stored(), [tile]);
+    }
+
+    #[tokio::test]
+    async fn hung_requests_time_out() {
+        let policy = RetryPolicy {
+            max_retries: 1,
+            initial_delay: Duration::from_millis(1),
+            timeout: Duration::from_millis(10),
+        };
+        let backend = MockBackend::with_default(MockTile::Hang);
+        let result = request_with_retries(&backend, TileId::new(0, 0, 0), policy).await;
+        match result {
+            Err(err @ TileError::Timeout(_)) => assert!(err.is_transient()),
+            _ => panic!("expected a timeout"),
+        }
+        assert_eq!(backend.requests().len(), 2);
+    }
+
+    #[test]
+    fn retry_delays_double() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0), Duration::from_millis(250));
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn failed_prefetches_are_tried_again() {
+        let runtime = Runtime::new().unwrap();
+        let shutdown = Shutdown::new();
+        let api = MockBackend::with_default(MockTile::TransientError);
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let mut pipeline = pipeline(&[&api], policy, &shutdown, &runtime);
+
+        //Mock tiles are 2 pixels wide, so with the whole world 4 pixels across the screen shows
+        //zoom 1 and zoom 0 is prefetched
+        let view = crate::map::TileView::new(0.0, 0.0, 0.0, 4.0);
+        let world_tile = TileId::new(0, 0, 0);
+        let start = std::time::Instant::now();
+        while api
+            .requests()
+            .iter()
+            .filter(|&&tile| tile == world_tile)
+            .count()
+            < 2
+        {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Prefetch never retried"
+            );
+            pipeline.prefetch(&view, DVec2::new(4.0, 4.0));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn persistent_prefetch_errors_are_not_retried() {
+        let runtime = Runtime::new().unwrap();
+        let shutdown = Shutdown::new();
+        let api = MockBackend::with_default(MockTile::MissingApiKey);
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let mut pipeline = pipeline(&[&api], policy, &shutdown, &runtime);
+
+        //Prefetch every frame for long enough that a retry would have happened several times over
+        let view = crate::map::TileView::new(0.0, 0.0, 0.0, 4.0);
+        let world_tile = TileId::new(0, 0, 0);
+        let start = std::time::Instant::now();
+        while start.elapsed() < Duration::from_millis(200) {
+            pipeline.prefetch(&view, DVec2::new(4.0, 4.0));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let requests = api.requests();
+        assert_eq!(
+            requests.iter().filter(|&&tile| tile == world_tile).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn prefetch_covers_viewport() {
+        let viewport = WorldViewport {
+            top_left: DVec2::new(0.25, 0.25),
+            bottom_right: DVec2::new(0.5, 0.5),
+        };
+        let tiles: Vec<_> = tiles_covering(&viewport, 3).collect();
+        assert_eq!(tiles.len(), 4);
+        assert!(tiles.contains(&TileId::new(2, 2, 3)));
+        assert!(tiles.contains(&TileId::new(3, 3, 3)));
+        assert_eq!(tiles_covering(&viewport, 4).count(), 16);
+
+        //Past the antimeridian x wraps around, and each tile only shows up once
+        let wide = WorldViewport {
+            top_left: DVec2::new(-0.5, 0.0),
+            bottom_right: DVec2::new(1.5, 1.0),
+        };
+        let tiles: Vec<_> = tiles_covering(&wide, 1).collect();
+        assert_eq!(tiles.len(), 4);
+        assert!(tiles.iter().all(|tile| tile.x < 2 && tile.y < 2));
+    }
+
+    #[test]
+    fn urgent_tiles_first() {
+        let area = VisibleArea {
+            top_left: DVec2::new(0.25, 0.25),
+            bottom_right: DVec2::new(0.5, 0.5),
+            zoom: 4,
+        };
+        let corner = TileId::new(4, 4, 4);
+        let edge = TileId::new(7, 5, 4);
+        let center = TileId::new(5, 5, 4);
+
+        let mut pending = vec![corner, edge, center];
+        assert_eq!(take_most_urgent(&mut pending, Some(&area)), center);
+        assert_eq!(take_most_urgent(&mut pending, Some(&area)), edge);
+        assert_eq!(take_most_urgent(&mut pending, Some(&area)), corner);
+
+        //Before anything is on screen requests are taken in order
+        let mut pending = vec![corner, edge, center];
+        assert_eq!(take_most_urgent(&mut pending, None), corner);
+        assert_eq!(take_most_urgent(&mut pending, None), edge);
+    }
 
     #[test]
     fn test_u64_and_tile() {
@@ -303,6 +1356,97 @@ mod tests {
         }
     }
 
+    #[test]
+    fn tile_visibility() {
+        //Zoomed in on the top left quarter of the world
+        let area = VisibleArea {
+            top_left: DVec2::new(0.0, 0.0),
+            bottom_right: DVec2::new(0.5, 0.5),
+            zoom: 3,
+        };
+        assert!(is_tile_visible(TileId::new(0, 0, 3), &area));
+        assert!(is_tile_visible(TileId::new(3, 3, 3), &area));
+        //Just off screen tiles are kept as a margin
+        assert!(is_tile_visible(TileId::new(4, 4, 3), &area));
+        assert!(!is_tile_visible(TileId::new(6, 6, 3), &area));
+        assert!(!is_tile_visible(TileId::new(0, 6, 3), &area));
+
+        //Parent tiles covering the screen are used as placeholders
+        assert!(is_tile_visible(TileId::new(0, 0, 0), &area));
+        assert!(is_tile_visible(TileId::new(0, 0, 1), &area));
+
+        //Tiles from a deeper zoom are stale
+        assert!(!is_tile_visible(TileId::new(0, 0, 4), &area));
+    }
+
+    #[test]
+    fn tile_visibility_wraps_horizontally() {
+        //Looking across the anti meridian into the next copy of the world
+        let area = VisibleArea {
+            top_left: DVec2::new(0.9, 0.4),
+            bottom_right: DVec2::new(1.2, 0.6),
+            zoom: 4,
+        };
+        assert!(is_tile_visible(TileId::new(15, 7, 4), &area));
+        assert!(is_tile_visible(TileId::new(0, 7, 4), &area));
+        assert!(is_tile_visible(TileId::new(2, 7, 4), &area));
+        assert!(!is_tile_visible(TileId::new(8, 7, 4), &area));
+    }
+
+    #[test]
+    fn gpu_cache_stays_bounded() {
+        let budget = 64;
+        let mut cache = GpuTileCache::new(budget);
+        let mut image_map = conrod_core::image::Map::new();
+        let area = VisibleArea {
+            top_left: DVec2::new(0.0, 0.0),
+            bottom_right: DVec2::new(0.02, 0.02),
+            zoom: 10,
+        };
+
+        for x in 0..1000 {
+            let tile = TileId::new(x, 0, 10);
+            assert!(cache.insert_image(tile, image_map.insert(())).is_none());
+            for image in cache.evict(Some(&area)) {
+                assert!(image_map.remove(image).is_some());
+            }
+            cache.next_frame();
+
+            assert!(cache.on_gpu() <= budget);
+            assert_eq!(cache.on_gpu(), image_map.len());
+        }
+
+        //The tiles on screen are kept over the ones that scrolled off
+        assert!(matches!(
+            cache.get(TileId::new(0, 0, 10)),
+            Some(CachedTile::Cached { .. })
+        ));
+        assert!(cache.get(TileId::new(999, 0, 10)).is_some());
+        assert!(cache.get(TileId::new(500, 0, 10)).is_none());
+    }
+
+    #[test]
+    fn gpu_cache_keeps_tiles_in_use() {
+        let mut cache = GpuTileCache::new(1);
+        let mut image_map = conrod_core::image::Map::new();
+
+        for x in 0..4 {
+            cache.insert_image(TileId::new(x, 0, 2), image_map.insert(()));
+        }
+        //All of these are being drawn this frame, so nothing can go
+        for x in 0..4 {
+            cache.get(TileId::new(x, 0, 2));
+        }
+        assert!(cache.evict(None).is_empty());
+        assert_eq!(cache.on_gpu(), 4);
+
+        //Next frame only one is drawn, so the rest are evicted
+        cache.next_frame();
+        cache.get(TileId::new(2, 0, 2));
+        assert_eq!(cache.evict(None).len(), 3);
+        assert!(cache.get(TileId::new(2, 0, 2)).is_some());
+    }
+
     #[test]
     fn tile_and_intmap() {
         let tile = TileId {
@@ -316,6 +1460,6 @@ mod tests {
 
         let bits = tile_coord_to_u64(tile);
 
-        assert_eq!(*map.get(bits).unwrap(), true);
+        assert!(*map.get(bits).unwrap());
     }
 }
@@ -0,0 +1,307 @@
+use super::{pipeline, Backend, DiskCache, ReadinessStatus, RetryPolicy, TileId, TileKind};
+use crate::{util, Shutdown, WorldViewport};
+
+use glam::DVec2;
+use log::warn;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Semaphore};
+
+/// The most tiles downloaded at once while prefetching
+const MAX_CONCURRENT_DOWNLOADS: usize = 8;
+
+/// An area of the map bounded by two latitudes and two longitudes, in degrees
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Region {
+    pub lat_min: f64,
+    pub lon_min: f64,
+    pub lat_max: f64,
+    pub lon_max: f64,
+}
+
+impl Region {
+    /// Returns the part of the world covered by this region. The world is 0..1 in both directions,
+    /// with y increasing to the south
+    fn viewport(&self) -> WorldViewport {
+        WorldViewport {
+            top_left: DVec2::new(
+                util::x_from_longitude(self.lon_min),
+                util::y_from_latitude(self.lat_max),
+            ),
+            bottom_right: DVec2::new(
+                util::x_from_longitude(self.lon_max),
+                util::y_from_latitude(self.lat_min),
+            ),
+        }
+    }
+}
+
+/// Why [`prefetch_region`] refused to download a region
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum PrefetchError {
+    #[error(
+        "OpenStreetMap's tile usage policy forbids downloading tiles in bulk. \
+         Set {0} to a tile server that allows it"
+    )]
+    BulkDownloadForbidden(&'static str),
+}
+
+/// How far along [`prefetch_region`] is
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrefetchProgress {
+    /// The number of tiles in the region
+    pub total: usize,
+    /// Tiles that were already in the disk cache
+    pub cached: usize,
+    /// Tiles that were downloaded into the disk cache
+    pub downloaded: usize,
+    /// Tiles that couldn't be downloaded, or don't exist
+    pub failed: usize,
+}
+
+impl PrefetchProgress {
+    /// The number of tiles dealt with so far
+    pub fn done(&self) -> usize {
+        self.cached + self.downloaded + self.failed
+    }
+}
+
+/// Returns every tile covering `region` at the zoom levels in `zoom_levels`
+pub fn region_tiles(region: &Region, zoom_levels: RangeInclusive<u32>) -> Vec<TileId> {
+    let viewport = region.viewport();
+    zoom_levels
+        .flat_map(|zoom| pipeline::tiles_covering(&viewport, zoom))
+        .collect()
+}
+
+/// Downloads every tile of `kind` covering `region` at the zoom levels in `zoom_levels` into the
+/// disk cache, so that they load straight away the next time the app starts. Zoom levels past what
+/// the tile server provides are skipped.
+///
+/// Runs without a window, so the tiles are never decoded for the GPU. `progress` is called after
+/// each tile. Once `shutdown` is triggered no more downloads are started, and this returns as soon
+/// as the ones in progress are written to the disk cache.
+///
+/// Street tiles can only be prefetched from a custom tile server, since OpenStreetMap's servers
+/// are run on donations and don't allow bulk downloads
+pub async fn prefetch_region(
+    region: Region,
+    zoom_levels: RangeInclusive<u32>,
+    kind: TileKind,
+    retry_policy: RetryPolicy,
+    shutdown: &Shutdown,
+    progress: impl FnMut(PrefetchProgress),
+) -> Result<PrefetchProgress, PrefetchError> {
+    let shutdown = shutdown.subscribe();
+    if !super::allows_bulk_download(kind) {
+        return Err(PrefetchError::BulkDownloadForbidden(
+            super::STREET_TILES_VAR,
+        ));
+    }
+    let (cache_data, requester) = super::tile_source(kind);
+    let max_zoom = requester
+        .max_zoom()
+        .map_or(crate::MAX_ZOOM_LEVEL, |max| max.min(crate::MAX_ZOOM_LEVEL));
+    let zoom_levels = *zoom_levels.start()..=(*zoom_levels.end()).min(max_zoom);
+    let tiles = region_tiles(&region, zoom_levels);
+
+    let cache = DiskCache::new(cache_data, false);
+    Ok(prefetch_tiles(
+        tiles,
+        &cache,
+        requester.into(),
+        retry_policy,
+        shutdown,
+        progress,
+    )
+    .await)
+}
+
+/// Requests each of `tiles` that isn't in `cache` from `requester`, which is expected to store them
+/// in `cache` itself.
+///
+/// Stops starting requests once `shutdown` is received, but always waits for the ones already
+/// started so that no tile is left half written
+async fn prefetch_tiles(
+    tiles: Vec<TileId>,
+    cache: &dyn Backend,
+    requester: Arc<dyn Backend>,
+    retry_policy: RetryPolicy,
+    mut shutdown: broadcast::Receiver<()>,
+    mut progress: impl FnMut(PrefetchProgress),
+) -> PrefetchProgress {
+    let mut state = PrefetchProgress {
+        total: tiles.len(),
+        ..Default::default()
+    };
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_DOWNLOADS));
+    let (result_tx, mut result_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut downloading = 0;
+
+    for tile in tiles {
+        if crate::is_shutting_down(&mut shutdown) {
+            break;
+        }
+        if let ReadinessStatus::Available = cache.readiness(tile).await {
+            state.cached += 1;
+            progress(state);
+            continue;
+        }
+        let permit = tokio::select! {
+            biased;
+            _ = shutdown.recv() => break,
+            permit = permits.clone().acquire_owned() => permit.expect("Prefetch semaphore closed"),
+        };
+        let requester = requester.clone();
+        let result_tx = result_tx.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let result =
+                pipeline::request_with_retries(requester.as_ref(), tile, retry_policy).await;
+            if let Err(err) = &result {
+                warn!("Failed to prefetch tile {:?}: {}", tile, err);
+            }
+            let _ = result_tx.send(matches!(result, Ok(Some(_))));
+        });
+        downloading += 1;
+
+        //Report downloads that finished while we were waiting for a permit
+        while let Ok(downloaded) = result_rx.try_recv() {
+            downloading -= 1;
+            record_download(&mut state, downloaded);
+            progress(state);
+        }
+    }
+    while downloading > 0 {
+        let downloaded = result_rx.recv().await.expect("Prefetch task panicked");
+        downloading -= 1;
+        record_download(&mut state, downloaded);
+        progress(state);
+    }
+    state
+}
+
+fn record_download(state: &mut PrefetchProgress, downloaded: bool) {
+    if downloaded {
+        state.downloaded += 1;
+    } else {
+        state.failed += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::mock_backend::{MockBackend, MockTile};
+
+    #[test]
+    fn region_coverage() {
+        let world = Region {
+            lat_min: -85.0,
+            lon_min: -180.0,
+            lat_max: 85.0,
+            lon_max: 180.0,
+        };
+        assert_eq!(region_tiles(&world, 0..=0), [TileId::new(0, 0, 0)]);
+        assert_eq!(region_tiles(&world, 0..=2).len(), 1 + 4 + 16);
+
+        //Around Daytona Beach only one tile is needed until zoomed in quite a bit
+        let daytona = Region {
+            lat_min: 29.1,
+            lon_min: -81.1,
+            lat_max: 29.2,
+            lon_max: -81.0,
+        };
+        assert_eq!(region_tiles(&daytona, 5..=5), [TileId::new(8, 13, 5)]);
+    }
+
+    #[tokio::test]
+    async fn prefetch_skips_cached_tiles() {
+        let (cached, missing) = (TileId::new(0, 1, 1), TileId::new(1, 1, 1));
+        let cache = MockBackend::new();
+        cache.set_tile(cached, MockTile::solid([255, 255, 255, 255]));
+        let api = MockBackend::with_default(MockTile::solid([0, 0, 255, 255]));
+        api.set_tile(missing, MockTile::Missing);
+        api.set_tile(TileId::new(1, 0, 1), MockTile::Error);
+        let world = Region {
+            lat_min: -85.0,
+            lon_min: -180.0,
+            lat_max: 85.0,
+            lon_max: 180.0,
+        };
+        let tiles = region_tiles(&world, 1..=1);
+
+        let shutdown = Shutdown::new();
+        let mut updates = 0;
+        let result = prefetch_tiles(
+            tiles,
+            &cache,
+            Arc::new(api.clone()),
+            RetryPolicy::default(),
+            shutdown.subscribe(),
+            |_| updates += 1,
+        )
+        .await;
+        assert_eq!(
+            result,
+            PrefetchProgress {
+                total: 4,
+                cached: 1,
+                downloaded: 1,
+                failed: 2,
+            }
+        );
+        assert_eq!(updates, 4);
+        assert_eq!(api.requests().len(), 3);
+        assert!(!api.requests().contains(&cached));
+    }
+
+    #[tokio::test]
+    async fn prefetch_stops_on_shutdown() {
+        let api = MockBackend::with_default(MockTile::solid([0, 0, 255, 255]));
+        let tiles = vec![TileId::new(0, 0, 1), TileId::new(1, 0, 1)];
+
+        let shutdown = Shutdown::new();
+        let receiver = shutdown.subscribe();
+        shutdown.trigger();
+        let result = prefetch_tiles(
+            tiles,
+            &MockBackend::new(),
+            Arc::new(api.clone()),
+            RetryPolicy::default(),
+            receiver,
+            |_| {},
+        )
+        .await;
+        assert_eq!(result.total, 2);
+        assert_eq!(result.done(), 0);
+        assert!(api.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn openstreetmap_is_never_prefetched() {
+        //Only the default OpenStreetMap servers are off limits
+        if std::env::var_os(crate::tile::STREET_TILES_VAR).is_some() {
+            return;
+        }
+        let daytona = Region {
+            lat_min: 29.1,
+            lon_min: -81.1,
+            lat_max: 29.2,
+            lon_max: -81.0,
+        };
+        let result = prefetch_region(
+            daytona,
+            5..=5,
+            TileKind::Street,
+            RetryPolicy::default(),
+            &Shutdown::new(),
+            |_| panic!("No tiles should be requested"),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(PrefetchError::BulkDownloadForbidden(_))
+        ));
+    }
+}
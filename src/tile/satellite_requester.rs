@@ -1,29 +1,51 @@
 use super::{disk_cache::DiskCacheData, Backend, ReadinessStatus, TileError, TileId};
 
 use async_trait::async_trait;
+use log::warn;
 use maptiler_cloud::{Maptiler, TileRequest};
 use rand::Rng;
 
+/// The environment variable holding the Maptiler api keys, separated by commas
+const MAPTILER_KEY_VAR: &str = "MAPTILER_KEY";
+
 pub struct SatelliteRequester {
-    maptiler: Maptiler,
+    /// `None` if no api key was provided, in which case every request fails
+    maptiler: Option<Maptiler>,
     cache_data: DiskCacheData,
 }
 
 impl SatelliteRequester {
+    /// Creates a new requester using one of the keys in the `MAPTILER_KEY` environment variable.
+    ///
+    /// A key is picked at random so that usage is spread across all of them
     pub fn new(cache_data: DiskCacheData) -> Self {
-        let api_keys = [
-            "GBnoGxmU64rzYqypBLp9",
-            "VrgC04XoV1a84R5VkUnL",
-            "aDXLSzDyrKtWljFn5vKR",
-        ];
+        let keys = std::env::var(MAPTILER_KEY_VAR).unwrap_or_default();
+        let api_keys = parse_api_keys(&keys);
+        let maptiler = if api_keys.is_empty() {
+            warn!(
+                "{} is not set, so satellite imagery can't be downloaded",
+                MAPTILER_KEY_VAR
+            );
+            None
+        } else {
+            let key = api_keys[rand::thread_rng().gen_range(0..api_keys.len())];
+            Some(Maptiler::new(key).expect("Failed to create maptiler TLS backend!"))
+        };
         Self {
-            maptiler: Maptiler::new(api_keys[rand::thread_rng().gen_range(0..api_keys.len())])
-                .expect("Failed to create maptiler TLS backend!"),
+            maptiler,
             cache_data,
         }
     }
 }
 
+/// Splits a comma separated list of api keys, ignoring whitespace and empty entries
+fn parse_api_keys(keys: &str) -> Vec<&str> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .collect()
+}
+
 #[async_trait]
 impl Backend for SatelliteRequester {
     fn name(&self) -> &'static str {
@@ -31,6 +53,10 @@ impl Backend for SatelliteRequester {
     }
 
     async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        let maptiler = self
+            .maptiler
+            .as_ref()
+            .ok_or(TileError::MissingApiKey(MAPTILER_KEY_VAR))?;
         let req = match TileRequest::new(
             maptiler_cloud::TileSet::Satellite,
             tile.x,
@@ -40,7 +66,7 @@ impl Backend for SatelliteRequester {
             Ok(req) => req,
             Err(_err) => return Ok(None),
         };
-        let bytes = self.maptiler.create_request(req).execute().await?;
+        let bytes = maptiler.create_request(req).execute().await?;
         let _ = self.cache_data.cache_tile(tile, bytes.as_slice()).await;
         Ok(Some(bytes))
     }
@@ -53,7 +79,24 @@ impl Backend for SatelliteRequester {
         Some(128)
     }
 
+    fn max_zoom(&self) -> Option<u32> {
+        Some(maptiler_cloud::TileSet::Satellite.max_zoom())
+    }
+
     fn ignore_transparent_tiles(&self) -> bool {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_key_parsing() {
+        assert!(parse_api_keys("").is_empty());
+        assert!(parse_api_keys(" , ,").is_empty());
+        assert_eq!(parse_api_keys("abc"), vec!["abc"]);
+        assert_eq!(parse_api_keys("abc, def,,ghi "), vec!["abc", "def", "ghi"]);
+    }
+}
@@ -0,0 +1,236 @@
+use super::{disk_cache::DiskCacheData, Backend, ReadinessStatus, TileError, TileId};
+
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+
+/// A piece of a [`UrlTemplate`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    /// `{z}`
+    Zoom,
+    /// `{x}`
+    X,
+    /// `{y}`
+    Y,
+    /// `{s}`, one of the subdomains the tiles are spread across
+    Subdomain,
+}
+
+/// Why a [`UrlTemplate`] couldn't be parsed
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("unknown placeholder {{{0}}}, expected {{z}}, {{x}}, {{y}} or {{s}}")]
+    UnknownPlaceholder(String),
+    #[error("a {{ is never closed")]
+    UnclosedBrace,
+    #[error("the {{{0}}} placeholder is missing")]
+    MissingPlaceholder(char),
+    #[error("{{s}} is used but no subdomains were given")]
+    NoSubdomains,
+}
+
+/// A tile URL with placeholders for the tile coordinates, like
+/// `https://{s}.tile.example.com/{z}/{x}/{y}.png`.
+///
+/// `{z}`, `{x}` and `{y}` are required. `{s}` is replaced by one of the template's subdomains,
+/// picked from the tile coordinates so that each tile always comes from the same server
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UrlTemplate {
+    parts: Vec<TemplatePart>,
+    subdomains: Vec<String>,
+}
+
+impl UrlTemplate {
+    /// Parses `template`, checking that it has every coordinate placeholder and that
+    /// `subdomains` isn't empty if it uses `{s}`
+    pub fn parse(template: &str, subdomains: Vec<String>) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Literal(rest[..start].to_owned()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or(TemplateError::UnclosedBrace)?
+                + start;
+            let part = match &rest[start + 1..end] {
+                "z" => TemplatePart::Zoom,
+                "x" => TemplatePart::X,
+                "y" => TemplatePart::Y,
+                "s" => TemplatePart::Subdomain,
+                other => return Err(TemplateError::UnknownPlaceholder(other.to_owned())),
+            };
+            parts.push(part);
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_owned()));
+        }
+
+        for (part, name) in [
+            (TemplatePart::Zoom, 'z'),
+            (TemplatePart::X, 'x'),
+            (TemplatePart::Y, 'y'),
+        ] {
+            if !parts.contains(&part) {
+                return Err(TemplateError::MissingPlaceholder(name));
+            }
+        }
+        if parts.contains(&TemplatePart::Subdomain) && subdomains.is_empty() {
+            return Err(TemplateError::NoSubdomains);
+        }
+        Ok(Self { parts, subdomains })
+    }
+
+    /// Returns the URL of `tile`
+    pub fn url(&self, tile: TileId) -> String {
+        let mut url = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => url.push_str(text),
+                TemplatePart::Zoom => url.push_str(&tile.zoom.to_string()),
+                TemplatePart::X => url.push_str(&tile.x.to_string()),
+                TemplatePart::Y => url.push_str(&tile.y.to_string()),
+                TemplatePart::Subdomain => {
+                    let index = (tile.x as usize + tile.y as usize) % self.subdomains.len();
+                    url.push_str(&self.subdomains[index]);
+                }
+            }
+        }
+        url
+    }
+}
+
+/// Fetches tiles from any server that serves them at URLs following a [`UrlTemplate`]
+pub struct TemplateRequester {
+    template: UrlTemplate,
+    client: reqwest::Client,
+    permits: Semaphore,
+    tile_size: u32,
+    max_zoom: Option<u32>,
+    cache_data: DiskCacheData,
+}
+
+impl TemplateRequester {
+    /// Creates a requester for tiles `tile_size` pixels wide, available up to `max_zoom`.
+    ///
+    /// At most `max_downloads` tiles are downloaded at once, and requests are sent with
+    /// `user_agent`, which many tile servers require to identify the application
+    pub fn new(
+        template: UrlTemplate,
+        tile_size: u32,
+        max_zoom: Option<u32>,
+        max_downloads: usize,
+        user_agent: &str,
+        cache_data: DiskCacheData,
+    ) -> Self {
+        Self {
+            template,
+            client: reqwest::Client::builder()
+                .user_agent(user_agent)
+                .build()
+                .expect("Failed to create tile server TLS backend!"),
+            permits: Semaphore::new(max_downloads),
+            tile_size,
+            max_zoom,
+            cache_data,
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for TemplateRequester {
+    fn name(&self) -> &'static str {
+        "Template Requester"
+    }
+
+    async fn request_inner(&self, tile: TileId) -> Result<Option<Vec<u8>>, TileError> {
+        let url = self.template.url(tile);
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("Tile server semaphore closed");
+
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        let bytes = response.bytes().await?.to_vec();
+        let _ = self.cache_data.cache_tile(tile, bytes.as_slice()).await;
+        Ok(Some(bytes))
+    }
+
+    async fn readiness(&self, _tile: TileId) -> ReadinessStatus {
+        ReadinessStatus::Unknown
+    }
+
+    fn tile_size(&self) -> Option<u32> {
+        Some(self.tile_size)
+    }
+
+    fn max_zoom(&self) -> Option<u32> {
+        self.max_zoom
+    }
+
+    fn ignore_transparent_tiles(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subdomains(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn template_substitution() {
+        let template = UrlTemplate::parse(
+            "https://{s}.tiles.example.com/{z}/{x}/{y}.png?style=dark",
+            subdomains(&["a", "b", "c"]),
+        )
+        .unwrap();
+        assert_eq!(
+            template.url(TileId::new(3, 5, 4)),
+            "https://c.tiles.example.com/4/3/5.png?style=dark"
+        );
+        assert_eq!(
+            template.url(TileId::new(0, 0, 0)),
+            "https://a.tiles.example.com/0/0/0.png?style=dark"
+        );
+        assert_eq!(
+            template.url(TileId::new(1, 0, 1)),
+            "https://b.tiles.example.com/1/1/0.png?style=dark"
+        );
+
+        //The coordinates can be in any order, and subdomains are optional
+        let template = UrlTemplate::parse("http://localhost/{y}-{x}@{z}", Vec::new()).unwrap();
+        assert_eq!(
+            template.url(TileId::new(12, 34, 6)),
+            "http://localhost/34-12@6"
+        );
+    }
+
+    #[test]
+    fn invalid_templates() {
+        let parse = |template| UrlTemplate::parse(template, Vec::new());
+        assert_eq!(
+            parse("https://example.com/{z}/{x}.png"),
+            Err(TemplateError::MissingPlaceholder('y'))
+        );
+        assert_eq!(
+            parse("https://example.com/{z}/{x}/{y"),
+            Err(TemplateError::UnclosedBrace)
+        );
+        assert_eq!(
+            parse("https://example.com/{zoom}/{x}/{y}.png"),
+            Err(TemplateError::UnknownPlaceholder("zoom".to_owned()))
+        );
+        assert_eq!(
+            parse("https://{s}.example.com/{z}/{x}/{y}.png"),
+            Err(TemplateError::NoSubdomains)
+        );
+    }
+}
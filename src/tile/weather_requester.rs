@@ -6,8 +6,8 @@
 #![allow(deprecated)]
 
 use async_trait::async_trait;
+use log::debug;
 use rain_viewer::RequestArguments;
-use simple_moving_average::SMA;
 
 use std::{
     sync::atomic::Ordering,
@@ -40,6 +40,9 @@ pub struct WeatherRequester {
     available: tokio::sync::RwLock<Option<WeatherData>>,
     state: AtomicWeatherDataState,
     tile_size: u32,
+    /// Rain viewer has no radar imagery past this zoom level, so requesting deeper tiles is wasted
+    /// api traffic
+    max_zoom: u32,
     cache_data: DiskCacheData,
     req: rain_viewer::WeatherRequester,
 }
@@ -50,6 +53,7 @@ impl WeatherRequester {
             available: tokio::sync::RwLock::new(None),
             state: AtomicWeatherDataState::new(WeatherDataState::Uninitialized),
             tile_size: 512,
+            max_zoom: 10,
             cache_data,
             req: rain_viewer::WeatherRequester::new(),
         }
@@ -90,7 +94,7 @@ impl Backend for WeatherRequester {
                         //This means its our responsibility to load the data
                         let mut guard = self.available.write().await;
                         if guard.is_some() {
-                            println!("Someone initialized available already?!");
+                            debug!("Someone initialized available already?!");
                             continue;
                         }
                         match self.update_maps().await {
@@ -100,9 +104,10 @@ impl Backend for WeatherRequester {
                                     .store(WeatherDataState::Available, Ordering::Release);
                             }
                             Err(err) => {
+                                //Let the next request try again
                                 self.state
                                     .store(WeatherDataState::Uninitialized, Ordering::Release);
-                                println!("Failed to get data while initializing: {:?}", err);
+                                return Err(err.into());
                             }
                         }
                     }
@@ -140,10 +145,10 @@ impl Backend for WeatherRequester {
                             //We were able to modify the state to AvailableUpdating.
                             //This means its our responsibility to load the new data
                             drop(guard);
-                            println!("Task is getting new data");
+                            debug!("Task is getting new data");
                             if let Ok(new_data) = self.update_maps().await {
                                 *self.available.write().await = Some(new_data);
-                                println!("Loaded new data");
+                                debug!("Loaded new data");
                             }
 
                             self.state
@@ -167,12 +172,12 @@ impl Backend for WeatherRequester {
                                         self.cache_data.cache_tile(tile, bytes.as_slice()).await;
                                     return Ok(Some(bytes));
                                 }
-                                Err(err) => {
-                                    println!("failed to get tile {:?}: {:?}", tile, err);
-                                }
+                                Err(err) => return Err(err.into()),
                             }
                         }
                     }
+                    //No radar frames, or the tile is out of range
+                    return Ok(None);
                 }
             }
         }
@@ -187,17 +192,7 @@ impl Backend for WeatherRequester {
 
         let result = self.request_inner(tile).await?;
 
-        let duration = start.elapsed();
-        {
-            let mut guard = crate::MAP_PERF_DATA.lock();
-            guard
-                .backend_request_secs
-                .entry(self.name())
-                .or_insert_with(|| {
-                    simple_moving_average::SumTreeSMA::from_zero(std::time::Duration::ZERO)
-                })
-                .add_sample(duration);
-        }
+        crate::Profiler::record_backend_request(self.name(), start.elapsed());
         match result {
             Some(bytes) => Ok(Some(load_tile(bytes).await?)),
             None => Ok(None),
@@ -208,6 +203,10 @@ impl Backend for WeatherRequester {
         Some(self.tile_size)
     }
 
+    fn max_zoom(&self) -> Option<u32> {
+        Some(self.max_zoom)
+    }
+
     fn ignore_transparent_tiles(&self) -> bool {
         true
     }
@@ -1,3 +1,7 @@
+//The conrod derive macros generate their impls inside of a const block, which newer compilers
+//warn about. We can't change the generated code so allow it for the whole file
+#![allow(non_local_definitions)]
+
 use conrod_core::Sizeable;
 use conrod_core::{
     self, position, text, widget, widget_ids, Color, Colorable, FontSize, Labelable, Positionable,
@@ -21,6 +25,9 @@ pub struct FilterButton<'a> {
     /// Whether the button is currently enabled, i.e. whether it responds to
     /// user input.
     enabled: bool,
+    /// Whether the filter this button controls is active. Shown with
+    /// `selected_color` instead of `color`, and kept while hovering or pressing.
+    selected: bool,
 }
 /// Represents the unique styling for our widget.
 #[derive(Copy, Clone, Debug, Default, PartialEq, WidgetStyle)]
@@ -28,6 +35,9 @@ pub struct Style {
     /// Color of the Button's pressable area.
     #[conrod(default = "theme.shape_color")]
     pub color: Option<Color>,
+    /// Color of the Button's pressable area while it is selected.
+    #[conrod(default = "conrod_core::color::LIGHT_BLUE")]
+    pub selected_color: Option<Color>,
     /// Width of the border surrounding the button
     #[conrod(default = "theme.border_width")]
     pub border: Option<Scalar>,
@@ -75,6 +85,7 @@ impl<'a> FilterButton<'a> {
             maybe_label: None,
             style: Style::default(),
             enabled: true,
+            selected: false,
         }
     }
 
@@ -116,6 +127,19 @@ impl<'a> FilterButton<'a> {
         self
     }
 
+    ///Shows the button as selected, so active filters stand out
+    pub fn selected(mut self, selected: bool) -> Self {
+        self.selected = selected;
+        self
+    }
+
+    ///Color used instead of the normal one while the button is selected
+    #[allow(dead_code)]
+    pub fn selected_color(mut self, color: Color) -> Self {
+        self.style.selected_color = Some(color);
+        self
+    }
+
     ///Enabled button
     #[allow(dead_code)]
     pub fn enabled(mut self, flag: bool) -> Self {
@@ -155,7 +179,11 @@ impl<'a> Widget for FilterButton<'a> {
             ..
         } = args;
 
-        let FilterButton { maybe_label, .. } = self;
+        let FilterButton {
+            maybe_label,
+            selected,
+            ..
+        } = self;
 
         let (button_color, event) = {
             let input = ui.widget_input(id);
@@ -163,7 +191,11 @@ impl<'a> Widget for FilterButton<'a> {
             //If button was clicked
             let event = input.clicks().left().next().map(|_| ());
 
-            let color = style.color(&ui.theme);
+            let color = if selected {
+                style.selected_color(&ui.theme)
+            } else {
+                style.color(&ui.theme)
+            };
             let color = input.mouse().map_or(color, |mouse| {
                 if mouse.buttons.left().is_down() {
                     conrod_core::color::DARK_GREY
@@ -260,10 +292,12 @@ fn label(button_id: widget::Id, label_id: widget::Id, label: &str, style: &Style
         .set(label_id, ui);
 }
 
+/// Draws a filter button, highlighted if its filter is `selected`. Returns true if it was clicked
 pub fn draw(
     widget_id: widget::id::Id,
     ui: &mut UiCell,
-    label: String,
+    label: &str,
+    selected: bool,
     widget_x_position: f64,
     widget_y_position: f64,
 ) -> bool {
@@ -271,9 +305,10 @@ pub fn draw(
         .x(widget_x_position)
         .y(widget_y_position)
         .w_h(150.0, 30.0)
+        .selected(selected)
         .label_font_size(10)
         .label_color(conrod_core::color::BLACK)
-        .label(label.as_str())
+        .label(label)
         .set(widget_id, ui)
         .is_some()
 }
@@ -1,7 +1,7 @@
 //! Various utilities for commonly needed things.
 //!
-//! Currently thin contains functions for interpolation, normalization, and mapping one range of
-//! values to another
+//! Currently thin contains functions for interpolation, normalization, mapping one range of
+//! values to another, and distances and directions on the globe
 
 /// Performs linear interpolation for `f` in 0..1 between `a` and `b`
 pub fn lerp<T, F>(a: T, b: T, f: F) -> T
@@ -15,6 +15,11 @@ where
     a + ((b - a) * f)
 }
 
+/// Eases `t` in 0..1 so that it starts fast and slows down as it approaches 1
+pub fn ease_out_cubic(t: f64) -> f64 {
+    1.0 - (1.0 - t).powi(3)
+}
+
 /// Normalizes `value` in range a..b to 0..1
 pub fn normalize<T, F>(a: T, b: T, value: T) -> F
 where
@@ -110,6 +115,54 @@ pub fn longitude_from_x(x: f64) -> f64 {
     map(0.0, 1.0, x, -180.0, 180.0)
 }
 
+/// The mean radius of the earth in meters, used for distances along its surface
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Returns the great circle distance in meters between two points given as latitude and longitude
+/// in degrees, using the haversine formula
+pub fn haversine_distance_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let delta_lat = lat2 - lat1;
+    let delta_lon = (lon2 - lon1).to_radians();
+
+    let a =
+        (delta_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (delta_lon / 2.0).sin().powi(2);
+    //Rounding can push `a` just past 1 for points on opposite sides of the world
+    2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin()
+}
+
+/// Returns the direction to start flying in to get from the first point to the second along a great
+/// circle, in degrees clockwise from north in 0..360
+pub fn initial_bearing_deg(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let delta_lon = (lon2 - lon1).to_radians();
+
+    let y = delta_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * delta_lon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Returns the (latitude, longitude) in degrees reached by travelling `distance_m` meters along a
+/// great circle from the given point, starting in the direction `bearing_deg` clockwise from north.
+///
+/// The inverse of [`haversine_distance_m`] and [`initial_bearing_deg`]. The longitude is wrapped
+/// into -180..180
+pub fn project_point(lat: f64, lon: f64, bearing_deg: f64, distance_m: f64) -> (f64, f64) {
+    let lat = lat.to_radians();
+    let bearing = bearing_deg.to_radians();
+    let angular_distance = distance_m / EARTH_RADIUS_M;
+
+    let new_lat = (lat.sin() * angular_distance.cos()
+        + lat.cos() * angular_distance.sin() * bearing.cos())
+    .clamp(-1.0, 1.0)
+    .asin();
+    let delta_lon = (bearing.sin() * angular_distance.sin() * lat.cos())
+        .atan2(angular_distance.cos() - lat.sin() * new_lat.sin());
+
+    let new_lon = (lon + delta_lon.to_degrees() + 180.0).rem_euclid(360.0) - 180.0;
+    (new_lat.to_degrees(), new_lon)
+}
+
 /// Rounds a number down to the nearest multiple of `modulo`
 pub fn modulo_floor(val: f64, modulo: f64) -> f64 {
     val - (val.rem_euclid(modulo))
@@ -159,6 +212,93 @@ mod tests {
         ish_bounded(latitude_from_y(0.25), 66.5, 0.05);
     }
 
+    #[test]
+    fn great_circle_distance() {
+        //JFK to Heathrow
+        ish_bounded(
+            haversine_distance_m(40.6413, -73.7781, 51.4700, -0.4543),
+            5_540_000.0,
+            5_000.0,
+        );
+        //Daytona Beach to Orlando
+        ish_bounded(
+            haversine_distance_m(29.1799, -81.0581, 28.4312, -81.3081),
+            86_740.0,
+            100.0,
+        );
+        //Sydney to Los Angeles, across the antimeridian
+        ish_bounded(
+            haversine_distance_m(-33.9399, 151.1753, 33.9416, -118.4085),
+            12_060_000.0,
+            5_000.0,
+        );
+        ish(haversine_distance_m(10.0, 20.0, 10.0, 20.0), 0.0);
+        //Half way around the world
+        ish_bounded(
+            haversine_distance_m(0.0, 0.0, 0.0, 180.0),
+            std::f64::consts::PI * EARTH_RADIUS_M,
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn great_circle_bearing() {
+        ish_bounded(
+            initial_bearing_deg(40.6413, -73.7781, 51.4700, -0.4543),
+            51.35,
+            0.01,
+        );
+        ish_bounded(
+            initial_bearing_deg(29.1799, -81.0581, 28.4312, -81.3081),
+            196.37,
+            0.01,
+        );
+        ish_bounded(
+            initial_bearing_deg(-33.9399, 151.1753, 33.9416, -118.4085),
+            61.0,
+            0.01,
+        );
+        ish(initial_bearing_deg(0.0, 0.0, 10.0, 0.0), 0.0);
+        ish(initial_bearing_deg(0.0, 0.0, 0.0, 10.0), 90.0);
+        ish(initial_bearing_deg(0.0, 0.0, -10.0, 0.0), 180.0);
+        ish(initial_bearing_deg(0.0, 0.0, 0.0, -10.0), 270.0);
+    }
+
+    #[test]
+    fn projection_round_trips() {
+        let starts = [
+            (40.6413, -73.7781),
+            (29.1799, -81.0581),
+            (-33.9399, 151.1753),
+            (0.0, 179.9),
+        ];
+        for &(lat, lon) in starts.iter() {
+            for &bearing in [0.0, 45.0, 90.0, 135.0, 200.0, 315.0].iter() {
+                for &distance in [10.0, 1_000.0, 250_000.0, 5_000_000.0].iter() {
+                    let (end_lat, end_lon) = project_point(lat, lon, bearing, distance);
+                    assert!((-180.0..180.0).contains(&end_lon));
+                    ish_bounded(
+                        haversine_distance_m(lat, lon, end_lat, end_lon),
+                        distance,
+                        1e-6 * distance,
+                    );
+                    //Compare the angle between them, so 359.9 is close to 0
+                    let bearing_error = initial_bearing_deg(lat, lon, end_lat, end_lon) - bearing;
+                    ish_bounded((bearing_error + 180.0).rem_euclid(360.0) - 180.0, 0.0, 1e-6);
+                }
+            }
+        }
+
+        //Due east along the equator for a quarter of the world
+        let quarter = std::f64::consts::FRAC_PI_2 * EARTH_RADIUS_M;
+        let (lat, lon) = project_point(0.0, 0.0, 90.0, quarter);
+        ish(lat, 0.0);
+        ish(lon, 90.0);
+        //Crossing the antimeridian wraps around
+        let (_, lon) = project_point(0.0, 170.0, 90.0, quarter / 4.5);
+        ish(lon, -170.0);
+    }
+
     #[test]
     fn test_modulo_floor() {
         assert_eq!(modulo_floor(4.5, 2.0), 4.0);
@@ -1,9 +1,13 @@
 mod math;
 mod perf;
 mod profiler;
+mod shutdown;
 mod string;
+mod units;
 
 pub use math::*;
 pub use perf::*;
 pub use profiler::*;
+pub use shutdown::*;
 pub use string::*;
+pub use units::*;
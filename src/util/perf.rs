@@ -1,18 +1,25 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
 
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use simple_moving_average::{SumTreeSMA, SMA};
 
+use super::{profile_scope, take_profile_data, NamedSample, ScopeSampler};
+
 /// The performance data recorded across the entire application relating to tiles
-pub struct PerformanceData {
-    pub tiles_rendered: usize,
-    pub tiles_on_gpu: usize,
-    pub tiles_in_memory: usize,
-    pub zoom: u32,
-    pub backend_request_secs: HashMap<&'static str, SumTreeSMA<Duration, u32, 16>>,
-    pub tile_decode_time: SumTreeSMA<Duration, u32, 16>,
-    pub tile_upload_time: SumTreeSMA<Duration, u32, 16>,
+struct PerformanceData {
+    tiles_rendered: usize,
+    tiles_on_gpu: usize,
+    tiles_in_memory: usize,
+    zoom: u32,
+    backend_request_secs: HashMap<&'static str, SumTreeSMA<Duration, u32, 16>>,
+    backend_retries: HashMap<&'static str, usize>,
+    backend_health: HashMap<&'static str, BackendHealth>,
+    tile_decode_time: SumTreeSMA<Duration, u32, 16>,
+    tile_upload_time: SumTreeSMA<Duration, u32, 16>,
 }
 
 /// Largely the same as [`PerformanceData`], but is clone for getting a snapshot out of the mutex
@@ -24,18 +31,56 @@ pub struct PerformanceDataSnapshot {
     pub tiles_in_memory: usize,
     pub zoom: u32,
     pub backend_request_secs: Vec<(&'static str, Duration)>,
+    /// How many times requests to each backend were retried since the app started
+    pub backend_retries: Vec<(&'static str, usize)>,
+    /// How well requests to each backend have been going lately
+    pub backend_health: Vec<(&'static str, BackendHealth)>,
     pub tile_decode_time: Duration,
     pub tile_upload_time: Duration,
 }
 
+/// How many of the latest requests to a backend its success rate is taken over
+const HEALTH_WINDOW: usize = 32;
+
+/// The outcome of the latest requests to a backend, for spotting one that has stopped working
+#[derive(Clone, Debug, Default)]
+pub struct BackendHealth {
+    /// When a request last returned a tile
+    pub last_success: Option<Instant>,
+    /// Whether each of the last [`HEALTH_WINDOW`] requests succeeded, oldest first
+    recent: VecDeque<bool>,
+}
+
+impl BackendHealth {
+    /// Records the outcome of a request that finished at `now`
+    pub(crate) fn record(&mut self, success: bool, now: Instant) {
+        if self.recent.len() == HEALTH_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(success);
+        if success {
+            self.last_success = Some(now);
+        }
+    }
+
+    /// The fraction of recent requests that succeeded, or `None` before the first request
+    pub fn success_rate(&self) -> Option<f64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let successes = self.recent.iter().filter(|success| **success).count();
+        Some(successes as f64 / self.recent.len() as f64)
+    }
+}
+
 lazy_static! {
     /// The global performance data for tile data
-    pub static ref MAP_PERF_DATA: Mutex<PerformanceData> = Mutex::new(Default::default());
+    static ref MAP_PERF_DATA: Mutex<PerformanceData> = Mutex::new(Default::default());
 }
 
 impl PerformanceData {
     /// Takes a snapshot of the current data, collecting the counters into one snapshot
-    pub fn snapshot(&mut self) -> PerformanceDataSnapshot {
+    fn snapshot(&self) -> PerformanceDataSnapshot {
         PerformanceDataSnapshot {
             tiles_rendered: self.tiles_rendered,
             tiles_on_gpu: self.tiles_on_gpu,
@@ -48,6 +93,12 @@ impl PerformanceData {
                 .iter()
                 .map(|(k, v)| (*k, v.get_average()))
                 .collect(),
+            backend_retries: self.backend_retries.iter().map(|(k, v)| (*k, *v)).collect(),
+            backend_health: self
+                .backend_health
+                .iter()
+                .map(|(k, v)| (*k, v.clone()))
+                .collect(),
         }
     }
 }
@@ -60,8 +111,156 @@ impl Default for PerformanceData {
             tiles_in_memory: Default::default(),
             zoom: Default::default(),
             backend_request_secs: Default::default(),
+            backend_retries: Default::default(),
+            backend_health: Default::default(),
             tile_decode_time: SumTreeSMA::from_zero(Duration::ZERO),
             tile_upload_time: SumTreeSMA::from_zero(Duration::ZERO),
         }
     }
 }
+
+/// Everything the profiler recorded, taken once per frame by [`Profiler::snapshot`]
+pub struct ProfileSnapshot {
+    /// Counters and timings for tiles, which are averaged over many frames
+    pub map: PerformanceDataSnapshot,
+    /// The scopes sampled since the last snapshot, sorted by name
+    pub scopes: Vec<(&'static str, NamedSample)>,
+}
+
+/// The one place performance data is recorded and read from.
+///
+/// Scope timings are kept per thread, see [`profile_scope`], while tile counters and timings are
+/// shared by every thread so that background tasks can report them
+pub struct Profiler;
+
+impl Profiler {
+    /// Times how long the returned guard is alive for, see [`profile_scope`]
+    pub fn scope(name: &'static str) -> ScopeSampler {
+        profile_scope(name)
+    }
+
+    /// Records how long a request to the backend named `name` took
+    pub fn record_backend_request(name: &'static str, duration: Duration) {
+        MAP_PERF_DATA
+            .lock()
+            .backend_request_secs
+            .entry(name)
+            .or_insert_with(|| SumTreeSMA::from_zero(Duration::ZERO))
+            .add_sample(duration);
+    }
+
+    /// Records that a request to the backend named `name` failed and is being retried
+    pub fn record_backend_retry(name: &'static str) {
+        *MAP_PERF_DATA
+            .lock()
+            .backend_retries
+            .entry(name)
+            .or_default() += 1;
+    }
+
+    /// Records whether a request to the backend named `name` returned a tile. Requests for tiles
+    /// the backend doesn't have count as neither
+    pub fn record_backend_result(name: &'static str, success: bool) {
+        MAP_PERF_DATA
+            .lock()
+            .backend_health
+            .entry(name)
+            .or_default()
+            .record(success, Instant::now());
+    }
+
+    /// Records how long decoding a tile image took
+    pub fn record_tile_decode(duration: Duration) {
+        MAP_PERF_DATA.lock().tile_decode_time.add_sample(duration);
+    }
+
+    /// Records how long uploading a tile texture to the GPU took
+    pub fn record_tile_upload(duration: Duration) {
+        MAP_PERF_DATA.lock().tile_upload_time.add_sample(duration);
+    }
+
+    /// Records that one pipeline went from `before` to `after` textures on the GPU. Several
+    /// pipelines share the total, so only the difference is applied
+    pub fn record_tiles_on_gpu(before: usize, after: usize) {
+        let mut guard = MAP_PERF_DATA.lock();
+        guard.tiles_on_gpu = guard.tiles_on_gpu + after - before;
+    }
+
+    /// Records how many tiles were drawn this frame, and at which zoom level
+    pub fn record_tiles_rendered(count: usize, zoom: u32) {
+        let mut guard = MAP_PERF_DATA.lock();
+        guard.tiles_rendered = count;
+        guard.zoom = zoom;
+    }
+
+    /// Takes everything recorded since the last snapshot. Should be called once per frame on the
+    /// thread that does the rendering, even when the data isn't shown, so that the scopes of
+    /// different frames aren't mixed together
+    pub fn snapshot() -> ProfileSnapshot {
+        let mut scopes: Vec<_> = take_profile_data().into_iter().collect();
+        scopes.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        ProfileSnapshot {
+            map: MAP_PERF_DATA.lock().snapshot(),
+            scopes,
+        }
+    }
+}
+
+/// Writes the frame times captured by the frame profiler to `writer` as a CSV file, with one row
+/// per frame
+pub fn write_frame_times_csv(
+    mut writer: impl std::io::Write,
+    frame_times: &[f64],
+) -> std::io::Result<()> {
+    writeln!(writer, "frame,frame_time_ms")?;
+    for (i, frame_time) in frame_times.iter().enumerate() {
+        writeln!(writer, "{},{}", i, frame_time)?;
+    }
+    writer.flush()
+}
+
+/// Saves the frame times captured by the frame profiler to `bench-<unix time>.csv` in the current
+/// directory, returning the path of the file
+pub fn save_frame_times(frame_times: &[f64]) -> std::io::Result<std::path::PathBuf> {
+    let unix_time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let path = std::path::PathBuf::from(format!("bench-{}.csv", unix_time));
+    let file = std::fs::File::create(&path)?;
+    write_frame_times_csv(std::io::BufWriter::new(file), frame_times)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_times_csv() {
+        let mut csv = Vec::new();
+        write_frame_times_csv(&mut csv, &[16.5, 17.25, 33.0]).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "frame,frame_time_ms\n0,16.5\n1,17.25\n2,33\n"
+        );
+    }
+
+    #[test]
+    fn backend_success_rate() {
+        let start = Instant::now();
+        let mut health = BackendHealth::default();
+        assert_eq!(health.success_rate(), None);
+
+        health.record(true, start);
+        health.record(false, start + Duration::from_secs(1));
+        assert_eq!(health.success_rate(), Some(0.5));
+        assert_eq!(health.last_success, Some(start));
+
+        //Only the latest requests count
+        for _ in 0..HEALTH_WINDOW {
+            health.record(false, start);
+        }
+        assert_eq!(health.success_rate(), Some(0.0));
+        assert_eq!(health.last_success, Some(start));
+    }
+}
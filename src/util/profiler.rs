@@ -1,23 +1,81 @@
+use log::warn;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::Mutex,
     time::{Duration, Instant},
 };
 
+/// How many of the most recent samples of each scope are kept to compute [`SampleStats`] from.
+///
+/// Two seconds worth at 60 FPS for scopes that run once a frame
+const HISTORY_LENGTH: usize = 120;
+
 /// A group of associated samples that correspond with the length of an operation
 #[derive(Clone, Debug)]
 pub struct NamedSample {
+    /// The samples taken since the last call to [`take_profile_data`]
     completed: Vec<Duration>,
-    in_progress: Option<Instant>,
+    /// When each unfinished scope started. Scopes with the same name can be nested, such as in
+    /// recursive code, in which case the innermost one is last
+    in_progress: Vec<Instant>,
+    /// The last [`HISTORY_LENGTH`] samples, which are kept across frames
+    history: VecDeque<Duration>,
+}
+
+/// Statistics about the recent samples of a scope, see [`NamedSample::stats`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SampleStats {
+    /// The number of samples taken during the last frame
+    pub count: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    /// 95% of recent samples took this long or less
+    pub p95: Duration,
 }
 
 impl NamedSample {
+    fn new() -> Self {
+        Self {
+            completed: Vec::new(),
+            in_progress: Vec::new(),
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+        }
+    }
+
     pub fn get_samples(&self) -> &Vec<Duration> {
-        if cfg!(debug_assertions) && self.in_progress.is_some() {
-            println!("Perf Warn: getting completed samples while perf sample is in progress");
+        if cfg!(debug_assertions) && !self.in_progress.is_empty() {
+            warn!("Perf Warn: getting completed samples while perf sample is in progress");
         }
         &self.completed
     }
+
+    /// Returns statistics about the recent samples of this scope, which include samples from
+    /// previous frames so that spikes stay visible. Returns `None` if there are no samples
+    pub fn stats(&self) -> Option<SampleStats> {
+        let mut sorted: Vec<_> = self.history.iter().copied().collect();
+        sorted.sort_unstable();
+        let min = *sorted.first()?;
+        let max = *sorted.last()?;
+        let mean = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+        let p95_index = ((sorted.len() as f64 * 0.95).ceil() as usize).max(1) - 1;
+
+        Some(SampleStats {
+            count: self.completed.len(),
+            min,
+            max,
+            mean,
+            p95: sorted[p95_index],
+        })
+    }
+
+    fn record(&mut self, sample: Duration) {
+        self.completed.push(sample);
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+    }
 }
 
 struct Samples(Mutex<HashMap<&'static str, NamedSample>>);
@@ -31,6 +89,7 @@ thread_local! {
 ///
 /// Once `ScopeSampler` is dropped, there will be an entry name in [`take_profile_data`] with the
 /// correspond duration `ScopeSampler` was alive for. Can be repeated to add more samples to `name`.
+/// Scopes with the same name can also be nested, each one adding its own sample.
 pub fn profile_scope(name: &'static str) -> ScopeSampler {
     SAMPLES.with(|samples| {
         samples.start(name);
@@ -38,19 +97,24 @@ pub fn profile_scope(name: &'static str) -> ScopeSampler {
     ScopeSampler { name }
 }
 
-/// Takes the map associating named scopes to their duration, leaving an empty map.
+/// Takes the map associating named scopes to their duration, for every scope that was sampled
+/// since the last call.
 ///
 /// To gather profiling data each frame, this function should be called once per frame, with
-/// [`profile_scope`] being called many times for each bit of code that should be sampled.
+/// [`profile_scope`] being called many times for each bit of code that should be sampled. The
+/// history behind [`NamedSample::stats`] is kept between calls
 pub fn take_profile_data() -> HashMap<&'static str, NamedSample> {
     SAMPLES.with(|samples| {
-        let mut result = HashMap::new();
-        {
-            let mut guard = samples.0.lock().unwrap();
-            std::mem::swap(&mut result, &mut guard);
-        }
-
-        result
+        let mut guard = samples.0.lock().unwrap();
+        guard
+            .iter_mut()
+            .filter(|(_, sample)| !sample.completed.is_empty())
+            .map(|(name, sample)| {
+                let taken = sample.clone();
+                sample.completed.clear();
+                (*name, taken)
+            })
+            .collect()
     })
 }
 
@@ -88,10 +152,10 @@ impl Samples {
         };
         let start = sample
             .in_progress
-            .take()
+            .pop()
             .unwrap_or_else(|| panic!("No sample started!"));
 
-        sample.completed.push(end - start);
+        sample.record(end - start);
     }
 
     fn start(&self, name: &'static str) {
@@ -99,14 +163,68 @@ impl Samples {
         let start = Instant::now();
 
         let mut guard = self.0.lock().unwrap();
-        let sample = guard.entry(name).or_insert(NamedSample {
-            completed: Vec::new(),
-            in_progress: None,
-        });
+        let sample = guard.entry(name).or_insert_with(NamedSample::new);
+        sample.in_progress.push(start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if sample.in_progress.is_some() {
-            panic!("Sample already in progress! End must be called first");
+    #[test]
+    fn sample_stats() {
+        let mut sample = NamedSample::new();
+        assert_eq!(sample.stats(), None);
+
+        for ms in 1..=20 {
+            sample.record(Duration::from_millis(ms));
         }
-        sample.in_progress = Some(start);
+        let stats = sample.stats().unwrap();
+        assert_eq!(stats.count, 20);
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.max, Duration::from_millis(20));
+        assert_eq!(stats.mean, Duration::from_micros(10_500));
+        assert_eq!(stats.p95, Duration::from_millis(19));
+
+        // Only the most recent samples are kept
+        for _ in 0..HISTORY_LENGTH {
+            sample.record(Duration::from_millis(2));
+        }
+        let stats = sample.stats().unwrap();
+        assert_eq!(stats.max, Duration::from_millis(2));
+        assert_eq!(stats.count, 20 + HISTORY_LENGTH);
+    }
+
+    #[test]
+    fn nested_scopes() {
+        let outer = profile_scope("nested scope");
+        std::thread::sleep(Duration::from_millis(5));
+        profile_scope("nested scope").end();
+        std::thread::sleep(Duration::from_millis(5));
+        outer.end();
+
+        let data = take_profile_data();
+        let samples = data["nested scope"].get_samples();
+        assert_eq!(samples.len(), 2);
+        // The inner scope ends first and is shorter than the outer one, which contains it
+        assert!(samples[0] < samples[1]);
+        assert!(samples[1] >= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn profile_data_keeps_history() {
+        profile_scope("test scope").end();
+        profile_scope("test scope").end();
+        let data = take_profile_data();
+        assert_eq!(data["test scope"].get_samples().len(), 2);
+
+        // Scopes that didn't run are left out, but their history is kept for next time
+        assert!(!take_profile_data().contains_key("test scope"));
+        profile_scope("test scope").end();
+        let data = take_profile_data();
+        let stats = data["test scope"].stats().unwrap();
+        assert_eq!(stats.count, 1);
+        assert_eq!(data["test scope"].history.len(), 3);
     }
 }
@@ -0,0 +1,64 @@
+use tokio::sync::broadcast;
+
+/// Tells background tasks that the app is closing, so that they can stop once they are done with
+/// what they are working on.
+///
+/// Clones trigger the same shutdown. Dropping every clone also counts as shutting down
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    sender: broadcast::Sender<()>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(1);
+        Self { sender }
+    }
+
+    /// Returns a receiver that gets a message, or is closed, once the app is shutting down. Must
+    /// be called before [`Shutdown::trigger`] to see it
+    pub fn subscribe(&self) -> broadcast::Receiver<()> {
+        self.sender.subscribe()
+    }
+
+    /// Tells every subscribed task to stop
+    pub fn trigger(&self) {
+        //No subscribers just means there is nothing to stop
+        let _ = self.sender.send(());
+    }
+}
+
+/// Returns true if `receiver`, from [`Shutdown::subscribe`], has been told to shut down, without
+/// waiting
+pub fn is_shutting_down(receiver: &mut broadcast::Receiver<()>) -> bool {
+    !matches!(
+        receiver.try_recv(),
+        Err(broadcast::error::TryRecvError::Empty)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shutdown_reaches_subscribers() {
+        let shutdown = Shutdown::new();
+        let mut receiver = shutdown.subscribe();
+        assert!(!is_shutting_down(&mut receiver));
+        shutdown.clone().trigger();
+        assert!(is_shutting_down(&mut receiver));
+
+        //Nobody being left to trigger a shutdown counts as one
+        let shutdown = Shutdown::new();
+        let mut receiver = shutdown.subscribe();
+        drop(shutdown);
+        assert!(is_shutting_down(&mut receiver));
+    }
+}
@@ -28,6 +28,11 @@ impl<const N: usize> StringFormatter<N> {
         self.index = 0;
     }
 
+    /// Returns how many more bytes can be written before the buffer is full
+    pub fn remaining(&self) -> usize {
+        N - self.index
+    }
+
     pub fn as_str(&self) -> &str {
         // # Safety
         //
@@ -40,25 +45,45 @@ impl<const N: usize> StringFormatter<N> {
 }
 
 impl<const N: usize> std::fmt::Write for StringFormatter<N> {
+    /// Appends `s`, or as much of it as fits if the buffer is full, in which case an error is
+    /// returned. Only whole characters are written so that [`StringFormatter::as_str`] stays valid
     fn write_str(&mut self, s: &str) -> Result<(), std::fmt::Error> {
-        let src = s.as_ptr();
-        let dst = &mut self.buf[self.index] as *mut u8;
-        let len = s.len();
-
-        if self.index + len > self.buf.len() {
-            //Would overflow
-            return Err(std::fmt::Error);
+        let mut len = s.len().min(self.remaining());
+        while !s.is_char_boundary(len) {
+            len -= 1;
         }
-        // # Safety
-        //
-        // 1. `src` is valid for reads length `len`, by the bounds check above
-        // 2. `dst` is valid for writes length `len`, by the bounds check above
-        //    - We have exclusive access to self so we have exclusive access to self.buf
-        // 3. Because we have exclusive access to all of self.buf, then it is impossible for src to
-        //    overlap dst
-        unsafe { std::ptr::copy_nonoverlapping(src, dst, len) };
+
+        self.buf[self.index..self.index + len].copy_from_slice(&s.as_bytes()[..len]);
         self.index += len;
 
-        Ok(())
+        if len == s.len() {
+            Ok(())
+        } else {
+            Err(std::fmt::Error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    #[test]
+    fn formatter_truncates_when_full() {
+        let mut buf: StringFormatter<8> = StringFormatter::new();
+        write!(buf, "{}", 1234).unwrap();
+        assert_eq!(buf.remaining(), 4);
+
+        assert!(write!(buf, "{}", 56789).is_err());
+        assert_eq!(buf.as_str(), "12345678");
+        assert_eq!(buf.remaining(), 0);
+        assert!(buf.write_str("9").is_err());
+        assert!(buf.write_str("").is_ok());
+
+        // Characters aren't split in half
+        buf.clear();
+        assert!(buf.write_str("1234567é").is_err());
+        assert_eq!(buf.as_str(), "1234567");
     }
 }
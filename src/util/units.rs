@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+const FEET_PER_METER: f64 = 1.0 / 0.3048;
+const METERS_PER_MILE: f64 = 1609.344;
+const METERS_PER_NAUTICAL_MILE: f64 = 1852.0;
+
+/// Which units altitudes, speeds and distances are shown in. Planes always store SI values, these
+/// only change how they are written
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Units {
+    /// Meters, kilometers and kilometers per hour
+    #[default]
+    Metric,
+    /// Feet, miles and miles per hour
+    Imperial,
+    /// Feet, nautical miles and knots, as pilots and air traffic control use
+    Aviation,
+}
+
+impl Units {
+    /// Returns the next units, for cycling through them with a button
+    pub fn next(self) -> Self {
+        match self {
+            Units::Metric => Units::Imperial,
+            Units::Imperial => Units::Aviation,
+            Units::Aviation => Units::Metric,
+        }
+    }
+
+    /// The unit long distances are written in, short enough to fit on a button
+    pub fn distance_label(self) -> &'static str {
+        match self {
+            Units::Metric => "km",
+            Units::Imperial => "mi",
+            Units::Aviation => "NM",
+        }
+    }
+}
+
+/// Formats an altitude in meters, like `3281 ft`
+pub fn format_altitude(meters: f64, units: Units) -> String {
+    match units {
+        Units::Metric => format!("{:.0} m", meters),
+        Units::Imperial | Units::Aviation => format!("{:.0} ft", meters * FEET_PER_METER),
+    }
+}
+
+/// Formats a speed in meters per second, like `194 kt`
+pub fn format_speed(meters_per_second: f64, units: Units) -> String {
+    match units {
+        Units::Metric => format!("{:.0} km/h", meters_per_second * 3.6),
+        Units::Imperial => format!("{:.0} mph", meters_per_second * 3600.0 / METERS_PER_MILE),
+        Units::Aviation => format!(
+            "{:.0} kt",
+            meters_per_second * 3600.0 / METERS_PER_NAUTICAL_MILE
+        ),
+    }
+}
+
+/// Formats a rate of climb in meters per second, like `1500 ft/min`. Negative rates are descents
+pub fn format_vertical_rate(meters_per_second: f64, units: Units) -> String {
+    match units {
+        Units::Metric => format!("{:.1} m/s", meters_per_second),
+        Units::Imperial | Units::Aviation => {
+            format!("{:.0} ft/min", meters_per_second * 60.0 * FEET_PER_METER)
+        }
+    }
+}
+
+/// Formats a distance in meters, using the small unit (meters or feet) for short distances and
+/// the large one past it, with a decimal place below 100 of the large unit
+pub fn format_distance(meters: f64, units: Units) -> String {
+    let (large_unit, meters_per_large) = match units {
+        Units::Metric => ("km", 1000.0),
+        Units::Imperial => ("mi", METERS_PER_MILE),
+        Units::Aviation => ("NM", METERS_PER_NAUTICAL_MILE),
+    };
+    //A thousand meters is about half a mile or nautical mile, so feet go up to the same point
+    if meters < 1000.0 {
+        return format_altitude(meters, units);
+    }
+    let large = meters / meters_per_large;
+    if large < 100.0 {
+        format!("{:.1} {}", large, large_unit)
+    } else {
+        format!("{:.0} {}", large, large_unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_conversions() {
+        use Units::*;
+        assert_eq!(format_altitude(1000.0, Metric), "1000 m");
+        assert_eq!(format_altitude(1000.0, Imperial), "3281 ft");
+        assert_eq!(format_altitude(1000.0, Aviation), "3281 ft");
+
+        assert_eq!(format_speed(100.0, Metric), "360 km/h");
+        assert_eq!(format_speed(100.0, Imperial), "224 mph");
+        assert_eq!(format_speed(100.0, Aviation), "194 kt");
+
+        assert_eq!(format_vertical_rate(-2.54, Metric), "-2.5 m/s");
+        assert_eq!(format_vertical_rate(-2.54, Aviation), "-500 ft/min");
+
+        assert_eq!(format_distance(12.4, Metric), "12 m");
+        assert_eq!(format_distance(999.0, Metric), "999 m");
+        assert_eq!(format_distance(86_742.1, Metric), "86.7 km");
+        assert_eq!(format_distance(5_540_018.9, Metric), "5540 km");
+        assert_eq!(format_distance(300.0, Imperial), "984 ft");
+        assert_eq!(format_distance(16_093.44, Imperial), "10.0 mi");
+        assert_eq!(format_distance(5_540_018.9, Aviation), "2991 NM");
+    }
+
+    #[test]
+    fn units_cycle() {
+        assert_eq!(Units::default(), Units::Metric);
+        assert_eq!(Units::Metric.next(), Units::Imperial);
+        assert_eq!(Units::Metric.next().next().next(), Units::Metric);
+    }
+}
@@ -0,0 +1,185 @@
+use std::path::Path;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::util::Units;
+use crate::{BaseLayer, ThemeKind};
+
+/// Where the view state is kept between sessions
+const VIEW_STATE_PATH: &str = ".cache/view_state.json";
+
+/// Where older versions kept the view state, encoded with bincode. Only read if there is no
+/// [`VIEW_STATE_PATH`] yet
+const LEGACY_VIEW_STATE_PATH: &str = ".cache/view_state.bin";
+
+/// User choices about how the map is shown, which are saved so they survive restarts.
+///
+/// Saved as JSON so that fields can be added without losing the rest of an older file. Fields it
+/// doesn't have take their default value
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewState {
+    pub base_layer: BaseLayer,
+    pub theme: ThemeKind,
+    pub units: Units,
+}
+
+impl ViewState {
+    /// Loads the view state saved by the last session, or the defaults if there isn't one
+    pub fn load() -> Self {
+        Self::load_from(
+            Path::new(VIEW_STATE_PATH),
+            Path::new(LEGACY_VIEW_STATE_PATH),
+        )
+    }
+
+    /// Saves the view state so that [`ViewState::load`] returns it next session
+    pub fn save(&self) -> Result<(), std::io::Error> {
+        self.save_to(Path::new(VIEW_STATE_PATH))
+    }
+
+    fn load_from(path: &Path, legacy_path: &Path) -> Self {
+        if let Ok(bytes) = std::fs::read(path) {
+            return match serde_json::from_slice(&bytes) {
+                Ok(state) => state,
+                Err(err) => {
+                    warn!(
+                        "Couldn't read the view state in {}, using the defaults: {}",
+                        path.display(),
+                        err
+                    );
+                    Self::default()
+                }
+            };
+        }
+        match std::fs::read(legacy_path) {
+            Ok(bytes) => decode_legacy(&bytes).unwrap_or_else(|| {
+                warn!(
+                    "Couldn't read the view state in {}, using the defaults",
+                    legacy_path.display()
+                );
+                Self::default()
+            }),
+            //Nothing saved yet
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), std::io::Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let bytes = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path, bytes)
+    }
+}
+
+/// Decodes a view state saved with bincode by an older version. Each version appended a field, so
+/// the longest layout is tried first
+fn decode_legacy(bytes: &[u8]) -> Option<ViewState> {
+    if let Ok((base_layer, theme, units)) = bincode::deserialize(bytes) {
+        return Some(ViewState {
+            base_layer,
+            theme,
+            units,
+        });
+    }
+    if let Ok((base_layer, theme)) = bincode::deserialize(bytes) {
+        return Some(ViewState {
+            base_layer,
+            theme,
+            ..ViewState::default()
+        });
+    }
+    bincode::deserialize(bytes)
+        .ok()
+        .map(|(base_layer,)| ViewState {
+            base_layer,
+            ..ViewState::default()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_state_round_trip() {
+        let folder = std::env::temp_dir().join(format!("ft-view-state-{}", std::process::id()));
+        let path = folder.join("view_state.json");
+        let legacy_path = folder.join("view_state.bin");
+        assert_eq!(
+            ViewState::load_from(&path, &legacy_path),
+            ViewState::default()
+        );
+
+        let state = ViewState {
+            base_layer: BaseLayer::Street,
+            theme: ThemeKind::Light,
+            units: Units::Aviation,
+        };
+        state.save_to(&path).unwrap();
+        assert_eq!(ViewState::load_from(&path, &legacy_path), state);
+
+        //Fields missing from an older file keep their defaults, and the rest survive
+        std::fs::write(&path, r#"{"base_layer":"Street"}"#).unwrap();
+        assert_eq!(
+            ViewState::load_from(&path, &legacy_path),
+            ViewState {
+                base_layer: BaseLayer::Street,
+                ..ViewState::default()
+            }
+        );
+
+        //Garbage falls back to the defaults instead of failing
+        std::fs::write(&path, [0xFF; 3]).unwrap();
+        assert_eq!(
+            ViewState::load_from(&path, &legacy_path),
+            ViewState::default()
+        );
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn legacy_view_state_is_migrated() {
+        let folder = std::env::temp_dir().join(format!("ft-legacy-state-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("view_state.json");
+        let legacy_path = folder.join("view_state.bin");
+
+        //Before themes were added only the base layer was saved
+        let bytes = bincode::serialize(&(BaseLayer::Street,)).unwrap();
+        std::fs::write(&legacy_path, bytes).unwrap();
+        assert_eq!(
+            ViewState::load_from(&path, &legacy_path),
+            ViewState {
+                base_layer: BaseLayer::Street,
+                ..ViewState::default()
+            }
+        );
+
+        let bytes = bincode::serialize(&(BaseLayer::Street, ThemeKind::Light)).unwrap();
+        std::fs::write(&legacy_path, bytes).unwrap();
+        assert_eq!(
+            ViewState::load_from(&path, &legacy_path),
+            ViewState {
+                base_layer: BaseLayer::Street,
+                theme: ThemeKind::Light,
+                ..ViewState::default()
+            }
+        );
+
+        let state = ViewState {
+            base_layer: BaseLayer::Street,
+            theme: ThemeKind::Light,
+            units: Units::Imperial,
+        };
+        let bytes = bincode::serialize(&(state.base_layer, state.theme, state.units)).unwrap();
+        std::fs::write(&legacy_path, bytes).unwrap();
+        assert_eq!(ViewState::load_from(&path, &legacy_path), state);
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}